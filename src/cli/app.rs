@@ -1,72 +1,412 @@
 use std::fmt::{self, Display};
+use std::process::ExitCode;
+use std::time::Duration;
+
+use tokio_util::sync::CancellationToken;
 
 pub use super::args::{Args, Command, Parser};
-use super::config::{Config, ConfigError};
+use super::args::{CacheSubcommand, DeviceSubcommand, PushSubcommand, SchemaSubcommand};
+use super::changes::PushOptions;
+use super::config::on_disk_device::OnDiskDevice;
+use super::config::{ConfigBuilder, ConfigError, LockMode};
 use super::ops::{
-    device_subcommand, health, init, pull, push, schema_subcommand, stage, DeviceSubcommandError,
-    HealthError, InitError, PullError, PushError, SchemaSubcommandError, StageError,
+    add_cid, apply, bench, blame, cache_subcommand, cat, cid_info, commit, dag_get,
+    device_subcommand, diff_manifest, diff_roots, du, export, fsck, hash, health, index_subcommand,
+    init, lint, ls, manifest_db_subcommand, migrate_root, pull, push, push_subcommand, reconcile,
+    reencode, replicate, reset, root_plan, schema_subcommand, serve, set_previous_root, squash,
+    stage, stat, tag, tag_subcommand, tags, target_params, verify, verify_car, watch, where_pinned,
+    AddCidError, BenchError, BlameError, CacheSubcommandError, CatError, CidInfoError, CommitError,
+    DagGetError, DeviceSubcommandError, DiffManifestError, DuError, ExportError, FsckError,
+    HashError, HealthError, IndexSubcommandError, InitError, LintError, LsError, ManifestDbError,
+    MigrateRootError, PatchError, PullError, PullTarget, PushError, PushSubcommandError,
+    ReconcileError, ReencodeError, ReplicateError, ResetError, RootPlanError,
+    SchemaSubcommandError, ServeError, SetPreviousRootError, SquashError, StageError, StatError,
+    TagError, VerifyCarError, VerifyError, WatchError, WhereError,
 };
 
 pub struct App;
 
 impl App {
-    pub async fn run() {
+    /// Run the cli, returning the status to exit with
+    pub async fn run() -> ExitCode {
+        tracing_subscriber::fmt::init();
+        capture_error(Self::run_result(None).await)
+    }
+
+    /// Run the cli, stopping the op at its next safe point once the token is cancelled
+    pub async fn run_with_token(token: CancellationToken) -> ExitCode {
         tracing_subscriber::fmt::init();
-        capture_error(Self::run_result().await);
+        capture_error(Self::run_result(Some(token)).await)
     }
 
-    async fn run_result() -> Result<(), AppError> {
+    async fn run_result(token: Option<CancellationToken>) -> Result<(), AppError> {
         let args = Args::parse();
-        let config = Config::parse_args(&args)?;
+        let mut builder = ConfigBuilder::from_args(&args)?;
+        if let Some(source) = &args.device_config {
+            let device_config = OnDiskDevice::from_json(&source.read().await?)?;
+            builder = builder.with_device_config(device_config);
+        }
+        if let Some(token) = token {
+            builder = builder.with_cancellation_token(token);
+        }
+        let config = builder.build()?;
+        // Held until the op is done
+        let _lock = match lock_mode(&args.command) {
+            Some(mode) => config.lock(mode)?,
+            None => None,
+        };
         match args.command {
+            Command::AddCid {
+                cid,
+                path,
+                probe,
+                force,
+            } => {
+                add_cid(&config, &cid, &path, probe, force).await?;
+            }
+            Command::Apply { patch } => {
+                apply(&config, &patch).await?;
+            }
+            Command::Bench { count, size } => {
+                bench(&config, count, size).await?;
+            }
+            Command::Blame {
+                path,
+                root,
+                max_depth,
+            } => {
+                blame(&config, &path, root.as_ref(), max_depth).await?;
+            }
+            Command::Cache { subcommand } => {
+                cache_subcommand(&config, &subcommand).await?;
+            }
+            Command::Cat { path, cid } => {
+                cat(&config, path.as_deref(), cid.as_ref()).await?;
+            }
+            Command::CidInfo => {
+                cid_info(&config)?;
+            }
+            Command::DagGet { cid, path, remote } => {
+                dag_get(&config, &cid, &path, remote).await?;
+            }
             Command::Device { subcommand } => {
                 device_subcommand(&config, &subcommand)?;
             }
+            Command::Diff {
+                from,
+                to,
+                remote,
+                export,
+            } => {
+                diff_roots(&config, &from, &to, remote, export.as_deref()).await?;
+            }
+            Command::DiffManifest { a, b } => {
+                diff_manifest(&a, &b)?;
+            }
+            Command::Du { root, remote } => {
+                du(&config, &root, remote).await?;
+            }
+            Command::Export {
+                out,
+                sniff,
+                root,
+                path,
+                cid,
+            } => {
+                export(
+                    &config,
+                    &out,
+                    sniff,
+                    root.as_ref(),
+                    path.as_deref(),
+                    cid.as_ref(),
+                )
+                .await?;
+            }
+            Command::Fsck => {
+                fsck(&config).await?;
+            }
+            Command::Hash { path } => {
+                hash(&path)?;
+            }
             Command::Health => {
                 health(&config).await?;
             }
+            Command::Index { subcommand } => {
+                index_subcommand(&config, &subcommand).await?;
+            }
             Command::Init => {
                 init(&config)?;
             }
-            Command::Pull => {
-                pull(&config).await?;
+            Command::Lint => {
+                lint(&config)?;
+            }
+            Command::Ls { cid, remote } => {
+                ls(&config, &cid, remote).await?;
+            }
+            Command::ManifestDb { subcommand } => {
+                manifest_db_subcommand(&config, &subcommand)?;
+            }
+            Command::MigrateRoot {
+                to_rpc,
+                to_chain_id,
+                to_contract_address,
+                switch,
+                yes,
+            } => {
+                migrate_root(
+                    &config,
+                    &to_rpc,
+                    to_chain_id,
+                    to_contract_address,
+                    switch,
+                    yes,
+                )
+                .await?;
             }
-            Command::Stage => {
-                stage(&config).await?;
+            Command::Pull {
+                include,
+                exclude,
+                plan,
+                output,
+                to_tar,
+                keep_going,
+                layout,
+                to_dir,
+            } => {
+                let target = PullTarget::new(to_tar, layout, to_dir)?;
+                pull(
+                    &config, &include, &exclude, plan, output, &target, keep_going,
+                )
+                .await?;
             }
-            Command::Stat => {
-                let change_log = config.change_log()?;
-                let displayable_change_log = change_log.displayable();
-                println!("{}", displayable_change_log);
+            Command::Squash { yes } => {
+                squash(&config, yes).await?;
+            }
+            Command::Stage {
+                no_cache,
+                allow_large,
+            } => {
+                stage(&config, no_cache, allow_large).await?;
+            }
+            Command::Stat { no_cache } => {
+                stat(&config, no_cache).await?;
+            }
+            Command::Commit => {
+                commit(&config)?;
+            }
+            Command::Reconcile { force } => {
+                reconcile(&config, force).await?;
+            }
+            Command::Reencode {
+                to_hash,
+                cid_version,
+                unpin_old,
+                yes,
+            } => {
+                let target = target_params(&to_hash, cid_version)?;
+                reencode(&config, target, unpin_old, yes).await?;
+            }
+            Command::Replicate { path, replication } => {
+                replicate(&config, &path, replication.as_ref()).await?;
+            }
+            Command::Reset => {
+                reset(&config)?;
+            }
+            Command::RootPlan { output } => {
+                root_plan(&config, output).await?;
             }
             Command::Schema { subcommand } => {
                 schema_subcommand(&config, &subcommand).await?;
             }
-            Command::Push => {
-                push(&config).await?;
+            Command::Serve { addr } => {
+                serve(&config, addr).await?;
+            }
+            Command::SetPreviousRoot { root, resolve } => {
+                set_previous_root(&config, &root, resolve).await?;
+            }
+            Command::Tag {
+                subcommand: Some(subcommand),
+                ..
+            } => {
+                tag_subcommand(&config, &subcommand)?;
+            }
+            Command::Tag {
+                subcommand: None,
+                name,
+                root,
+                force,
+            } => {
+                // Clap requires a name without a subcommand
+                tag(&config, &name.unwrap(), root.as_ref(), force)?;
+            }
+            Command::Tags => {
+                tags(&config)?;
+            }
+            Command::Push {
+                subcommand: Some(subcommand),
+                ..
+            } => {
+                push_subcommand(&config, &subcommand).await?;
+            }
+            Command::Push {
+                subcommand: None,
+                local,
+                fail_fast,
+                name_pin,
+                yes,
+                force,
+                path,
+                skip_balance_check,
+                allow_orphan_previous,
+            } => {
+                let options = PushOptions {
+                    fail_fast,
+                    name_pin,
+                    force,
+                    path,
+                    skip_balance_check,
+                    allow_orphan_previous,
+                    prepare: false,
+                    unattended: false,
+                };
+                push(&config, local, yes, &options).await?;
+            }
+            Command::Verify => {
+                verify(&config).await?;
+            }
+            Command::VerifyCar {
+                car,
+                root,
+                manifest,
+            } => {
+                verify_car(&car, &root, manifest.as_ref()).await?;
+            }
+            Command::Watch {
+                debounce_ms,
+                include,
+                exclude,
+                local,
+                yes,
+                metrics_addr,
+            } => {
+                let debounce = Duration::from_millis(debounce_ms);
+                watch(
+                    &config,
+                    debounce,
+                    &include,
+                    &exclude,
+                    local,
+                    yes,
+                    metrics_addr,
+                )
+                .await?;
+            }
+            Command::Where { root, gateway } => {
+                where_pinned(&config, root.as_ref(), &gateway).await?;
             }
         }
         Ok(())
     }
 }
 
+/// How a command holds the working dir while it runs: ops that only read state
+/// share it, anything that changes it has it to itself
+/// # Returns None for long running ops that shouldn't hold up others, i.e. serve,
+///  or that lock it themselves while they change it, i.e. watch
+fn lock_mode(command: &Command) -> Option<LockMode> {
+    match command {
+        Command::Serve { .. } | Command::Watch { .. } => None,
+        Command::Bench { .. }
+        | Command::Blame { .. }
+        | Command::Cat { .. }
+        | Command::CidInfo
+        | Command::DagGet { .. }
+        | Command::Diff { .. }
+        | Command::DiffManifest { .. }
+        | Command::Du { .. }
+        | Command::Export { .. }
+        | Command::Hash { .. }
+        | Command::Health
+        | Command::Lint
+        | Command::Ls { .. }
+        | Command::ManifestDb { .. }
+        | Command::RootPlan { .. }
+        | Command::Stat { .. }
+        | Command::Tags
+        | Command::Verify
+        | Command::VerifyCar { .. }
+        | Command::Where { .. }
+        | Command::Cache {
+            subcommand: CacheSubcommand::Stats,
+        }
+        | Command::Device {
+            subcommand: DeviceSubcommand::Ls | DeviceSubcommand::Show,
+        }
+        | Command::Push {
+            subcommand: Some(PushSubcommand::Status),
+            ..
+        }
+        | Command::Schema {
+            subcommand: SchemaSubcommand::Ls,
+        } => Some(LockMode::Shared),
+        _ => Some(LockMode::Exclusive),
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum AppError {
+    AddCid(#[from] AddCidError),
+    Bench(#[from] BenchError),
+    Blame(#[from] BlameError),
+    CacheSubcommand(#[from] CacheSubcommandError),
+    Cat(#[from] CatError),
+    CidInfo(#[from] CidInfoError),
+    Commit(#[from] CommitError),
     Config(#[from] ConfigError),
+    DagGet(#[from] DagGetError),
     DeviceSubcommand(#[from] DeviceSubcommandError),
+    DiffManifest(#[from] DiffManifestError),
+    Du(#[from] DuError),
+    Export(#[from] ExportError),
+    Fsck(#[from] FsckError),
+    Hash(#[from] HashError),
     Init(#[from] InitError),
     Health(#[from] HealthError),
+    Lint(#[from] LintError),
+    IndexSubcommand(#[from] IndexSubcommandError),
+    Ls(#[from] LsError),
+    ManifestDb(#[from] ManifestDbError),
+    MigrateRoot(#[from] MigrateRootError),
+    Patch(#[from] PatchError),
+    Squash(#[from] SquashError),
     Stage(#[from] StageError),
     Push(#[from] PushError),
+    PushSubcommand(#[from] PushSubcommandError),
+    Reconcile(#[from] ReconcileError),
+    Reencode(#[from] ReencodeError),
+    Replicate(#[from] ReplicateError),
     SchemaSubcommand(#[from] SchemaSubcommandError),
+    Serve(#[from] ServeError),
+    SetPreviousRoot(#[from] SetPreviousRootError),
     Pull(#[from] PullError),
+    Reset(#[from] ResetError),
+    RootPlan(#[from] RootPlanError),
+    Stat(#[from] StatError),
+    Tag(#[from] TagError),
+    Verify(#[from] VerifyError),
+    VerifyCar(#[from] VerifyCarError),
+    Watch(#[from] WatchError),
+    Where(#[from] WhereError),
 }
 
-fn capture_error<T>(result: Result<T, AppError>) {
+/// Report a failed op, and pick the status to exit with
+fn capture_error<T>(result: Result<T, AppError>) -> ExitCode {
     match result {
-        Ok(_) => {}
+        Ok(_) => ExitCode::SUCCESS,
         Err(e) => {
             eprintln!("{}", e);
+            ExitCode::FAILURE
         }
     }
 }
@@ -0,0 +1,155 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use cid::Cid;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use futures_util::StreamExt;
+use tar::{Builder, EntryType, Header};
+
+use crate::device::{CidParams, Device, DeviceError, FileHasher, IpfsError};
+use crate::types::Object;
+
+/// The file under a tar archive, gzipped if its name asks for it
+enum ArchiveFile {
+    Plain(File),
+    Gzip(GzEncoder<File>),
+}
+
+impl Write for ArchiveFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Plain(file) => file.write(buf),
+            Self::Gzip(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Plain(file) => file.flush(),
+            Self::Gzip(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Whether an archive path asks for gzip
+fn is_gzip(path: &Path) -> bool {
+    let name = path.to_string_lossy();
+    name.ends_with(".tar.gz") || name.ends_with(".tgz")
+}
+
+/// A tar archive of objects, each stored at its manifest path
+/// Tar headers carry an entry's size up front, so each object is streamed into a
+/// single spool file next to the archive, verified against its cid on the way, and
+/// copied in from there. No object is ever held in memory whole
+pub struct TarArchive {
+    builder: Builder<ArchiveFile>,
+    spool_path: PathBuf,
+}
+
+impl TarArchive {
+    /// Start a new archive at `path`, gzipped if it ends in .tar.gz or .tgz
+    pub fn create(path: &Path) -> Result<Self, ArchiveError> {
+        let file = File::create(path)?;
+        let file = if is_gzip(path) {
+            ArchiveFile::Gzip(GzEncoder::new(file, Compression::default()))
+        } else {
+            ArchiveFile::Plain(file)
+        };
+        Ok(Self {
+            builder: Builder::new(file),
+            spool_path: path.with_extension("spool"),
+        })
+    }
+
+    /// Stream an object into the archive at its path
+    /// Links are stored as symlinks to their target
+    /// # Returns the number of bytes the object was stored as
+    pub async fn append_object(
+        &mut self,
+        device: &Device,
+        path: &Path,
+        object: &Object,
+    ) -> Result<u64, ArchiveError> {
+        let mut header = Header::new_gnu();
+        let mtime = object.mtime().unwrap_or(object.updated_at());
+        header.set_mtime(mtime.timestamp().max(0) as u64);
+        if let Some(target) = object.link() {
+            header.set_entry_type(EntryType::Symlink);
+            header.set_mode(0o777);
+            header.set_size(0);
+            self.builder.append_link(&mut header, path, target)?;
+            return Ok(0);
+        }
+
+        let cid = object.cid();
+        let mut spool = File::create(&self.spool_path)?;
+        let mut hasher = FileHasher::new();
+        let mut bytes = 0;
+        let mut stream = device.stream_object(cid).await?;
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            hasher.update(&chunk)?;
+            spool.write_all(&chunk)?;
+            bytes += chunk.len() as u64;
+        }
+        drop(spool);
+
+        // We can only recompute cids made with the params we add data with
+        if CidParams::of(cid) == CidParams::default() {
+            let actual = hasher.finish()?;
+            if actual != *cid {
+                return Err(ArchiveError::CidMismatch {
+                    path: path.to_path_buf(),
+                    expected: *cid,
+                    actual,
+                });
+            }
+        } else {
+            tracing::warn!(
+                "can't verify {} ({}), archiving it as is",
+                path.display(),
+                cid
+            );
+        }
+
+        header.set_entry_type(EntryType::Regular);
+        header.set_mode(0o644);
+        header.set_size(bytes);
+        self.builder
+            .append_data(&mut header, path, File::open(&self.spool_path)?)?;
+        Ok(bytes)
+    }
+
+    /// Write out the end of the archive, closing the gzip stream if there is one
+    pub fn finish(self) -> Result<(), ArchiveError> {
+        let spool_path = self.spool_path;
+        match self.builder.into_inner()? {
+            ArchiveFile::Plain(mut file) => file.flush()?,
+            ArchiveFile::Gzip(encoder) => {
+                encoder.finish()?;
+            }
+        }
+        if spool_path.exists() {
+            std::fs::remove_file(spool_path)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ArchiveError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("device error: {0}")]
+    Device(#[from] DeviceError),
+    #[error("ipfs error: {0}")]
+    Ipfs(#[from] IpfsError),
+    #[error("object {path:?} streamed in as {actual}, expected {expected}")]
+    CidMismatch {
+        path: PathBuf,
+        expected: Cid,
+        actual: Cid,
+    },
+}
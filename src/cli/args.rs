@@ -1,9 +1,22 @@
+use std::net::{IpAddr, SocketAddr};
 use std::path::PathBuf;
 
+use cid::Cid;
 use clap::{command, Subcommand};
 use ethers::types::Address;
 use url::Url;
 
+use crate::cli::cid_arg::{parse_cid, parse_root_arg, parse_root_cid, RootArg};
+use crate::cli::config::on_disk_device::PreviousRootCheck;
+use crate::cli::config::{parse_config_source, ConfigSource};
+use crate::cli::ops::{parse_previous_root, PullLayout, PullOutput, RootPlanOutput};
+use crate::cli::walk::SymlinkPolicy;
+use crate::device::{
+    parse_address, parse_dns_override, parse_eth_mirror, ConfirmUpdates, ManifestCompression,
+    RootCidAbi, RootLayout, RootReconcile, RootTarget,
+};
+use crate::types::Replication;
+
 pub use clap::Parser;
 
 #[derive(Parser, Debug)]
@@ -16,9 +29,32 @@ pub struct Args {
     /// Working dir to run a command on
     #[clap(short, long)]
     pub dir: Option<String>,
+    /// Dir holding the objects, if kept apart from the working dir
+    #[clap(long, env = "DOR_STORE_DATA_DIR")]
+    pub data_dir: Option<String>,
     /// Private Secp256k1 Admin Key (should be contract deployer)
     #[clap(long)]
     pub admin_key: Option<String>,
+    /// CAR file or directory of blocks (named by cid) to read manifests and
+    /// objects from, rather than the ipfs remote and gateway
+    #[clap(long, env = "DOR_STORE_BLOCKS")]
+    pub blocks: Option<String>,
+    /// Read manifests and objects from the device's S3 mirror, rather than the ipfs
+    /// remote and gateway. Objects replicated off the mirror aren't there to read.
+    /// Requires the `s3` feature
+    #[clap(long, env = "DOR_STORE_FROM_S3", conflicts_with = "blocks")]
+    pub from_s3: bool,
+    /// Override the device's RootCid contract address
+    #[clap(long, env = "DOR_STORE_CONTRACT_ADDRESS", value_parser = parse_address)]
+    pub contract_address: Option<Address>,
+    /// Read the device config from here instead of the device's file on disk: a path,
+    /// `-` for stdin, or an http(s) url -- fetched with a bearer token from
+    /// DOR_STORE_CONFIG_TOKEN if set. Same json as a device's device.json
+    #[clap(long, env = "DOR_STORE_DEVICE_CONFIG", value_parser = parse_config_source)]
+    pub device_config: Option<ConfigSource>,
+    /// Give up on the command after this many seconds, stopping at the next safe point
+    #[clap(long, env = "DOR_STORE_TIMEOUT_SECS")]
+    pub timeout_secs: Option<u64>,
 }
 
 // TODO: balance this
@@ -26,29 +62,489 @@ pub struct Args {
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug, Subcommand, Clone, PartialEq)]
 pub enum Command {
+    /// Stage content already on ipfs at a path by its cid, without a local file
+    /// Pushing pins it on the remote by cid
+    AddCid {
+        #[clap(value_parser = parse_cid)]
+        cid: Cid,
+        path: PathBuf,
+        /// Fetch it through the gateway first, to check it's there and tag it with
+        /// its size and content type
+        #[clap(long)]
+        probe: bool,
+        /// Replace an object already at the path
+        #[clap(long)]
+        force: bool,
+    },
+    /// Time adding synthetic objects to the remote and fetching them back through the
+    /// gateway, reporting throughput and latency percentiles. The objects are
+    /// unpinned afterwards
+    Bench {
+        /// How many objects to add and fetch
+        #[clap(long, default_value_t = 10)]
+        count: usize,
+        /// Bytes in each object
+        #[clap(long, default_value_t = 1 << 20)]
+        size: usize,
+    },
+    /// Find the root an object's cid last changed in, walking back through previous
+    /// roots, along with when that root was published if we can tell
+    Blame {
+        /// Path of the object, which may have been removed since
+        path: PathBuf,
+        /// Root cid, or tag, to start from -- the base root if not given
+        #[clap(value_parser = parse_root_arg)]
+        root: Option<RootArg>,
+        /// Walk at most this many roots back, for long histories -- unlimited if not
+        /// given
+        #[clap(long)]
+        max_depth: Option<usize>,
+    },
+    /// Apply a patch written by `diff --export` on top of our root, bringing in its
+    /// objects and committing the result for the next push. Refuses patches taken
+    /// from any other root
+    Apply { patch: PathBuf },
+    /// Manage the on disk content cache
+    Cache {
+        #[clap(subcommand)]
+        subcommand: CacheSubcommand,
+    },
+    /// Write an object's contents to stdout, by its path in the current root or its cid
+    #[clap(group(
+        clap::ArgGroup::new("object")
+            .required(true)
+            .multiple(true)
+            .args(["path", "cid"])
+    ))]
+    Cat {
+        /// Path of the object in the current root
+        #[clap(long)]
+        path: Option<PathBuf>,
+        /// Cid of the object, read straight from the gateway -- checked against --path if
+        /// both are given
+        #[clap(long, value_parser = parse_cid)]
+        cid: Option<Cid>,
+    },
+    /// Show the cid version, codec, and hash function of every object
+    CidInfo,
+    /// Commit staged changes to the change log -- the next push publishes them
+    Commit,
+    /// Print a node within a dag, fetching only the blocks along its path
+    DagGet {
+        #[clap(value_parser = parse_cid)]
+        cid: Cid,
+        /// Ipld path from the root of the dag to the node
+        #[clap(long, short, default_value = "")]
+        path: String,
+        /// Read against the remote rather than the local node
+        #[clap(long)]
+        remote: bool,
+    },
     /// Manages Devices
     Device {
         #[clap(subcommand)]
         subcommand: DeviceSubcommand,
     },
+    /// Export the objects of the current root to a directory
+    Export {
+        /// Directory to export to
+        #[clap(long, short)]
+        out: PathBuf,
+        /// Sniff content types, appending extensions to paths that lack one
+        /// and recording detected MIME types in a sidecar index
+        #[clap(long)]
+        sniff: bool,
+        /// Export from this root cid or tag, rather than the current root
+        #[clap(long, value_parser = parse_root_arg)]
+        root: Option<RootArg>,
+        /// Only export the object at this path in the current root
+        #[clap(long)]
+        path: Option<PathBuf>,
+        /// Only export the object with this cid -- checked against --path if both are given
+        #[clap(long, value_parser = parse_cid)]
+        cid: Option<Cid>,
+    },
+    /// Check the current root is fully pinned on the remote, re-pinning anything missing
+    /// Safe to run repeatedly
+    Fsck,
     /// Check the health of the device (connection to remote resources)
     Health,
+    /// Manage the index of file hashes shared by stage, stat, and push
+    Index {
+        #[clap(subcommand)]
+        subcommand: IndexSubcommand,
+    },
+    /// Size a root's whole dag, with shared blocks counted once, and show what
+    /// deduplication saves over the objects' summed sizes
+    Du {
+        /// Root cid, or tag
+        #[clap(value_parser = parse_root_arg)]
+        root: RootArg,
+        /// Walk the dag against the remote rather than the local node
+        #[clap(long)]
+        remote: bool,
+    },
     /// Initialize a new space to pull and work on changes
     Init,
+    /// Check the base and staged manifests for corruption -- malformed cids, paths,
+    /// previous roots, and tags -- without touching the network
+    Lint,
+    /// List the entries of a unix-fs directory by its cid
+    Ls {
+        #[clap(value_parser = parse_cid)]
+        cid: Cid,
+        /// List against the remote rather than the local node
+        #[clap(long)]
+        remote: bool,
+    },
+    /// Compare the manifests of two roots, the same way diff-manifest does
+    Diff {
+        /// Root cid, or tag, to diff from
+        #[clap(value_parser = parse_root_arg)]
+        from: RootArg,
+        /// Root cid, or tag, to diff to
+        #[clap(value_parser = parse_root_arg)]
+        to: RootArg,
+        /// Read the manifests from the remote rather than the local node
+        #[clap(long)]
+        remote: bool,
+        /// Also write the difference to this file as a patch, for `apply`
+        #[clap(long)]
+        export: Option<PathBuf>,
+    },
+    /// Compare two manifests on disk: objects added and removed, content changes, and
+    /// metadata-only changes like a re-tagged object, each listed under its own kind
+    DiffManifest { a: PathBuf, b: PathBuf },
+    /// Copy manifests into SQLite databases, to query, edit, and diff stores too big
+    /// to load whole. The working dir's own manifests stay json, so add, stage,
+    /// status, and push don't read databases -- convert back with `to-json` first
+    /// Requires dor-store built with the `sqlite` feature
+    ManifestDb {
+        #[clap(subcommand)]
+        subcommand: ManifestDbSubcommand,
+    },
+    /// Fetch every object of the current root and check it hashes to its cid,
+    /// streaming each one so memory stays flat however big it is. Objects made with
+    /// other cid params are rehashed with their own, and fail if they can't be
+    Verify,
+    /// Check a CAR file holds every block a root references, and that each one hashes
+    /// to its cid. Runs offline
+    VerifyCar {
+        car: PathBuf,
+        #[clap(value_parser = parse_root_cid)]
+        root: Cid,
+        /// Read the manifest from this file instead of from the CAR
+        #[clap(long)]
+        manifest: Option<PathBuf>,
+    },
+    /// Seed a newly deployed RootCid contract with the current root
+    MigrateRoot {
+        /// Rpc url of the chain hosting the new contract
+        #[clap(long)]
+        to_rpc: Url,
+        #[clap(long)]
+        to_chain_id: u16,
+        /// Address of the new RootCid contract -- checksummed if mixed case
+        #[clap(long, value_parser = parse_address)]
+        to_contract_address: Address,
+        /// Point the device at the new contract once it's seeded
+        #[clap(long)]
+        switch: bool,
+        /// Don't ask for confirmation before seeding the contract
+        #[clap(long, short)]
+        yes: bool,
+    },
+    /// Print the cid a push would give a local file, without contacting a node
+    Hash { path: PathBuf },
     /// Pull the remote to the local dot directory -- overwrites any changes
-    Pull,
+    Pull {
+        /// Only pull objects whose paths match one of these glob patterns
+        #[clap(long)]
+        include: Vec<String>,
+        /// Skip objects whose paths match one of these glob patterns
+        #[clap(long)]
+        exclude: Vec<String>,
+        /// Only fetch the manifest and report what would be downloaded or overwritten
+        #[clap(long)]
+        plan: bool,
+        /// How to report progress -- jsonl streams an event per object as it's handled
+        #[clap(long, value_enum, default_value_t = PullOutput::Text)]
+        output: PullOutput,
+        /// Stream objects into this tar archive instead of the working dir, verifying
+        /// each one's cid. Gzipped if it ends in .tar.gz
+        #[clap(long, conflicts_with = "plan")]
+        to_tar: Option<PathBuf>,
+        /// Carry on past objects that can't be fetched, then list them and fail.
+        /// Pulling again retries only what's missing
+        #[clap(long)]
+        keep_going: bool,
+        /// How to lay objects out -- cid writes each distinct object once, named by
+        /// its cid, with an index.json of paths to cids, into --to-dir
+        #[clap(long, value_enum, default_value_t = PullLayout::Path)]
+        layout: PullLayout,
+        /// Directory to write a cid layout into. Local state is left untouched
+        #[clap(long, conflicts_with = "plan")]
+        to_dir: Option<PathBuf>,
+    },
+    /// Catch up with a root another device pushed, merging in our committed changes
+    /// Conflicting changes are listed for resolving by hand, and nothing is touched
+    Reconcile {
+        /// Pull the remote's changes over edits in the working dir that haven't been staged
+        #[clap(long)]
+        force: bool,
+    },
+    /// Re-add objects made with other hash params, i.e. sha2 or cidv0, under the
+    /// target params, and push a root pointing at their new cids
+    Reencode {
+        /// Hash function to re-encode objects to
+        #[clap(long, default_value = "blake3")]
+        to_hash: String,
+        /// Cid version to re-encode objects to
+        #[clap(long, default_value_t = 1)]
+        cid_version: u64,
+        /// Unpin the old cids from the remote once the new root has landed
+        #[clap(long)]
+        unpin_old: bool,
+        /// Don't ask to confirm the root update
+        #[clap(long, short)]
+        yes: bool,
+    },
+    /// Set which of the device's replicas pushes copy an object to. The node always
+    /// holds it. The policy is staged with the object, for every device pushing it
+    Replicate {
+        path: PathBuf,
+        /// `all`, a number of replicas counting the node, or a comma separated list
+        /// of `node`, `pin_service`, and `s3` -- leave out to use the device's default
+        replication: Option<Replication>,
+    },
+    /// Discard staged changes, leaving the change log and working dir untouched
+    Reset,
+    /// Show the root update the next push would send -- old and new root, chain, and
+    /// contract -- without pinning anything or touching the chain
+    RootPlan {
+        #[clap(long, value_enum, default_value_t = RootPlanOutput::Text)]
+        output: RootPlanOutput,
+    },
     /// Stage changes against the local ipfs instance -- may be run mutliple times in a row
-    Stage,
-    /// Stat changes
-    Stat,
+    /// Changes are held in a stage until committed
+    Stage {
+        /// Re-hash every file, rather than reusing hashes of unchanged files
+        #[clap(long)]
+        no_cache: bool,
+        /// Stage new or changed files even if they're over the device's max object size
+        #[clap(long)]
+        allow_large: bool,
+    },
+    /// Stat committed, staged, and unstaged changes
+    Stat {
+        /// Re-hash every file, rather than reusing hashes of unchanged files
+        #[clap(long)]
+        no_cache: bool,
+    },
     /// Schema management. These effect the schema definitions in the dot directory
-    /// Changes to schemas will be reflected in the next push
+    /// Changes to schemas are staged, and reflected in the next push once committed
     Schema {
         #[clap(subcommand)]
         subcommand: SchemaSubcommand,
     },
+    /// Serve objects over http, by path in the current root or at `/ipfs/<cid>`
+    /// for cids the current root holds. Objects are streamed through the cache and
+    /// gateway, and checked against their cid as they go out. Requires the `serve`
+    /// feature
+    Serve {
+        /// Address to listen on
+        #[clap(long, default_value = "127.0.0.1:8080")]
+        addr: SocketAddr,
+    },
+    /// Advanced: re-point the staged manifest's previous root, or clear it with `none`
+    /// Leaves the on-chain root alone -- commit and push to publish the change
+    SetPreviousRoot {
+        /// Root cid, tag, or `none`
+        #[clap(value_parser = parse_previous_root)]
+        root: RootArg,
+        /// Also check the root can be read as a manifest from the remote
+        #[clap(long)]
+        resolve: bool,
+    },
+    /// Push a root with a whole manifest in place of the chain of deltas behind the
+    /// current one. Objects are left as they are
+    Squash {
+        /// Don't ask to confirm the root update
+        #[clap(long, short)]
+        yes: bool,
+    },
+    /// Name a root, like a git tag -- usable anywhere a root cid is
+    #[clap(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+    Tag {
+        #[clap(subcommand)]
+        subcommand: Option<TagSubcommand>,
+        /// Name to tag the root with
+        #[clap(required = true)]
+        name: Option<String>,
+        /// Root cid or tag to tag -- defaults to the current root
+        #[clap(value_parser = parse_root_arg)]
+        root: Option<RootArg>,
+        /// Move the tag if it already names another root
+        #[clap(long, short)]
+        force: bool,
+    },
+    /// List the device's tags
+    Tags,
     /// Squash and sync changes with the remote
-    Push,
+    #[clap(args_conflicts_with_subcommands = true)]
+    Push {
+        #[clap(subcommand)]
+        subcommand: Option<PushSubcommand>,
+        /// Only pin against the local node and commit the new root locally,
+        /// skipping the remote and root update. A later push publishes it
+        #[clap(long)]
+        local: bool,
+        /// Stop at the first object that fails to push
+        #[clap(long)]
+        fail_fast: bool,
+        /// Name the root's pin with the remote's pin name template
+        #[clap(long)]
+        name_pin: bool,
+        /// Don't ask for confirmation before updating the root
+        #[clap(long, short)]
+        yes: bool,
+        /// Push even if the changes go over the device's push guardrail
+        #[clap(long)]
+        force: bool,
+        /// Only pin and publish committed changes under this path prefix
+        #[clap(long, conflicts_with = "local")]
+        path: Option<PathBuf>,
+        /// Don't check the signer can pay for the root update before pinning
+        #[clap(long)]
+        skip_balance_check: bool,
+        /// Push even if the root being replaced can't be fetched from the remote,
+        /// breaking the history chain behind the new root
+        #[clap(long)]
+        allow_orphan_previous: bool,
+    },
+    /// Watch the working dir, pushing changes once they settle
+    Watch {
+        /// How long changes must settle before pushing, in milliseconds
+        #[clap(long, default_value_t = 2000)]
+        debounce_ms: u64,
+        /// Only push on changes to paths matching one of these glob patterns
+        #[clap(long)]
+        include: Vec<String>,
+        /// Ignore changes to paths matching one of these glob patterns
+        #[clap(long)]
+        exclude: Vec<String>,
+        /// Only pin against the local node and commit roots locally
+        #[clap(long)]
+        local: bool,
+        /// Send root updates without confirming them. Watch never prompts, so
+        /// without this, pushes whose updates need confirming fail
+        #[clap(long, short)]
+        yes: bool,
+        /// Serve prometheus metrics at `/metrics` on this address
+        /// Requires the `metrics` feature
+        #[clap(long)]
+        metrics_addr: Option<SocketAddr>,
+    },
+    /// Show which of our nodes hold a root's manifest and objects, and how
+    /// completely, to check on redundancy before relying on it
+    Where {
+        /// Root cid, or tag -- the base root if not given
+        #[clap(value_parser = parse_root_arg)]
+        root: Option<RootArg>,
+        /// Also ask this public gateway, i.e. https://dweb.link
+        #[clap(long)]
+        gateway: Vec<Url>,
+    },
+}
+
+#[derive(Debug, Subcommand, Clone, PartialEq)]
+pub enum CacheSubcommand {
+    /// Evict everything the device's cache policy no longer allows
+    Prune,
+    /// Remove cached objects the current root no longer references
+    /// Safe to run anytime -- at worst an object is fetched again later
+    Gc {
+        /// Also keep objects referenced by this many roots before the current one
+        #[clap(long, default_value_t = 0)]
+        keep_roots: usize,
+    },
+    /// Show the size and hit rate of the cache
+    Stats,
+}
+
+#[derive(Debug, Subcommand, Clone, PartialEq)]
+pub enum IndexSubcommand {
+    /// Re-hash every file in the working dir, replacing the index
+    Rebuild,
+    /// Forget every indexed hash, so the next command re-hashes everything
+    Clear,
+}
+
+#[derive(Debug, Subcommand, Clone, PartialEq)]
+pub enum PushSubcommand {
+    /// Show where an interrupted or failed push got to
+    Status,
+    /// Finish an interrupted or failed push, saving its root if the update already landed
+    Resume {
+        /// Don't ask for confirmation before updating the root
+        #[clap(long, short)]
+        yes: bool,
+    },
+    /// Forget an interrupted or failed push
+    Abort,
+    /// Pin every committed change and write the new root, but hold off on the root
+    /// update until `push commit` -- so the remote has everything well before the
+    /// root points at it
+    Prepare {
+        /// Stop at the first object that fails to push
+        #[clap(long)]
+        fail_fast: bool,
+        /// Name the root's pin with the remote's pin name template
+        #[clap(long)]
+        name_pin: bool,
+        /// Push even if the changes go over the device's push guardrail
+        #[clap(long)]
+        force: bool,
+        /// Only pin committed changes under this path prefix
+        #[clap(long)]
+        path: Option<PathBuf>,
+        /// Don't check the signer can pay for the root update before pinning
+        #[clap(long)]
+        skip_balance_check: bool,
+        /// Prepare even if the root being replaced can't be fetched from the remote
+        #[clap(long)]
+        allow_orphan_previous: bool,
+    },
+    /// Send the root update for a prepared push, once its blocks are checked to
+    /// still be on the remote
+    Commit {
+        /// Don't ask for confirmation before updating the root
+        #[clap(long, short)]
+        yes: bool,
+    },
+}
+
+#[derive(Debug, Subcommand, Clone, PartialEq)]
+pub enum TagSubcommand {
+    /// Remove a tag. The root it named is left alone
+    Rm { name: String },
+}
+
+#[derive(Debug, Subcommand, Clone, PartialEq)]
+pub enum ManifestDbSubcommand {
+    /// Write the base manifest to a database
+    Export {
+        db: PathBuf,
+        /// Write the staged manifest instead
+        #[clap(long)]
+        staged: bool,
+    },
+    /// Write a json manifest on disk to a database
+    FromJson { manifest: PathBuf, db: PathBuf },
+    /// Write a database back out as a json manifest
+    ToJson { db: PathBuf, out: PathBuf },
+    /// Compare two databases the way diff-manifest does, a row at a time
+    Diff { a: PathBuf, b: PathBuf },
 }
 
 #[derive(Debug, Subcommand, Clone, PartialEq)]
@@ -88,12 +584,39 @@ pub enum DeviceSubcommand {
         eth_rpc: Url,
         #[clap(long)]
         eth_chain_id: u16,
-        #[clap(long)]
+        /// Address of the RootCid contract -- checksummed if mixed case
+        #[clap(long, value_parser = parse_address)]
         contract_address: Address,
+        /// Abi of the contract: `v1` for the RootCid contract in this repo (the
+        /// default), `v2` for one deployed keeping its previous cid and able to batch
+        /// updates, or a path to an abi or forge artifact
+        #[clap(long)]
+        contract_abi: Option<RootCidAbi>,
+        /// Check there's a deployed contract at the address before using it
+        #[clap(long)]
+        verify_contract: bool,
+        /// When to ask for confirmation before sending a root update
+        #[clap(long, value_enum, default_value_t = ConfirmUpdates::Mainnet)]
+        confirm_updates: ConfirmUpdates,
         #[clap(long, short)]
         ipfs_url: Url,
         #[clap(long)]
         ipfs_gateway_url: Url,
+        /// Name of a key on the ipfs remote to publish the root cid against
+        #[clap(long)]
+        ipns_key: Option<String>,
+        /// Lifetime of published ipns records (i.e. "24h")
+        #[clap(long)]
+        ipns_lifetime: Option<String>,
+        /// Where to read and publish the root cid
+        #[clap(long, value_enum, default_value_t = RootTarget::Eth)]
+        root_target: RootTarget,
+        /// Read the signing key from this OS keyring service, rather than a keystore
+        #[clap(long, requires = "keyring_account")]
+        keyring_service: Option<String>,
+        /// Account of the OS keyring entry holding the signing key
+        #[clap(long, requires = "keyring_service")]
+        keyring_account: Option<String>,
     },
     /// Update a Device
     Update {
@@ -103,12 +626,124 @@ pub enum DeviceSubcommand {
         eth_rpc: Option<Url>,
         #[clap(long)]
         eth_chain_id: Option<u16>,
-        #[clap(long)]
+        /// Address of the RootCid contract -- checksummed if mixed case
+        #[clap(long, value_parser = parse_address)]
         contract_address: Option<Address>,
+        /// Abi of the contract: `v1` for the RootCid contract in this repo, `v2` for
+        /// one deployed keeping its previous cid and able to batch updates, or a path
+        /// to an abi or forge artifact
+        #[clap(long)]
+        contract_abi: Option<RootCidAbi>,
+        /// Check there's a deployed contract at the address before using it
+        #[clap(long)]
+        verify_contract: Option<bool>,
+        /// When to ask for confirmation before sending a root update
+        #[clap(long, value_enum)]
+        confirm_updates: Option<ConfirmUpdates>,
+        /// Seconds to wait between checks for a root update's receipt
+        #[clap(long)]
+        receipt_poll_interval_secs: Option<u64>,
+        /// Checks for a root update's receipt before giving up and reporting it as pending
+        #[clap(long)]
+        receipt_max_attempts: Option<u32>,
+        /// Warn before pushing if the contract's previous root disagrees with our
+        /// manifest's. Only for contracts that keep their previous root, i.e. v2
+        #[clap(long)]
+        check_previous_root: Option<bool>,
         #[clap(long, short)]
         ipfs_url: Option<Url>,
         #[clap(long)]
         ipfs_gateway_url: Option<Url>,
+        /// Cache objects read from the gateway on disk
+        #[clap(long)]
+        cache: Option<bool>,
+        /// Cap on the size of the content cache, evicting the least recently used first
+        #[clap(long)]
+        cache_max_bytes: Option<u64>,
+        /// Evict cached objects that haven't been read in this many seconds
+        #[clap(long)]
+        cache_max_age_secs: Option<u64>,
+        /// What published roots point at: the manifest, or a directory of the objects
+        #[clap(long)]
+        root_layout: Option<RootLayout>,
+        /// How to encode published manifests -- compressed manifests are smaller to pin,
+        /// but only readable by clients that know to decompress them
+        #[clap(long)]
+        manifest_compression: Option<ManifestCompression>,
+        /// Ask before pushing more than this many new or changed objects
+        #[clap(long)]
+        max_added_objects: Option<usize>,
+        /// Ask before pushing more than this many bytes of new or changed objects
+        #[clap(long)]
+        max_added_bytes: Option<u64>,
+        /// Mirror pushed roots at this MFS path on the node -- pass an empty path to stop
+        #[clap(long)]
+        mfs_path: Option<String>,
+        /// Reach a host of the api or gateway at an address in place of looking it up,
+        /// as `host=ip` -- pass `host=` to stop. Subdomains of the host follow it
+        #[clap(long, value_parser = parse_dns_override)]
+        dns_override: Vec<(String, Option<IpAddr>)>,
+        /// Where to read the root from when the eth remote is down: `ipns`, a path to a
+        /// file holding the root cid, or `none`
+        #[clap(long)]
+        root_fallback: Option<String>,
+        /// What to do with symlinks in the working dir: skip them, follow them, or store
+        /// them as links
+        #[clap(long, value_enum)]
+        symlinks: Option<SymlinkPolicy>,
+        /// Refuse to stage new or changed files bigger than this many bytes -- pass 0 for
+        /// no limit
+        #[clap(long)]
+        max_object_size: Option<u64>,
+        /// Record file mtimes in the manifest and restore them on pull and export.
+        /// Root cids then depend on when files were written, not just what's in them
+        #[clap(long)]
+        record_mtimes: Option<bool>,
+        /// Write manifests as deltas on top of the previous root's. Readers walk back
+        /// to the last whole manifest, so `squash` once the chain gets long. Every
+        /// 64th manifest along a chain is written whole regardless
+        #[clap(long)]
+        delta_manifests: Option<bool>,
+        /// What a push does if the root it replaces can't be fetched from the remote:
+        /// carry on, warn, or refuse unless run with --allow-orphan-previous
+        #[clap(long, value_enum)]
+        previous_root_check: Option<PreviousRootCheck>,
+        /// Append a JSON line recording every on chain root update to this file -- pass
+        /// an empty path to stop
+        #[clap(long)]
+        audit_log: Option<PathBuf>,
+        /// Endpoint of an S3 compatible service to mirror pushes to. Needed along with
+        /// --s3-bucket to add a mirror
+        #[clap(long)]
+        s3_endpoint: Option<Url>,
+        /// Bucket to mirror pushes to -- pass an empty name to stop mirroring.
+        /// Credentials are read from the usual AWS environment variables and profile
+        #[clap(long)]
+        s3_bucket: Option<String>,
+        #[clap(long)]
+        s3_region: Option<String>,
+        /// Prefix for every key written to the bucket, so it can be shared
+        #[clap(long)]
+        s3_prefix: Option<String>,
+        /// Address the bucket in the path rather than the host, as MinIO expects
+        #[clap(long)]
+        s3_path_style: Option<bool>,
+        /// Fail pushes the mirror can't keep up with, rather than catching it up later
+        #[clap(long)]
+        s3_required: Option<bool>,
+        /// Mirror the root on another RootCid contract, as `chain_id,address,rpc_url`
+        /// -- pass `chain_id,address` to stop. Pulls read it alongside the device's
+        /// contract, and pushes bring it up to date
+        #[clap(long, value_parser = parse_eth_mirror)]
+        eth_mirror: Vec<(u16, Address, Option<Url>)>,
+        /// How pulls pick a root when the mirrors disagree with the device's contract
+        #[clap(long, value_enum)]
+        root_reconcile: Option<RootReconcile>,
+        /// Which replicas pushes copy objects without a policy of their own to: `all`,
+        /// a number counting the node, or a comma separated list of `node`,
+        /// `pin_service`, and `s3`
+        #[clap(long)]
+        replication: Option<Replication>,
     },
     /// Set a configuration value
     Set { alias: String },
@@ -0,0 +1,144 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use cid::Cid;
+use serde::{Deserialize, Serialize};
+
+use crate::device::RootUpdateReceipt;
+
+/// One on chain root update, as a line of the audit log
+/// Unlike tracing, the audit log is a permanent record: lines are only ever
+/// appended, and each is on disk before we move on
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// When we saw the update land
+    pub timestamp: DateTime<Utc>,
+    pub chain_id: u16,
+    /// The RootCid contract updated, as a checksummed address
+    pub contract: String,
+    /// The root cid we replaced
+    #[serde(with = "crate::types::cid_serde")]
+    pub previous_root: Cid,
+    /// The root cid we published
+    #[serde(with = "crate::types::cid_serde")]
+    pub root: Cid,
+    pub tx_hash: String,
+    pub block_number: Option<u64>,
+    pub gas_used: Option<u64>,
+}
+
+impl AuditRecord {
+    pub fn new(
+        chain_id: u16,
+        contract: String,
+        previous_root: Cid,
+        root: Cid,
+        receipt: &RootUpdateReceipt,
+    ) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            chain_id,
+            contract,
+            previous_root,
+            root,
+            tx_hash: receipt.id.clone(),
+            block_number: receipt.block_number,
+            gas_used: receipt.gas_used,
+        }
+    }
+
+    /// Append the record to the audit log at path, creating it if need be
+    /// The line goes out in a single append and is synced before we return, so a
+    /// crash leaves the log holding either the whole record or none of it
+    pub fn append(&self, path: &Path) -> std::io::Result<()> {
+        let mut line = serde_json::to_vec(self)?;
+        line.push(b'\n');
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        file.write_all(&line)?;
+        file.sync_data()
+    }
+
+    /// Find the latest record of an update to a root in the audit log at path
+    /// Lines that don't parse, i.e. one cut short by a full disk, are skipped
+    pub fn find(path: &Path, root: &Cid) -> std::io::Result<Option<Self>> {
+        let log = std::fs::read_to_string(path)?;
+        Ok(log
+            .lines()
+            .rev()
+            .filter_map(|line| serde_json::from_str::<AuditRecord>(line).ok())
+            .find(|record| record.root == *root))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_append_as_lines() {
+        let path =
+            std::env::temp_dir().join(format!("dor-store-audit-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let receipt = RootUpdateReceipt {
+            id: "0x01".to_string(),
+            block_number: Some(7),
+            gas_used: Some(21000),
+        };
+        let first = AuditRecord::new(
+            1,
+            "0x00".to_string(),
+            Cid::default(),
+            Cid::default(),
+            &receipt,
+        );
+        let second = AuditRecord {
+            block_number: None,
+            ..first.clone()
+        };
+        first.append(&path).unwrap();
+        second.append(&path).unwrap();
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        let records = log
+            .lines()
+            .map(|line| serde_json::from_str::<AuditRecord>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(records, vec![first, second]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn records_are_found_by_root() {
+        let path =
+            std::env::temp_dir().join(format!("dor-store-audit-find-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let root = Cid::try_from("QmWATWQ7fVPP2EFGu71UkfnqhYXDYH566qy47CnJDgvs8u").unwrap();
+        let receipt = |id: &str| RootUpdateReceipt {
+            id: id.to_string(),
+            block_number: Some(7),
+            gas_used: None,
+        };
+        let record = |root: Cid, id: &str| {
+            AuditRecord::new(1, "0x00".to_string(), Cid::default(), root, &receipt(id))
+        };
+        record(root, "0x01").append(&path).unwrap();
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"truncated\n")
+            .unwrap();
+        record(Cid::default(), "0x02").append(&path).unwrap();
+        record(root, "0x03").append(&path).unwrap();
+
+        let found = AuditRecord::find(&path, &root).unwrap().unwrap();
+        assert_eq!(found.tx_hash, "0x03");
+        let missing = Cid::try_from("QmdfTbBqBPQ7VNxZEYEj14VmRuZBkqFbiwReogJgS1zR1n").unwrap();
+        assert!(AuditRecord::find(&path, &missing).unwrap().is_none());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,110 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
+use std::time::{Duration, Instant};
+
+use tokio_util::sync::CancellationToken;
+
+/// Set once the user asks us to stop
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+static INSTALL: Once = Once::new();
+/// How often cancelled() checks in
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Handle Ctrl-C ourselves, so long running ops can stop at a safe point rather
+/// than dying part way through a step
+/// The first signal only marks us cancelled -- it's up to the op to notice, finish
+/// or abandon what it's doing, and report what it left behind. A second signal
+/// exits immediately. Safe to call more than once
+pub fn install() {
+    INSTALL.call_once(|| {
+        tokio::spawn(async {
+            loop {
+                if tokio::signal::ctrl_c().await.is_err() {
+                    tracing::warn!("failed to listen for ctrl-c");
+                    return;
+                }
+                if CANCELLED.swap(true, Ordering::SeqCst) {
+                    eprintln!("cancelled again, exiting now -- local state may be inconsistent");
+                    std::process::exit(130);
+                }
+                eprintln!("cancelling after the current step, ctrl-c again to exit now");
+            }
+        });
+    });
+}
+
+/// Whether the user has asked us to stop
+pub fn is_cancelled() -> bool {
+    CANCELLED.load(Ordering::SeqCst)
+}
+
+/// Bounds a whole op, rather than any single request it makes
+/// Ops check in between units of work -- i.e. per object, and before sending a
+/// root update -- and stop cleanly once the token is cancelled, the deadline
+/// passes, or the user hits ctrl-c. Steps already under way are seen through:
+/// a root update that's been broadcast can't be called back, so we always
+/// finish saving local state after one
+#[derive(Debug, Clone, Default)]
+pub struct Cancellation {
+    token: Option<CancellationToken>,
+    deadline: Option<Instant>,
+}
+
+impl Cancellation {
+    /// Stop once a token is cancelled, i.e. by a service embedding us
+    pub fn with_token(mut self, token: CancellationToken) -> Self {
+        self.token = Some(token);
+        self
+    }
+
+    /// Stop once a timeout from now has passed
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.deadline = Some(Instant::now() + timeout);
+        self
+    }
+
+    /// Check whether we should stop before the next unit of work
+    pub fn check(&self) -> Result<(), CancelReason> {
+        let token_cancelled = self
+            .token
+            .as_ref()
+            .map(|token| token.is_cancelled())
+            .unwrap_or(false);
+        if token_cancelled || is_cancelled() {
+            return Err(CancelReason::Cancelled);
+        }
+        if let Some(deadline) = self.deadline {
+            if Instant::now() >= deadline {
+                return Err(CancelReason::DeadlineExceeded);
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolve once we should stop
+    pub async fn cancelled(&self) -> CancelReason {
+        loop {
+            if let Err(reason) = self.check() {
+                return reason;
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+}
+
+/// Why an op stopped early
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CancelReason {
+    Cancelled,
+    DeadlineExceeded,
+}
+
+impl std::fmt::Display for CancelReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Cancelled => "cancelled",
+            Self::DeadlineExceeded => "deadline exceeded",
+        };
+        write!(f, "{}", s)
+    }
+}
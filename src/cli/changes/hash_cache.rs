@@ -0,0 +1,60 @@
+use std::collections::BTreeMap;
+use std::fs::Metadata;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use cid::Cid;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+struct HashCacheEntry {
+    modified: SystemTime,
+    size: u64,
+    cid: Cid,
+}
+
+/// Cids of files in the working dir, keyed by path -- the index stage, stat, and push
+/// consult so a file is only hashed once between changes
+/// An entry is only trusted while the file's mtime and size are unchanged
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+pub struct HashCache(BTreeMap<PathBuf, HashCacheEntry>);
+
+impl HashCache {
+    /// Get the cached cid of a file, if it hasn't changed since it was hashed
+    pub fn get(&self, path: &PathBuf, metadata: &Metadata) -> Option<Cid> {
+        let entry = self.0.get(path)?;
+        let modified = metadata.modified().ok()?;
+        if entry.modified == modified && entry.size == metadata.len() {
+            Some(entry.cid)
+        } else {
+            None
+        }
+    }
+
+    /// Size of a file as of when it was last hashed, if it hashed to `cid`
+    pub fn size_of(&self, path: &PathBuf, cid: &Cid) -> Option<u64> {
+        let entry = self.0.get(path)?;
+        (entry.cid == *cid).then_some(entry.size)
+    }
+
+    /// Number of indexed files
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Record the cid of a file as of its current mtime and size
+    pub fn insert(&mut self, path: PathBuf, metadata: &Metadata, cid: Cid) {
+        // Without an mtime we can't tell when the entry goes stale
+        let Ok(modified) = metadata.modified() else {
+            return;
+        };
+        self.0.insert(
+            path,
+            HashCacheEntry {
+                modified,
+                size: metadata.len(),
+                cid,
+            },
+        );
+    }
+}
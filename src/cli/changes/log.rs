@@ -56,6 +56,23 @@ impl Log {
     pub fn new() -> Self {
         Self(BTreeMap::new())
     }
+
+    /// The entries of this log that differ from another
+    /// Paths tracked by the other log but dropped from this one are marked as removed
+    pub fn delta(&self, from: &Log) -> Log {
+        let mut delta = Log::new();
+        for (path, entry) in self.iter() {
+            if from.get(path) != Some(entry) {
+                delta.insert(path.clone(), entry.clone());
+            }
+        }
+        for (path, (cid, _change_type)) in from.iter() {
+            if !self.contains_key(path) {
+                delta.insert(path.clone(), (*cid, ChangeType::Removed));
+            }
+        }
+        delta
+    }
 }
 
 pub struct DisplayableLog(pub Log);
@@ -1,11 +1,18 @@
 use cid::Cid;
 use serde::{Deserialize, Serialize};
 
+use crate::cli::filter::PathFilter;
 use crate::types::DorStore;
 
+mod hash_cache;
 mod log;
+mod pull_retry;
+mod push_state;
 
+pub use hash_cache::HashCache;
 pub use log::{ChangeType, DisplayableLog, Log};
+pub use pull_retry::PullRetry;
+pub use push_state::{PushOptions, PushStage, PushState};
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct ChangeLog {
@@ -15,6 +22,14 @@ pub struct ChangeLog {
     log: Log,
     /// The versions of the DorFS currently staged
     versions: Vec<(Cid, DorStore)>,
+    /// The root cid of the last version, if it has been committed against the local node
+    /// but not yet published
+    #[serde(default)]
+    committed: Option<Cid>,
+    /// Filter selecting which paths of the DorFS are checked out in the working dir
+    /// Paths outside of it are left untouched by diffs
+    #[serde(default)]
+    filter: PathFilter,
 }
 
 impl ChangeLog {
@@ -27,9 +42,21 @@ impl ChangeLog {
             manager_alias,
             log,
             versions: vec![(*root_cid, dor_store.clone())],
+            committed: None,
+            filter: PathFilter::default(),
         }
     }
 
+    /// Only track the paths selected by the filter in the working dir
+    pub fn with_filter(mut self, filter: PathFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn filter(&self) -> &PathFilter {
+        &self.filter
+    }
+
     pub fn wipe(&mut self, dor_store: &DorStore, root_cid: &Cid) {
         let mut log = Log::new();
         for (path, object) in dor_store.objects().iter() {
@@ -37,11 +64,23 @@ impl ChangeLog {
         }
         self.log = log;
         self.versions = vec![(*root_cid, dor_store.clone())];
+        self.committed = None;
     }
 
     pub fn update(&mut self, log: &Log, dor_store: &DorStore, root_cid: &Cid) {
         self.log = log.clone();
         self.versions.push((*root_cid, dor_store.clone()));
+        // Anything committed is now stale
+        self.committed = None;
+    }
+
+    /// Mark the last version as committed against the local node under the given root cid
+    pub fn commit(&mut self, root_cid: &Cid) {
+        self.committed = Some(*root_cid);
+    }
+
+    pub fn committed(&self) -> Option<&Cid> {
+        self.committed.as_ref()
     }
 
     pub fn manager_alias(&self) -> &String {
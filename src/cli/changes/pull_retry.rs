@@ -0,0 +1,29 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use cid::Cid;
+use serde::{Deserialize, Serialize};
+
+use crate::cli::filter::PathFilter;
+use crate::types::cid_serde;
+
+/// Objects a pull couldn't fetch, kept in the dot dir until a pull gets them
+/// Everything else the pull selected is already in the working dir, so pulling the
+/// same root with the same filter again only has to retry these
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PullRetry {
+    /// The root the pull was of
+    #[serde(with = "cid_serde")]
+    pub root_cid: Cid,
+    /// The filter the pull selected objects with
+    pub filter: PathFilter,
+    /// Paths of the objects that failed
+    pub paths: BTreeSet<PathBuf>,
+}
+
+impl PullRetry {
+    /// Whether a pull of a root with a filter picks up where this one left off
+    pub fn retries(&self, root_cid: &Cid, filter: &PathFilter) -> bool {
+        &self.root_cid == root_cid && &self.filter == filter
+    }
+}
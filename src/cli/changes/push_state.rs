@@ -0,0 +1,242 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use cid::Cid;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::types::cid_serde;
+
+/// How far a push got
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PushStage {
+    /// Still pinning objects
+    Pinning,
+    /// Stopped with objects that failed to pin
+    Failed,
+    /// Everything is pinned and the root is written, waiting on `push commit` to
+    /// send the root update
+    Prepared,
+    /// Everything is pinned, and the root update is being sent
+    Publishing,
+    /// The root update was sent, but we never saw it land
+    Broadcast,
+}
+
+impl Display for PushStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Pinning => "pinning",
+            Self::Failed => "failed",
+            Self::Prepared => "prepared",
+            Self::Publishing => "publishing",
+            Self::Broadcast => "broadcast",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// The options a push ran with, so it can be resumed the same way
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PushOptions {
+    pub fail_fast: bool,
+    pub name_pin: bool,
+    pub force: bool,
+    pub path: Option<PathBuf>,
+    #[serde(default)]
+    pub skip_balance_check: bool,
+    #[serde(default)]
+    pub allow_orphan_previous: bool,
+    #[serde(default)]
+    pub prepare: bool,
+    /// Nobody's there to answer prompts, so refuse anything that would ask
+    #[serde(default)]
+    pub unattended: bool,
+}
+
+/// Where a push got to, kept in the dot dir from the first object pinned until
+/// the new root is saved locally
+/// Anything left behind is a push that was interrupted or failed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PushState {
+    started_at: DateTime<Utc>,
+    options: PushOptions,
+    /// The root the push publishes over
+    previous_root_cid: Cid,
+    /// The root being published, once every object is pinned
+    root_cid: Option<Cid>,
+    stage: PushStage,
+    /// Objects known to be on the remote, with the cid that was pinned -- a path
+    /// re-committed since holds a cid that still needs pinning
+    #[serde(deserialize_with = "pinned_or_legacy")]
+    pinned: BTreeMap<PathBuf, PinnedCid>,
+    /// Objects that failed to pin, with why
+    failed: BTreeMap<PathBuf, String>,
+    /// Hash of the root update transaction, if we know it was sent
+    tx_hash: Option<String>,
+    /// The last error the push stopped on
+    error: Option<String>,
+}
+
+impl PushState {
+    pub fn new(previous_root_cid: Cid, options: PushOptions) -> Self {
+        Self {
+            started_at: Utc::now(),
+            options,
+            previous_root_cid,
+            root_cid: None,
+            stage: PushStage::Pinning,
+            pinned: BTreeMap::new(),
+            failed: BTreeMap::new(),
+            tx_hash: None,
+            error: None,
+        }
+    }
+
+    pub fn options(&self) -> &PushOptions {
+        &self.options
+    }
+
+    pub fn previous_root_cid(&self) -> &Cid {
+        &self.previous_root_cid
+    }
+
+    pub fn root_cid(&self) -> Option<&Cid> {
+        self.root_cid.as_ref()
+    }
+
+    pub fn stage(&self) -> PushStage {
+        self.stage
+    }
+
+    pub fn tx_hash(&self) -> Option<&String> {
+        self.tx_hash.as_ref()
+    }
+
+    pub fn is_pinned(&self, path: &Path, cid: &Cid) -> bool {
+        self.pinned.get(path).map(|pinned| &pinned.0) == Some(cid)
+    }
+
+    pub fn pinned(&mut self, path: &Path, cid: &Cid) {
+        self.failed.remove(path);
+        self.pinned.insert(path.to_path_buf(), PinnedCid(*cid));
+    }
+
+    pub fn failed(&mut self, path: &Path, error: String) {
+        self.failed.insert(path.to_path_buf(), error);
+    }
+
+    /// Note the push stopped on an error
+    pub fn stopped(&mut self, error: String) {
+        if self.stage == PushStage::Pinning && !self.failed.is_empty() {
+            self.stage = PushStage::Failed;
+        }
+        self.error = Some(error);
+    }
+
+    /// Note every object is pinned and `root_cid` is written, but we're holding off
+    /// on the update
+    pub fn prepared(&mut self, root_cid: Cid) {
+        self.root_cid = Some(root_cid);
+        self.stage = PushStage::Prepared;
+        self.error = None;
+    }
+
+    /// Note every object is pinned, and we're sending the update to `root_cid`
+    pub fn publishing(&mut self, root_cid: Cid) {
+        self.root_cid = Some(root_cid);
+        self.stage = PushStage::Publishing;
+        self.error = None;
+    }
+
+    /// Note the update was sent as `tx_hash`, but hasn't landed
+    pub fn broadcast(&mut self, tx_hash: String) {
+        self.stage = PushStage::Broadcast;
+        self.tx_hash = Some(tx_hash);
+    }
+}
+
+/// A pinned object's cid, kept as a string like the cids in manifests
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+struct PinnedCid(#[serde(with = "cid_serde")] Cid);
+
+/// Read the pinned objects of a push state
+/// States written before cids were kept only listed paths, which can't say what
+/// was pinned -- those are dropped, and re-pinned on resume
+fn pinned_or_legacy<'de, D>(deserializer: D) -> Result<BTreeMap<PathBuf, PinnedCid>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Pinned {
+        Cids(BTreeMap<PathBuf, PinnedCid>),
+        Paths(Vec<PathBuf>),
+    }
+    Ok(match Pinned::deserialize(deserializer)? {
+        Pinned::Cids(pinned) => pinned,
+        Pinned::Paths(_) => BTreeMap::new(),
+    })
+}
+
+impl Display for PushState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "started: {}", self.started_at)?;
+        writeln!(f, "stage: {}", self.stage)?;
+        writeln!(f, "previous root: {}", self.previous_root_cid)?;
+        if let Some(root_cid) = &self.root_cid {
+            writeln!(f, "root: {}", root_cid)?;
+        }
+        if let Some(path) = &self.options.path {
+            writeln!(f, "path: {}", path.display())?;
+        }
+        writeln!(
+            f,
+            "pinned: {}, failed: {}",
+            self.pinned.len(),
+            self.failed.len()
+        )?;
+        for (path, error) in self.failed.iter() {
+            writeln!(f, "failed {}: {}", path.display(), error)?;
+        }
+        match &self.tx_hash {
+            Some(tx_hash) => writeln!(f, "root update: {}", tx_hash)?,
+            None if self.stage == PushStage::Publishing => {
+                writeln!(f, "root update: may have been sent")?
+            }
+            None => writeln!(f, "root update: not sent")?,
+        }
+        if let Some(error) = &self.error {
+            writeln!(f, "error: {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_utils::cid;
+
+    #[test]
+    fn recommitted_objects_are_pinned_again() {
+        let path = Path::new("data.csv");
+        let mut push_state = PushState::new(cid(0), PushOptions::default());
+        push_state.pinned(path, &cid(1));
+        assert!(push_state.is_pinned(path, &cid(1)));
+        assert!(!push_state.is_pinned(path, &cid(2)));
+
+        let json = serde_json::to_value(&push_state).unwrap();
+        let push_state: PushState = serde_json::from_value(json.clone()).unwrap();
+        assert!(push_state.is_pinned(path, &cid(1)));
+
+        let mut legacy = json;
+        legacy["pinned"] = serde_json::json!(["data.csv"]);
+        let push_state: PushState = serde_json::from_value(legacy).unwrap();
+        assert!(!push_state.is_pinned(path, &cid(1)));
+    }
+}
@@ -0,0 +1,179 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use cid::Cid;
+
+use crate::device::CidParams;
+use crate::types::is_tag_name;
+
+/// Prefixes people paste along with a cid, from gateway paths and urls
+const CID_PREFIXES: [&str; 3] = ["ipfs://", "/ipfs/", "ipfs/"];
+
+/// Name of the multibase a cid string is in, from its leading character
+/// Cidv0s have no multibase prefix, and are always base58btc
+fn multibase_name(s: &str) -> &'static str {
+    if s.starts_with("Qm") && s.len() == 46 {
+        return "base58btc (cidv0)";
+    }
+    match s.chars().next() {
+        Some('b') | Some('B') => "base32",
+        Some('z') => "base58btc",
+        Some('f') | Some('F') => "base16",
+        Some('k') | Some('K') => "base36",
+        Some('m') | Some('u') => "base64",
+        _ => "an unknown base",
+    }
+}
+
+/// Trim whitespace and any gateway prefix or path from a pasted cid
+fn normalize(s: &str) -> &str {
+    let mut s = s.trim();
+    for prefix in CID_PREFIXES {
+        if let Some(rest) = s.strip_prefix(prefix) {
+            s = rest;
+            break;
+        }
+    }
+    s.split('/').next().unwrap_or(s)
+}
+
+fn decode(s: &str) -> Result<Cid, CidArgError> {
+    let normalized = normalize(s);
+    Cid::from_str(normalized).map_err(|e| CidArgError::Invalid {
+        input: s.to_string(),
+        base: multibase_name(normalized),
+        source: e,
+    })
+}
+
+/// Parse a cid argument, in any multibase and with or without an `/ipfs/` prefix
+/// Cids made with other params than we hash with are accepted, with a warning that
+/// they won't match anything this device adds
+pub fn parse_cid(s: &str) -> Result<Cid, CidArgError> {
+    let cid = decode(s)?;
+    let params = CidParams::of(&cid);
+    if params != CidParams::default() {
+        tracing::warn!(
+            "{} is a {} cid, but this device adds objects as {} -- it won't match any of them",
+            cid,
+            params,
+            CidParams::default()
+        );
+    }
+    Ok(cid)
+}
+
+/// Parse a cid argument that has to be one of our roots, so must have been made
+/// with the params we hash with
+pub fn parse_root_cid(s: &str) -> Result<Cid, CidArgError> {
+    let cid = decode(s)?;
+    let params = CidParams::of(&cid);
+    if params != CidParams::default() {
+        return Err(CidArgError::WrongParams {
+            cid,
+            actual: params,
+            expected: CidParams::default(),
+        });
+    }
+    Ok(cid)
+}
+
+/// A root argument: either a root cid, or the name of a tag pointing at one
+#[derive(Debug, Clone, PartialEq)]
+pub enum RootArg {
+    Cid(Cid),
+    Tag(String),
+}
+
+impl Display for RootArg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cid(cid) => write!(f, "{}", cid),
+            Self::Tag(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// Parse a root argument, taking anything that isn't a cid but could be a tag
+/// name as one. Tags are resolved against the device later, see Config::resolve_root
+pub fn parse_root_arg(s: &str) -> Result<RootArg, CidArgError> {
+    match decode(s) {
+        Ok(_) => Ok(RootArg::Cid(parse_root_cid(s)?)),
+        Err(_) if is_tag_name(s.trim()) => Ok(RootArg::Tag(s.trim().to_string())),
+        Err(e) => Err(e),
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CidArgError {
+    #[error(
+        "{input:?} looks like {base}, but isn't a valid cid: {source}. Expected a cidv1 \
+         such as bafk..., in any multibase"
+    )]
+    Invalid {
+        input: String,
+        base: &'static str,
+        source: cid::Error,
+    },
+    #[error(
+        "{cid} is a {actual} cid, but roots are {expected} -- a cidv0 or a cid hashed \
+         differently can't be one of ours"
+    )]
+    WrongParams {
+        cid: Cid,
+        actual: CidParams,
+        expected: CidParams,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::hash_offline;
+    use cid::multihash::Multihash;
+
+    #[test]
+    fn normalizes_pasted_cids() {
+        let cid = hash_offline(b"hello").unwrap();
+        for input in [
+            cid.to_string(),
+            format!("  {}\n", cid),
+            format!("/ipfs/{}", cid),
+            format!("ipfs://{}/some/path", cid),
+            cid.to_string_of_base(cid::multibase::Base::Base58Btc)
+                .unwrap(),
+        ] {
+            assert_eq!(parse_root_cid(&input).unwrap(), cid);
+        }
+    }
+
+    #[test]
+    fn rejects_cidv0_roots() {
+        let hash = Multihash::wrap(0x12, &[0; 32]).unwrap();
+        let v0 = Cid::new_v0(hash).unwrap();
+        assert_eq!(parse_cid(&v0.to_string()).unwrap(), v0);
+        assert!(matches!(
+            parse_root_cid(&v0.to_string()),
+            Err(CidArgError::WrongParams { .. })
+        ));
+        assert!(matches!(
+            parse_root_cid("bafknotacid"),
+            Err(CidArgError::Invalid { base: "base32", .. })
+        ));
+    }
+
+    #[test]
+    fn parses_tags_as_roots() {
+        let cid = hash_offline(b"hello").unwrap();
+        assert_eq!(
+            parse_root_arg(&format!("/ipfs/{}", cid)).unwrap(),
+            RootArg::Cid(cid)
+        );
+        assert_eq!(
+            parse_root_arg("v1.0").unwrap(),
+            RootArg::Tag("v1.0".to_string())
+        );
+        assert!(parse_root_arg("release candidate").is_err());
+        assert!(parse_root_arg("ipfs://notacid").is_err());
+    }
+}
@@ -0,0 +1,191 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ethers::signers::LocalWallet;
+use ethers::types::Address;
+use tokio_util::sync::CancellationToken;
+
+use crate::cli::args::Args;
+use crate::cli::cancel::Cancellation;
+use crate::device::{BlockVerifier, EthRemote, IpfsRemote};
+
+use super::on_disk_default::OnDiskDefault;
+use super::on_disk_device::OnDiskDevice;
+use super::on_disk_device::{keyring_wallet, WalletSource};
+use super::{Config, ConfigError};
+
+/// Builds a Config in code, i.e. for a service embedding dor-store that doesn't
+/// want to write config files first
+/// Parsing args and on disk defaults is just one way to fill it in -- either way
+/// the result is validated the same way when built
+#[derive(Debug, Default)]
+pub struct ConfigBuilder {
+    working_dir: Option<PathBuf>,
+    data_dir: Option<PathBuf>,
+    device_alias: Option<String>,
+    admin_key_string: Option<String>,
+    contract_address: Option<Address>,
+    blocks: Option<PathBuf>,
+    from_s3: bool,
+    ipfs_remote: Option<IpfsRemote>,
+    eth_remote: Option<EthRemote>,
+    wallet_source: Option<WalletSource>,
+    device_config: Option<OnDiskDevice>,
+    block_verifier: Option<Arc<dyn BlockVerifier>>,
+    timeout: Option<Duration>,
+    cancellation_token: Option<CancellationToken>,
+}
+
+impl ConfigBuilder {
+    /// Fill in a builder from args and on disk defaults
+    pub fn from_args(args: &Args) -> Result<Self, ConfigError> {
+        let on_disk_default = OnDiskDefault::load()?;
+        Ok(Self {
+            working_dir: args.dir.clone().map(PathBuf::from),
+            data_dir: args.data_dir.clone().map(PathBuf::from),
+            device_alias: on_disk_default.device_alias(),
+            admin_key_string: args.admin_key.clone(),
+            contract_address: args.contract_address,
+            blocks: args.blocks.clone().map(PathBuf::from),
+            from_s3: args.from_s3,
+            timeout: args.timeout_secs.map(Duration::from_secs),
+            ..Default::default()
+        })
+    }
+
+    /// Dir holding the local dot directory -- defaults to the current working dir
+    pub fn with_working_dir(mut self, working_dir: PathBuf) -> Self {
+        self.working_dir = Some(working_dir);
+        self
+    }
+
+    /// Dir object paths are resolved against -- defaults to the working dir
+    pub fn with_data_dir(mut self, data_dir: PathBuf) -> Self {
+        self.data_dir = Some(data_dir);
+        self
+    }
+
+    /// Alias of the on disk device to use, and keep root state under
+    pub fn with_device_alias(mut self, alias: String) -> Self {
+        self.device_alias = Some(alias);
+        self
+    }
+
+    /// Admin key for managing the RootCid contract, hex encoded
+    pub fn with_admin_key(mut self, admin_key: String) -> Self {
+        self.admin_key_string = Some(admin_key);
+        self
+    }
+
+    /// Override the device's RootCid contract address
+    pub fn with_contract_address(mut self, contract_address: Address) -> Self {
+        self.contract_address = Some(contract_address);
+        self
+    }
+
+    /// CAR file or block directory to read manifests and objects from, in place of the remote
+    pub fn with_blocks(mut self, blocks: PathBuf) -> Self {
+        self.blocks = Some(blocks);
+        self
+    }
+
+    /// Read manifests and objects from the device's S3 mirror, in place of the remote
+    pub fn with_from_s3(mut self) -> Self {
+        self.from_s3 = true;
+        self
+    }
+
+    /// Use this IpfsRemote in place of the device's, without saving it to disk
+    pub fn with_ipfs_remote(mut self, ipfs_remote: IpfsRemote) -> Self {
+        self.ipfs_remote = Some(ipfs_remote);
+        self
+    }
+
+    /// Use this EthRemote in place of the device's, without saving it to disk
+    /// A contract address override still takes precedence over its address
+    pub fn with_eth_remote(mut self, eth_remote: EthRemote) -> Self {
+        self.eth_remote = Some(eth_remote);
+        self
+    }
+
+    /// Read the device's signing key from here, rather than where the device says
+    pub fn with_wallet_source(mut self, wallet_source: WalletSource) -> Self {
+        self.wallet_source = Some(wallet_source);
+        self
+    }
+
+    /// Use this device config in place of the device's file on disk, i.e. one read from
+    /// a config source or made with OnDiskDevice::in_memory. Its alias becomes the device
+    /// alias, so root state is still kept under it -- starting from the default root if
+    /// the device was never created on disk
+    pub fn with_device_config(mut self, device_config: OnDiskDevice) -> Self {
+        self.device_alias = Some(device_config.alias());
+        self.device_config = Some(device_config);
+        self
+    }
+
+    /// Give up on ops after this long, stopping at their next safe point
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Hold every block read, i.e. on pulls from a CAR or the gateway, to a policy of our own
+    /// Rejected blocks fail the op with the verifier's reason
+    pub fn with_block_verifier(mut self, block_verifier: Arc<dyn BlockVerifier>) -> Self {
+        self.block_verifier = Some(block_verifier);
+        self
+    }
+
+    /// Stop ops at their next safe point once this token is cancelled
+    pub fn with_cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.cancellation_token = Some(token);
+        self
+    }
+
+    /// Check everything set and produce a Config
+    pub fn build(self) -> Result<Config, ConfigError> {
+        if let Some(admin_key_string) = &self.admin_key_string {
+            admin_key_string
+                .parse::<LocalWallet>()
+                .map_err(|_| ConfigError::InvalidAdminKey)?;
+        }
+
+        // Overrides are applied to a device, so there has to be one
+        let overrides_device =
+            self.ipfs_remote.is_some() || self.eth_remote.is_some() || self.wallet_source.is_some();
+        if overrides_device && self.device_alias.is_none() {
+            return Err(ConfigError::NoSetDevice);
+        }
+
+        // Don't hand back a config that can't sign
+        if let Some(WalletSource::Keyring { service, account }) = &self.wallet_source {
+            keyring_wallet(service, account)?;
+        }
+
+        let mut cancellation = Cancellation::default();
+        if let Some(timeout) = self.timeout {
+            cancellation = cancellation.with_timeout(timeout);
+        }
+        if let Some(token) = self.cancellation_token {
+            cancellation = cancellation.with_token(token);
+        }
+
+        Ok(Config {
+            working_dir: self.working_dir.unwrap_or_else(|| PathBuf::from(".")),
+            data_dir: self.data_dir,
+            device_alias: self.device_alias,
+            admin_key_string: self.admin_key_string,
+            contract_address: self.contract_address,
+            blocks: self.blocks,
+            from_s3: self.from_s3,
+            ipfs_remote: self.ipfs_remote,
+            eth_remote: self.eth_remote,
+            wallet_source: self.wallet_source,
+            device_config: self.device_config,
+            block_verifier: self.block_verifier,
+            cancellation,
+        })
+    }
+}
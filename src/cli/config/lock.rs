@@ -0,0 +1,87 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::path::{Path, PathBuf};
+
+use super::ConfigError;
+
+/// How an op holds the working dir
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    /// Reads state -- any number of ops can hold it at once, so long as nothing
+    /// holds it exclusively
+    Shared,
+    /// Changes state -- no other op can hold it at all
+    Exclusive,
+}
+
+/// An advisory lock on a working dir's dot dir, held until it's dropped
+/// The os lets go of it if we crash, so a stale lock file never blocks anyone
+#[derive(Debug)]
+pub struct WorkingDirLock {
+    _file: File,
+}
+
+impl WorkingDirLock {
+    /// Take the lock without waiting for it
+    /// # Args
+    /// - path: the lock file, created if there isn't one
+    /// - mode: whether we only read state or change it
+    pub fn acquire(path: &Path, mode: LockMode) -> Result<Self, ConfigError> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)?;
+        let locked = match mode {
+            LockMode::Shared => file.try_lock_shared(),
+            LockMode::Exclusive => file.try_lock(),
+        };
+        match locked {
+            Ok(()) => Ok(Self { _file: file }),
+            Err(TryLockError::WouldBlock) => Err(ConfigError::Locked(
+                path.parent().map(PathBuf::from).unwrap_or_default(),
+            )),
+            Err(TryLockError::Error(e)) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lock_path(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("dor-store-lock-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("lock")
+    }
+
+    #[test]
+    fn second_mutation_is_rejected() {
+        let path = lock_path("exclusive");
+        let held = WorkingDirLock::acquire(&path, LockMode::Exclusive).unwrap();
+        assert!(matches!(
+            WorkingDirLock::acquire(&path, LockMode::Exclusive),
+            Err(ConfigError::Locked(_))
+        ));
+        assert!(matches!(
+            WorkingDirLock::acquire(&path, LockMode::Shared),
+            Err(ConfigError::Locked(_))
+        ));
+
+        // Released once the op is done with it
+        drop(held);
+        WorkingDirLock::acquire(&path, LockMode::Exclusive).unwrap();
+    }
+
+    #[test]
+    fn reads_share_the_lock() {
+        let path = lock_path("shared");
+        let _first = WorkingDirLock::acquire(&path, LockMode::Shared).unwrap();
+        let _second = WorkingDirLock::acquire(&path, LockMode::Shared).unwrap();
+        assert!(matches!(
+            WorkingDirLock::acquire(&path, LockMode::Exclusive),
+            Err(ConfigError::Locked(_))
+        ));
+    }
+}
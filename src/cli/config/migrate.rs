@@ -0,0 +1,117 @@
+use serde_json::Value;
+
+use super::ConfigError;
+
+/// Current version of the on disk device config schema
+/// Bump this and add a migration below whenever the shape of the config changes
+pub const DEVICE_CONFIG_VERSION: u64 = 1;
+
+/// Upgrade a raw on disk device config to the current version
+/// Configs written before versioning are treated as version 0
+/// # Returns the migrated config, and whether anything changed
+pub fn migrate_device_config(mut config: Value) -> Result<(Value, bool), ConfigError> {
+    let version = match config.get("version") {
+        None => 0,
+        Some(version) => version
+            .as_u64()
+            .ok_or(ConfigError::InvalidConfigVersion(version.to_string()))?,
+    };
+    if version > DEVICE_CONFIG_VERSION {
+        return Err(ConfigError::UnsupportedConfigVersion(version));
+    }
+
+    migrate(&mut config, version, DEVICE_CONFIG_VERSION)?;
+    config["version"] = Value::from(DEVICE_CONFIG_VERSION);
+
+    Ok((config, version != DEVICE_CONFIG_VERSION))
+}
+
+/// Run each migration from one version up to another
+/// A version with no migration onward can't be upgraded, so it's refused rather
+/// than half migrated
+fn migrate(config: &mut Value, version: u64, target: u64) -> Result<(), ConfigError> {
+    for from in version..target {
+        match from {
+            0 => v0_to_v1(config)?,
+            _ => return Err(ConfigError::UnsupportedConfigVersion(from)),
+        }
+    }
+    Ok(())
+}
+
+/// v1 moved the contract address from the top level onto the eth remote
+fn v0_to_v1(config: &mut Value) -> Result<(), ConfigError> {
+    let object = config
+        .as_object_mut()
+        .ok_or(ConfigError::InvalidConfigVersion(
+            "not an object".to_string(),
+        ))?;
+    if let Some(contract_address) = object.remove("contract_address") {
+        if let Some(eth_remote) = object.get_mut("eth_remote").and_then(Value::as_object_mut) {
+            eth_remote.insert("contract_address".to_string(), contract_address);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::Address;
+
+    use super::super::on_disk_device::OnDiskDevice;
+    use super::*;
+
+    const ADDRESS: &str = "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed";
+
+    fn v0_config() -> Value {
+        serde_json::json!({
+            "alias": "test",
+            "contract_address": ADDRESS,
+            "eth_remote": { "rpc_url": "http://localhost:8545/", "chain_id": 31337 },
+            "ipfs_remote": {
+                "api_url": "http://localhost:5001/",
+                "gateway_url": "http://localhost:8080/"
+            }
+        })
+    }
+
+    #[test]
+    fn migrate_v0() {
+        let (config, migrated) = migrate_device_config(v0_config()).unwrap();
+        assert!(migrated);
+        assert_eq!(config["version"], DEVICE_CONFIG_VERSION);
+        assert!(config.get("contract_address").is_none());
+
+        let device: OnDiskDevice = serde_json::from_value(config).unwrap();
+        let address: Address = ADDRESS.parse().unwrap();
+        assert_eq!(device.eth_remote().contract_address, address);
+        assert_eq!(device.alias(), "test");
+    }
+
+    #[test]
+    fn migrate_current_is_noop() {
+        let (current, _) = migrate_device_config(v0_config()).unwrap();
+        let (config, migrated) = migrate_device_config(current.clone()).unwrap();
+        assert!(!migrated);
+        assert_eq!(config, current);
+    }
+
+    #[test]
+    fn migrate_future_errors() {
+        let mut config = v0_config();
+        config["version"] = Value::from(DEVICE_CONFIG_VERSION + 1);
+        assert!(matches!(
+            migrate_device_config(config),
+            Err(ConfigError::UnsupportedConfigVersion(_))
+        ));
+    }
+
+    #[test]
+    fn missing_migrations_error() {
+        let (mut config, _) = migrate_device_config(v0_config()).unwrap();
+        assert!(matches!(
+            migrate(&mut config, DEVICE_CONFIG_VERSION, DEVICE_CONFIG_VERSION + 1),
+            Err(ConfigError::UnsupportedConfigVersion(version)) if version == DEVICE_CONFIG_VERSION
+        ));
+    }
+}
@@ -1,20 +1,44 @@
+use std::collections::BTreeMap;
 use std::fs::create_dir_all;
-use std::{env, io::Write, path::PathBuf};
+use std::sync::Arc;
+use std::{
+    env,
+    io::Write,
+    path::{Path, PathBuf},
+};
 
 use cid::Cid;
 use ethers::signers::LocalWallet;
 use ethers::types::Address;
-use fs_tree::FsTree;
-
-use crate::cli::changes::ChangeLog;
-use crate::device::{Device, EthRemote, IpfsRemote};
-use crate::types::DorStore;
-
+use serde::{Deserialize, Serialize};
+
+use crate::cli::cancel::Cancellation;
+use crate::cli::changes::{ChangeLog, HashCache, PullRetry, PushState};
+use crate::cli::cid_arg::RootArg;
+use crate::cli::walk::{walk, SymlinkPolicy, WalkEntry};
+use crate::device::{
+    open_block_source, BlockSourceError, BlockVerifier, CacheError, CachePolicy, ContentCache,
+    Device, EthRemote, IpfsRemote, IpnsRemote, ManifestCompression, RootFallback, RootLayout,
+    RootMigration, RootReconcile, RootTarget, S3Remote,
+};
+#[cfg(feature = "s3")]
+use crate::device::{S3Store, S3StoreError};
+use crate::types::{DorStore, Replication, Tags};
+
+mod builder;
+mod lock;
+mod migrate;
 mod on_disk_default;
 pub mod on_disk_device;
+mod source;
 
+pub use builder::ConfigBuilder;
+pub use lock::{LockMode, WorkingDirLock};
 use on_disk_default::OnDiskDefault;
-use on_disk_device::OnDiskDevice;
+use on_disk_device::{
+    OnDiskDevice, OnDiskDeviceError, PreviousRootCheck, PushGuardrail, WalletSource,
+};
+pub use source::{parse_config_source, ConfigSource, CONFIG_TOKEN_ENV};
 
 use super::args::Args;
 
@@ -32,17 +56,37 @@ pub const DEVICE_CONFIG_NAME: &str = "device.json";
 pub const ROOT_CID_NAME: &str = "root";
 // name to lookup on disk base dor-store under a given device alias
 pub const BASE_DOR_STORE_NAME: &str = "base.json";
+// name to lookup root tags under a given device alias
+pub const TAGS_NAME: &str = "tags.json";
+// name of the content cache directory under a given device alias
+pub const CACHE_DIR_NAME: &str = "cache";
 
 // path to folder containing local changes tracking in the given working dir
 pub const DEFAULT_LOCAL_DOT_DIR: &str = ".fs";
 // name to lookup change log within a dot dir
 pub const CHANGE_LOG_NAME: &str = "changes.json";
+// name to lookup the staged copy of the change log within a dot dir
+pub const STAGE_NAME: &str = "stage.json";
+// name to lookup cached working dir hashes within a dot dir
+pub const HASH_CACHE_NAME: &str = "hashes.json";
+// name to lookup the state of an unfinished push within a dot dir
+pub const PUSH_STATE_NAME: &str = "push.json";
+// name of the record of objects a pull couldn't fetch, within a dot dir
+pub const PULL_RETRY_NAME: &str = "pull.json";
+// name of the journal of a published root's state being saved, within a dot dir
+pub const PUBLISH_JOURNAL_NAME: &str = "published.json";
+// name of the file ops lock the working dir with, within a dot dir
+pub const LOCK_NAME: &str = "lock";
 
 #[derive(Debug)]
 pub struct Config {
     /// Working dir -- defaults to the current working dir
+    /// Holds the local dot directory tracking changes
     working_dir: PathBuf,
 
+    /// Dir object paths are resolved against -- defaults to the working dir
+    data_dir: Option<PathBuf>,
+
     /// Alias for the device to use
     device_alias: Option<String>,
 
@@ -51,6 +95,29 @@ pub struct Config {
     /// Cannot be on disk defaults or in env
     /// Should be the key that deployed the contract
     admin_key_string: Option<String>,
+
+    /// Override for the device's RootCid contract address
+    contract_address: Option<Address>,
+
+    /// CAR file or block directory to read manifests and objects from, in place of the remote
+    blocks: Option<PathBuf>,
+
+    /// Read manifests and objects from the device's S3 mirror, in place of the remote
+    from_s3: bool,
+
+    /// Overrides for the device's remotes and wallet, i.e. set in code by a builder
+    ipfs_remote: Option<IpfsRemote>,
+    eth_remote: Option<EthRemote>,
+    wallet_source: Option<WalletSource>,
+
+    /// Device config read from a config source, used in place of the device's file on disk
+    device_config: Option<OnDiskDevice>,
+
+    /// Policy blocks we read have to pass, in place of only checking their hashes
+    block_verifier: Option<Arc<dyn BlockVerifier>>,
+
+    /// When to give up on the op we're running
+    cancellation: Cancellation,
 }
 
 // TODO: should isolate side effects from config
@@ -58,51 +125,58 @@ pub struct Config {
 impl Config {
     /// Parse the config from args and on disk defaults
     pub fn parse_args(args: &Args) -> Result<Self, ConfigError> {
-        let on_disk_config = OnDiskDefault::load()?;
-
-        let device_alias = on_disk_config.device_alias();
-
-        let maybe_dir = args.dir.clone();
-        let working_dir_str = match maybe_dir {
-            Some(s) => s,
-            None => ".".to_string(),
-        };
-        let working_dir = PathBuf::from(working_dir_str);
-
-        let admin_key_string = args.admin_key.clone();
-
-        Ok(Self {
-            working_dir,
-            device_alias,
-            admin_key_string,
-        })
+        ConfigBuilder::from_args(args)?.build()
     }
 
     pub fn working_dir(&self) -> &PathBuf {
         &self.working_dir
     }
 
-    /// Get the next fs-tree from the working directory           
-    pub fn fs_tree(&self) -> Result<FsTree, ConfigError> {
-        let working_dir = self.working_dir();
-        let dot_dir = PathBuf::from(DEFAULT_LOCAL_DOT_DIR);
-        // Read Fs-tree at dir or pwd, stripping off the local dot directory
-        let next = match fs_tree::FsTree::read_at(working_dir.to_str().unwrap())? {
-            fs_tree::FsTree::Directory(mut d) => {
-                let _res = &d.remove_entry(&dot_dir);
-                fs_tree::FsTree::Directory(d)
-            }
-            _ => {
-                return Err(ConfigError::DotDirNotADirectory);
-            }
-        };
+    /// Dir holding the objects -- the working dir unless a separate data dir is set
+    pub fn data_dir(&self) -> &PathBuf {
+        self.data_dir.as_ref().unwrap_or(&self.working_dir)
+    }
+
+    /// Walk the data directory, leaving out the local dot directory
+    /// Symlinks are handled by the device's symlink policy
+    /// # Returns every entry by its path, in path order, starting with the root at ""
+    pub fn walk(&self) -> Result<BTreeMap<PathBuf, WalkEntry>, ConfigError> {
+        let symlink_policy = self.on_disk_device()?.symlink_policy();
+        let mut next = walk(
+            self.data_dir(),
+            Path::new(DEFAULT_LOCAL_DOT_DIR),
+            symlink_policy,
+        )?;
+        next.insert(PathBuf::new(), WalkEntry::Directory);
         Ok(next)
     }
+
     pub fn with_device_alias(&mut self, alias: String) -> &Self {
         self.device_alias = Some(alias);
         self
     }
 
+    /// When to give up on the op we're running
+    pub fn cancellation(&self) -> &Cancellation {
+        &self.cancellation
+    }
+
+    /// Lock the working dir for the op we're about to run, failing rather than
+    /// waiting if another op holds it
+    /// Ops that change state first finish saving a published root, if that was cut short
+    /// # Returns None outside an initialized working dir, where there's nothing to lock
+    pub fn lock(&self, mode: LockMode) -> Result<Option<WorkingDirLock>, ConfigError> {
+        let dot_path = self.working_dir.join(DEFAULT_LOCAL_DOT_DIR);
+        if !dot_path.is_dir() {
+            return Ok(None);
+        }
+        let lock = WorkingDirLock::acquire(&dot_path.join(LOCK_NAME), mode)?;
+        if mode == LockMode::Exclusive {
+            self.recover_published()?;
+        }
+        Ok(Some(lock))
+    }
+
     /* Methods */
 
     pub fn list_on_disk_devices() -> Result<Vec<OnDiskDevice>, ConfigError> {
@@ -111,11 +185,20 @@ impl Config {
 
     pub fn create_on_disk_device(
         alias: String,
-        contract_address: Address,
         ipfs_remote: IpfsRemote,
         eth_remote: EthRemote,
+        ipns_remote: Option<IpnsRemote>,
+        root_target: RootTarget,
+        wallet_source: WalletSource,
     ) -> Result<OnDiskDevice, ConfigError> {
-        let device = OnDiskDevice::new(alias, ipfs_remote, eth_remote, contract_address)?;
+        let device = OnDiskDevice::new(
+            alias,
+            ipfs_remote,
+            eth_remote,
+            ipns_remote,
+            root_target,
+            wallet_source,
+        )?;
         Ok(device)
     }
 
@@ -137,8 +220,7 @@ impl Config {
         let change_log_path = dot_path.join(CHANGE_LOG_NAME);
         let change_log = ChangeLog::new(alias, &base, &root_cid);
         let change_log_str = serde_json::to_string_pretty(&change_log)?;
-        let mut change_log_file = std::fs::File::create(change_log_path)?;
-        change_log_file.write_all(change_log_str.as_bytes())?;
+        write_atomic(&change_log_path, change_log_str.as_bytes())?;
         Ok(())
     }
 
@@ -167,14 +249,243 @@ impl Config {
         let change_log_path = dot_path.join(CHANGE_LOG_NAME);
 
         let change_log_str = serde_json::to_string_pretty(&change_log)?;
-        let mut change_log_file = std::fs::File::create(change_log_path)?;
-        change_log_file.write_all(change_log_str.as_bytes())?;
+        write_atomic(&change_log_path, change_log_str.as_bytes())?;
+
+        Ok(())
+    }
+
+    /// Whether there is a staged copy of the change log
+    pub fn has_stage(&self) -> bool {
+        let dot_path = self.working_dir.join(DEFAULT_LOCAL_DOT_DIR);
+        dot_path.join(STAGE_NAME).exists()
+    }
+
+    /// Get the staged copy of the change log, falling back to the change log itself
+    pub fn stage(&self) -> Result<ChangeLog, ConfigError> {
+        let dot_path = self.working_dir.join(DEFAULT_LOCAL_DOT_DIR);
+        let stage_path = dot_path.join(STAGE_NAME);
+
+        if !stage_path.exists() {
+            return self.change_log();
+        }
+
+        let alias = self.device_alias.clone().ok_or(ConfigError::NoSetDevice)?;
+
+        let stage_str = std::fs::read_to_string(stage_path)?;
+        let stage: ChangeLog = serde_json::from_str(&stage_str)?;
+
+        if stage.manager_alias() != &alias {
+            return Err(ConfigError::ChangeLogNotFound);
+        }
+
+        Ok(stage)
+    }
+
+    /// Write the staged copy of the change log. The change log itself is left untouched
+    pub fn set_stage(&self, stage: ChangeLog) -> Result<(), ConfigError> {
+        let dot_path = self.working_dir.join(DEFAULT_LOCAL_DOT_DIR);
+        let stage_path = dot_path.join(STAGE_NAME);
+
+        let stage_str = serde_json::to_string_pretty(&stage)?;
+        write_atomic(&stage_path, stage_str.as_bytes())?;
+
+        Ok(())
+    }
+
+    /// Discard the staged copy of the change log, if any
+    pub fn reset_stage(&self) -> Result<(), ConfigError> {
+        let dot_path = self.working_dir.join(DEFAULT_LOCAL_DOT_DIR);
+        let stage_path = dot_path.join(STAGE_NAME);
+
+        if stage_path.exists() {
+            std::fs::remove_file(stage_path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Get the state of an unfinished push, if one was left behind
+    pub fn push_state(&self) -> Result<Option<PushState>, ConfigError> {
+        let dot_path = self.working_dir.join(DEFAULT_LOCAL_DOT_DIR);
+        let push_state_path = dot_path.join(PUSH_STATE_NAME);
+
+        if !push_state_path.exists() {
+            return Ok(None);
+        }
+
+        let push_state_str = std::fs::read_to_string(push_state_path)?;
+        let push_state = serde_json::from_str(&push_state_str)?;
+        Ok(Some(push_state))
+    }
+
+    pub fn set_push_state(&self, push_state: &PushState) -> Result<(), ConfigError> {
+        let dot_path = self.working_dir.join(DEFAULT_LOCAL_DOT_DIR);
+        let push_state_path = dot_path.join(PUSH_STATE_NAME);
+
+        let push_state_str = serde_json::to_string_pretty(push_state)?;
+        write_atomic(&push_state_path, push_state_str.as_bytes())?;
+
+        Ok(())
+    }
+
+    /// Forget the state of an unfinished push, if any
+    pub fn clear_push_state(&self) -> Result<(), ConfigError> {
+        let dot_path = self.working_dir.join(DEFAULT_LOCAL_DOT_DIR);
+        let push_state_path = dot_path.join(PUSH_STATE_NAME);
+
+        if push_state_path.exists() {
+            std::fs::remove_file(push_state_path)?;
+        }
+
+        Ok(())
+    }
+
+    /// The objects the last pull couldn't fetch, if it left any
+    pub fn pull_retry(&self) -> Result<Option<PullRetry>, ConfigError> {
+        let dot_path = self.working_dir.join(DEFAULT_LOCAL_DOT_DIR);
+        let pull_retry_path = dot_path.join(PULL_RETRY_NAME);
+
+        if !pull_retry_path.exists() {
+            return Ok(None);
+        }
+
+        let pull_retry_str = std::fs::read_to_string(pull_retry_path)?;
+        let pull_retry = serde_json::from_str(&pull_retry_str)?;
+        Ok(Some(pull_retry))
+    }
+
+    pub fn set_pull_retry(&self, pull_retry: &PullRetry) -> Result<(), ConfigError> {
+        let dot_path = self.working_dir.join(DEFAULT_LOCAL_DOT_DIR);
+        let pull_retry_path = dot_path.join(PULL_RETRY_NAME);
+
+        let pull_retry_str = serde_json::to_string_pretty(pull_retry)?;
+        write_atomic(&pull_retry_path, pull_retry_str.as_bytes())?;
+
+        Ok(())
+    }
+
+    /// Forget the objects the last pull couldn't fetch, if any
+    pub fn clear_pull_retry(&self) -> Result<(), ConfigError> {
+        let dot_path = self.working_dir.join(DEFAULT_LOCAL_DOT_DIR);
+        let pull_retry_path = dot_path.join(PULL_RETRY_NAME);
+
+        if pull_retry_path.exists() {
+            std::fs::remove_file(pull_retry_path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Save a published root as our state: its root cid and base, the change log
+    /// carried over onto it, and no unfinished push
+    /// These are spread across the device and working dirs, so they're journaled
+    /// together first. If we're cut short, the next op to lock the working dir
+    /// finishes the save from the journal
+    pub fn save_published(
+        &self,
+        root_cid: &Cid,
+        base: &DorStore,
+        change_log: ChangeLog,
+    ) -> Result<(), ConfigError> {
+        self.save_journaled(PublishJournal {
+            root_cid: *root_cid,
+            base: base.clone(),
+            change_log,
+            pushed: true,
+        })
+    }
+
+    /// Save a pulled root as our state: its root cid and base, and the change log
+    /// on top of it. Journaled like save_published, but leaves any
+    /// unfinished push of ours alone, and forgets what earlier pulls left to retry
+    pub fn save_pulled(
+        &self,
+        root_cid: &Cid,
+        base: &DorStore,
+        change_log: ChangeLog,
+    ) -> Result<(), ConfigError> {
+        self.save_journaled(PublishJournal {
+            root_cid: *root_cid,
+            base: base.clone(),
+            change_log,
+            pushed: false,
+        })?;
+        self.clear_pull_retry()
+    }
+
+    fn save_journaled(&self, journal: PublishJournal) -> Result<(), ConfigError> {
+        let dot_path = self.working_dir.join(DEFAULT_LOCAL_DOT_DIR);
+        let journal_path = dot_path.join(PUBLISH_JOURNAL_NAME);
+
+        let journal_str = serde_json::to_string(&journal)?;
+        write_atomic(&journal_path, journal_str.as_bytes())?;
+        self.apply_published(journal)?;
+        std::fs::remove_file(journal_path)?;
+
+        Ok(())
+    }
+
+    /// Finish saving a published root from its journal, if a save was cut short
+    fn recover_published(&self) -> Result<(), ConfigError> {
+        let dot_path = self.working_dir.join(DEFAULT_LOCAL_DOT_DIR);
+        let journal_path = dot_path.join(PUBLISH_JOURNAL_NAME);
+
+        if !journal_path.exists() {
+            return Ok(());
+        }
+
+        let journal_str = std::fs::read_to_string(&journal_path)?;
+        let journal: PublishJournal = serde_json::from_str(&journal_str)?;
+        tracing::warn!(
+            "finishing saving published root {}, which was cut short",
+            journal.root_cid
+        );
+        self.apply_published(journal)?;
+        std::fs::remove_file(journal_path)?;
+
+        Ok(())
+    }
+
+    /// Write out each part of a published root's state -- safe to repeat
+    fn apply_published(&self, journal: PublishJournal) -> Result<(), ConfigError> {
+        self.set_root_cid(&journal.root_cid)?;
+        self.set_base(&journal.base)?;
+        self.set_change_log(journal.change_log)?;
+        if journal.pushed {
+            self.clear_push_state()?;
+        }
+        Ok(())
+    }
+
+    /// Get the cached hashes of the working dir, empty if there are none
+    pub fn hash_cache(&self) -> Result<HashCache, ConfigError> {
+        let dot_path = self.working_dir.join(DEFAULT_LOCAL_DOT_DIR);
+        let hash_cache_path = dot_path.join(HASH_CACHE_NAME);
+
+        if !hash_cache_path.exists() {
+            return Ok(HashCache::default());
+        }
+
+        let hash_cache_str = std::fs::read_to_string(hash_cache_path)?;
+        let hash_cache = serde_json::from_str(&hash_cache_str)?;
+        Ok(hash_cache)
+    }
+
+    pub fn set_hash_cache(&self, hash_cache: &HashCache) -> Result<(), ConfigError> {
+        let dot_path = self.working_dir.join(DEFAULT_LOCAL_DOT_DIR);
+        let hash_cache_path = dot_path.join(HASH_CACHE_NAME);
+
+        let hash_cache_str = serde_json::to_string(hash_cache)?;
+        write_atomic(&hash_cache_path, hash_cache_str.as_bytes())?;
 
         Ok(())
     }
 
     pub fn root_cid(&self) -> Result<Cid, ConfigError> {
         let device_alias = self.device_alias.clone().ok_or(ConfigError::NoSetDevice)?;
+        if !self.has_root_state(&device_alias)? {
+            return Ok(Cid::default());
+        }
         let cid = OnDiskDevice::root_cid(device_alias)?;
         Ok(cid)
     }
@@ -186,24 +497,207 @@ impl Config {
 
     pub fn base(&self) -> Result<DorStore, ConfigError> {
         let device_alias = self.device_alias.clone().ok_or(ConfigError::NoSetDevice)?;
+        if !self.has_root_state(&device_alias)? {
+            return Ok(DorStore::default());
+        }
         let base = OnDiskDevice::base(device_alias)?;
         Ok(base)
     }
 
+    /// The base dor store as it's stored on disk, for checking it before it's parsed
+    pub fn raw_base(&self) -> Result<String, ConfigError> {
+        let device_alias = self.device_alias.clone().ok_or(ConfigError::NoSetDevice)?;
+        if !self.has_root_state(&device_alias)? {
+            return Ok(serde_json::to_string(&DorStore::default())?);
+        }
+        OnDiskDevice::raw_base(device_alias)
+    }
+
+    /// A device config handed to us in memory starts out at the default root, until
+    /// its first commit writes root state under its alias
+    fn has_root_state(&self, device_alias: &str) -> Result<bool, ConfigError> {
+        if self.device_config.is_none() {
+            return Ok(true);
+        }
+        OnDiskDevice::has_root_cid(device_alias.to_string())
+    }
+
     pub fn set_base(&self, base: &DorStore) -> Result<(), ConfigError> {
         let device_alias = self.device_alias.clone().ok_or(ConfigError::NoSetDevice)?;
         OnDiskDevice::set_base(device_alias, base)
     }
 
-    pub fn device(&self) -> Result<Device, ConfigError> {
+    pub fn tags(&self) -> Result<Tags, ConfigError> {
         let device_alias = self.device_alias.clone().ok_or(ConfigError::NoSetDevice)?;
-        let device_config = OnDiskDevice::load(device_alias)?;
-        let device = Device::try_from(device_config).unwrap();
+        OnDiskDevice::tags(device_alias)
+    }
+
+    pub fn set_tags(&self, tags: &Tags) -> Result<(), ConfigError> {
+        let device_alias = self.device_alias.clone().ok_or(ConfigError::NoSetDevice)?;
+        OnDiskDevice::set_tags(device_alias, tags)
+    }
+
+    /// Resolve a root argument to its cid, looking tags up against the device's
+    pub fn resolve_root(&self, root: &RootArg) -> Result<Cid, ConfigError> {
+        match root {
+            RootArg::Cid(cid) => Ok(*cid),
+            RootArg::Tag(name) => self
+                .tags()?
+                .get(name)
+                .map(|tag| *tag.root())
+                .ok_or_else(|| ConfigError::TagNotFound(name.clone())),
+        }
+    }
+
+    pub fn update_on_disk_device(
+        alias: String,
+        ipfs_remote: Option<IpfsRemote>,
+        eth_remote: Option<EthRemote>,
+        cache_policy: Option<CachePolicy>,
+        root_layout: Option<RootLayout>,
+        manifest_compression: Option<ManifestCompression>,
+        push_guardrail: Option<PushGuardrail>,
+    ) -> Result<OnDiskDevice, ConfigError> {
+        let device = OnDiskDevice::update(
+            alias,
+            ipfs_remote,
+            eth_remote,
+            cache_policy,
+            root_layout,
+            manifest_compression,
+            push_guardrail,
+        )?;
+        Ok(device)
+    }
+
+    pub fn set_root_fallback(
+        alias: String,
+        root_fallback: Option<RootFallback>,
+    ) -> Result<OnDiskDevice, ConfigError> {
+        let device = OnDiskDevice::set_root_fallback(alias, root_fallback)?;
+        Ok(device)
+    }
+
+    pub fn set_symlink_policy(
+        alias: String,
+        symlink_policy: SymlinkPolicy,
+    ) -> Result<OnDiskDevice, ConfigError> {
+        let device = OnDiskDevice::set_symlink_policy(alias, symlink_policy)?;
+        Ok(device)
+    }
+
+    pub fn set_replication(
+        alias: String,
+        replication: Replication,
+    ) -> Result<OnDiskDevice, ConfigError> {
+        let device = OnDiskDevice::set_replication(alias, replication)?;
+        Ok(device)
+    }
+
+    pub fn set_max_object_size(
+        alias: String,
+        max_object_size: Option<u64>,
+    ) -> Result<OnDiskDevice, ConfigError> {
+        let device = OnDiskDevice::set_max_object_size(alias, max_object_size)?;
+        Ok(device)
+    }
+
+    pub fn set_record_mtimes(
+        alias: String,
+        record_mtimes: bool,
+    ) -> Result<OnDiskDevice, ConfigError> {
+        let device = OnDiskDevice::set_record_mtimes(alias, record_mtimes)?;
+        Ok(device)
+    }
+
+    pub fn set_delta_manifests(
+        alias: String,
+        delta_manifests: bool,
+    ) -> Result<OnDiskDevice, ConfigError> {
+        let device = OnDiskDevice::set_delta_manifests(alias, delta_manifests)?;
+        Ok(device)
+    }
+
+    pub fn set_previous_root_check(
+        alias: String,
+        previous_root_check: PreviousRootCheck,
+    ) -> Result<OnDiskDevice, ConfigError> {
+        let device = OnDiskDevice::set_previous_root_check(alias, previous_root_check)?;
+        Ok(device)
+    }
+
+    pub fn set_audit_log(
+        alias: String,
+        audit_log: Option<PathBuf>,
+    ) -> Result<OnDiskDevice, ConfigError> {
+        let device = OnDiskDevice::set_audit_log(alias, audit_log)?;
+        Ok(device)
+    }
+
+    pub fn set_eth_mirrors(
+        alias: String,
+        eth_mirrors: Vec<EthRemote>,
+        root_reconcile: RootReconcile,
+    ) -> Result<OnDiskDevice, ConfigError> {
+        let device = OnDiskDevice::set_eth_mirrors(alias, eth_mirrors, root_reconcile)?;
+        Ok(device)
+    }
+
+    pub fn set_s3_mirror(
+        alias: String,
+        s3_mirror: Option<S3Remote>,
+    ) -> Result<OnDiskDevice, ConfigError> {
+        let device = OnDiskDevice::set_s3_mirror(alias, s3_mirror)?;
+        Ok(device)
+    }
+
+    pub fn record_root_migration(
+        alias: String,
+        migration: RootMigration,
+        switch_to: Option<EthRemote>,
+    ) -> Result<OnDiskDevice, ConfigError> {
+        let device = OnDiskDevice::record_root_migration(alias, migration, switch_to)?;
+        Ok(device)
+    }
+
+    pub fn device(&self) -> Result<Device, ConfigError> {
+        let device_config = self.on_disk_device()?;
+        let mut device = match Device::try_from(device_config) {
+            Ok(device) => device,
+            // Surface config problems, like a missing keyring entry, as they are
+            Err(OnDiskDeviceError::Config(e)) => return Err(e),
+            Err(e) => return Err(ConfigError::LoadDevice(Box::new(e))),
+        };
+        if let Some(blocks) = &self.blocks {
+            device = device.with_block_source(Some(open_block_source(blocks)?));
+        }
+        if self.from_s3 {
+            #[cfg(feature = "s3")]
+            {
+                device = device.with_block_source(Some(Box::new(self.s3_store()?)));
+            }
+            #[cfg(not(feature = "s3"))]
+            return Err(ConfigError::S3Disabled);
+        }
+        if let Some(block_verifier) = &self.block_verifier {
+            device = device.with_block_verifier(block_verifier.clone());
+        }
+        if self.on_disk_device()?.cache_policy().enabled {
+            device = device.with_cache(Some(self.content_cache()?));
+        }
+        // The root we last pulled or pushed is usually the one the next delta goes
+        // on top of, so hand over its manifest rather than have it read back
+        if self.on_disk_device()?.delta_manifests() {
+            device = device.with_delta_manifests(true);
+            if let (Ok(root_cid), Ok(base)) = (self.root_cid(), self.base()) {
+                device = device.with_delta_base(Some((root_cid, base)));
+            }
+        }
         match self.admin_key_string.clone() {
             Some(admin_key_string) => {
                 let admin_key = admin_key_string
                     .parse::<LocalWallet>()
-                    .expect("invalid key");
+                    .map_err(|_| ConfigError::InvalidAdminKey)?;
                 let device = device.with_wallet(admin_key);
                 Ok(device)
             }
@@ -211,6 +705,24 @@ impl Config {
         }
     }
 
+    /// Open the device's S3 mirror
+    #[cfg(feature = "s3")]
+    pub fn s3_store(&self) -> Result<S3Store, ConfigError> {
+        let on_disk_device = self.on_disk_device()?;
+        let s3_remote = on_disk_device
+            .s3_mirror()
+            .ok_or(ConfigError::MissingS3Mirror)?;
+        Ok(S3Store::new(s3_remote)?)
+    }
+
+    /// Open the device's on disk content cache
+    pub fn content_cache(&self) -> Result<ContentCache, ConfigError> {
+        let on_disk_device = self.on_disk_device()?;
+        let cache_path = OnDiskDevice::cache_path(on_disk_device.alias())?;
+        let cache = ContentCache::open(&cache_path, *on_disk_device.cache_policy())?;
+        Ok(cache)
+    }
+
     pub fn device_alias(&self) -> Option<String> {
         self.device_alias.clone()
     }
@@ -220,13 +732,59 @@ impl Config {
         on_disk_config.set_device_alias(alias)
     }
 
+    /// Load the on disk device, applying any overrides
     pub fn on_disk_device(&self) -> Result<OnDiskDevice, ConfigError> {
         let device_alias = self.device_alias.clone().ok_or(ConfigError::NoSetDevice)?;
-        let device_config = OnDiskDevice::load(device_alias)?;
+        let mut device_config = match &self.device_config {
+            Some(device_config) => device_config.clone(),
+            None => OnDiskDevice::load(device_alias)?,
+        };
+        if let Some(ipfs_remote) = &self.ipfs_remote {
+            device_config = device_config.with_ipfs_remote(ipfs_remote.clone());
+        }
+        if let Some(eth_remote) = &self.eth_remote {
+            device_config = device_config.with_eth_remote(eth_remote.clone());
+        }
+        if let Some(wallet_source) = &self.wallet_source {
+            device_config = device_config.with_wallet_source(wallet_source.clone());
+        }
+        if let Some(contract_address) = self.contract_address {
+            device_config = device_config.with_contract_address(contract_address);
+        }
         Ok(device_config)
     }
 }
 
+/// Everything saving a published or pulled root writes, in one place
+#[derive(Serialize, Deserialize)]
+struct PublishJournal {
+    #[serde(with = "crate::types::cid_serde")]
+    root_cid: Cid,
+    base: DorStore,
+    change_log: ChangeLog,
+    /// Whether the root was published by a push of ours, whose state goes with it
+    /// Journals from before pulls were saved this way are all pushes
+    #[serde(default = "journaled_push")]
+    pushed: bool,
+}
+
+fn journaled_push() -> bool {
+    true
+}
+
+/// Write a file so readers only ever see the old or the new contents, never a
+/// partial write -- by writing alongside it and renaming into place
+fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), ConfigError> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    let mut tmp_file = std::fs::File::create(&tmp_path)?;
+    tmp_file.write_all(contents)?;
+    tmp_file.sync_all()?;
+    std::fs::rename(tmp_path, path)?;
+    Ok(())
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum ConfigError {
     #[error("failed to create directory: {0}")]
@@ -239,14 +797,57 @@ pub enum ConfigError {
     DotDirNotADirectory,
     #[error("device alias not found: {0}")]
     DeviceNotFound(String),
+    #[error("failed to load device: {0}")]
+    LoadDevice(Box<OnDiskDeviceError>),
     // #[error("device exists: {0}")]
     // DeviceExists(String),
     #[error("change log not found")]
     ChangeLogNotFound,
+    #[error("root target requires an ipns remote")]
+    MissingIpnsRemote,
+    #[error("devices kept in memory have no keystore -- sign from the keyring instead")]
+    InMemoryKeystore,
+    #[error("invalid config version: {0}")]
+    InvalidConfigVersion(String),
+    #[error("config version {0} is newer than this version of dor-store supports")]
+    UnsupportedConfigVersion(u64),
+    #[error("another operation is in progress in {0:?} -- wait for it to finish")]
+    Locked(PathBuf),
     #[error("cid error: {0}")]
     Cid(#[from] cid::Error),
+    #[error("block source error: {0}")]
+    BlockSource(#[from] BlockSourceError),
+    #[error("cache error: {0}")]
+    Cache(#[from] CacheError),
+    #[error("keyring error: {0}")]
+    Keyring(#[from] keyring::Error),
+    #[error("no keyring entry for service {0}, account {1}")]
+    MissingKeyringEntry(String, String),
+    #[error("keyring entry for service {0}, account {1} isn't a valid private key")]
+    InvalidKeyringEntry(String, String),
     #[error("fs tree error: {0}")]
     FsTree(#[from] fs_tree::Error),
+    #[error("admin key isn't a valid private key")]
+    InvalidAdminKey,
+    #[error("no tag named {0:?}, and it isn't a valid cid either")]
+    TagNotFound(String),
+    #[error("config file {0:?} not found")]
+    ConfigSourceNotFound(PathBuf),
+    #[error("failed to fetch config from {url}: {source}")]
+    ConfigFetch {
+        url: url::Url,
+        source: reqwest::Error,
+    },
+    #[error("failed to fetch config from {url}: server responded {status}")]
+    ConfigFetchStatus { url: url::Url, status: u16 },
+    #[error("device has no s3 mirror configured")]
+    MissingS3Mirror,
+    #[cfg(feature = "s3")]
+    #[error("s3 error: {0}")]
+    S3(#[from] S3StoreError),
+    #[cfg(not(feature = "s3"))]
+    #[error("s3 mirrors need dor-store built with the `s3` feature")]
+    S3Disabled,
 }
 
 /// Grab config path
@@ -1,40 +1,172 @@
 use std::convert::TryFrom;
 use std::fmt::Display;
 use std::str::FromStr;
-use std::{
-    fs::{create_dir_all, File},
-    io::Write,
-    path::PathBuf,
-};
+use std::{fs::create_dir_all, path::PathBuf};
 
 use cid::Cid;
 use ethers::signers::{LocalWallet, Wallet};
 use ethers::types::Address;
 use serde::{Deserialize, Serialize};
 
+use crate::cli::hooks::HookConfig;
+use crate::cli::walk::SymlinkPolicy;
 use crate::device::{
-    Device, EthClient, EthClientError, EthRemote, IpfsClient, IpfsError, IpfsGateway, IpfsRemote,
+    CachePolicy, Device, EthClient, EthClientError, EthRemote, FileRootStore, IpfsClient,
+    IpfsError, IpfsGateway, IpfsRemote, IpnsRemote, IpnsRootStore, ManifestCompression,
+    RootCidError, RootCidStore, RootFallback, RootLayout, RootMigration, RootReconcile, RootTarget,
+    S3Remote,
 };
-use crate::types::DorStore;
+use crate::types::{DorStore, Replication, Tags};
 
+use super::migrate::{migrate_device_config, DEVICE_CONFIG_VERSION};
 use super::{
-    xdg_config_home, ConfigError, BASE_DOR_STORE_NAME, DEVICE_CONFIG_NAME, DEVICE_KEYSTORE_NAME,
-    ROOT_CID_NAME,
+    write_atomic, xdg_config_home, ConfigError, BASE_DOR_STORE_NAME, CACHE_DIR_NAME,
+    DEVICE_CONFIG_NAME, DEVICE_KEYSTORE_NAME, ROOT_CID_NAME, TAGS_NAME,
 };
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 /// An OnDiskDevice Configuration
 /// Specifies both connection to remote, and api for managing device state via an alias
 pub struct OnDiskDevice {
+    /// Version of the config schema, see migrate.rs
+    #[serde(default)]
+    version: u64,
+
     alias: String,
 
     // Remote configuration
-    /// Address for the contract publishing our root cid
-    contract_address: Address,
     /// Connection to an EthRemote
     eth_remote: EthRemote,
     /// Connection to an IpfsRemote
     ipfs_remote: IpfsRemote,
+    /// Optional IPNS key on the IpfsRemote to publish our root cid against
+    #[serde(default)]
+    ipns_remote: Option<IpnsRemote>,
+    /// Which RootCidStore(s) to read and publish our root cid against
+    #[serde(default)]
+    root_target: RootTarget,
+    /// Other EthRemotes mirroring our RootCid contract, read alongside it on pull and
+    /// brought up to the roots we push
+    #[serde(default)]
+    eth_mirrors: Vec<EthRemote>,
+    /// How to pick a root when our mirrors disagree
+    #[serde(default)]
+    root_reconcile: RootReconcile,
+    /// Where to read our root cid from when the eth remote can't be read
+    #[serde(default)]
+    root_fallback: Option<RootFallback>,
+    /// Where to read the device's signing key from
+    #[serde(default)]
+    wallet_source: WalletSource,
+    /// When to cache and evict objects read from the gateway
+    #[serde(default)]
+    cache_policy: CachePolicy,
+    /// Commands to run around pushes
+    #[serde(default)]
+    hooks: HookConfig,
+    /// What the roots we publish point at
+    #[serde(default)]
+    root_layout: RootLayout,
+    /// How the manifests we publish are encoded
+    #[serde(default)]
+    manifest_compression: ManifestCompression,
+    /// How big a push can get before we ask about it
+    #[serde(default)]
+    push_guardrail: PushGuardrail,
+    /// What to do with symlinks in the working dir
+    #[serde(default)]
+    symlink_policy: SymlinkPolicy,
+    /// Biggest new file we'll stage without `--allow-large`, in bytes
+    #[serde(default)]
+    max_object_size: Option<u64>,
+    /// Record file mtimes in the manifest, to restore on pull and export
+    /// Off by default: mtimes make root cids depend on when files were written
+    #[serde(default)]
+    record_mtimes: bool,
+    /// Write manifests as deltas on top of the previous root's, rather than whole
+    /// Off by default: every reader then walks the chain back to a whole manifest,
+    /// until it's squashed
+    #[serde(default)]
+    delta_manifests: bool,
+    /// What to do when a push finds its previous root's manifest can't be fetched
+    #[serde(default)]
+    previous_root_check: PreviousRootCheck,
+    /// Append a record of every on chain root update to this JSON lines file
+    #[serde(default)]
+    audit_log: Option<PathBuf>,
+    /// Roots carried over to new RootCid contracts, oldest first
+    #[serde(default)]
+    root_migrations: Vec<RootMigration>,
+    /// Optional S3 compatible bucket to mirror pushed blocks and roots to
+    #[serde(default)]
+    s3_mirror: Option<S3Remote>,
+    /// Which replicas pushes copy objects to, unless they have a policy of their own
+    #[serde(default)]
+    replication: Replication,
+}
+
+/// Limits on what a single push adds, past which it needs confirming or `--force`
+/// Catches mistakes like committing a build directory before we pin it and pay for the root
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PushGuardrail {
+    /// Most new or changed objects a push can add
+    #[serde(default)]
+    pub max_added_objects: Option<usize>,
+    /// Most bytes of new or changed objects a push can add
+    #[serde(default)]
+    pub max_added_bytes: Option<u64>,
+}
+
+impl PushGuardrail {
+    /// Whether a push adding this much goes over our limits
+    pub fn exceeded(&self, added_objects: usize, added_bytes: u64) -> bool {
+        self.max_added_objects
+            .map(|max| added_objects > max)
+            .unwrap_or(false)
+            || self
+                .max_added_bytes
+                .map(|max| added_bytes > max)
+                .unwrap_or(false)
+    }
+}
+
+/// What a push does if the root it's replacing can't be fetched from the remote
+/// A new root links back to the one it replaces, so an unpinned previous root
+/// breaks the history chain behind it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum PreviousRootCheck {
+    /// Don't check
+    Off,
+    /// Warn, and push anyway
+    #[default]
+    Warn,
+    /// Refuse to push, unless it's run with `--allow-orphan-previous`
+    Error,
+}
+
+/// Where a device's signing key lives
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WalletSource {
+    /// A keystore alongside the device config
+    #[default]
+    Keystore,
+    /// An entry in the OS keyring (Keychain, Secret Service, Credential Manager)
+    /// holding the hex encoded private key. Read when the device is loaded, and
+    /// never written to disk
+    Keyring { service: String, account: String },
+}
+
+impl Display for WalletSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WalletSource::Keystore => write!(f, "keystore"),
+            WalletSource::Keyring { service, account } => {
+                write!(f, "keyring {}/{}", service, account)
+            }
+        }
+    }
 }
 
 impl OnDiskDevice {
@@ -43,8 +175,28 @@ impl OnDiskDevice {
         alias: String,
         ipfs_remote: IpfsRemote,
         eth_remote: EthRemote,
-        contract_address: Address,
+        ipns_remote: Option<IpnsRemote>,
+        root_target: RootTarget,
+        wallet_source: WalletSource,
     ) -> Result<Self, ConfigError> {
+        if root_target != RootTarget::Eth && ipns_remote.is_none() {
+            return Err(ConfigError::MissingIpnsRemote);
+        }
+
+        // Don't create a device that can't sign
+        if let WalletSource::Keyring { service, account } = &wallet_source {
+            keyring_wallet(service, account)?;
+        }
+
+        let config = Self::fresh(
+            alias.clone(),
+            ipfs_remote,
+            eth_remote,
+            ipns_remote,
+            root_target,
+            wallet_source,
+        );
+
         let mut rng = rand::thread_rng();
         let xdg_path = xdg_config_home()?;
         let device_path = xdg_path.join(alias.clone());
@@ -57,15 +209,11 @@ impl OnDiskDevice {
 
         create_dir_all(&device_path)?;
 
-        let _wallet =
-            Wallet::new_keystore(&device_path, &mut rng, "", Some(DEVICE_KEYSTORE_NAME)).unwrap();
-
-        let config = Self {
-            alias: alias.clone(),
-            contract_address,
-            eth_remote,
-            ipfs_remote,
-        };
+        if config.wallet_source == WalletSource::Keystore {
+            let _wallet =
+                Wallet::new_keystore(&device_path, &mut rng, "", Some(DEVICE_KEYSTORE_NAME))
+                    .unwrap();
+        }
 
         let cid = Cid::default();
         let base = DorStore::default();
@@ -78,7 +226,72 @@ impl OnDiskDevice {
         Ok(config)
     }
 
+    /// Create a device config without writing anything to disk, i.e. for a service
+    /// embedding dor-store to hand to ConfigBuilder::with_device_config
+    /// There's no keystore to hold its key, so it has to sign from the keyring
+    pub fn in_memory(
+        alias: String,
+        ipfs_remote: IpfsRemote,
+        eth_remote: EthRemote,
+        ipns_remote: Option<IpnsRemote>,
+        root_target: RootTarget,
+        wallet_source: WalletSource,
+    ) -> Result<Self, ConfigError> {
+        if root_target != RootTarget::Eth && ipns_remote.is_none() {
+            return Err(ConfigError::MissingIpnsRemote);
+        }
+        match &wallet_source {
+            WalletSource::Keystore => return Err(ConfigError::InMemoryKeystore),
+            WalletSource::Keyring { service, account } => keyring_wallet(service, account)?,
+        };
+        Ok(Self::fresh(
+            alias,
+            ipfs_remote,
+            eth_remote,
+            ipns_remote,
+            root_target,
+            wallet_source,
+        ))
+    }
+
+    fn fresh(
+        alias: String,
+        ipfs_remote: IpfsRemote,
+        eth_remote: EthRemote,
+        ipns_remote: Option<IpnsRemote>,
+        root_target: RootTarget,
+        wallet_source: WalletSource,
+    ) -> Self {
+        Self {
+            version: DEVICE_CONFIG_VERSION,
+            alias,
+            eth_remote,
+            ipfs_remote,
+            ipns_remote,
+            root_target,
+            wallet_source,
+            cache_policy: CachePolicy::default(),
+            hooks: HookConfig::default(),
+            eth_mirrors: Vec::new(),
+            root_reconcile: RootReconcile::default(),
+            root_fallback: None,
+            root_layout: RootLayout::default(),
+            manifest_compression: ManifestCompression::default(),
+            push_guardrail: PushGuardrail::default(),
+            symlink_policy: SymlinkPolicy::default(),
+            max_object_size: None,
+            record_mtimes: false,
+            delta_manifests: false,
+            previous_root_check: PreviousRootCheck::default(),
+            audit_log: None,
+            root_migrations: Vec::new(),
+            s3_mirror: None,
+            replication: Replication::default(),
+        }
+    }
+
     /// Return the on disk device
+    /// Configs written by older versions are migrated, and saved back in the current shape
     pub fn load(alias: String) -> Result<Self, ConfigError> {
         let device_path = device_path(alias.clone())?;
         let config_path = device_path.join(DEVICE_CONFIG_NAME);
@@ -86,15 +299,156 @@ impl OnDiskDevice {
             return Err(ConfigError::DeviceNotFound(alias.clone()));
         }
         let config = std::fs::read_to_string(config_path)?;
-        let config: Self = serde_json::from_str(&config)?;
+        let (config, migrated) = Self::parse(&config)?;
+        if migrated {
+            tracing::info!(
+                "migrated device config {} to version {}",
+                alias,
+                config.version
+            );
+            config.save(alias)?;
+        }
         Ok(config)
     }
 
+    /// Read a device config from json in the on disk format, i.e. from a config source
+    /// Older versions are migrated in memory, but not saved back anywhere
+    pub fn from_json(config: &str) -> Result<Self, ConfigError> {
+        let (config, migrated) = Self::parse(config)?;
+        if migrated {
+            tracing::info!(
+                "migrated device config {} to version {} -- update its source to skip this",
+                config.alias,
+                config.version
+            );
+        }
+        Ok(config)
+    }
+
+    fn parse(config: &str) -> Result<(Self, bool), ConfigError> {
+        let (config, migrated) = migrate_device_config(serde_json::from_str(config)?)?;
+        Ok((serde_json::from_value(config)?, migrated))
+    }
+
     /// alias
     pub fn alias(&self) -> String {
         self.alias.clone()
     }
 
+    /// Connection to the EthRemote
+    pub fn eth_remote(&self) -> &EthRemote {
+        &self.eth_remote
+    }
+
+    /// Which RootCidStore(s) the root is published against
+    pub fn root_target(&self) -> RootTarget {
+        self.root_target
+    }
+
+    /// Connection to the IpfsRemote
+    pub fn ipfs_remote(&self) -> &IpfsRemote {
+        &self.ipfs_remote
+    }
+
+    /// When to cache and evict objects read from the gateway
+    pub fn cache_policy(&self) -> &CachePolicy {
+        &self.cache_policy
+    }
+
+    /// Commands to run around pushes
+    pub fn hooks(&self) -> &HookConfig {
+        &self.hooks
+    }
+
+    /// How big a push can get before we ask about it
+    pub fn push_guardrail(&self) -> &PushGuardrail {
+        &self.push_guardrail
+    }
+
+    /// What to do with symlinks in the working dir
+    pub fn symlink_policy(&self) -> SymlinkPolicy {
+        self.symlink_policy
+    }
+
+    /// Biggest new file we'll stage without `--allow-large`, in bytes
+    pub fn max_object_size(&self) -> Option<u64> {
+        self.max_object_size
+    }
+
+    /// What a push does if its previous root can't be fetched
+    pub fn previous_root_check(&self) -> PreviousRootCheck {
+        self.previous_root_check
+    }
+
+    /// Where to record on chain root updates, if anywhere
+    pub fn audit_log(&self) -> Option<&PathBuf> {
+        self.audit_log.as_ref()
+    }
+
+    /// Whether to record file mtimes in the manifest
+    pub fn record_mtimes(&self) -> bool {
+        self.record_mtimes
+    }
+
+    /// Whether to write manifests as deltas on top of the previous root's
+    pub fn delta_manifests(&self) -> bool {
+        self.delta_manifests
+    }
+
+    /// Other contracts mirroring our RootCid contract
+    pub fn eth_mirrors(&self) -> &[EthRemote] {
+        &self.eth_mirrors
+    }
+
+    /// How to pick a root when our mirrors disagree
+    pub fn root_reconcile(&self) -> RootReconcile {
+        self.root_reconcile
+    }
+
+    /// Bucket to mirror pushed blocks and roots to, if any
+    pub fn s3_mirror(&self) -> Option<&S3Remote> {
+        self.s3_mirror.as_ref()
+    }
+
+    /// What the roots we publish point at
+    pub fn root_layout(&self) -> RootLayout {
+        self.root_layout
+    }
+
+    /// How the manifests we publish are encoded
+    pub fn manifest_compression(&self) -> ManifestCompression {
+        self.manifest_compression
+    }
+
+    /// Which replicas pushes copy objects to, unless they have a policy of their own
+    pub fn replication(&self) -> &Replication {
+        &self.replication
+    }
+
+    /// Override the IpfsRemote, without saving it to disk
+    pub fn with_ipfs_remote(mut self, ipfs_remote: IpfsRemote) -> Self {
+        self.ipfs_remote = ipfs_remote;
+        self
+    }
+
+    /// Override the EthRemote, without saving it to disk
+    pub fn with_eth_remote(mut self, eth_remote: EthRemote) -> Self {
+        self.eth_remote = eth_remote;
+        self
+    }
+
+    /// Override where the signing key is read from, without saving it to disk
+    pub fn with_wallet_source(mut self, wallet_source: WalletSource) -> Self {
+        self.wallet_source = wallet_source;
+        self
+    }
+
+    /// Override the contract address, without saving it to disk
+    pub fn with_contract_address(mut self, contract_address: Address) -> Self {
+        self.eth_remote.contract_address = contract_address;
+        self
+    }
+
     /// Return a list of all on disk devices
     pub fn list() -> Result<Vec<Self>, ConfigError> {
         let xdg_path = xdg_config_home()?;
@@ -113,11 +467,14 @@ impl OnDiskDevice {
     }
 
     /// Update the on disk device configuration
-    pub fn _update(
+    pub fn update(
         alias: String,
         ipfs_remote: Option<IpfsRemote>,
         eth_remote: Option<EthRemote>,
-        contract_address: Option<Address>,
+        cache_policy: Option<CachePolicy>,
+        root_layout: Option<RootLayout>,
+        manifest_compression: Option<ManifestCompression>,
+        push_guardrail: Option<PushGuardrail>,
     ) -> Result<Self, ConfigError> {
         let mut device = Self::load(alias.clone())?;
 
@@ -127,14 +484,141 @@ impl OnDiskDevice {
         if let Some(eth_remote) = eth_remote {
             device.eth_remote = eth_remote;
         }
-        if let Some(contract_address) = contract_address {
-            device.contract_address = contract_address;
+        if let Some(cache_policy) = cache_policy {
+            device.cache_policy = cache_policy;
+        }
+        if let Some(root_layout) = root_layout {
+            device.root_layout = root_layout;
+        }
+        if let Some(manifest_compression) = manifest_compression {
+            device.manifest_compression = manifest_compression;
+        }
+        if let Some(push_guardrail) = push_guardrail {
+            device.push_guardrail = push_guardrail;
         }
 
         device.save(alias.clone())?;
         Ok(device)
     }
 
+    /// Set where to read our root cid from when the eth remote can't be read
+    pub fn set_root_fallback(
+        alias: String,
+        root_fallback: Option<RootFallback>,
+    ) -> Result<Self, ConfigError> {
+        let mut device = Self::load(alias.clone())?;
+        if root_fallback == Some(RootFallback::Ipns) && device.ipns_remote.is_none() {
+            return Err(ConfigError::MissingIpnsRemote);
+        }
+        device.root_fallback = root_fallback;
+        device.save(alias)?;
+        Ok(device)
+    }
+
+    /// Set what to do with symlinks in the working dir
+    pub fn set_symlink_policy(
+        alias: String,
+        symlink_policy: SymlinkPolicy,
+    ) -> Result<Self, ConfigError> {
+        let mut device = Self::load(alias.clone())?;
+        device.symlink_policy = symlink_policy;
+        device.save(alias)?;
+        Ok(device)
+    }
+
+    /// Set which replicas pushes copy objects without a policy of their own to
+    pub fn set_replication(alias: String, replication: Replication) -> Result<Self, ConfigError> {
+        let mut device = Self::load(alias.clone())?;
+        device.replication = replication;
+        device.save(alias)?;
+        Ok(device)
+    }
+
+    /// Set the biggest new file we'll stage without `--allow-large`
+    pub fn set_max_object_size(
+        alias: String,
+        max_object_size: Option<u64>,
+    ) -> Result<Self, ConfigError> {
+        let mut device = Self::load(alias.clone())?;
+        device.max_object_size = max_object_size;
+        device.save(alias)?;
+        Ok(device)
+    }
+
+    /// Set whether to record file mtimes in the manifest
+    pub fn set_record_mtimes(alias: String, record_mtimes: bool) -> Result<Self, ConfigError> {
+        let mut device = Self::load(alias.clone())?;
+        device.record_mtimes = record_mtimes;
+        device.save(alias)?;
+        Ok(device)
+    }
+
+    /// Set whether to write manifests as deltas on top of the previous root's
+    pub fn set_delta_manifests(alias: String, delta_manifests: bool) -> Result<Self, ConfigError> {
+        let mut device = Self::load(alias.clone())?;
+        device.delta_manifests = delta_manifests;
+        device.save(alias)?;
+        Ok(device)
+    }
+
+    /// Set what a push does if its previous root can't be fetched
+    pub fn set_previous_root_check(
+        alias: String,
+        previous_root_check: PreviousRootCheck,
+    ) -> Result<Self, ConfigError> {
+        let mut device = Self::load(alias.clone())?;
+        device.previous_root_check = previous_root_check;
+        device.save(alias)?;
+        Ok(device)
+    }
+
+    /// Set where to record on chain root updates, or stop recording them
+    pub fn set_audit_log(alias: String, audit_log: Option<PathBuf>) -> Result<Self, ConfigError> {
+        let mut device = Self::load(alias.clone())?;
+        device.audit_log = audit_log;
+        device.save(alias)?;
+        Ok(device)
+    }
+
+    /// Set the other contracts mirroring our RootCid contract, and how to pick a
+    /// root when they disagree with it
+    pub fn set_eth_mirrors(
+        alias: String,
+        eth_mirrors: Vec<EthRemote>,
+        root_reconcile: RootReconcile,
+    ) -> Result<Self, ConfigError> {
+        let mut device = Self::load(alias.clone())?;
+        device.eth_mirrors = eth_mirrors;
+        device.root_reconcile = root_reconcile;
+        device.save(alias)?;
+        Ok(device)
+    }
+
+    /// Set the bucket to mirror pushed blocks and roots to, or stop mirroring
+    pub fn set_s3_mirror(alias: String, s3_mirror: Option<S3Remote>) -> Result<Self, ConfigError> {
+        let mut device = Self::load(alias.clone())?;
+        device.s3_mirror = s3_mirror;
+        device.save(alias)?;
+        Ok(device)
+    }
+
+    /// Record a root migration against the on disk device
+    /// # Args
+    /// - switch_to: point the device at this remote from now on, i.e. the new contract's
+    pub fn record_root_migration(
+        alias: String,
+        migration: RootMigration,
+        switch_to: Option<EthRemote>,
+    ) -> Result<Self, ConfigError> {
+        let mut device = Self::load(alias.clone())?;
+        device.root_migrations.push(migration);
+        if let Some(eth_remote) = switch_to {
+            device.eth_remote = eth_remote;
+        }
+        device.save(alias)?;
+        Ok(device)
+    }
+
     /// Read the keystore from disk for the device
     pub fn keystore(alias: String) -> Result<LocalWallet, ConfigError> {
         let device_path = device_path(alias.clone())?;
@@ -143,6 +627,20 @@ impl OnDiskDevice {
         Ok(wallet)
     }
 
+    /// Read the device's signing key from wherever its wallet source points
+    pub fn wallet(&self) -> Result<LocalWallet, ConfigError> {
+        match &self.wallet_source {
+            WalletSource::Keystore => Self::keystore(self.alias.clone()),
+            WalletSource::Keyring { service, account } => keyring_wallet(service, account),
+        }
+    }
+
+    /// Path to the content cache for the device
+    pub fn cache_path(alias: String) -> Result<PathBuf, ConfigError> {
+        let device_path = device_path(alias)?;
+        Ok(device_path.join(CACHE_DIR_NAME))
+    }
+
     /// Read the root cid from disk for the device
     pub fn root_cid(alias: String) -> Result<Cid, ConfigError> {
         let device_path = device_path(alias.clone())?;
@@ -152,32 +650,66 @@ impl OnDiskDevice {
         Ok(root_cid)
     }
 
+    /// Whether the device has root state on disk yet -- in memory devices don't until
+    /// their first commit
+    pub fn has_root_cid(alias: String) -> Result<bool, ConfigError> {
+        let device_path = device_path(alias)?;
+        Ok(device_path.join(ROOT_CID_NAME).exists())
+    }
+
     /// Set the root cid on disk for the device
     pub fn set_root_cid(alias: String, cid: &Cid) -> Result<(), ConfigError> {
         let device_path = device_path(alias.clone())?;
+        create_dir_all(&device_path)?;
         let root_cid_path = device_path.join(ROOT_CID_NAME);
         let root_cid_str = cid.to_string();
-        let mut root_cid_file = File::create(root_cid_path)?;
-        root_cid_file.write_all(root_cid_str.as_bytes())?;
+        write_atomic(&root_cid_path, root_cid_str.as_bytes())?;
         Ok(())
     }
 
     /// Read the base dor store metadata from disk for the device
     pub fn base(alias: String) -> Result<DorStore, ConfigError> {
-        let device_path = device_path(alias.clone())?;
-        let base_path = device_path.join(BASE_DOR_STORE_NAME);
-        let base_str = std::fs::read_to_string(base_path)?;
+        let base_str = OnDiskDevice::raw_base(alias)?;
         let base = serde_json::from_str(&base_str)?;
         Ok(base)
     }
 
+    /// Read the base dor store metadata for the device as it's stored, without parsing it
+    pub fn raw_base(alias: String) -> Result<String, ConfigError> {
+        let device_path = device_path(alias.clone())?;
+        let base_path = device_path.join(BASE_DOR_STORE_NAME);
+        Ok(std::fs::read_to_string(base_path)?)
+    }
+
     /// Set the base dor store metadata for the device
     pub fn set_base(alias: String, base: &DorStore) -> Result<(), ConfigError> {
         let device_path = device_path(alias.clone())?;
+        create_dir_all(&device_path)?;
         let base_path = device_path.join(BASE_DOR_STORE_NAME);
         let base_str = serde_json::to_string(&base)?;
-        let mut base_file = File::create(base_path)?;
-        base_file.write_all(base_str.as_bytes())?;
+        write_atomic(&base_path, base_str.as_bytes())?;
+        Ok(())
+    }
+
+    /// Read the device's root tags from disk. A device that never tagged anything has none
+    pub fn tags(alias: String) -> Result<Tags, ConfigError> {
+        let device_path = device_path(alias.clone())?;
+        let tags_path = device_path.join(TAGS_NAME);
+        if !tags_path.exists() {
+            return Ok(Tags::default());
+        }
+        let tags_str = std::fs::read_to_string(tags_path)?;
+        let tags = serde_json::from_str(&tags_str)?;
+        Ok(tags)
+    }
+
+    /// Set the device's root tags
+    pub fn set_tags(alias: String, tags: &Tags) -> Result<(), ConfigError> {
+        let device_path = device_path(alias.clone())?;
+        create_dir_all(&device_path)?;
+        let tags_path = device_path.join(TAGS_NAME);
+        let tags_str = serde_json::to_string(&tags)?;
+        write_atomic(&tags_path, tags_str.as_bytes())?;
         Ok(())
     }
 
@@ -187,8 +719,7 @@ impl OnDiskDevice {
         let config_path = device_path.join(DEVICE_CONFIG_NAME);
 
         let config_json = serde_json::to_string(&self)?;
-        let mut config_file = File::create(config_path)?;
-        config_file.write_all(config_json.as_bytes())?;
+        write_atomic(&config_path, config_json.as_bytes())?;
         Ok(())
     }
 }
@@ -197,16 +728,35 @@ impl TryFrom<OnDiskDevice> for Device {
     type Error = OnDiskDeviceError;
 
     fn try_from(on_disk_device: OnDiskDevice) -> Result<Self, Self::Error> {
+        let wallet = on_disk_device.wallet()?;
+        let mut mirrors = Vec::new();
+        for eth_mirror in on_disk_device.eth_mirrors {
+            let contract_address = eth_mirror.contract_address;
+            mirrors.push((EthClient::try_from(eth_mirror)?, contract_address));
+        }
         let eth_remote = on_disk_device.eth_remote;
         let ipfs_remote = on_disk_device.ipfs_remote;
-        let contract_address = on_disk_device.contract_address;
-        let alias = on_disk_device.alias;
+        let contract_address = eth_remote.contract_address;
+        let root_target = on_disk_device.root_target;
 
         let eth = EthClient::try_from(eth_remote)?;
         let local_ipfs_client = IpfsClient::default();
         let ipfs_client = IpfsClient::try_from(ipfs_remote.clone())?;
+        let ipns = match on_disk_device.ipns_remote {
+            Some(ipns_remote) => Some(IpnsRootStore::new(ipfs_remote.clone(), ipns_remote)?),
+            None => None,
+        };
+        let fallback: Option<Box<dyn RootCidStore>> = match on_disk_device.root_fallback {
+            Some(RootFallback::Ipns) => {
+                let ipns = ipns
+                    .clone()
+                    .ok_or(OnDiskDeviceError::Config(ConfigError::MissingIpnsRemote))?;
+                Some(Box::new(ipns))
+            }
+            Some(RootFallback::File { path }) => Some(Box::new(FileRootStore::new(path))),
+            None => None,
+        };
         let ipfs_gateway = IpfsGateway::from(ipfs_remote);
-        let wallet = OnDiskDevice::keystore(alias.clone())?;
 
         let device = Device::new(
             contract_address,
@@ -215,7 +765,12 @@ impl TryFrom<OnDiskDevice> for Device {
             ipfs_gateway,
             eth,
             wallet,
-        );
+        )
+        .with_ipns(ipns, root_target)
+        .with_mirrors(mirrors, on_disk_device.root_reconcile)
+        .with_fallback(fallback)
+        .with_root_layout(on_disk_device.root_layout)
+        .with_manifest_compression(on_disk_device.manifest_compression);
 
         Ok(device)
     }
@@ -225,17 +780,40 @@ impl Display for OnDiskDevice {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let eth_remote = self.eth_remote.clone();
         let ipfs_remote = self.ipfs_remote.clone();
-        let contract_address = self.contract_address;
         let alias = self.alias.clone();
+        let ipns_remote = match &self.ipns_remote {
+            Some(ipns_remote) => ipns_remote.to_string(),
+            None => "none".to_string(),
+        };
+        let root_target = self.root_target;
+        let wallet_source = &self.wallet_source;
 
         write!(
             f,
-            "alias: {}, eth_remote: {}, ipfs_remote: {}, contract_address: {}",
-            alias, eth_remote, ipfs_remote, contract_address
+            "alias: {}, eth_remote: {}, ipfs_remote: {}, ipns_remote: {}, root_target: {}, wallet_source: {}",
+            alias, eth_remote, ipfs_remote, ipns_remote, root_target, wallet_source
         )
     }
 }
 
+/// Read a hex encoded private key from an OS keyring entry
+pub(super) fn keyring_wallet(service: &str, account: &str) -> Result<LocalWallet, ConfigError> {
+    let entry = keyring::Entry::new(service, account)?;
+    let key = match entry.get_password() {
+        Ok(key) => key,
+        Err(keyring::Error::NoEntry) => {
+            return Err(ConfigError::MissingKeyringEntry(
+                service.to_string(),
+                account.to_string(),
+            ))
+        }
+        Err(e) => return Err(e.into()),
+    };
+    key.trim()
+        .parse::<LocalWallet>()
+        .map_err(|_| ConfigError::InvalidKeyringEntry(service.to_string(), account.to_string()))
+}
+
 fn device_path(alias: String) -> Result<PathBuf, ConfigError> {
     let xdg_path = xdg_config_home()?;
     let device_path = xdg_path.join(alias);
@@ -254,4 +832,6 @@ pub enum OnDiskDeviceError {
     Ipfs(#[from] IpfsError),
     #[error("wallet error: {0}")]
     Wallet(#[from] ethers::signers::WalletError),
+    #[error("root cid error: {0}")]
+    RootCid(#[from] RootCidError),
 }
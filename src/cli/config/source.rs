@@ -0,0 +1,81 @@
+use std::io::Read;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use url::Url;
+
+use super::ConfigError;
+
+/// Env var holding a bearer token to fetch config urls with
+pub const CONFIG_TOKEN_ENV: &str = "DOR_STORE_CONFIG_TOKEN";
+
+/// How long to wait on a config url before giving up on it
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where to read a device config from, in place of the device's file on disk
+/// The contents are the same json as a device's device.json
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigSource {
+    /// `-`: read it from stdin, i.e. a secret piped in by the container runtime
+    Stdin,
+    /// An http(s) url, i.e. a config service. Fetched with a bearer token from
+    /// DOR_STORE_CONFIG_TOKEN if it's set
+    Url(Url),
+    Path(PathBuf),
+}
+
+/// Parse a config source argument: `-`, an http(s) url, or a file path
+pub fn parse_config_source(s: &str) -> Result<ConfigSource, String> {
+    if s == "-" {
+        return Ok(ConfigSource::Stdin);
+    }
+    if s.starts_with("http://") || s.starts_with("https://") {
+        let url = Url::parse(s).map_err(|e| format!("invalid config url {}: {}", s, e))?;
+        return Ok(ConfigSource::Url(url));
+    }
+    Ok(ConfigSource::Path(PathBuf::from(s)))
+}
+
+impl ConfigSource {
+    /// Read the config out of the source
+    pub async fn read(&self) -> Result<String, ConfigError> {
+        match self {
+            Self::Stdin => {
+                let mut config = String::new();
+                std::io::stdin().read_to_string(&mut config)?;
+                Ok(config)
+            }
+            Self::Url(url) => fetch(url).await,
+            Self::Path(path) => {
+                if !path.exists() {
+                    return Err(ConfigError::ConfigSourceNotFound(path.clone()));
+                }
+                Ok(std::fs::read_to_string(path)?)
+            }
+        }
+    }
+}
+
+async fn fetch(url: &Url) -> Result<String, ConfigError> {
+    let fetch_error = |source| ConfigError::ConfigFetch {
+        url: url.clone(),
+        source,
+    };
+    let client = reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .build()
+        .map_err(fetch_error)?;
+    let mut request = client.get(url.clone());
+    if let Ok(token) = std::env::var(CONFIG_TOKEN_ENV) {
+        request = request.bearer_auth(token);
+    }
+    let response = request.send().await.map_err(fetch_error)?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(ConfigError::ConfigFetchStatus {
+            url: url.clone(),
+            status: status.as_u16(),
+        });
+    }
+    response.text().await.map_err(fetch_error)
+}
@@ -0,0 +1,74 @@
+use std::path::Path;
+
+use glob::{MatchOptions, Pattern};
+use serde::{Deserialize, Serialize};
+
+/// Options for matching paths -- `*` does not cross directory boundaries, `**` does
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// A set of include and exclude glob patterns for selecting paths within a DorStore
+/// A path is selected if it matches any include (or there are none), and no exclude
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct PathFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl PathFilter {
+    /// Create a new filter, validating all of its patterns
+    pub fn new(include: Vec<String>, exclude: Vec<String>) -> Result<Self, FilterError> {
+        for pattern in include.iter().chain(exclude.iter()) {
+            Pattern::new(pattern)?;
+        }
+        Ok(Self { include, exclude })
+    }
+
+    /// Whether the filter selects every path
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Whether a path is selected by the filter
+    pub fn matches(&self, path: &Path) -> bool {
+        let included =
+            self.include.is_empty() || self.include.iter().any(|p| pattern_matches(p, path));
+        included && !self.exclude.iter().any(|p| pattern_matches(p, path))
+    }
+}
+
+fn pattern_matches(pattern: &str, path: &Path) -> bool {
+    // Patterns are validated on construction
+    Pattern::new(pattern)
+        .map(|p| p.matches_path_with(path, MATCH_OPTIONS))
+        .unwrap_or(false)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FilterError {
+    #[error("invalid glob pattern: {0}")]
+    Pattern(#[from] glob::PatternError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn include_and_exclude() {
+        let filter = PathFilter::new(
+            vec!["data/**/*.csv".to_string()],
+            vec!["data/tmp/**".to_string()],
+        )
+        .unwrap();
+        assert!(filter.matches(Path::new("data/a/b.csv")));
+        assert!(filter.matches(Path::new("data/b.csv")));
+        assert!(!filter.matches(Path::new("data/tmp/b.csv")));
+        assert!(!filter.matches(Path::new("data/b.json")));
+        assert!(!filter.matches(Path::new("other/b.csv")));
+        assert!(PathFilter::default().matches(Path::new("anything")));
+    }
+}
@@ -0,0 +1,188 @@
+use std::process::Command;
+
+use cid::Cid;
+use serde::{Deserialize, Serialize};
+
+/// What a successful push published
+#[derive(Debug, Clone)]
+pub struct PushOutcome {
+    /// The root cid we replaced
+    pub previous_root_cid: Cid,
+    /// The root cid we published
+    pub root_cid: Cid,
+    /// Hash of the transaction that updated the root, if it went on chain
+    pub tx_hash: Option<String>,
+}
+
+/// Something to run after a push has published its root
+pub trait PostPushHook {
+    /// How to refer to the hook when reporting failures
+    fn name(&self) -> String;
+
+    fn run(&self, outcome: &PushOutcome) -> Result<(), HookError>;
+}
+
+/// A shell command run after a push, via `sh -c`
+/// The command inherits our environment, plus:
+/// - DOR_STORE_ROOT_CID: the published root cid
+/// - DOR_STORE_PREVIOUS_ROOT_CID: the root cid it replaced
+/// - DOR_STORE_TX_HASH: hash of the root update transaction, empty if there wasn't one
+pub struct ShellHook(pub String);
+
+impl PostPushHook for ShellHook {
+    fn name(&self) -> String {
+        self.0.clone()
+    }
+
+    fn run(&self, outcome: &PushOutcome) -> Result<(), HookError> {
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(&self.0)
+            .env("DOR_STORE_ROOT_CID", outcome.root_cid.to_string())
+            .env(
+                "DOR_STORE_PREVIOUS_ROOT_CID",
+                outcome.previous_root_cid.to_string(),
+            )
+            .env(
+                "DOR_STORE_TX_HASH",
+                outcome.tx_hash.clone().unwrap_or_default(),
+            )
+            .status()?;
+        if !status.success() {
+            return Err(HookError::Status(self.0.clone(), status.code()));
+        }
+        Ok(())
+    }
+}
+
+/// Hooks configured on a device
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HookConfig {
+    /// Shell commands run after a successful push, in order. See ShellHook
+    #[serde(default)]
+    pub post_push: Vec<String>,
+    /// Fail the push if any hook fails
+    /// The root is already published either way -- nothing is rolled back
+    #[serde(default)]
+    pub fail_on_error: bool,
+}
+
+impl HookConfig {
+    pub fn post_push_hooks(&self) -> Vec<Box<dyn PostPushHook>> {
+        self.post_push
+            .iter()
+            .map(|command| Box::new(ShellHook(command.clone())) as Box<dyn PostPushHook>)
+            .collect()
+    }
+}
+
+/// Run post push hooks in order
+/// Every hook runs, even if an earlier one fails. Failures are reported as they
+/// happen, and only returned as an error if fail_on_error is set
+pub fn run_post_push_hooks(
+    hooks: &[Box<dyn PostPushHook>],
+    outcome: &PushOutcome,
+    fail_on_error: bool,
+) -> Result<(), HookError> {
+    let mut failed = 0;
+    for hook in hooks {
+        if let Err(e) = hook.run(outcome) {
+            tracing::error!("post push hook {} failed: {}", hook.name(), e);
+            failed += 1;
+        }
+    }
+    if failed > 0 && fail_on_error {
+        return Err(HookError::Failed(failed));
+    }
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum HookError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("hook `{0}` exited with status {1:?}")]
+    Status(String, Option<i32>),
+    #[error("{0} post push hooks failed")]
+    Failed(usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::{Path, PathBuf};
+
+    use super::*;
+    use crate::test_utils::cid;
+
+    fn hook_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("dor-store-hooks-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Write an executable shell script, and a hook that runs it
+    fn script(dir: &Path, name: &str, body: &str) -> Box<dyn PostPushHook> {
+        let path = dir.join(name);
+        std::fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        Box::new(ShellHook(path.display().to_string()))
+    }
+
+    fn outcome() -> PushOutcome {
+        PushOutcome {
+            previous_root_cid: cid(1),
+            root_cid: cid(2),
+            tx_hash: Some("0xabc".to_string()),
+        }
+    }
+
+    #[test]
+    fn hooks_see_the_published_root() {
+        let dir = hook_dir("env");
+        let out = dir.join("env");
+        let hooks = [script(
+            &dir,
+            "record.sh",
+            &format!(
+                "echo \"$DOR_STORE_PREVIOUS_ROOT_CID $DOR_STORE_ROOT_CID $DOR_STORE_TX_HASH\" > {}",
+                out.display()
+            ),
+        )];
+
+        run_post_push_hooks(&hooks, &outcome(), true).unwrap();
+        let env = std::fs::read_to_string(&out).unwrap();
+        assert_eq!(env.trim(), format!("{} {} 0xabc", cid(1), cid(2)));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn failing_hooks_only_fail_the_push_when_asked() {
+        let dir = hook_dir("fail");
+        let ran = dir.join("ran");
+        let hooks = [
+            script(&dir, "fail.sh", "exit 3"),
+            script(&dir, "after.sh", &format!("touch {}", ran.display())),
+        ];
+
+        assert!(matches!(
+            hooks[0].run(&outcome()),
+            Err(HookError::Status(_, Some(3)))
+        ));
+        assert!(matches!(
+            run_post_push_hooks(&hooks, &outcome(), true),
+            Err(HookError::Failed(1))
+        ));
+        // Later hooks run all the same
+        assert!(ran.exists());
+        std::fs::remove_file(&ran).unwrap();
+
+        run_post_push_hooks(&hooks, &outcome(), false).unwrap();
+        assert!(ran.exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,316 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::path::{Path, PathBuf};
+
+use cid::Cid;
+use serde::de::{MapAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+
+use crate::device::CidParams;
+use crate::types::{cid_serde, normalize_path, Object, Schema};
+
+/// Something wrong with a manifest, and the object it's wrong with if any
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintIssue {
+    /// The object's path as the manifest has it, or None for the manifest itself
+    pub path: Option<PathBuf>,
+    pub problem: String,
+}
+
+impl LintIssue {
+    fn manifest(problem: String) -> Self {
+        Self {
+            path: None,
+            problem,
+        }
+    }
+
+    fn object(path: &str, problem: String) -> Self {
+        Self {
+            path: Some(PathBuf::from(path)),
+            problem,
+        }
+    }
+}
+
+impl Display for LintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{}: {}", path.display(), self.problem),
+            None => write!(f, "manifest: {}", self.problem),
+        }
+    }
+}
+
+/// Object entries in the order the manifest lists them -- unlike a map, duplicate
+/// paths survive being read, so we can report them
+struct RawEntries(Vec<(String, Value)>);
+
+impl<'de> Deserialize<'de> for RawEntries {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct EntriesVisitor;
+
+        impl<'de> Visitor<'de> for EntriesVisitor {
+            type Value = RawEntries;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a map of paths to objects")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<RawEntries, A::Error> {
+                let mut entries = Vec::new();
+                while let Some(entry) = map.next_entry::<String, Value>()? {
+                    entries.push(entry);
+                }
+                Ok(RawEntries(entries))
+            }
+        }
+
+        deserializer.deserialize_map(EntriesVisitor)
+    }
+}
+
+/// Just enough of a manifest's shape to check each part of it on its own
+#[derive(Deserialize)]
+struct RawManifest {
+    object_set: RawEntries,
+    previous_root: Option<Value>,
+    schema: Option<Value>,
+}
+
+/// Check a manifest, as it's stored, for everything that would make it unsafe to
+/// publish, without touching the network
+/// Every problem is reported, not just the first
+/// # Args
+/// - manifest: the manifest's json
+/// - params: the params every cid in it should have been made with
+pub fn lint_manifest(manifest: &str, params: CidParams) -> Vec<LintIssue> {
+    let raw: RawManifest = match serde_json::from_str(manifest) {
+        Ok(raw) => raw,
+        Err(e) => return vec![LintIssue::manifest(format!("isn't a manifest: {}", e))],
+    };
+    let mut issues = Vec::new();
+
+    match raw.previous_root.map(cid_serde::deserialize) {
+        None => issues.push(LintIssue::manifest("has no previous root".to_string())),
+        Some(Err(e)) => issues.push(LintIssue::manifest(format!(
+            "previous root isn't a valid cid: {}",
+            e
+        ))),
+        Some(Ok(cid)) if cid != Cid::default() && CidParams::of(&cid) != params => {
+            issues.push(LintIssue::manifest(format!(
+                "previous root {} is a {} cid, expected {}",
+                cid,
+                CidParams::of(&cid),
+                params
+            )))
+        }
+        Some(Ok(_)) => {}
+    }
+
+    // Without a schema to check against, tags can't be checked either
+    let schema = match raw.schema.map(serde_json::from_value::<Schema>) {
+        Some(Ok(schema)) => Some(schema),
+        Some(Err(e)) => {
+            issues.push(LintIssue::manifest(format!("schema is malformed: {}", e)));
+            None
+        }
+        None => {
+            issues.push(LintIssue::manifest("has no schema".to_string()));
+            None
+        }
+    };
+
+    let mut seen: BTreeMap<PathBuf, String> = BTreeMap::new();
+    for (path, value) in raw.object_set.0 {
+        let normalized = normalize_path(Path::new(&path));
+        if normalized.as_os_str().is_empty() {
+            issues.push(LintIssue::object(&path, "path is empty".to_string()));
+        } else if let Some(first) = seen.get(&normalized) {
+            issues.push(LintIssue::object(
+                &path,
+                format!("duplicates the path {:?}", first),
+            ));
+        } else {
+            seen.insert(normalized, path.clone());
+        }
+        lint_object(&path, value, params, schema.as_ref(), &mut issues);
+    }
+    issues
+}
+
+/// Check a single object entry, adding anything wrong with it to issues
+fn lint_object(
+    path: &str,
+    value: Value,
+    params: CidParams,
+    schema: Option<&Schema>,
+    issues: &mut Vec<LintIssue>,
+) {
+    let cid = match value.get("cid").cloned().map(cid_serde::deserialize) {
+        Some(Ok(cid)) => cid,
+        Some(Err(e)) => {
+            issues.push(LintIssue::object(path, format!("cid isn't valid: {}", e)));
+            return;
+        }
+        None => {
+            issues.push(LintIssue::object(path, "has no cid".to_string()));
+            return;
+        }
+    };
+    // External objects are pinned as they are, not made by us
+    let external = value.get("external") == Some(&Value::Bool(true));
+    if !external && CidParams::of(&cid) != params {
+        issues.push(LintIssue::object(
+            path,
+            format!(
+                "cid {} is a {} cid, expected {}",
+                cid,
+                CidParams::of(&cid),
+                params
+            ),
+        ));
+    }
+
+    let object: Object = match serde_json::from_value(value) {
+        Ok(object) => object,
+        Err(e) => {
+            issues.push(LintIssue::object(
+                path,
+                format!("object is malformed: {}", e),
+            ));
+            return;
+        }
+    };
+    let schema = match schema {
+        Some(schema) => schema,
+        None => return,
+    };
+    for (name, metadata) in object.metadata() {
+        let fields = match schema.get(name) {
+            Some(fields) => fields,
+            None => {
+                issues.push(LintIssue::object(
+                    path,
+                    format!("is tagged with {:?}, which isn't in the schema", name),
+                ));
+                continue;
+            }
+        };
+        let values = match metadata.as_object() {
+            Some(values) => values,
+            None => {
+                issues.push(LintIssue::object(
+                    path,
+                    format!("{:?} metadata isn't an object", name),
+                ));
+                continue;
+            }
+        };
+        for field in values.keys().filter(|field| !fields.contains(field)) {
+            issues.push(LintIssue::object(
+                path,
+                format!(
+                    "{:?} metadata has {:?}, which isn't one of its fields",
+                    name, field
+                ),
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_utils::{cid, cid_with_hash};
+
+    fn object(cid: &str, metadata: Value) -> Value {
+        json!({
+            "created_at": "2024-01-01T00:00:00Z",
+            "updated_at": "2024-01-01T00:00:00Z",
+            "cid": cid,
+            "metadata": metadata,
+        })
+    }
+
+    #[test]
+    fn clean_manifests_pass() {
+        let manifest = json!({
+            "object_set": {
+                "a.txt": object(&cid(1).to_string(), json!({ "review": { "status": "ok" } })),
+            },
+            "previous_root": cid(2).to_string(),
+            "schema": { "review": ["status"] },
+        });
+        let issues = lint_manifest(&manifest.to_string(), CidParams::default());
+        assert_eq!(issues, vec![]);
+    }
+
+    #[test]
+    fn every_problem_is_reported() {
+        // Written out by hand, since a json map can't hold the same key twice
+        let manifest = format!(
+            r#"{{
+                "object_set": {{
+                    "a.txt": {good},
+                    "./a.txt": {good},
+                    "bad-cid": {bad_cid},
+                    "sha256": {sha256},
+                    "tagged": {tagged}
+                }},
+                "previous_root": "not a cid",
+                "schema": {{ "review": ["status"] }}
+            }}"#,
+            good = object(&cid(1).to_string(), json!({})),
+            bad_cid = object("not-a-cid", json!({})),
+            sha256 = object(&cid_with_hash(0x12, 3).to_string(), json!({})),
+            tagged = object(
+                &cid(4).to_string(),
+                json!({ "review": { "stats": "typo" }, "missing": {} })
+            ),
+        );
+        let issues = lint_manifest(&manifest, CidParams::default());
+        let paths = issues
+            .iter()
+            .map(|issue| issue.path.as_ref().map(|p| p.display().to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![
+                None,
+                Some("./a.txt".to_string()),
+                Some("bad-cid".to_string()),
+                Some("sha256".to_string()),
+                Some("tagged".to_string()),
+                Some("tagged".to_string()),
+            ]
+        );
+        assert!(issues[1].problem.contains("duplicates"));
+    }
+
+    #[test]
+    fn external_objects_keep_their_own_params() {
+        let external = Object::external(cid_with_hash(0x12, 5));
+        let manifest = json!({
+            "object_set": { "dataset": external },
+            "previous_root": cid(2).to_string(),
+            "schema": {},
+        });
+        let issues = lint_manifest(&manifest.to_string(), CidParams::default());
+        assert_eq!(issues, vec![]);
+        assert_eq!(
+            serde_json::to_value(&external).unwrap()["external"],
+            json!(true)
+        );
+    }
+
+    #[test]
+    fn unreadable_manifests_are_one_issue() {
+        let issues = lint_manifest("{ not json", CidParams::default());
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].path, None);
+    }
+}
@@ -1,7 +1,23 @@
 mod app;
+mod archive;
 mod args;
+mod audit;
+mod cancel;
 mod changes;
+mod cid_arg;
 mod config;
+mod filter;
+mod hooks;
+mod lint;
 mod ops;
+mod walk;
 
 pub use app::App;
+pub use changes::PushOptions;
+pub use config::on_disk_device::{OnDiskDevice, WalletSource};
+pub use config::{Config, ConfigBuilder, ConfigError};
+pub use ops::{
+    commit, fsck, health, init, pull, push, reset, stage, stat, verify, CommitError, FsckError,
+    HealthError, InitError, PullError, PullOutput, PullTarget, PushError, ResetError, StageError,
+    StatError, VerifyError,
+};
@@ -0,0 +1,132 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use cid::Cid;
+use futures_util::TryStreamExt;
+use serde_json::{Map, Value};
+
+use super::export::sniff;
+use crate::cli::changes::ChangeType;
+use crate::cli::config::{Config, ConfigError};
+use crate::device::{Device, DeviceError};
+use crate::types::{normalize_path, Object};
+
+/// Schema probed objects are tagged under
+pub const CONTENT_SCHEMA_NAME: &str = "content";
+/// How much of an object to keep for sniffing its type
+const SNIFF_LEN: usize = 8192;
+/// How long each step of probing an object gets before we give up on it
+const PROBE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Stage content that's already on ipfs at a path, by its cid alone -- no local
+/// file needed. Pushing pins it on our remote by cid, leaving the node to fetch it
+/// # Args
+/// - cid: the content to reference
+/// - path: where it goes in the manifest
+/// - probe: look it up first, failing if it can't be, and tag it with its size and
+///   content type
+/// - force: replace an object already at the path
+pub async fn add_cid(
+    config: &Config,
+    cid: &Cid,
+    path: &PathBuf,
+    probe: bool,
+    force: bool,
+) -> Result<(), AddCidError> {
+    let path = normalize_path(path);
+    if path.as_os_str().is_empty() {
+        return Err(AddCidError::EmptyPath);
+    }
+    // A file there would be staged over the reference on the next stage
+    if std::fs::symlink_metadata(config.data_dir().join(&path)).is_ok() {
+        return Err(AddCidError::InWorkingDir(path));
+    }
+
+    let device = config.device()?;
+    let mut change_log = config.stage()?;
+    let (_cid, last_dor_store) = change_log.last_version().unwrap();
+    let mut dor_store = last_dor_store.clone();
+    if let Some(existing) = dor_store.get_object(&path) {
+        if existing.cid() == cid && existing.is_external() {
+            println!("{} already references {}", path.display(), cid);
+            return Ok(());
+        }
+        if !force {
+            return Err(AddCidError::Exists(path, *existing.cid()));
+        }
+    }
+
+    let mut object = Object::external(*cid);
+    if probe {
+        let (size, content_type) = probe_object(&device, cid).await?;
+        let fields = vec!["size".to_string(), "content_type".to_string()];
+        let schema_name = CONTENT_SCHEMA_NAME.to_string();
+        if dor_store.get_schema_entry(&schema_name).is_none() {
+            dor_store.insert_schema_entry(&schema_name, &fields);
+        }
+        let mut values = Map::new();
+        values.insert("size".to_string(), Value::String(size.to_string()));
+        if let Some(content_type) = content_type {
+            values.insert("content_type".to_string(), Value::String(content_type));
+        }
+        object.tag(&schema_name, &Value::Object(values));
+        println!("probed {}: {} bytes", cid, size);
+    }
+    let change_type = match dor_store.get_object(&path) {
+        Some(_) => ChangeType::Modified,
+        None => ChangeType::Added,
+    };
+    dor_store.insert_object(path.clone(), object);
+
+    let root_cid = device.hash_dor_store(&dor_store, false).await?;
+    let mut log = change_log.log().clone();
+    log.insert(path.clone(), (*cid, change_type));
+    change_log.update(&log, &dor_store, &root_cid);
+    config.set_stage(change_log)?;
+    println!("staged {} at {}", cid, path.display());
+    Ok(())
+}
+
+/// Look an object up for its size and sniffed content type
+/// The size comes from its root block on our remote, and only the start of it is
+/// read through the gateway to sniff, however big it is -- each within PROBE_TIMEOUT
+async fn probe_object(device: &Device, cid: &Cid) -> Result<(u64, Option<String>), AddCidError> {
+    let size = tokio::time::timeout(PROBE_TIMEOUT, device.object_size(cid, true))
+        .await
+        .map_err(|_| AddCidError::ProbeTimeout(*cid, PROBE_TIMEOUT))??;
+    let head = tokio::time::timeout(PROBE_TIMEOUT, read_head(device, cid))
+        .await
+        .map_err(|_| AddCidError::ProbeTimeout(*cid, PROBE_TIMEOUT))??;
+    Ok((size, sniff(&head).map(|(mime, _)| mime)))
+}
+
+/// The first SNIFF_LEN bytes of an object, leaving the rest unread
+async fn read_head(device: &Device, cid: &Cid) -> Result<Vec<u8>, AddCidError> {
+    let mut stream = device.stream_object(cid).await?;
+    let mut head = Vec::new();
+    while head.len() < SNIFF_LEN {
+        let chunk = match stream.try_next().await? {
+            Some(chunk) => chunk,
+            None => break,
+        };
+        let take = (SNIFF_LEN - head.len()).min(chunk.len());
+        head.extend_from_slice(&chunk[..take]);
+    }
+    Ok(head)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AddCidError {
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("device error: {0}")]
+    Device(#[from] DeviceError),
+    #[error("can't add an object at the root of the store")]
+    EmptyPath,
+    #[error("{0:?} is in the working dir -- stage it instead, or pick another path")]
+    InWorkingDir(PathBuf),
+    #[error("{0:?} already holds {1} -- pass --force to replace it")]
+    Exists(PathBuf, Cid),
+    #[error("couldn't probe {0} within {1:?} -- is anyone providing it?")]
+    ProbeTimeout(Cid, Duration),
+}
@@ -0,0 +1,237 @@
+use std::fmt::Display;
+use std::io::Cursor;
+use std::time::{Duration, Instant};
+
+use cid::Cid;
+use rand::RngCore;
+
+use crate::cli::cancel::CancelReason;
+use crate::cli::config::{Config, ConfigError};
+use crate::device::{Device, DeviceError};
+
+/// How many test objects to unpin at once when cleaning up
+const UNPIN_CONCURRENCY: usize = 16;
+/// How long cleaning up the test objects gets
+const UNPIN_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Add synthetic objects to the remote one at a time, fetch each back through the
+/// gateway, and report throughput and latency percentiles for both
+/// Objects are random, so none are deduplicated against what the remote already
+/// holds. They're unpinned once we're done, even if the run fails part way
+/// # Args
+/// - count: how many objects to add and fetch
+/// - size: bytes in each object
+pub async fn bench(config: &Config, count: usize, size: usize) -> Result<(), BenchError> {
+    let device = config.device()?;
+    let mut cids = Vec::new();
+    let result = run(config, &device, count, size, &mut cids).await;
+    clean_up(&device, &cids).await;
+    let report = result?;
+    println!("{}", report);
+    Ok(())
+}
+
+/// Add then fetch every object, keeping track of what we've added so it can be
+/// cleaned up however the run ends
+async fn run(
+    config: &Config,
+    device: &Device,
+    count: usize,
+    size: usize,
+    cids: &mut Vec<Cid>,
+) -> Result<BenchReport, BenchError> {
+    let mut adds = Vec::with_capacity(count);
+    for _ in 0..count {
+        config
+            .cancellation()
+            .check()
+            .map_err(BenchError::Cancelled)?;
+        let mut data = vec![0u8; size];
+        rand::thread_rng().fill_bytes(&mut data);
+        let started = Instant::now();
+        let cid = device.write_ipfs_data(Cursor::new(data), true).await?;
+        adds.push(started.elapsed());
+        cids.push(cid);
+    }
+
+    // A fetch that fails doesn't stop the rest -- flaky gateways are worth knowing about
+    let mut fetches = Vec::with_capacity(count);
+    let mut failed_fetches = 0;
+    for cid in cids.iter() {
+        config
+            .cancellation()
+            .check()
+            .map_err(BenchError::Cancelled)?;
+        let started = Instant::now();
+        match device.read_ipfs_gateway_data(cid, None).await {
+            Ok(data) if data.len() == size => fetches.push(started.elapsed()),
+            Ok(data) => {
+                tracing::warn!("fetched {} bytes of {}, expected {}", data.len(), cid, size);
+                failed_fetches += 1;
+            }
+            Err(e) => {
+                tracing::warn!("failed to fetch {}: {}", cid, e);
+                failed_fetches += 1;
+            }
+        }
+    }
+
+    Ok(BenchReport {
+        count,
+        size,
+        adds: Timings::new(adds),
+        fetches: Timings::new(fetches),
+        failed_fetches,
+    })
+}
+
+/// Unpin the test objects, warning about any we couldn't
+async fn clean_up(device: &Device, cids: &[Cid]) {
+    if cids.is_empty() {
+        return;
+    }
+    let report = device
+        .unpin_many(cids, true, UNPIN_CONCURRENCY, UNPIN_TIMEOUT)
+        .await;
+    for (cid, e) in report.failed.iter() {
+        tracing::warn!("failed to unpin test object {}: {}", cid, e);
+    }
+    for cid in report.timed_out.iter() {
+        tracing::warn!("timed out unpinning test object {}", cid);
+    }
+}
+
+/// How long each of a run of requests took, fastest first
+struct Timings(Vec<Duration>);
+
+impl Timings {
+    fn new(mut durations: Vec<Duration>) -> Self {
+        durations.sort();
+        Self(durations)
+    }
+
+    /// The nearest rank percentile, if there were any requests
+    fn percentile(&self, percent: usize) -> Option<Duration> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let rank = (percent * self.0.len()).div_ceil(100).max(1);
+        Some(self.0[rank.min(self.0.len()) - 1])
+    }
+
+    /// Bytes per second across the run, with requests made one after another
+    fn throughput(&self, size: usize) -> f64 {
+        let elapsed: Duration = self.0.iter().sum();
+        if elapsed.is_zero() {
+            return 0.0;
+        }
+        (size * self.0.len()) as f64 / elapsed.as_secs_f64()
+    }
+}
+
+struct BenchReport {
+    count: usize,
+    size: usize,
+    adds: Timings,
+    fetches: Timings,
+    failed_fetches: usize,
+}
+
+impl BenchReport {
+    fn write_timings(
+        f: &mut std::fmt::Formatter<'_>,
+        name: &str,
+        timings: &Timings,
+        size: usize,
+    ) -> std::fmt::Result {
+        let (p50, p90, p99, max) = match (
+            timings.percentile(50),
+            timings.percentile(90),
+            timings.percentile(99),
+            timings.percentile(100),
+        ) {
+            (Some(p50), Some(p90), Some(p99), Some(max)) => (p50, p90, p99, max),
+            _ => return write!(f, "{}: none completed", name),
+        };
+        write!(
+            f,
+            "{}: {:.2} MiB/s, p50 {:?}, p90 {:?}, p99 {:?}, max {:?}",
+            name,
+            timings.throughput(size) / (1 << 20) as f64,
+            p50,
+            p90,
+            p99,
+            max
+        )
+    }
+}
+
+impl Display for BenchReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "objects: {} of {} bytes", self.count, self.size)?;
+        Self::write_timings(f, "add", &self.adds, self.size)?;
+        writeln!(f)?;
+        Self::write_timings(f, "fetch", &self.fetches, self.size)?;
+        if self.failed_fetches > 0 {
+            write!(f, " ({} failed)", self.failed_fetches)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BenchError {
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("device error: {0}")]
+    Device(#[from] DeviceError),
+    #[error("bench stopped, {0}")]
+    Cancelled(CancelReason),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(durations: impl IntoIterator<Item = u64>) -> Timings {
+        Timings::new(durations.into_iter().map(Duration::from_millis).collect())
+    }
+
+    #[test]
+    fn no_requests_have_no_timings() {
+        let timings = millis([]);
+        for percent in [0, 50, 100] {
+            assert_eq!(timings.percentile(percent), None);
+        }
+        assert_eq!(timings.throughput(1024), 0.0);
+    }
+
+    #[test]
+    fn one_request_is_every_percentile() {
+        let timings = millis([250]);
+        for percent in [0, 1, 50, 99, 100] {
+            assert_eq!(
+                timings.percentile(percent),
+                Some(Duration::from_millis(250))
+            );
+        }
+        assert_eq!(timings.throughput(1024), 4096.0);
+    }
+
+    #[test]
+    fn percentiles_take_the_nearest_rank() {
+        // 1ms through 100ms, made in no particular order
+        let timings = millis((1..=100).rev());
+        for (percent, expected) in [(0, 1), (1, 1), (50, 50), (90, 90), (99, 99), (100, 100)] {
+            assert_eq!(
+                timings.percentile(percent),
+                Some(Duration::from_millis(expected))
+            );
+        }
+        // 100 requests of 101 bytes over 5.05s
+        assert!((timings.throughput(101) - 2000.0).abs() < 1e-9);
+
+        // All instant -- nothing to divide by
+        assert_eq!(millis([0; 100]).throughput(1024), 0.0);
+    }
+}
@@ -0,0 +1,307 @@
+use std::fmt::Display;
+use std::future::Future;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use cid::Cid;
+
+use crate::cli::audit::AuditRecord;
+use crate::cli::cancel::{CancelReason, Cancellation};
+use crate::cli::cid_arg::RootArg;
+use crate::cli::config::{Config, ConfigError};
+use crate::device::{Device, DeviceError};
+use crate::types::DorStore;
+
+/// What the object at a path was before the root that last changed it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Change {
+    /// The path was added
+    Added,
+    /// The path held another object
+    Changed(Cid),
+    /// The path was removed, and held this object before
+    Removed(Cid),
+    /// The path was the same in every root we walked back to before hitting our
+    /// max depth, so it last changed in some root older still
+    Older,
+}
+
+/// How we know when a root was published
+enum Published {
+    /// Our own audit log recorded the update
+    Audited(AuditRecord),
+    /// The contract's events had it
+    OnChain {
+        tx_hash: String,
+        block_number: Option<u64>,
+        timestamp: Option<DateTime<Utc>>,
+    },
+    Unknown,
+}
+
+struct Blame {
+    path: PathBuf,
+    /// The object at the path as of the root we started from, if any
+    cid: Option<Cid>,
+    /// The root the path last changed in
+    root: Cid,
+    change: Change,
+    published: Published,
+    /// How many roots we read manifests for
+    walked: usize,
+}
+
+impl Display for Blame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "path: {}", self.path.display())?;
+        match self.cid {
+            Some(cid) => writeln!(f, "cid: {}", cid)?,
+            None => writeln!(f, "cid: none")?,
+        }
+        let change = match self.change {
+            Change::Added => "added".to_string(),
+            Change::Changed(cid) => format!("changed from {}", cid),
+            Change::Removed(cid) => format!("removed, was {}", cid),
+            Change::Older => "unchanged as far back as --max-depth reaches".to_string(),
+        };
+        writeln!(f, "root: {} ({})", self.root, change)?;
+        match &self.published {
+            Published::Audited(record) => {
+                let block_number = record
+                    .block_number
+                    .map(|block_number| block_number.to_string())
+                    .unwrap_or("unknown".to_string());
+                writeln!(
+                    f,
+                    "published: tx {}, block {}, seen {}",
+                    record.tx_hash, block_number, record.timestamp
+                )?;
+            }
+            Published::OnChain {
+                tx_hash,
+                block_number,
+                timestamp,
+            } => {
+                let block_number = block_number
+                    .map(|block_number| block_number.to_string())
+                    .unwrap_or("unknown".to_string());
+                let timestamp = timestamp
+                    .map(|timestamp| timestamp.to_string())
+                    .unwrap_or("unknown".to_string());
+                writeln!(
+                    f,
+                    "published: tx {}, block {}, at {}",
+                    tx_hash, block_number, timestamp
+                )?;
+            }
+            Published::Unknown => writeln!(f, "published: unknown")?,
+        }
+        write!(f, "walked {} roots", self.walked)
+    }
+}
+
+/// Find the root the object at a path last changed in, like `git blame` for a
+/// single path: the previous root chain is walked back from a root until the
+/// path's cid differs, or the chain ends
+/// Only the cid is compared, so metadata changes like a re-tag don't count.
+/// Paths missing from the starting root are blamed on the root that removed them
+/// # Args
+/// - path: the object's path
+/// - root: the root to start from, the base root if None
+/// - max_depth: how many roots back from there to walk at most, if limited
+pub async fn blame(
+    config: &Config,
+    path: &PathBuf,
+    root: Option<&RootArg>,
+    max_depth: Option<usize>,
+) -> Result<(), BlameError> {
+    let device = config.device()?;
+    let change_log = config.change_log()?;
+    let (base_root, base) = change_log.first_version().unwrap();
+    let (root_cid, dor_store) = match root {
+        Some(root) => {
+            let root = config.resolve_root(root)?;
+            if root == *base_root {
+                (root, base.clone())
+            } else {
+                (root, device.read_dor_store(&root, true).await?)
+            }
+        }
+        None => (*base_root, base.clone()),
+    };
+    let cid = dor_store.get_object(path).map(|object| *object.cid());
+    let (root_cid, change, walked) = walk(
+        path,
+        root_cid,
+        dor_store,
+        max_depth,
+        config.cancellation(),
+        |root| {
+            let device = &device;
+            async move { device.read_dor_store(&root, true).await }
+        },
+    )
+    .await?;
+
+    let blame = Blame {
+        path: path.clone(),
+        cid,
+        root: root_cid,
+        change,
+        published: published(config, &device, &root_cid).await?,
+        walked,
+    };
+    println!("{}", blame);
+    Ok(())
+}
+
+/// Walk the previous root chain back from a root until the object at a path differs
+/// # Args
+/// - root_cid, dor_store: the root to start from, and its manifest
+/// - max_depth: how many roots back to walk at most, if limited
+/// - read_dor_store: reads the manifest for a root
+/// # Returns the root the path last changed in, how, and how many roots we read
+async fn walk<F, Fut>(
+    path: &PathBuf,
+    mut root_cid: Cid,
+    mut dor_store: DorStore,
+    max_depth: Option<usize>,
+    cancellation: &Cancellation,
+    mut read_dor_store: F,
+) -> Result<(Cid, Change, usize), BlameError>
+where
+    F: FnMut(Cid) -> Fut,
+    Fut: Future<Output = Result<DorStore, DeviceError>>,
+{
+    let cid = dor_store.get_object(path).map(|object| *object.cid());
+    let mut walked = 1;
+    let change = loop {
+        cancellation.check().map_err(BlameError::Cancelled)?;
+        let previous_root = *dor_store.previous_root();
+        if previous_root == Cid::default() {
+            match cid {
+                Some(_) => break Change::Added,
+                None => return Err(BlameError::NotFound(path.clone())),
+            }
+        }
+        if max_depth
+            .map(|max_depth| walked > max_depth)
+            .unwrap_or(false)
+        {
+            break Change::Older;
+        }
+        let previous = read_dor_store(previous_root).await?;
+        walked += 1;
+        let previous_cid = previous.get_object(path).map(|object| *object.cid());
+        match (previous_cid, cid) {
+            (previous_cid, cid) if previous_cid == cid => {}
+            (None, _) => break Change::Added,
+            (Some(previous_cid), Some(_)) => break Change::Changed(previous_cid),
+            (Some(previous_cid), None) => break Change::Removed(previous_cid),
+        }
+        root_cid = previous_root;
+        dor_store = previous;
+    };
+    Ok((root_cid, change, walked))
+}
+
+/// Work out when a root was published, preferring our own audit log to the
+/// contract's events, which only reach back so far
+async fn published(
+    config: &Config,
+    device: &Device,
+    root_cid: &Cid,
+) -> Result<Published, BlameError> {
+    let on_disk_device = config.on_disk_device()?;
+    if let Some(audit_log) = on_disk_device.audit_log() {
+        match AuditRecord::find(audit_log, root_cid) {
+            Ok(Some(record)) => return Ok(Published::Audited(record)),
+            Ok(None) => {}
+            Err(e) => tracing::warn!("failed to read audit log {}: {}", audit_log.display(), e),
+        }
+    }
+    Ok(match device.find_root_update(root_cid).await {
+        Some(update) => Published::OnChain {
+            tx_hash: update.tx_hash,
+            block_number: update.block_number,
+            timestamp: update.timestamp,
+        },
+        None => Published::Unknown,
+    })
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BlameError {
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("device error: {0}")]
+    Device(#[from] DeviceError),
+    #[error("no object at {0:?} in any root")]
+    NotFound(PathBuf),
+    #[error("blame stopped, {0}")]
+    Cancelled(CancelReason),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::test_utils::cid;
+    use crate::types::Object;
+
+    fn store(previous_root: Option<Cid>, objects: &[(&str, u8)]) -> DorStore {
+        let mut dor_store = DorStore::default();
+        if let Some(previous_root) = previous_root {
+            dor_store.set_previous_root(previous_root);
+        }
+        for (path, byte) in objects {
+            dor_store.insert_object(PathBuf::from(path), Object::new(cid(*byte)));
+        }
+        dor_store
+    }
+
+    #[tokio::test]
+    async fn walks_back_to_the_root_a_path_changed_in() {
+        // b changes in the second root, c is removed in the third
+        let (r1, r2, r3) = (cid(101), cid(102), cid(103));
+        let chain = HashMap::from([
+            (r1, store(None, &[("a", 1), ("b", 2), ("c", 3)])),
+            (r2, store(Some(r1), &[("a", 1), ("b", 4), ("c", 3)])),
+            (r3, store(Some(r2), &[("a", 1), ("b", 4)])),
+        ]);
+
+        let cases = [
+            ("a", None, Some((r1, Change::Added, 3))),
+            ("b", None, Some((r2, Change::Changed(cid(2)), 2))),
+            ("c", None, Some((r3, Change::Removed(cid(3)), 2))),
+            ("d", None, None),
+            ("a", Some(0), Some((r3, Change::Older, 1))),
+            ("a", Some(1), Some((r2, Change::Older, 2))),
+            ("b", Some(1), Some((r2, Change::Older, 2))),
+            ("a", Some(2), Some((r1, Change::Added, 3))),
+            ("c", Some(1), Some((r3, Change::Removed(cid(3)), 2))),
+        ];
+        for (path, max_depth, expected) in cases {
+            let path = PathBuf::from(path);
+            let result = walk(
+                &path,
+                r3,
+                chain[&r3].clone(),
+                max_depth,
+                &Cancellation::default(),
+                |root| {
+                    let dor_store = chain[&root].clone();
+                    async move { Ok(dor_store) }
+                },
+            )
+            .await;
+            match expected {
+                Some(expected) => {
+                    assert_eq!(result.unwrap(), expected, "{:?} {:?}", path, max_depth)
+                }
+                None => assert!(matches!(result, Err(BlameError::NotFound(_)))),
+            }
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use std::collections::HashSet;
+
+use cid::Cid;
+
+use crate::cli::args::CacheSubcommand;
+use crate::cli::config::{Config, ConfigError};
+use crate::device::{CacheError, DeviceError};
+use crate::types::DorStore;
+
+/// The cids of every object referenced by our current base and, optionally, by the
+/// roots before it
+/// The change log's versions are included, so nothing staged or committed is lost
+/// # Args
+/// - keep_roots: how many previous roots to also keep objects for
+async fn live_set(
+    config: &Config,
+    keep_roots: usize,
+) -> Result<HashSet<Cid>, CacheSubcommandError> {
+    let mut live = HashSet::new();
+    let mut add = |dor_store: &DorStore| {
+        live.extend(dor_store.objects().values().map(|object| *object.cid()));
+    };
+    let change_log = config.change_log()?;
+    for version in [change_log.first_version(), change_log.last_version()]
+        .into_iter()
+        .flatten()
+    {
+        add(&version.1);
+    }
+
+    let mut dor_store = config.base()?;
+    add(&dor_store);
+    if keep_roots > 0 {
+        let device = config.device()?;
+        for _ in 0..keep_roots {
+            let previous_root = *dor_store.previous_root();
+            if previous_root == Cid::default() {
+                break;
+            }
+            dor_store = device.read_dor_store(&previous_root, true).await?;
+            add(&dor_store);
+        }
+    }
+    Ok(live)
+}
+
+pub async fn cache_subcommand(
+    config: &Config,
+    subcommand: &CacheSubcommand,
+) -> Result<(), CacheSubcommandError> {
+    let cache = config.content_cache()?;
+    match subcommand {
+        CacheSubcommand::Prune => {
+            println!("{}", cache.prune()?);
+        }
+        CacheSubcommand::Gc { keep_roots } => {
+            let live = live_set(config, *keep_roots).await?;
+            println!("{}", cache.retain(&live)?);
+        }
+        CacheSubcommand::Stats => {
+            println!("{}", cache.stats());
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CacheSubcommandError {
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("cache error: {0}")]
+    Cache(#[from] CacheError),
+    #[error("device error: {0}")]
+    Device(#[from] DeviceError),
+}
@@ -0,0 +1,79 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use cid::Cid;
+
+use crate::cli::config::{Config, ConfigError};
+use crate::device::DeviceError;
+use crate::types::DorStore;
+
+/// Work out which object a path, a cid, or both refer to
+/// A cid alone is read as is, without looking at the base. With both, the object
+/// at the path must have the cid
+/// # Returns the cid to read
+pub fn resolve_object(
+    base: &DorStore,
+    path: Option<&Path>,
+    cid: Option<&Cid>,
+) -> Result<Cid, ResolveError> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => return cid.copied().ok_or(ResolveError::NoObject),
+    };
+    let object = base
+        .get_object(&path)
+        .ok_or_else(|| ResolveError::NotFound(path.clone()))?;
+    match cid {
+        Some(cid) if cid != object.cid() => Err(ResolveError::Mismatch {
+            path,
+            expected: *cid,
+            actual: *object.cid(),
+        }),
+        _ => Ok(*object.cid()),
+    }
+}
+
+/// Write the contents of an object to stdout
+/// # Args
+/// - path: the object's path within the current base
+/// - cid: the object's cid, read straight from the gateway or node. Checked
+///   against the object at path if both are given
+pub async fn cat(config: &Config, path: Option<&Path>, cid: Option<&Cid>) -> Result<(), CatError> {
+    let device = config.device()?;
+    // A cid alone doesn't need the base
+    let cid = match (path, cid) {
+        (None, Some(cid)) => *cid,
+        _ => resolve_object(&config.base()?, path, cid)?,
+    };
+    let data = device.read_object(&cid).await?;
+    let mut stdout = std::io::stdout();
+    stdout.write_all(&data)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ResolveError {
+    #[error("give an object's path, cid, or both")]
+    NoObject,
+    #[error("no object at {0:?}")]
+    NotFound(PathBuf),
+    #[error("object at {path:?} is {actual}, not {expected}")]
+    Mismatch {
+        path: PathBuf,
+        expected: Cid,
+        actual: Cid,
+    },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CatError {
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("device error: {0}")]
+    Device(#[from] DeviceError),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("{0}")]
+    Resolve(#[from] ResolveError),
+}
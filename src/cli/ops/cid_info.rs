@@ -0,0 +1,21 @@
+use crate::cli::config::{Config, ConfigError};
+
+/// Dump the cid info of every object in the current base
+pub fn cid_info(config: &Config) -> Result<(), CidInfoError> {
+    let base = config.base()?;
+    for (path, object) in base.objects().iter() {
+        println!(
+            "{}\t{}\t{}",
+            path.display(),
+            object.cid(),
+            object.cid_info()
+        );
+    }
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CidInfoError {
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+}
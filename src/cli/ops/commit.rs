@@ -0,0 +1,30 @@
+use crate::cli::config::{Config, ConfigError};
+
+/// Merge the staged copy of the change log into the change log
+/// The next push will publish the committed changes
+pub fn commit(config: &Config) -> Result<(), CommitError> {
+    if !config.has_stage() {
+        tracing::info!("nothing to commit");
+        return Ok(());
+    }
+
+    let change_log = config.change_log()?;
+    let stage = config.stage()?;
+
+    // The stage must have been made against the change log's base
+    if stage.first_version() != change_log.first_version() {
+        return Err(CommitError::StaleStage);
+    }
+
+    config.set_change_log(stage)?;
+    config.reset_stage()?;
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CommitError {
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("stage was made against a different base -- reset and stage again")]
+    StaleStage,
+}
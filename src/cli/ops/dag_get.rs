@@ -0,0 +1,31 @@
+use cid::Cid;
+
+use crate::cli::config::{Config, ConfigError};
+use crate::device::DeviceError;
+
+/// Print a single node within a dag as json, fetching only the blocks along its path
+/// # Args
+/// - cid: the root of the dag
+/// - path: an ipld path from the root to the node, i.e. `links/0/Hash`
+/// - remote: whether to read against the remote rather than the local node
+pub async fn dag_get(
+    config: &Config,
+    cid: &Cid,
+    path: &str,
+    remote: bool,
+) -> Result<(), DagGetError> {
+    let device = config.device()?;
+    let node = device.read_dag_path(cid, path, remote).await?;
+    println!("{}", serde_json::to_string_pretty(&node)?);
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DagGetError {
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("device error: {0}")]
+    Device(#[from] DeviceError),
+    #[error("serde error: {0}")]
+    Serde(#[from] serde_json::Error),
+}
@@ -1,6 +1,12 @@
+use std::path::PathBuf;
+
 use crate::cli::args::DeviceSubcommand;
+use crate::cli::config::on_disk_device::{OnDiskDevice, WalletSource};
 use crate::cli::config::{Config, ConfigError};
-use crate::device::{EthRemote, IpfsRemote};
+use crate::device::{
+    EthRemote, IpfsRemote, IpnsRemote, RootFallback, S3Remote, DEFAULT_RECEIPT_MAX_ATTEMPTS,
+    DEFAULT_RECEIPT_POLL_INTERVAL_SECS,
+};
 
 pub fn device_subcommand(
     config: &Config,
@@ -12,33 +18,255 @@ pub fn device_subcommand(
             eth_rpc,
             eth_chain_id,
             contract_address,
+            contract_abi,
+            verify_contract,
+            confirm_updates,
             ipfs_url,
             ipfs_gateway_url,
+            ipns_key,
+            ipns_lifetime,
+            root_target,
+            keyring_service,
+            keyring_account,
         } => {
             let eth_remote = EthRemote {
                 rpc_url: eth_rpc.clone(),
                 chain_id: *eth_chain_id,
+                contract_address: *contract_address,
+                verify_contract: *verify_contract,
+                confirm_updates: *confirm_updates,
+                receipt_poll_interval_secs: DEFAULT_RECEIPT_POLL_INTERVAL_SECS,
+                receipt_max_attempts: DEFAULT_RECEIPT_MAX_ATTEMPTS,
+                check_previous_root: false,
+                contract_abi: contract_abi.clone().unwrap_or_default(),
             };
             let ipfs_remote = IpfsRemote {
                 api_url: ipfs_url.clone(),
                 gateway_url: ipfs_gateway_url.clone(),
+                ..Default::default()
+            };
+            let ipns_remote = ipns_key
+                .clone()
+                .map(|key| IpnsRemote::new(key, ipns_lifetime.clone()));
+            let wallet_source = match (keyring_service, keyring_account) {
+                (Some(service), Some(account)) => WalletSource::Keyring {
+                    service: service.clone(),
+                    account: account.clone(),
+                },
+                _ => WalletSource::Keystore,
             };
             Config::create_on_disk_device(
                 alias.clone(),
-                *contract_address,
                 ipfs_remote,
                 eth_remote,
+                ipns_remote,
+                *root_target,
+                wallet_source,
             )?;
         }
         DeviceSubcommand::Update {
-            alias: _,
-            eth_rpc: _,
-            eth_chain_id: _,
-            contract_address: _,
-            ipfs_url: _,
-            ipfs_gateway_url: _,
+            alias,
+            eth_rpc,
+            eth_chain_id,
+            contract_address,
+            contract_abi,
+            verify_contract,
+            confirm_updates,
+            receipt_poll_interval_secs,
+            receipt_max_attempts,
+            check_previous_root,
+            ipfs_url,
+            ipfs_gateway_url,
+            cache,
+            cache_max_bytes,
+            cache_max_age_secs,
+            root_layout,
+            manifest_compression,
+            max_added_objects,
+            max_added_bytes,
+            mfs_path,
+            dns_override,
+            root_fallback,
+            symlinks,
+            max_object_size,
+            record_mtimes,
+            delta_manifests,
+            previous_root_check,
+            audit_log,
+            s3_endpoint,
+            s3_bucket,
+            s3_region,
+            s3_prefix,
+            s3_path_style,
+            s3_required,
+            eth_mirror,
+            root_reconcile,
+            replication,
         } => {
-            todo!("updating devices is not supported yet")
+            let on_disk_device = OnDiskDevice::load(alias.clone())?;
+
+            let mut eth_remote = on_disk_device.eth_remote().clone();
+            if let Some(eth_rpc) = eth_rpc {
+                eth_remote.rpc_url = eth_rpc.clone();
+            }
+            if let Some(eth_chain_id) = eth_chain_id {
+                eth_remote.chain_id = *eth_chain_id;
+            }
+            if let Some(contract_address) = contract_address {
+                eth_remote.contract_address = *contract_address;
+            }
+            if let Some(contract_abi) = contract_abi {
+                eth_remote.contract_abi = contract_abi.clone();
+            }
+            if let Some(verify_contract) = verify_contract {
+                eth_remote.verify_contract = *verify_contract;
+            }
+            if let Some(confirm_updates) = confirm_updates {
+                eth_remote.confirm_updates = *confirm_updates;
+            }
+            if let Some(receipt_poll_interval_secs) = receipt_poll_interval_secs {
+                eth_remote.receipt_poll_interval_secs = *receipt_poll_interval_secs;
+            }
+            if let Some(receipt_max_attempts) = receipt_max_attempts {
+                eth_remote.receipt_max_attempts = *receipt_max_attempts;
+            }
+            if let Some(check_previous_root) = check_previous_root {
+                eth_remote.check_previous_root = *check_previous_root;
+            }
+
+            let mut ipfs_remote = on_disk_device.ipfs_remote().clone();
+            if let Some(ipfs_url) = ipfs_url {
+                ipfs_remote.api_url = ipfs_url.clone();
+            }
+            if let Some(ipfs_gateway_url) = ipfs_gateway_url {
+                ipfs_remote.gateway_url = ipfs_gateway_url.clone();
+            }
+            if let Some(mfs_path) = mfs_path {
+                ipfs_remote.mfs_path = Some(mfs_path.clone()).filter(|path| !path.is_empty());
+            }
+            for (host, ip) in dns_override {
+                ipfs_remote.dns_overrides.set(host.clone(), *ip);
+            }
+
+            let mut cache_policy = *on_disk_device.cache_policy();
+            if let Some(cache) = cache {
+                cache_policy.enabled = *cache;
+            }
+            if let Some(cache_max_bytes) = cache_max_bytes {
+                cache_policy.max_bytes = Some(*cache_max_bytes);
+            }
+            if let Some(cache_max_age_secs) = cache_max_age_secs {
+                cache_policy.max_age_secs = Some(*cache_max_age_secs);
+            }
+
+            let mut push_guardrail = *on_disk_device.push_guardrail();
+            if let Some(max_added_objects) = max_added_objects {
+                push_guardrail.max_added_objects = Some(*max_added_objects);
+            }
+            if let Some(max_added_bytes) = max_added_bytes {
+                push_guardrail.max_added_bytes = Some(*max_added_bytes);
+            }
+
+            Config::update_on_disk_device(
+                alias.clone(),
+                Some(ipfs_remote),
+                Some(eth_remote),
+                Some(cache_policy),
+                *root_layout,
+                *manifest_compression,
+                Some(push_guardrail),
+            )?;
+
+            if let Some(root_fallback) = root_fallback {
+                let root_fallback = match root_fallback.as_str() {
+                    "none" => None,
+                    "ipns" => Some(RootFallback::Ipns),
+                    path => Some(RootFallback::File {
+                        path: PathBuf::from(path),
+                    }),
+                };
+                Config::set_root_fallback(alias.clone(), root_fallback)?;
+            }
+            if let Some(symlinks) = symlinks {
+                Config::set_symlink_policy(alias.clone(), *symlinks)?;
+            }
+            if let Some(max_object_size) = max_object_size {
+                let max_object_size = Some(*max_object_size).filter(|size| *size > 0);
+                Config::set_max_object_size(alias.clone(), max_object_size)?;
+            }
+            if let Some(record_mtimes) = record_mtimes {
+                Config::set_record_mtimes(alias.clone(), *record_mtimes)?;
+            }
+            if let Some(delta_manifests) = delta_manifests {
+                Config::set_delta_manifests(alias.clone(), *delta_manifests)?;
+            }
+            if let Some(previous_root_check) = previous_root_check {
+                Config::set_previous_root_check(alias.clone(), *previous_root_check)?;
+            }
+            if let Some(replication) = replication {
+                Config::set_replication(alias.clone(), replication.clone())?;
+            }
+            if let Some(audit_log) = audit_log {
+                let audit_log = Some(audit_log.clone()).filter(|path| !path.as_os_str().is_empty());
+                Config::set_audit_log(alias.clone(), audit_log)?;
+            }
+
+            if !eth_mirror.is_empty() || root_reconcile.is_some() {
+                let mut eth_mirrors = on_disk_device.eth_mirrors().to_vec();
+                for (chain_id, contract_address, rpc_url) in eth_mirror {
+                    eth_mirrors.retain(|mirror| {
+                        mirror.chain_id != *chain_id || mirror.contract_address != *contract_address
+                    });
+                    // Mirrors talk to their contract the way the device does to its own
+                    if let Some(rpc_url) = rpc_url {
+                        eth_mirrors.push(EthRemote {
+                            rpc_url: rpc_url.clone(),
+                            chain_id: *chain_id,
+                            contract_address: *contract_address,
+                            ..eth_remote.clone()
+                        });
+                    }
+                }
+                let root_reconcile = root_reconcile.unwrap_or(on_disk_device.root_reconcile());
+                Config::set_eth_mirrors(alias.clone(), eth_mirrors, root_reconcile)?;
+            }
+
+            let s3_flags = s3_endpoint.is_some()
+                || s3_bucket.is_some()
+                || s3_region.is_some()
+                || s3_prefix.is_some()
+                || s3_path_style.is_some()
+                || s3_required.is_some();
+            if s3_bucket.as_deref() == Some("") {
+                Config::set_s3_mirror(alias.clone(), None)?;
+            } else if s3_flags {
+                let mut s3_mirror = match (on_disk_device.s3_mirror(), s3_endpoint, s3_bucket) {
+                    (Some(s3_mirror), _, _) => s3_mirror.clone(),
+                    (None, Some(endpoint), Some(bucket)) => {
+                        S3Remote::new(endpoint.clone(), bucket.clone())
+                    }
+                    (None, _, _) => return Err(DeviceSubcommandError::IncompleteS3Mirror),
+                };
+                if let Some(s3_endpoint) = s3_endpoint {
+                    s3_mirror.endpoint = s3_endpoint.clone();
+                }
+                if let Some(s3_bucket) = s3_bucket {
+                    s3_mirror.bucket = s3_bucket.clone();
+                }
+                if let Some(s3_region) = s3_region {
+                    s3_mirror.region = s3_region.clone();
+                }
+                if let Some(s3_prefix) = s3_prefix {
+                    s3_mirror.prefix = s3_prefix.clone();
+                }
+                if let Some(s3_path_style) = s3_path_style {
+                    s3_mirror.path_style = *s3_path_style;
+                }
+                if let Some(s3_required) = s3_required {
+                    s3_mirror.required = *s3_required;
+                }
+                Config::set_s3_mirror(alias.clone(), Some(s3_mirror))?;
+            }
         }
         DeviceSubcommand::Set { alias } => {
             Config::set_device(alias.clone())?;
@@ -57,4 +285,6 @@ pub fn device_subcommand(
 pub enum DeviceSubcommandError {
     #[error("config error: {0}")]
     Config(#[from] ConfigError),
+    #[error("adding an s3 mirror needs both --s3-endpoint and --s3-bucket")]
+    IncompleteS3Mirror,
 }
@@ -1,32 +1,144 @@
 use std::fs::File;
+use std::io::Cursor;
 use std::path::PathBuf;
 
 use cid::Cid;
 
-use crate::cli::changes::{ChangeType, Log};
+use crate::cli::changes::{ChangeType, HashCache, Log};
 use crate::cli::config::{Config, ConfigError};
-use crate::device::{Device, DeviceError};
+use crate::cli::walk::{link_data, WalkEntry};
+use crate::device::{CidParams, Device, DeviceError};
 
-async fn hash_file(device: &Device, path: &PathBuf) -> Result<Cid, DiffError> {
+/// Hash a file in the working dir, reusing its cached hash if it hasn't changed
+/// Every hash is recorded in `next_cache`
+async fn hash_file(
+    device: &Device,
+    path: &PathBuf,
+    cache: &HashCache,
+    next_cache: &mut HashCache,
+) -> Result<Cid, DiffError> {
     if !path.exists() {
         return Err(DiffError::PathDoesNotExist(path.clone()));
     } else if path.is_dir() {
         return Err(DiffError::PathIsDirectory(path.clone()));
     };
 
+    let metadata = std::fs::metadata(path)?;
+    if let Some(cid) = cache.get(path, &metadata) {
+        next_cache.insert(path.clone(), &metadata, cid);
+        return Ok(cid);
+    }
+
     // Read the file and hash it against our local client
     let file = File::open(path)?;
     let cid = device.hash_ipfs_data(file, false).await?;
+    next_cache.insert(path.clone(), &metadata, cid);
     Ok(cid)
 }
 
-pub async fn diff(config: &Config) -> Result<Log, DiffError> {
+/// Whether an entry still holds the content of an object kept under other params --
+/// added by cid, or re-encoded to them -- hashing it with the cid's own params
+async fn hashes_back(
+    device: &Device,
+    path: &PathBuf,
+    entry: &WalkEntry,
+    cid: &Cid,
+) -> Result<bool, DiffError> {
+    let params = CidParams::of(cid);
+    if params == CidParams::default() {
+        return Ok(false);
+    }
+    let hash = match entry {
+        WalkEntry::Directory => return Ok(false),
+        WalkEntry::File => {
+            let file = File::open(path)?;
+            device.hash_ipfs_data_with(file, params, false).await?
+        }
+        WalkEntry::Link(target) => {
+            let data = Cursor::new(link_data(target));
+            device.hash_ipfs_data_with(data, params, false).await?
+        }
+    };
+    Ok(hash.as_ref() == Some(cid))
+}
+
+/// Refuse a file over max_object_size
+fn check_size(path: &PathBuf, max_object_size: Option<u64>) -> Result<(), DiffError> {
+    if let Some(max_object_size) = max_object_size {
+        let size = std::fs::metadata(path)?.len();
+        if size > max_object_size {
+            return Err(DiffError::ObjectTooLarge(
+                path.clone(),
+                size,
+                max_object_size,
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Hash something found walking the working dir
+/// Links are hashed by their target, and directories aren't hashed at all
+/// Files over max_object_size are refused before they're read
+async fn hash_entry(
+    device: &Device,
+    path: &PathBuf,
+    entry: &WalkEntry,
+    cache: &HashCache,
+    next_cache: &mut HashCache,
+    max_object_size: Option<u64>,
+) -> Result<Option<Cid>, DiffError> {
+    match entry {
+        WalkEntry::Directory => Ok(None),
+        WalkEntry::File => {
+            check_size(path, max_object_size)?;
+            Ok(Some(hash_file(device, path, cache, next_cache).await?))
+        }
+        WalkEntry::Link(target) => {
+            let data = Cursor::new(link_data(target));
+            Ok(Some(device.hash_ipfs_data(data, false).await?))
+        }
+    }
+}
+
+/// Diff the working dir against the stage
+/// # Args
+/// - no_cache: re-hash every file, rather than reusing the hashes of files
+///   whose mtime and size haven't changed
+/// - max_object_size: refuse new files bigger than this many bytes, and tracked
+///   files whose new content is
+pub async fn diff(
+    config: &Config,
+    no_cache: bool,
+    max_object_size: Option<u64>,
+) -> Result<Log, DiffError> {
     let device = config.device()?;
-    let change_log = config.change_log()?;
-    let working_dir = config.working_dir().clone();
+    let cache = if no_cache {
+        HashCache::default()
+    } else {
+        config.hash_cache()?
+    };
+    // Only keep entries for files that are still around
+    let mut next_cache = HashCache::default();
+    // Diff against anything already staged
+    let change_log = config.stage()?;
+    let working_dir = config.data_dir().clone();
     let mut base = change_log.log().clone();
     let mut update = base.clone();
-    let next = config.fs_tree()?;
+    let next = config.walk()?;
+
+    // Paths that weren't checked out aren't removed -- just leave them be
+    let filter = change_log.filter();
+    base.retain(|path, _| filter.matches(path));
+    // Nor are external objects, which never had a file to check out
+    let staged = change_log.last_version().map(|(_cid, staged)| staged);
+    if let Some(staged) = staged {
+        base.retain(|path, _| {
+            !staged.get_object(path).is_some_and(|object| {
+                object.is_external() && std::fs::symlink_metadata(working_dir.join(path)).is_err()
+            })
+        });
+    }
 
     // Insert the root directory hash into the change_log for comparison
     // This should always just get matched out and removed
@@ -40,13 +152,13 @@ pub async fn diff(config: &Config) -> Result<Log, DiffError> {
     let mut base_next = base_iter.next();
 
     loop {
-        match (next_next.clone(), base_next) {
+        match (next_next, base_next) {
             // If these are both something we got some work to do
-            (Some((_next_tree, next_path)), Some((base_path, (base_hash, base_type)))) => {
+            (Some((next_path, next_entry)), Some((base_path, (base_hash, base_type)))) => {
                 // For each object, assuming we stay aligned on a sorted list of paths:
                 // If the base comes before then this file was removed
                 // strip off the base object and log the removal
-                if base_path < &next_path {
+                if base_path < next_path {
                     let working_base_path = working_dir.clone().join(base_path);
                     if !working_base_path.is_dir() {
                         match base_type {
@@ -67,10 +179,18 @@ pub async fn diff(config: &Config) -> Result<Log, DiffError> {
 
                 // If next comes before base then the file was added
                 // strip off the next object and log the addition
-                if &next_path < base_path {
-                    let working_next_path = working_dir.clone().join(next_path.clone());
-                    if !working_next_path.is_dir() {
-                        let hash = hash_file(&device, &working_next_path).await?;
+                if next_path < base_path {
+                    let working_next_path = working_dir.join(next_path);
+                    if let Some(hash) = hash_entry(
+                        &device,
+                        &working_next_path,
+                        next_entry,
+                        &cache,
+                        &mut next_cache,
+                        max_object_size,
+                    )
+                    .await?
+                    {
                         update.insert(next_path.clone(), (hash, ChangeType::Added));
                     }
                     next_next = next_iter.next();
@@ -78,15 +198,50 @@ pub async fn diff(config: &Config) -> Result<Log, DiffError> {
                 }
 
                 // If they are equal then we are good. Move on to the next objects
-                if &next_path == base_path {
+                if next_path == base_path {
                     // These are either both files or both directories
                     // If they are both files then we need to compare hashes
-                    let working_next_path = working_dir.clone().join(next_path.clone());
-                    if !working_next_path.is_dir() {
+                    // Objects already tracked were let in once, so only new content is held
+                    // to the limit -- checked once hashing tells us it's new
+                    let working_next_path = working_dir.join(next_path);
+                    if let Some(next_hash) = hash_entry(
+                        &device,
+                        &working_next_path,
+                        next_entry,
+                        &cache,
+                        &mut next_cache,
+                        None,
+                    )
+                    .await?
+                    {
+                        // An object added by cid or re-encoded may be under other params --
+                        // it's unchanged for as long as it still hashes back to its cid
+                        let next_hash = if base_hash != &next_hash {
+                            match hashes_back(&device, &working_next_path, next_entry, base_hash)
+                                .await?
+                            {
+                                true => {
+                                    if matches!(next_entry, WalkEntry::File) {
+                                        let metadata = std::fs::metadata(&working_next_path)?;
+                                        next_cache.insert(
+                                            working_next_path.clone(),
+                                            &metadata,
+                                            *base_hash,
+                                        );
+                                    }
+                                    *base_hash
+                                }
+                                false => next_hash,
+                            }
+                        } else {
+                            next_hash
+                        };
                         // If the hashes are different then the file was modified
                         // strip off the next object and log the modification
-                        let next_hash = hash_file(&device, &working_next_path).await?;
                         if base_hash != &next_hash {
+                            if matches!(next_entry, WalkEntry::File) {
+                                check_size(&working_next_path, max_object_size)?;
+                            }
                             match base_type {
                                 ChangeType::Added => {
                                     update
@@ -109,10 +264,18 @@ pub async fn diff(config: &Config) -> Result<Log, DiffError> {
             }
 
             // Theres more old file than new, this file was removed
-            (Some((_next_tree, next_path)), None) => {
-                let working_next_path = working_dir.clone().join(next_path.clone());
-                if !working_next_path.is_dir() {
-                    let hash = hash_file(&device, &working_next_path).await?;
+            (Some((next_path, next_entry)), None) => {
+                let working_next_path = working_dir.join(next_path);
+                if let Some(hash) = hash_entry(
+                    &device,
+                    &working_next_path,
+                    next_entry,
+                    &cache,
+                    &mut next_cache,
+                    max_object_size,
+                )
+                .await?
+                {
                     update.insert(next_path.clone(), (hash, ChangeType::Added));
                 }
                 next_next = next_iter.next();
@@ -142,6 +305,8 @@ pub async fn diff(config: &Config) -> Result<Log, DiffError> {
         }
     }
 
+    config.set_hash_cache(&next_cache)?;
+
     Ok(update)
 }
 
@@ -161,4 +326,6 @@ pub enum DiffError {
     PathDoesNotExist(PathBuf),
     #[error("path is a directory")]
     PathIsDirectory(PathBuf),
+    #[error("{0:?} is {1} bytes, over the {2} byte object size limit -- use --allow-large to add it anyway")]
+    ObjectTooLarge(PathBuf, u64, u64),
 }
@@ -0,0 +1,125 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+use crate::device::{decode_dor_store, DeviceError};
+use crate::types::{DorStore, ManifestDiff, MetadataChange};
+
+/// Compare two DorStore manifests on disk, without touching the network
+/// Prints objects added and removed, objects whose content changed, and objects
+/// whose metadata changed, each grouped under its kind, then store level differences
+/// An object that was only re-tagged shows up under metadata alone
+/// # Args
+/// - a: the manifest to diff from
+/// - b: the manifest to diff to
+pub fn diff_manifest(a: &PathBuf, b: &PathBuf) -> Result<(), DiffManifestError> {
+    let a = read_manifest(a)?;
+    let b = read_manifest(b)?;
+    print_manifest_diff(&a, &b);
+    Ok(())
+}
+
+/// Print how two manifests differ, grouped by kind of change
+pub(super) fn print_manifest_diff(a: &DorStore, b: &DorStore) {
+    print_object_diff(&a.diff(b));
+    print_store_diff(a, b);
+}
+
+/// Print how the objects of two manifests differ, grouped by kind of change
+pub(super) fn print_object_diff(diff: &ManifestDiff) {
+    if !diff.added.is_empty() {
+        println!("added:");
+        for (path, cid) in diff.added.iter() {
+            println!("+ {} {}", path.display(), cid);
+        }
+    }
+    if !diff.removed.is_empty() {
+        println!("removed:");
+        for (path, cid) in diff.removed.iter() {
+            println!("- {} {}", path.display(), cid);
+        }
+    }
+    if !diff.content.is_empty() {
+        println!("content changed:");
+        for (path, from, to) in diff.content.iter() {
+            println!("~ {} {} -> {}", path.display(), from, to);
+        }
+    }
+    if !diff.metadata.is_empty() {
+        println!("metadata changed:");
+        for (path, change) in diff.metadata.iter() {
+            match change {
+                MetadataChange::Entry {
+                    schema_name,
+                    from,
+                    to,
+                } => println!(
+                    "~ {} [{}] {} -> {}",
+                    path.display(),
+                    schema_name,
+                    display_value(from.as_ref()),
+                    display_value(to.as_ref())
+                ),
+                MetadataChange::Mtime { from, to } => println!(
+                    "~ {} mtime {} -> {}",
+                    path.display(),
+                    display_mtime(from.as_ref()),
+                    display_mtime(to.as_ref())
+                ),
+            }
+        }
+    }
+}
+
+/// Print how two manifests differ, past their objects
+pub(super) fn print_store_diff(a: &DorStore, b: &DorStore) {
+    if a.previous_root() != b.previous_root() {
+        println!(
+            "previous root: {} -> {}",
+            a.previous_root(),
+            b.previous_root()
+        );
+    }
+    let schema_names: BTreeSet<&String> = a.schema().names().chain(b.schema().names()).collect();
+    for schema_name in schema_names {
+        let a_fields = a.schema().get(schema_name);
+        let fields = b.schema().get(schema_name);
+        if a_fields != fields {
+            println!(
+                "schema [{}]: {:?} -> {:?}",
+                schema_name,
+                a_fields.cloned().unwrap_or_default(),
+                fields.cloned().unwrap_or_default()
+            );
+        }
+    }
+}
+
+fn read_manifest(path: &PathBuf) -> Result<DorStore, DiffManifestError> {
+    let manifest = std::fs::read(path).map_err(|e| DiffManifestError::Read(path.clone(), e))?;
+    decode_dor_store(&manifest).map_err(|e| DiffManifestError::Parse(path.clone(), e))
+}
+
+fn display_value(value: Option<&Value>) -> String {
+    match value {
+        Some(value) => value.to_string(),
+        None => "none".to_string(),
+    }
+}
+
+fn display_mtime(mtime: Option<&DateTime<Utc>>) -> String {
+    match mtime {
+        Some(mtime) => mtime.to_rfc3339(),
+        None => "none".to_string(),
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DiffManifestError {
+    #[error("failed to read {0:?}: {1}")]
+    Read(PathBuf, std::io::Error),
+    #[error("failed to parse {0:?} as a manifest: {1}")]
+    Parse(PathBuf, DeviceError),
+}
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use cid::Cid;
+
+use crate::cli::cid_arg::RootArg;
+use crate::cli::config::{Config, ConfigError};
+use crate::device::{block_links, BlockSourceError, Device, DeviceError};
+
+const RAW_CODEC: u64 = 0x55;
+
+/// Sizes of a root's dag, both as stored and as if nothing were shared
+#[derive(Debug, Default)]
+struct DuReport {
+    /// Distinct blocks reachable from the root and its objects
+    blocks: usize,
+    /// Bytes across those blocks, each counted once
+    total: u64,
+    /// Bytes across the blocks reachable from objects, each counted once
+    objects: u64,
+    /// Bytes each object's dag would take on its own, summed over objects
+    naive: u64,
+}
+
+impl Display for DuReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let saved = self.naive.saturating_sub(self.objects);
+        writeln!(f, "blocks: {}", self.blocks)?;
+        writeln!(f, "total size: {}", self.total)?;
+        writeln!(f, "objects size: {}", self.objects)?;
+        writeln!(f, "naive objects size: {}", self.naive)?;
+        let percent = if self.naive > 0 {
+            saved as f64 * 100.0 / self.naive as f64
+        } else {
+            0.0
+        };
+        write!(f, "dedup savings: {} ({:.1}%)", saved, percent)
+    }
+}
+
+/// Walks blocks, remembering only the size of what's under each cid it has seen
+/// Block data is dropped as soon as its links are read, so large dags only cost a
+/// cid and a number per block
+struct DagSizer<'a> {
+    device: &'a Device,
+    remote: bool,
+    /// Cumulative size of the dag below each block walked, counting each reference
+    cumulative: HashMap<Cid, u64>,
+}
+
+impl<'a> DagSizer<'a> {
+    fn new(device: &'a Device, remote: bool) -> Self {
+        Self {
+            device,
+            remote,
+            cumulative: HashMap::new(),
+        }
+    }
+
+    /// Read a block's size and links. Raw leaves are only stat'd
+    async fn block(&self, cid: &Cid) -> Result<(u64, Vec<Cid>), DuError> {
+        if cid.codec() == RAW_CODEC {
+            let size = self
+                .device
+                .stat_ipfs_data(cid, self.remote)
+                .await?
+                .ok_or(DuError::Missing(*cid))?;
+            return Ok((size, Vec::new()));
+        }
+        let block = self.device.read_ipfs_data(cid, self.remote).await?;
+        let links = block_links(cid, &block)?;
+        Ok((block.len() as u64, links))
+    }
+
+    /// Walk the dag below a cid, skipping blocks already walked
+    /// # Returns the bytes of blocks seen for the first time, and the dag's
+    ///   cumulative size as if nothing in it were shared
+    async fn walk(&mut self, root: &Cid) -> Result<(u64, u64), DuError> {
+        let mut new_bytes = 0;
+        // Blocks whose links are still being walked, with their size and links
+        let mut open: HashMap<Cid, (u64, Vec<Cid>)> = HashMap::new();
+        let mut stack = vec![(*root, false)];
+        while let Some((cid, links_done)) = stack.pop() {
+            if links_done {
+                let (size, links) = open.remove(&cid).unwrap();
+                let below: u64 = links.iter().map(|link| self.cumulative[link]).sum();
+                self.cumulative.insert(cid, size + below);
+                continue;
+            }
+            if self.cumulative.contains_key(&cid) || open.contains_key(&cid) {
+                continue;
+            }
+            let (size, links) = self.block(&cid).await?;
+            new_bytes += size;
+            stack.push((cid, true));
+            for link in links.iter() {
+                stack.push((*link, false));
+            }
+            open.insert(cid, (size, links));
+        }
+        Ok((new_bytes, self.cumulative[root]))
+    }
+}
+
+/// Report the size of a root's full dag -- the manifest, the directory tree of
+/// directory roots, and every object -- with shared blocks counted once, next to
+/// what the objects would take if nothing were deduplicated
+/// # Args
+/// - root: the root or tag to size
+/// - remote: whether to walk it against the remote rather than the local node
+pub async fn du(config: &Config, root: &RootArg, remote: bool) -> Result<(), DuError> {
+    let root = &config.resolve_root(root)?;
+    let device = config.device()?;
+    let dor_store = device.read_dor_store(root, remote).await?;
+    let mut sizer = DagSizer::new(&device, remote);
+    let mut report = DuReport::default();
+
+    for object in dor_store.objects().values() {
+        let (new_bytes, cumulative) = sizer.walk(object.cid()).await?;
+        report.objects += new_bytes;
+        report.naive += cumulative;
+    }
+    let (new_bytes, _) = sizer.walk(root).await?;
+    report.total = report.objects + new_bytes;
+    report.blocks = sizer.cumulative.len();
+
+    println!("{}", report);
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DuError {
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("device error: {0}")]
+    Device(#[from] DeviceError),
+    #[error("block error: {0}")]
+    BlockSource(#[from] BlockSourceError),
+    #[error("block {0} not found")]
+    Missing(Cid),
+}
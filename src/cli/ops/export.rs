@@ -0,0 +1,146 @@
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use cid::Cid;
+use serde::{Deserialize, Serialize};
+
+use super::cat::{resolve_object, ResolveError};
+use crate::cli::cid_arg::RootArg;
+use crate::cli::config::{Config, ConfigError};
+use crate::device::DeviceError;
+
+/// Name of the sidecar index recording sniffed content types
+pub const MIME_INDEX_NAME: &str = "mime.json";
+
+/// A sniffed object within an export
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SniffedObject {
+    /// Detected MIME type
+    pub mime: String,
+    /// Path the object was exported to, relative to the export dir
+    pub path: PathBuf,
+}
+
+/// Sniff the content type of some data from its magic bytes
+/// Returns the MIME type and its canonical extension
+pub fn sniff(data: &[u8]) -> Option<(String, String)> {
+    infer::get(data).map(|t| (t.mime_type().to_string(), t.extension().to_string()))
+}
+
+/// Append an extension to a path if it doesn't already have one
+fn with_extension(path: &Path, extension: &str) -> PathBuf {
+    match path.extension() {
+        Some(_) => path.to_path_buf(),
+        None => path.with_extension(extension),
+    }
+}
+
+/// Export the objects of the current base to a directory by their paths
+/// # Args
+/// - out_dir: where to write objects to
+/// - root: export from this root or tag's manifest, read from the remote, rather
+///   than the current base
+/// - sniff_types: append sniffed extensions to paths that lack one and record
+///   detected MIME types in a sidecar index. Exports are otherwise byte-identical.
+///   A path another object is exported to keeps its name without the extension
+/// - path, cid: only export the one object at this path, or with this cid. A cid
+///   alone is exported under its own name. Checked against each other if both are given
+/// Files are given the mtimes their objects recorded, if any
+pub async fn export(
+    config: &Config,
+    out_dir: &Path,
+    sniff_types: bool,
+    root: Option<&RootArg>,
+    path: Option<&Path>,
+    cid: Option<&Cid>,
+) -> Result<(), ExportError> {
+    let device = config.device()?;
+    let base = match root {
+        Some(root) => {
+            let root_cid = config.resolve_root(root)?;
+            device.read_dor_store(&root_cid, true).await?
+        }
+        None => config.base()?,
+    };
+
+    let objects: Vec<(PathBuf, Cid, Option<DateTime<Utc>>)> = match (path, cid) {
+        (None, None) => base
+            .objects()
+            .iter()
+            .map(|(path, object)| (path.clone(), *object.cid(), object.mtime().cloned()))
+            .collect(),
+        (None, Some(cid)) => vec![(PathBuf::from(cid.to_string()), *cid, None)],
+        (Some(path), _) => {
+            let cid = resolve_object(&base, Some(path), cid)?;
+            let mtime = base
+                .get_object(&path.to_path_buf())
+                .and_then(|object| object.mtime().cloned());
+            vec![(path.to_path_buf(), cid, mtime)]
+        }
+    };
+
+    let mut mime_index = BTreeMap::new();
+    // Paths objects are exported to, so a sniffed extension never lands on another's
+    let mut taken: HashSet<PathBuf> = objects.iter().map(|(path, _, _)| path.clone()).collect();
+
+    for (path, cid, mtime) in objects.iter() {
+        let data = device.read_object(cid).await?;
+
+        let mut export_path = path.clone();
+        if sniff_types {
+            if let Some((mime, extension)) = sniff(&data) {
+                let sniffed_path = with_extension(path, &extension);
+                if sniffed_path == *path || taken.insert(sniffed_path.clone()) {
+                    export_path = sniffed_path;
+                } else {
+                    tracing::warn!(
+                        "not exporting {} as {}, which another object is exported to",
+                        path.display(),
+                        sniffed_path.display()
+                    );
+                }
+                mime_index.insert(
+                    path.clone(),
+                    SniffedObject {
+                        mime,
+                        path: export_path.clone(),
+                    },
+                );
+            }
+        }
+
+        let out_path = out_dir.join(&export_path);
+        if let Some(parent) = out_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = std::fs::File::create(out_path)?;
+        file.write_all(&data)?;
+        if let Some(mtime) = mtime {
+            file.set_modified((*mtime).into())?;
+        }
+    }
+
+    if sniff_types {
+        let mime_index_str = serde_json::to_string_pretty(&mime_index)?;
+        let mut mime_index_file = std::fs::File::create(out_dir.join(MIME_INDEX_NAME))?;
+        mime_index_file.write_all(mime_index_str.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExportError {
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("device error: {0}")]
+    Device(#[from] DeviceError),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("serde error: {0}")]
+    Serde(#[from] serde_json::Error),
+    #[error("{0}")]
+    Resolve(#[from] ResolveError),
+}
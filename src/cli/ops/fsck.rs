@@ -0,0 +1,250 @@
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{Cursor, Read};
+use std::path::PathBuf;
+
+use cid::Cid;
+
+use crate::cli::cancel::CancelReason;
+use crate::cli::config::{Config, ConfigError};
+use crate::cli::walk::link_data;
+use crate::device::{BlockStatus, CidParams, Device, DeviceError, ManifestCompression, RootLayout};
+use crate::types::{DorStore, Object};
+
+/// Where a missing object was recovered from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepairSource {
+    /// A matching file in the working directory
+    WorkingDir,
+    /// The local ipfs node
+    LocalIpfs,
+    /// The on disk base dor store
+    Base,
+    /// The target a link is stored as
+    Link,
+}
+
+impl Display for RepairSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::WorkingDir => "working dir",
+            Self::LocalIpfs => "local ipfs",
+            Self::Base => "on disk base",
+            Self::Link => "link target",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Try to re-pin a missing object against the remote, under the params its cid
+/// was made with
+/// Links are re-added from their target. Otherwise prefers the working copy,
+/// falling back to the local ipfs node -- external objects have no working copy,
+/// so only the node can bring them back
+async fn repair_object(
+    device: &Device,
+    working_path: &PathBuf,
+    object: &Object,
+) -> Result<Option<RepairSource>, FsckError> {
+    let cid = object.cid();
+    let params = CidParams::of(cid);
+    if let Some(target) = object.link() {
+        let data = Cursor::new(link_data(target));
+        return Ok(repin(device, data, params, cid)
+            .await?
+            .then_some(RepairSource::Link));
+    }
+
+    // Only trust the working copy if it still hashes to the expected cid
+    if !object.is_external() && working_path.is_file() {
+        let file = File::open(working_path)?;
+        let hash = device.hash_ipfs_data_with(file, params, false).await?;
+        if hash == Some(*cid) {
+            let file = File::open(working_path)?;
+            return Ok(repin(device, file, params, cid)
+                .await?
+                .then_some(RepairSource::WorkingDir));
+        }
+    }
+
+    if device.stat_ipfs_data(cid, false).await?.is_some() {
+        let data = Cursor::new(device.cat_ipfs_data(cid, false).await?);
+        return Ok(repin(device, data, params, cid)
+            .await?
+            .then_some(RepairSource::LocalIpfs));
+    }
+
+    Ok(None)
+}
+
+/// Add data to the remote under a cid's params, checking it comes back as the cid
+/// # Returns false if the node can't add data under those params
+async fn repin<R>(device: &Device, data: R, params: CidParams, cid: &Cid) -> Result<bool, FsckError>
+where
+    R: 'static + Read + Send + Sync + Unpin,
+{
+    match device.write_ipfs_data_with(data, params, true).await? {
+        Some(pinned_cid) if pinned_cid == *cid => Ok(true),
+        Some(pinned_cid) => Err(FsckError::CidMismatch(pinned_cid, *cid)),
+        None => {
+            tracing::warn!("can't re-add {} -- the node doesn't take {}", cid, params);
+            Ok(false)
+        }
+    }
+}
+
+/// Try to re-pin a missing manifest from the on disk base, the way its root was
+/// written. The device's layout, compression, and deltas may have changed since,
+/// so its own are tried first, then the rest, until one hashes back to the root
+/// # Returns whether the root was repaired
+async fn repair_manifest(
+    config: &Config,
+    root_cid: &Cid,
+    base: &DorStore,
+) -> Result<bool, FsckError> {
+    let on_disk_device = config.on_disk_device()?;
+    let current = (
+        on_disk_device.root_layout(),
+        on_disk_device.manifest_compression(),
+        on_disk_device.delta_manifests(),
+    );
+    let mut settings = Vec::new();
+    for root_layout in [RootLayout::Manifest, RootLayout::Directory] {
+        for compression in [ManifestCompression::None, ManifestCompression::Zstd] {
+            for delta_manifests in [false, true] {
+                settings.push((root_layout, compression, delta_manifests));
+            }
+        }
+    }
+    settings.sort_by_key(|setting| *setting != current);
+
+    for (root_layout, compression, delta_manifests) in settings {
+        let device = config
+            .device()?
+            .with_root_layout(root_layout)
+            .with_manifest_compression(compression)
+            .with_delta_manifests(delta_manifests);
+        // A delta needs the root it's on top of, which may be gone too
+        match device.hash_dor_store(base, true).await {
+            Ok(hash) if hash == *root_cid => {}
+            Ok(_) => continue,
+            Err(e) => {
+                tracing::debug!("can't hash the manifest as {:?}: {}", root_layout, e);
+                continue;
+            }
+        }
+        let pinned_cid = device.write_dor_store(base, true).await?;
+        return Ok(pinned_cid == *root_cid);
+    }
+    Ok(false)
+}
+
+/// Walk the current root, checking every object is present on the remote
+/// and re-pinning any that are missing from the working dir or local ipfs node.
+/// Safe to run repeatedly -- a healthy store is a no-op
+/// Stops between objects once the config's cancellation says so, reporting what
+/// was checked so far. A repair under way is seen through first
+pub async fn fsck(config: &Config) -> Result<(), FsckError> {
+    let device = config.device()?;
+    let working_dir = config.data_dir().clone();
+    let root_cid = config.root_cid()?;
+    let base = config.base()?;
+
+    let mut report = FsckReport::default();
+
+    if root_cid == Cid::default() {
+        tracing::info!("root cid is not set");
+        println!("{}", report);
+        return Ok(());
+    }
+
+    // Make sure the manifest itself is still pinned
+    if device.stat_ipfs_data(&root_cid, true).await?.is_none() {
+        let manifest_path = PathBuf::from("");
+        report.missing.push((manifest_path.clone(), root_cid));
+        if repair_manifest(config, &root_cid, &base).await? {
+            report
+                .repaired
+                .push((manifest_path, root_cid, RepairSource::Base));
+        } else {
+            report.unrecoverable.push((manifest_path, root_cid));
+        }
+    }
+
+    for (path, object) in base.objects().iter() {
+        if let Err(reason) = config.cancellation().check() {
+            println!("{}", report);
+            return Err(FsckError::Cancelled(reason));
+        }
+        report.checked += 1;
+        let cid = object.cid();
+        // Don't re-pin anything the pinning service is still working on
+        if device.block_status(cid, true).await? != BlockStatus::Absent {
+            continue;
+        }
+        report.missing.push((path.clone(), *cid));
+
+        let working_path = working_dir.join(path);
+        match repair_object(&device, &working_path, object).await? {
+            Some(source) => report.repaired.push((path.clone(), *cid, source)),
+            None => report.unrecoverable.push((path.clone(), *cid)),
+        }
+    }
+
+    println!("{}", report);
+
+    if !report.unrecoverable.is_empty() {
+        let paths = report
+            .unrecoverable
+            .into_iter()
+            .map(|(path, _cid)| path)
+            .collect();
+        return Err(FsckError::Unrecoverable(paths));
+    }
+
+    Ok(())
+}
+
+#[derive(Default)]
+struct FsckReport {
+    checked: usize,
+    missing: Vec<(PathBuf, Cid)>,
+    repaired: Vec<(PathBuf, Cid, RepairSource)>,
+    unrecoverable: Vec<(PathBuf, Cid)>,
+}
+
+impl Display for FsckReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "checked: {}, missing: {}, repaired: {}, unrecoverable: {}",
+            self.checked,
+            self.missing.len(),
+            self.repaired.len(),
+            self.unrecoverable.len()
+        )?;
+        for (path, cid, source) in self.repaired.iter() {
+            writeln!(f, "repaired {} ({}) from {}", path.display(), cid, source)?;
+        }
+        for (path, cid) in self.unrecoverable.iter() {
+            writeln!(f, "unrecoverable {} ({})", path.display(), cid)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FsckError {
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("device error: {0}")]
+    Device(#[from] DeviceError),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("cid mismatch: {0} != {1}")]
+    CidMismatch(Cid, Cid),
+    #[error("could not recover objects: {0:?}")]
+    Unrecoverable(Vec<PathBuf>),
+    #[error("fsck stopped, {0}")]
+    Cancelled(CancelReason),
+}
@@ -0,0 +1,38 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+
+use crate::device::{FileHasher, IpfsError};
+
+/// How much of a file to read into the hasher at a time
+const HASH_BUFFER_SIZE: usize = 1 << 20;
+
+/// Print the cid a push would give a local file, computed without a node
+/// The file is streamed through the hasher, so it's never held whole
+pub fn hash(path: &PathBuf) -> Result<(), HashError> {
+    if std::fs::metadata(path)?.is_dir() {
+        return Err(HashError::PathIsDirectory(path.clone()));
+    }
+    let mut file = File::open(path)?;
+    let mut hasher = FileHasher::new();
+    let mut buffer = vec![0; HASH_BUFFER_SIZE];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read])?;
+    }
+    println!("{}", hasher.finish()?);
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum HashError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("ipfs error: {0}")]
+    Ipfs(#[from] IpfsError),
+    #[error("path is a directory: {0:?}")]
+    PathIsDirectory(PathBuf),
+}
@@ -18,6 +18,8 @@ pub async fn health(config: &Config) -> Result<(), HealthError> {
     };
     let eth_online = root_cid.is_some();
 
+    let contract_deployed = device.contract_deployed().await.ok();
+
     let local_ipfs_online = device.ipfs_id(false).await.is_ok();
 
     let ipfs_online = device.ipfs_id(true).await.is_ok();
@@ -29,6 +31,7 @@ pub async fn health(config: &Config) -> Result<(), HealthError> {
         local_ipfs_online,
         ipfs_online,
         eth_online,
+        contract_deployed,
     };
 
     println!("{}", report);
@@ -42,6 +45,7 @@ struct HealthReport {
     local_ipfs_online: bool,
     ipfs_online: bool,
     eth_online: bool,
+    contract_deployed: Option<bool>,
 }
 
 impl Display for HealthReport {
@@ -68,11 +72,16 @@ impl Display for HealthReport {
             "offline"
         };
         let eth_online = if self.eth_online { "online" } else { "offline" };
+        let contract_deployed = match self.contract_deployed {
+            Some(true) => "deployed",
+            Some(false) => "no code",
+            None => "unknown",
+        };
 
         write!(
             f,
-            "alias: {}, chain_id: {}, root_cid: {}, local_ipfs: {}, ipfs: {}, eth: {}",
-            alias, chain_id, root_cid, local_ipfs_online, ipfs_online, eth_online
+            "alias: {}, chain_id: {}, root_cid: {}, local_ipfs: {}, ipfs: {}, eth: {}, contract: {}",
+            alias, chain_id, root_cid, local_ipfs_online, ipfs_online, eth_online, contract_deployed
         )
     }
 }
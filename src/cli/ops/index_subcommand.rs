@@ -0,0 +1,30 @@
+use super::diff::{diff, DiffError};
+use crate::cli::args::IndexSubcommand;
+use crate::cli::changes::HashCache;
+use crate::cli::config::{Config, ConfigError};
+
+pub async fn index_subcommand(
+    config: &Config,
+    subcommand: &IndexSubcommand,
+) -> Result<(), IndexSubcommandError> {
+    match subcommand {
+        IndexSubcommand::Rebuild => {
+            // Diffing without the index re-hashes every file, and records each hash
+            diff(config, true, None).await?;
+            println!("indexed {} files", config.hash_cache()?.len());
+        }
+        IndexSubcommand::Clear => {
+            config.set_hash_cache(&HashCache::default())?;
+            println!("cleared the index");
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum IndexSubcommandError {
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("diff error: {0}")]
+    Diff(#[from] DiffError),
+}
@@ -0,0 +1,53 @@
+use crate::cli::config::{Config, ConfigError};
+use crate::cli::lint::lint_manifest;
+use crate::device::CidParams;
+
+/// Check the base manifest, and what's staged on top of it, for corruption before
+/// it's pushed: cids that don't parse or weren't made the way we make them, paths
+/// that collide once normalized, a malformed previous root, and tags that don't
+/// fit the schema. Never touches the network
+/// Prints every problem found, failing if there were any
+pub fn lint(config: &Config) -> Result<(), LintError> {
+    let params = CidParams::default();
+    let base = config.raw_base()?;
+    let mut problems = lint_manifest(&base, params)
+        .into_iter()
+        .map(|issue| format!("base: {}", issue))
+        .collect::<Vec<_>>();
+
+    // The staged manifest was parsed to get here, so only collisions already
+    // resolved by normalizing paths can hide from this. Compare it to the base as
+    // parsed too, since the base on disk needn't be laid out the way we'd write it
+    let change_log = config.stage()?;
+    if let (Some((_, base)), Some((_, staged))) =
+        (change_log.first_version(), change_log.last_version())
+    {
+        if staged != base {
+            let staged = serde_json::to_string(staged)?;
+            problems.extend(
+                lint_manifest(&staged, params)
+                    .into_iter()
+                    .map(|issue| format!("staged: {}", issue)),
+            );
+        }
+    }
+
+    if problems.is_empty() {
+        println!("no problems found");
+        return Ok(());
+    }
+    for problem in problems.iter() {
+        println!("{}", problem);
+    }
+    Err(LintError::Problems(problems.len()))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LintError {
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("failed to serialize the staged manifest: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("found {0} problems")]
+    Problems(usize),
+}
@@ -0,0 +1,28 @@
+use cid::Cid;
+
+use crate::cli::config::{Config, ConfigError};
+use crate::device::DeviceError;
+
+/// List the entries of a unix-fs directory by its cid
+/// # Args
+/// - cid: the directory to list. Files list as themselves
+/// - remote: whether to list against the remote rather than the local node
+pub async fn ls(config: &Config, cid: &Cid, remote: bool) -> Result<(), LsError> {
+    let device = config.device()?;
+    let entries = device.list_ipfs_dir(cid, remote).await?;
+    for entry in entries {
+        println!(
+            "{}\t{}\t{}\t{}",
+            entry.kind, entry.cid, entry.size, entry.name
+        );
+    }
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LsError {
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("device error: {0}")]
+    Device(#[from] DeviceError),
+}
@@ -0,0 +1,137 @@
+use crate::cli::args::ManifestDbSubcommand;
+use crate::cli::config::{Config, ConfigError};
+
+#[cfg(feature = "sqlite")]
+use std::collections::BTreeMap;
+#[cfg(feature = "sqlite")]
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "sqlite")]
+use super::diff_manifest::{print_object_diff, print_store_diff};
+#[cfg(feature = "sqlite")]
+use crate::device::{decode_dor_store, DeviceError};
+#[cfg(feature = "sqlite")]
+use crate::types::{DorStore, SqliteManifest, SqliteManifestError};
+
+/// Convert manifests between json and SQLite databases, and work with the
+/// databases without loading them whole
+/// The working dir's own manifests stay json -- databases are copies of them
+/// Requires the `sqlite` feature
+#[cfg(feature = "sqlite")]
+pub fn manifest_db_subcommand(
+    config: &Config,
+    subcommand: &ManifestDbSubcommand,
+) -> Result<(), ManifestDbError> {
+    match subcommand {
+        ManifestDbSubcommand::Export { db, staged } => {
+            let dor_store = if *staged {
+                let change_log = config.stage()?;
+                change_log.last_version().unwrap().1.clone()
+            } else {
+                config.base()?
+            };
+            let sizes = known_sizes(config, &dor_store)?;
+            SqliteManifest::open(db)?.write_dor_store(&dor_store, &sizes)?;
+            println!(
+                "wrote {} objects to {}, {} with sizes",
+                dor_store.objects().len(),
+                db.display(),
+                sizes.len()
+            );
+        }
+        ManifestDbSubcommand::FromJson { manifest, db } => {
+            let data = read(manifest)?;
+            let dor_store = decode_dor_store(&data)
+                .map_err(|e| ManifestDbError::Parse(manifest.display().to_string(), e))?;
+            SqliteManifest::open(db)?.write_dor_store(&dor_store, &BTreeMap::new())?;
+            println!(
+                "wrote {} objects to {}",
+                dor_store.objects().len(),
+                db.display()
+            );
+        }
+        ManifestDbSubcommand::ToJson { db, out } => {
+            let dor_store = open(db)?.read_dor_store()?;
+            let data = serde_json::to_vec(&dor_store)?;
+            std::fs::write(out, data)
+                .map_err(|e| ManifestDbError::Io(out.display().to_string(), e))?;
+            println!(
+                "wrote {} objects to {}",
+                dor_store.objects().len(),
+                out.display()
+            );
+        }
+        ManifestDbSubcommand::Diff { a, b } => {
+            let a = open(a)?;
+            let b = open(b)?;
+            print_object_diff(&a.diff(&b)?);
+            print_store_diff(&a.read_header()?, &b.read_header()?);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub fn manifest_db_subcommand(
+    _config: &Config,
+    _subcommand: &ManifestDbSubcommand,
+) -> Result<(), ManifestDbError> {
+    Err(ManifestDbError::SqliteDisabled)
+}
+
+/// Sizes of the objects whose files in the working dir were last hashed to the
+/// object's cid, as the index recorded them
+#[cfg(feature = "sqlite")]
+fn known_sizes(
+    config: &Config,
+    dor_store: &DorStore,
+) -> Result<BTreeMap<PathBuf, u64>, ManifestDbError> {
+    let hash_cache = config.hash_cache()?;
+    let data_dir = config.data_dir();
+    Ok(dor_store
+        .objects()
+        .iter()
+        .filter_map(|(path, object)| {
+            let size = hash_cache.size_of(&data_dir.join(path), object.cid())?;
+            Some((path.clone(), size))
+        })
+        .collect())
+}
+
+/// Open a manifest database that has to exist already
+#[cfg(feature = "sqlite")]
+fn open(db: &Path) -> Result<SqliteManifest, ManifestDbError> {
+    if !db.is_file() {
+        return Err(ManifestDbError::Missing(db.display().to_string()));
+    }
+    Ok(SqliteManifest::open(db)?)
+}
+
+#[cfg(feature = "sqlite")]
+fn read(path: &Path) -> Result<Vec<u8>, ManifestDbError> {
+    std::fs::read(path).map_err(|e| ManifestDbError::Io(path.display().to_string(), e))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ManifestDbError {
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+    #[cfg(feature = "sqlite")]
+    #[error("manifest database error: {0}")]
+    Sqlite(#[from] SqliteManifestError),
+    #[cfg(feature = "sqlite")]
+    #[error("serde json error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[cfg(feature = "sqlite")]
+    #[error("failed to access {0}: {1}")]
+    Io(String, std::io::Error),
+    #[cfg(feature = "sqlite")]
+    #[error("failed to parse manifest {0}: {1}")]
+    Parse(String, DeviceError),
+    #[cfg(feature = "sqlite")]
+    #[error("no manifest database at {0}")]
+    Missing(String),
+    #[cfg(not(feature = "sqlite"))]
+    #[error("manifest databases need dor-store built with the `sqlite` feature")]
+    SqliteDisabled,
+}
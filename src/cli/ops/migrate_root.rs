@@ -0,0 +1,96 @@
+use std::io::Write;
+
+use cid::Cid;
+use ethers::types::Address;
+use url::Url;
+
+use super::push::{audit_root_update, sync_s3_mirror, PushError};
+use crate::cli::config::{Config, ConfigError};
+use crate::device::{DeviceError, EthRemote, RootUpdateReceipt};
+
+/// Seed a newly deployed RootCid contract with the device's current root
+/// The migration is recorded against the device, with the transactions on both ends
+/// # Args
+/// - to_rpc, to_chain_id, to_contract_address: where the new contract lives
+/// - switch: point the device at the new contract once it's seeded
+/// - yes: skip confirming the seed, even where the device asks for it
+pub async fn migrate_root(
+    config: &Config,
+    to_rpc: &Url,
+    to_chain_id: u16,
+    to_contract_address: Address,
+    switch: bool,
+    yes: bool,
+) -> Result<(), MigrateRootError> {
+    let on_disk_device = config.on_disk_device()?;
+    let eth_remote = on_disk_device.eth_remote().clone();
+    let to = EthRemote {
+        rpc_url: to_rpc.clone(),
+        chain_id: to_chain_id,
+        contract_address: to_contract_address,
+        ..eth_remote.clone()
+    };
+    if to.chain_id == eth_remote.chain_id && to.contract_address == eth_remote.contract_address {
+        return Err(MigrateRootError::SameContract);
+    }
+
+    if !yes && eth_remote.confirm_updates.required(to.chain_id) {
+        println!("chain id: {}", to.chain_id);
+        println!("contract: {:?}", to.contract_address);
+        print!("seed contract with the current root? [y/N] ");
+        std::io::stdout().flush()?;
+        let mut input = String::new();
+        std::io::stdin().read_line(&mut input)?;
+        if !matches!(input.trim().to_lowercase().as_str(), "y" | "yes") {
+            return Err(MigrateRootError::Aborted);
+        }
+    }
+
+    let device = config.device()?;
+    let migration = device.migrate_root_cid(to.clone()).await?;
+    println!("{}", migration);
+    let root_cid = migration.root_cid;
+    let seed_receipt = migration
+        .to_tx_hash
+        .clone()
+        .map(|tx_hash| RootUpdateReceipt {
+            id: tx_hash,
+            block_number: None,
+            gas_used: None,
+        });
+
+    let switch_to = if switch { Some(to) } else { None };
+    Config::record_root_migration(on_disk_device.alias(), migration, switch_to)?;
+    // The seed is an update over the new contract's unset root
+    if let Some(receipt) = &seed_receipt {
+        audit_root_update(
+            config,
+            to_chain_id,
+            &to_contract_address,
+            &Cid::default(),
+            &root_cid,
+            receipt,
+        )?;
+    }
+
+    // The root's the same, but the mirror may have missed it
+    let base = config.base()?;
+    sync_s3_mirror(config, &device, &base, base.previous_root(), &root_cid).await?;
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MigrateRootError {
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("device error: {0}")]
+    Device(#[from] DeviceError),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("push error: {0}")]
+    Push(#[from] PushError),
+    #[error("the new contract is the one the device already uses")]
+    SameContract,
+    #[error("migration aborted")]
+    Aborted,
+}
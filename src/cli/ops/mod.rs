@@ -1,16 +1,84 @@
+mod add_cid;
+mod bench;
+mod blame;
+mod cache_subcommand;
+mod cat;
+mod cid_info;
+mod commit;
+mod dag_get;
 mod device_subcommand;
 mod diff;
+mod diff_manifest;
+mod du;
+mod export;
+mod fsck;
+mod hash;
 mod health;
+mod index_subcommand;
 mod init;
+mod lint;
+mod ls;
+mod manifest_db;
+mod migrate_root;
+mod patch;
 mod pull;
 mod push;
+mod push_subcommand;
+mod reconcile;
+mod reencode;
+mod replicate;
+mod reset;
+mod root_plan;
 mod schema_subcommand;
+mod serve;
+mod set_previous_root;
+mod squash;
 mod stage;
+mod stat;
+mod tag;
+mod verify;
+mod verify_car;
+mod watch;
+mod where_pinned;
 
+pub use add_cid::{add_cid, AddCidError};
+pub use bench::{bench, BenchError};
+pub use blame::{blame, BlameError};
+pub use cache_subcommand::{cache_subcommand, CacheSubcommandError};
+pub use cat::{cat, CatError};
+pub use cid_info::{cid_info, CidInfoError};
+pub use commit::{commit, CommitError};
+pub use dag_get::{dag_get, DagGetError};
 pub use device_subcommand::{device_subcommand, DeviceSubcommandError};
+pub use diff_manifest::{diff_manifest, DiffManifestError};
+pub use du::{du, DuError};
+pub use export::{export, ExportError};
+pub use fsck::{fsck, FsckError};
+pub use hash::{hash, HashError};
 pub use health::{health, HealthError};
+pub use index_subcommand::{index_subcommand, IndexSubcommandError};
 pub use init::{init, InitError};
-pub use pull::{pull, PullError};
+pub use lint::{lint, LintError};
+pub use ls::{ls, LsError};
+pub use manifest_db::{manifest_db_subcommand, ManifestDbError};
+pub use migrate_root::{migrate_root, MigrateRootError};
+pub use patch::{apply, diff_roots, PatchError};
+pub use pull::{pull, PullError, PullLayout, PullOutput, PullTarget};
 pub use push::{push, PushError};
+pub use push_subcommand::{push_subcommand, PushSubcommandError};
+pub use reconcile::{reconcile, ReconcileError};
+pub use reencode::{reencode, target_params, ReencodeError};
+pub use replicate::{replicate, ReplicateError};
+pub use reset::{reset, ResetError};
+pub use root_plan::{root_plan, RootPlanError, RootPlanOutput};
 pub use schema_subcommand::{schema_subcommand, SchemaSubcommandError};
+pub use serve::{serve, ServeError};
+pub use set_previous_root::{parse_previous_root, set_previous_root, SetPreviousRootError};
+pub use squash::{squash, SquashError};
 pub use stage::{stage, StageError};
+pub use stat::{stat, StatError};
+pub use tag::{tag, tag_subcommand, tags, TagError};
+pub use verify::{verify, VerifyError};
+pub use verify_car::{verify_car, VerifyCarError};
+pub use watch::{watch, WatchError};
+pub use where_pinned::{where_pinned, WhereError};
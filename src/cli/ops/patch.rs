@@ -0,0 +1,140 @@
+use std::path::{Path, PathBuf};
+
+use cid::Cid;
+
+use super::diff::{diff, DiffError};
+use super::diff_manifest::print_manifest_diff;
+use super::pull::{object_needs_pull, pull_object, PullError};
+use crate::cli::changes::ChangeType;
+use crate::cli::cid_arg::RootArg;
+use crate::cli::config::{Config, ConfigError};
+use crate::device::DeviceError;
+use crate::types::Patch;
+
+/// Compare the manifests of two roots, grouped by kind of change
+/// # Args
+/// - from: the root or tag to diff from
+/// - to: the root or tag to diff to
+/// - remote: read the manifests from the remote rather than the local node
+/// - export: also write the difference here as a patch, for `apply` to reproduce
+pub async fn diff_roots(
+    config: &Config,
+    from: &RootArg,
+    to: &RootArg,
+    remote: bool,
+    export: Option<&Path>,
+) -> Result<(), PatchError> {
+    let from = config.resolve_root(from)?;
+    let to = config.resolve_root(to)?;
+    let device = config.device()?;
+    let from_store = device.read_dor_store(&from, remote).await?;
+    let to_store = device.read_dor_store(&to, remote).await?;
+    print_manifest_diff(&from_store, &to_store);
+
+    if let Some(export) = export {
+        let patch = Patch::new(from, &from_store, to, &to_store);
+        std::fs::write(export, serde_json::to_vec_pretty(&patch)?)?;
+        println!("wrote patch {} -> {} to {}", from, to, export.display());
+    }
+    Ok(())
+}
+
+/// Apply a patch on top of our current root: bring in the objects it adds or
+/// changes, drop the ones it removes, and commit the patched manifest for the
+/// next push to publish
+/// Refuses a patch taken from any root but ours, or one whose objects don't line
+/// up with our manifest, and won't run over changes in the working dir that
+/// haven't been committed
+/// # Args
+/// - patch: the patch file, as written by `diff --export`
+pub async fn apply(config: &Config, patch: &Path) -> Result<(), PatchError> {
+    let data = std::fs::read(patch)?;
+    let patch: Patch = serde_json::from_slice(&data)?;
+    if config.has_stage() {
+        return Err(PatchError::UncommittedStage);
+    }
+
+    let change_log = config.change_log()?;
+    let (root_cid, base) = change_log.first_version().unwrap();
+    let (next_root_cid, _) = change_log.last_version().unwrap();
+    if root_cid != next_root_cid {
+        return Err(PatchError::UnpushedChanges);
+    }
+    if patch.from() != root_cid {
+        return Err(PatchError::WrongBase(*patch.from(), *root_cid));
+    }
+    if patch.is_empty() {
+        println!("patch changes nothing");
+        return Ok(());
+    }
+    // Pulling the patch's objects would clobber edits we haven't staged yet
+    let unstaged = diff(config, false, None).await?.delta(change_log.log());
+    if let Some(path) = unstaged.keys().next() {
+        return Err(PatchError::UnstagedChanges(path.clone()));
+    }
+    let mut next_base = patch.apply(base).map_err(PatchError::Mismatch)?;
+
+    // Bring the working dir in line, so the next push has the objects to pin
+    let device = config.device()?;
+    let mut log = change_log.log().clone();
+    for (path, object) in patch.added().iter().chain(patch.modified().iter()) {
+        let working_path = config.data_dir().join(path);
+        if object_needs_pull(&device, &working_path, object).await? {
+            pull_object(&device, object, &working_path).await?;
+        }
+        let change_type = if patch.added().contains_key(path) {
+            ChangeType::Added
+        } else {
+            ChangeType::Modified
+        };
+        log.insert(path.clone(), (*object.cid(), change_type));
+    }
+    for path in patch.removed().keys() {
+        let working_path = config.data_dir().join(path);
+        if std::fs::symlink_metadata(&working_path).is_ok() {
+            std::fs::remove_file(&working_path)?;
+        }
+        log.insert(path.clone(), (Cid::default(), ChangeType::Removed));
+    }
+
+    next_base.set_previous_root(*root_cid);
+    let next_root_cid = device.hash_dor_store(&next_base, false).await?;
+    let mut next_change_log = change_log.clone();
+    next_change_log.update(&log, &next_base, &next_root_cid);
+    config.set_change_log(next_change_log)?;
+    println!(
+        "applied patch {} -> {}: {} added, {} modified, {} removed -- push to publish",
+        patch.from(),
+        patch.to(),
+        patch.added().len(),
+        patch.modified().len(),
+        patch.removed().len()
+    );
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PatchError {
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("device error: {0}")]
+    Device(#[from] DeviceError),
+    #[error("pull error: {0}")]
+    Pull(#[from] PullError),
+    #[error("diff error: {0}")]
+    Diff(#[from] DiffError),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("patch error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("there are staged changes -- commit or reset them before applying a patch")]
+    UncommittedStage,
+    #[error("there are committed changes that haven't been pushed -- push them first")]
+    UnpushedChanges,
+    #[error("{0:?} has changed in the working dir -- stage and commit it, or reset it, before applying a patch")]
+    UnstagedChanges(PathBuf),
+    #[error("patch applies on top of {0}, but our root is {1}")]
+    WrongBase(Cid, Cid),
+    #[error("patch doesn't fit our manifest at {0:?}")]
+    Mismatch(PathBuf),
+}
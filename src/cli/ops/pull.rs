@@ -1,12 +1,21 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Display;
 use std::fs::File;
+use std::future::Future;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use cid::Cid;
+use serde::Serialize;
 
-use crate::cli::changes::ChangeLog;
+use crate::cli::archive::{ArchiveError, TarArchive};
+use crate::cli::cancel::CancelReason;
+use crate::cli::changes::{ChangeLog, PullRetry};
 use crate::cli::config::{Config, ConfigError};
+use crate::cli::filter::{FilterError, PathFilter};
+use crate::cli::walk::{create_link, link_data};
 use crate::device::{Device, DeviceError};
+use crate::types::{DorStore, Object};
 
 pub async fn file_needs_pull(
     device: &Device,
@@ -28,57 +37,612 @@ pub async fn file_needs_pull(
     }
 }
 
-pub async fn pull_file(device: &Device, cid: &Cid, path: &PathBuf) -> Result<(), PullError> {
-    // TODO: replace with gateway read
-    let data = device.read_ipfs_gateway_data(cid, None).await?;
+/// Download an object into the working dir
+/// # Returns the number of bytes written
+pub async fn pull_file(device: &Device, cid: &Cid, path: &PathBuf) -> Result<u64, PullError> {
+    let data = device.read_object(cid).await?;
     let mut object_path = path.clone();
     object_path.pop();
     std::fs::create_dir_all(object_path)?;
     let mut file = std::fs::File::create(path)?;
     file.write_all(&data)?;
+    Ok(data.len() as u64)
+}
+
+/// Set the modification time of a file in the working dir to the one its object
+/// recorded, if it recorded one
+pub fn restore_mtime(path: &Path, object: &Object) -> Result<(), std::io::Error> {
+    if let Some(mtime) = object.mtime() {
+        let file = File::options().write(true).open(path)?;
+        file.set_modified((*mtime).into())?;
+    }
     Ok(())
 }
 
-pub async fn pull(config: &Config) -> Result<(), PullError> {
+/// Whether a link in the working dir is missing or points somewhere else
+pub fn link_needs_pull(path: &Path, target: &Path) -> bool {
+    match std::fs::read_link(path) {
+        Ok(current) => current != target,
+        Err(_) => true,
+    }
+}
+
+/// Recreate a link in the working dir, replacing whatever is at its path
+/// # Returns the number of bytes the link was stored as
+pub fn pull_link(path: &Path, target: &Path) -> Result<u64, PullError> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => {
+            return Err(PullError::PathIsDirectory(path.to_path_buf()))
+        }
+        Ok(_) => std::fs::remove_file(path)?,
+        Err(_) => {}
+    }
+    create_link(target, path)?;
+    Ok(link_data(target).len() as u64)
+}
+
+/// Whether an object in the working dir is missing or out of date
+pub async fn object_needs_pull(
+    device: &Device,
+    path: &PathBuf,
+    object: &Object,
+) -> Result<bool, PullError> {
+    match object.link() {
+        Some(target) => Ok(link_needs_pull(path, target)),
+        None => file_needs_pull(device, path, object.cid()).await,
+    }
+}
+
+/// Bring an object into the working dir, as a file or link
+/// # Returns the number of bytes written
+pub async fn pull_object(
+    device: &Device,
+    object: &Object,
+    path: &PathBuf,
+) -> Result<u64, PullError> {
+    match object.link() {
+        Some(target) => pull_link(path, target),
+        None => {
+            let bytes = pull_file(device, object.cid(), path).await?;
+            restore_mtime(path, object)?;
+            Ok(bytes)
+        }
+    }
+}
+
+/// Name of the path to cid index written alongside a cid layout
+pub const CID_INDEX_NAME: &str = "index.json";
+
+/// How a pull lays objects out on disk
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum PullLayout {
+    /// At their manifest paths, in the working dir
+    #[default]
+    Path,
+    /// Once per cid, flat under a directory, next to an index of paths to cids
+    Cid,
+}
+
+/// Where a pull puts the objects it fetches
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PullTarget {
+    /// The working dir, updating local state to the pulled root
+    WorkingDir,
+    /// A tar archive at this path, gzipped if it ends in .tar.gz
+    Tar(PathBuf),
+    /// A cid layout in this directory
+    CidDir(PathBuf),
+}
+
+impl PullTarget {
+    /// Work out the target from the pull's arguments
+    /// # Args
+    /// - to_tar: stream the objects into a tar archive here
+    /// - layout: lay objects out by path, or by cid in `to_dir`
+    /// - to_dir: where to write a cid layout
+    pub fn new(
+        to_tar: Option<PathBuf>,
+        layout: PullLayout,
+        to_dir: Option<PathBuf>,
+    ) -> Result<Self, PullError> {
+        match (to_tar, layout, to_dir) {
+            (Some(_), _, Some(_)) | (Some(_), PullLayout::Cid, _) => Err(PullError::TarLayout),
+            (Some(out), PullLayout::Path, None) => Ok(Self::Tar(out)),
+            (None, PullLayout::Cid, Some(out)) => Ok(Self::CidDir(out)),
+            (None, PullLayout::Cid, None) => Err(PullError::MissingLayoutDir),
+            (None, PullLayout::Path, Some(_)) => Err(PullError::PathLayoutDir),
+            (None, PullLayout::Path, None) => Ok(Self::WorkingDir),
+        }
+    }
+}
+
+/// How a pull reports its progress
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum PullOutput {
+    /// A summary once the pull is done
+    #[default]
+    Text,
+    /// One json event per line as each object is handled, for pipelines to follow along
+    Jsonl,
+}
+
+/// Something that happened to a single object during a pull
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum PullEvent {
+    /// Downloaded into the working dir
+    Fetched {
+        path: PathBuf,
+        cid: String,
+        bytes: u64,
+    },
+    /// Already in the working dir with a matching cid
+    Verified { path: PathBuf, cid: String },
+    /// Left out by the filter
+    Skipped { path: PathBuf, cid: String },
+    /// Couldn't be pulled -- the pull stops here, unless it's keeping going
+    Failed {
+        path: PathBuf,
+        cid: String,
+        error: String,
+    },
+}
+
+impl PullEvent {
+    /// Write the event out as a single line, as soon as it happens
+    fn emit(&self, output: PullOutput) -> Result<(), PullError> {
+        if output == PullOutput::Jsonl {
+            let mut stdout = std::io::stdout().lock();
+            writeln!(stdout, "{}", serde_json::to_string(self)?)?;
+            stdout.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// What a pull would do to the working dir, without downloading any objects
+#[derive(Debug, Default)]
+pub struct PullPlan {
+    /// Objects that aren't in the working dir, and would be downloaded
+    pub download: Vec<(PathBuf, Cid)>,
+    /// Objects already in the working dir with matching cids
+    pub present: Vec<(PathBuf, Cid)>,
+    /// Local files that differ from the remote, and would be overwritten
+    pub overwrite: Vec<(PathBuf, Cid)>,
+    /// Objects left out by the filter
+    pub skipped: usize,
+}
+
+impl Display for PullPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "download: {}, present: {}, overwrite: {}, skipped: {}",
+            self.download.len(),
+            self.present.len(),
+            self.overwrite.len(),
+            self.skipped
+        )?;
+        for (path, cid) in self.download.iter() {
+            writeln!(f, "download {} ({})", path.display(), cid)?;
+        }
+        for (path, cid) in self.overwrite.iter() {
+            writeln!(f, "\x1b[0;31moverwrite\x1b[0m {} ({})", path.display(), cid)?;
+        }
+        Ok(())
+    }
+}
+
+/// Objects a pull that kept going couldn't fetch
+#[derive(Debug, Default)]
+struct PullFailures(Vec<(PathBuf, Cid, String)>);
+
+impl PullFailures {
+    fn push(&mut self, path: &Path, cid: &Cid, error: String) {
+        self.0.push((path.to_path_buf(), *cid, error));
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn paths(&self) -> BTreeSet<PathBuf> {
+        self.0.iter().map(|(path, _, _)| path.clone()).collect()
+    }
+
+    /// Report what's missing, and fail the pull if anything is
+    fn finish(self, output: PullOutput) -> Result<(), PullError> {
+        if self.0.is_empty() {
+            return Ok(());
+        }
+        if output == PullOutput::Text {
+            println!("failed to pull {} objects:", self.0.len());
+            for (path, cid, error) in self.0.iter() {
+                println!("missing {} ({}): {}", path.display(), cid, error);
+            }
+        }
+        Err(PullError::Incomplete(self.0.len()))
+    }
+}
+
+/// Stream the objects of a manifest into a tar archive at their paths, verifying
+/// each against its cid as it comes in
+async fn pull_to_tar(
+    config: &Config,
+    device: &Device,
+    dor_store: &DorStore,
+    filter: &PathFilter,
+    out: &Path,
+    output: PullOutput,
+    keep_going: bool,
+) -> Result<(), PullError> {
+    let mut failures = PullFailures::default();
+    let mut archive = TarArchive::create(out)?;
+    let mut selected = 0;
+    let mut bytes = 0;
+    for (pulled, (path, object)) in dor_store.objects().iter().enumerate() {
+        config
+            .cancellation()
+            .check()
+            .map_err(|reason| PullError::Cancelled(reason, pulled))?;
+        let cid = object.cid().to_string();
+        if !filter.matches(path) {
+            PullEvent::Skipped {
+                path: path.clone(),
+                cid,
+            }
+            .emit(output)?;
+            continue;
+        }
+        selected += 1;
+        match archive.append_object(device, path, object).await {
+            Ok(object_bytes) => {
+                bytes += object_bytes;
+                PullEvent::Fetched {
+                    path: path.clone(),
+                    cid,
+                    bytes: object_bytes,
+                }
+                .emit(output)?;
+            }
+            Err(e) => {
+                PullEvent::Failed {
+                    path: path.clone(),
+                    cid,
+                    error: e.to_string(),
+                }
+                .emit(output)?;
+                if !keep_going {
+                    return Err(e.into());
+                }
+                failures.push(path, object.cid(), e.to_string());
+            }
+        }
+    }
+    archive.finish()?;
+    if output == PullOutput::Text {
+        println!(
+            "archived {} objects, {} bytes, to {}",
+            selected,
+            bytes,
+            out.display()
+        );
+    }
+    failures.finish(output)
+}
+
+/// Write each distinct object of a manifest once, at `<out>/<cid>`, and an index of
+/// manifest paths to cids at `<out>/index.json`. Links are stored as the target
+/// path they're hashed as. Objects already in the directory are verified, not fetched
+async fn pull_to_cid_dir(
+    config: &Config,
+    device: &Device,
+    dor_store: &DorStore,
+    filter: &PathFilter,
+    out: &Path,
+    output: PullOutput,
+    keep_going: bool,
+) -> Result<(), PullError> {
+    std::fs::create_dir_all(out)?;
+    let mut failures = PullFailures::default();
+    let mut index: BTreeMap<PathBuf, String> = BTreeMap::new();
+    let mut bytes = 0;
+    for (pulled, (path, object)) in dor_store.objects().iter().enumerate() {
+        config
+            .cancellation()
+            .check()
+            .map_err(|reason| PullError::Cancelled(reason, pulled))?;
+        let cid = object.cid().to_string();
+        if !filter.matches(path) {
+            PullEvent::Skipped {
+                path: path.clone(),
+                cid,
+            }
+            .emit(output)?;
+            continue;
+        }
+
+        let cid_path = out.join(&cid);
+        let result = match file_needs_pull(device, &cid_path, object.cid()).await {
+            Ok(true) => match object.link() {
+                Some(target) => {
+                    let data = link_data(target);
+                    std::fs::write(&cid_path, &data)
+                        .map(|_| Some(data.len() as u64))
+                        .map_err(PullError::from)
+                }
+                None => pull_file(device, object.cid(), &cid_path).await.map(Some),
+            },
+            Ok(false) => Ok(None),
+            Err(e) => Err(e),
+        };
+        let event = match &result {
+            Ok(Some(object_bytes)) => PullEvent::Fetched {
+                path: path.clone(),
+                cid: cid.clone(),
+                bytes: *object_bytes,
+            },
+            Ok(None) => PullEvent::Verified {
+                path: path.clone(),
+                cid: cid.clone(),
+            },
+            Err(e) => PullEvent::Failed {
+                path: path.clone(),
+                cid: cid.clone(),
+                error: e.to_string(),
+            },
+        };
+        event.emit(output)?;
+        match result {
+            Ok(object_bytes) => {
+                bytes += object_bytes.unwrap_or(0);
+                index.insert(path.clone(), cid);
+            }
+            Err(e) => {
+                if !keep_going {
+                    return Err(e);
+                }
+                failures.push(path, object.cid(), e.to_string());
+            }
+        }
+    }
+
+    let index_json = serde_json::to_string_pretty(&index)?;
+    std::fs::write(out.join(CID_INDEX_NAME), index_json)?;
+    if output == PullOutput::Text {
+        let distinct: std::collections::BTreeSet<&String> = index.values().collect();
+        println!(
+            "wrote {} objects as {} cids, {} new bytes, to {}",
+            index.len(),
+            distinct.len(),
+            bytes,
+            out.display()
+        );
+    }
+    failures.finish(output)
+}
+
+/// Pull the remote root into the working dir
+/// # Args
+/// - include: glob patterns selecting which object paths to pull (all if empty)
+/// - exclude: glob patterns of object paths to skip
+/// - plan: only fetch the manifest and report what pulling would change.
+///   Nothing is downloaded or written
+/// - output: how to report on the objects pulled. Plans are always reported as text
+/// - target: where to put the objects. Pulling into a tar archive or a cid layout
+///   leaves local state untouched
+/// - keep_going: carry on past objects that can't be fetched, then fail listing
+///   them. The root isn't saved until a pull gets everything, and the failures
+///   are kept, so pulling the same root again only fetches what's missing
+/// Stops between objects once the config's cancellation says so. Objects already
+/// written stay in place, but the working dir's state is left on its old root
+/// until a pull finishes
+pub async fn pull(
+    config: &Config,
+    include: &[String],
+    exclude: &[String],
+    plan: bool,
+    output: PullOutput,
+    target: &PullTarget,
+    keep_going: bool,
+) -> Result<(), PullError> {
+    let filter = PathFilter::new(include.to_vec(), exclude.to_vec())?;
+
     let on_disk_device = config.on_disk_device()?;
     let alias = on_disk_device.alias();
     let base_root_cid = Config::root_cid(config)?;
     let base_dor_store = Config::base(config)?;
     let device = config.device()?;
 
-    let root_cid = device.read_root_cid().await?;
-    if root_cid == base_root_cid {
-        tracing::info!("root cid is up to date");
-    } else {
-        config.set_root_cid(&root_cid)?;
+    if on_disk_device.eth_remote().verify_contract {
+        device.contract_deployed().await?;
     }
 
+    let root_cid = device.read_root_cid().await?;
+
     let mut dor_store = base_dor_store.clone();
     if root_cid != Cid::default() {
         tracing::info!("root cid is not set");
         dor_store = device.read_dor_store(&root_cid, true).await?;
     }
 
+    // Objects produced with other hash params won't verify against our node
+    if let Err(e) = device.check_cid_params(&dor_store) {
+        tracing::warn!("{}", e);
+    }
+
+    if plan {
+        let mut pull_plan = PullPlan::default();
+        for (path, object) in dor_store.objects().iter() {
+            if !filter.matches(path) {
+                pull_plan.skipped += 1;
+                continue;
+            }
+            let entry = (path.clone(), *object.cid());
+            let working_path = config.data_dir().join(path);
+            if std::fs::symlink_metadata(&working_path).is_err() {
+                pull_plan.download.push(entry);
+            } else if object_needs_pull(&device, &working_path, object).await? {
+                pull_plan.overwrite.push(entry);
+            } else {
+                pull_plan.present.push(entry);
+            }
+        }
+        println!("{}", pull_plan);
+        return Ok(());
+    }
+
+    match target {
+        PullTarget::WorkingDir => {}
+        PullTarget::Tar(out) => {
+            return pull_to_tar(
+                config, &device, &dor_store, &filter, out, output, keep_going,
+            )
+            .await;
+        }
+        PullTarget::CidDir(out) => {
+            return pull_to_cid_dir(
+                config, &device, &dor_store, &filter, out, output, keep_going,
+            )
+            .await;
+        }
+    }
+
+    if root_cid == base_root_cid {
+        tracing::info!("root cid is up to date");
+    }
     if dor_store == base_dor_store {
         tracing::info!("dor store is up to date");
-    } else {
-        config.set_base(&dor_store)?;
     }
 
-    let objects = dor_store.objects();
+    pull_into_working_dir(
+        config,
+        alias,
+        &root_cid,
+        &dor_store,
+        filter,
+        output,
+        keep_going,
+        |object, working_path| {
+            let device = &device;
+            async move {
+                if object_needs_pull(device, &working_path, object).await? {
+                    // TODO: this should use the gateway
+                    pull_object(device, object, &working_path).await.map(Some)
+                } else {
+                    Ok(None)
+                }
+            }
+        },
+    )
+    .await
+}
+
+/// Bring the objects of a pulled root into the working dir, then save the root as
+/// our state. Nothing is saved until every object is in, so a cancel or failure
+/// leaves the working dir on its old root. Failures are recorded for the next pull
+/// of the same root to retry, skipping everything that did make it
+/// # Args
+/// - fetch: brings an object into the working dir at a path
+///   Returns the bytes written, or None if it was already there
+#[allow(clippy::too_many_arguments)]
+async fn pull_into_working_dir<'a, F, Fut>(
+    config: &Config,
+    alias: String,
+    root_cid: &Cid,
+    dor_store: &'a DorStore,
+    filter: PathFilter,
+    output: PullOutput,
+    keep_going: bool,
+    mut fetch: F,
+) -> Result<(), PullError>
+where
+    F: FnMut(&'a Object, PathBuf) -> Fut,
+    Fut: Future<Output = Result<Option<u64>, PullError>>,
+{
+    let retry = config
+        .pull_retry()?
+        .filter(|retry| retry.retries(root_cid, &filter));
 
-    for (path, object) in objects.iter() {
-        let working_path = config.working_dir().join(path);
-        if !file_needs_pull(&device, &working_path, object.cid()).await? {
+    let mut selected = 0;
+    let mut skipped = 0;
+    let mut failures = PullFailures::default();
+    for (pulled, (path, object)) in dor_store.objects().iter().enumerate() {
+        config
+            .cancellation()
+            .check()
+            .map_err(|reason| PullError::Cancelled(reason, pulled))?;
+        let cid = object.cid().to_string();
+        if !filter.matches(path) {
+            skipped += 1;
+            PullEvent::Skipped {
+                path: path.clone(),
+                cid,
+            }
+            .emit(output)?;
             continue;
         }
+        selected += 1;
 
-        // TODO: this should use the gateway
-        pull_file(&device, object.cid(), &working_path).await?;
+        // The last pull of this root already got it
+        if retry
+            .as_ref()
+            .is_some_and(|retry| !retry.paths.contains(path))
+        {
+            PullEvent::Verified {
+                path: path.clone(),
+                cid,
+            }
+            .emit(output)?;
+            continue;
+        }
+
+        let result = fetch(object, config.data_dir().join(path)).await;
+        let event = match &result {
+            Ok(Some(bytes)) => PullEvent::Fetched {
+                path: path.clone(),
+                cid,
+                bytes: *bytes,
+            },
+            Ok(None) => PullEvent::Verified {
+                path: path.clone(),
+                cid,
+            },
+            Err(e) => PullEvent::Failed {
+                path: path.clone(),
+                cid,
+                error: e.to_string(),
+            },
+        };
+        event.emit(output)?;
+        if let Err(e) = result {
+            if !keep_going {
+                return Err(e);
+            }
+            failures.push(path, object.cid(), e.to_string());
+        }
     }
 
-    let change_log = ChangeLog::new(alias, &dor_store, &root_cid);
-    config.set_change_log(change_log)?;
+    if !filter.is_empty() && output == PullOutput::Text {
+        println!("selected {} objects, skipped {}", selected, skipped);
+    }
+    // Leave our state alone, so what we did fetch isn't counted as changed
+    if !failures.is_empty() {
+        config.set_pull_retry(&PullRetry {
+            root_cid: *root_cid,
+            filter,
+            paths: failures.paths(),
+        })?;
+        return failures.finish(output);
+    }
+
+    let change_log = ChangeLog::new(alias, dor_store, root_cid).with_filter(filter);
+    config.save_pulled(root_cid, dor_store, change_log)?;
+    // Anything staged was against the old change log
+    config.reset_stage()?;
 
     Ok(())
 }
@@ -95,4 +659,157 @@ pub enum PullError {
     Io(#[from] std::io::Error),
     #[error("path is a directory")]
     PathIsDirectory(PathBuf),
+    #[error("failed to encode event: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("filter error: {0}")]
+    Filter(#[from] FilterError),
+    #[error("archive error: {0}")]
+    Archive(#[from] ArchiveError),
+    #[error("{0} objects couldn't be pulled -- pull again to retry them")]
+    Incomplete(usize),
+    #[error("pull stopped after {1} objects, {0} -- pull again to finish")]
+    Cancelled(CancelReason, usize),
+    #[error("a cid layout needs somewhere to go -- pass --to-dir")]
+    MissingLayoutDir,
+    #[error("--to-dir only takes a cid layout -- pass --layout cid")]
+    PathLayoutDir,
+    #[error("a tar archive is laid out by path -- drop --layout cid and --to-dir")]
+    TarLayout,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::config::{ConfigBuilder, DEFAULT_LOCAL_DOT_DIR, PUBLISH_JOURNAL_NAME};
+    use crate::test_utils::cid;
+    use tokio_util::sync::CancellationToken;
+
+    fn working_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("dor-store-pull-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join(DEFAULT_LOCAL_DOT_DIR)).unwrap();
+        dir
+    }
+
+    fn dor_store(paths: &[&str]) -> DorStore {
+        let mut dor_store = DorStore::default();
+        for (i, path) in paths.iter().enumerate() {
+            dor_store.insert_object(PathBuf::from(path), Object::new(cid(i as u8)));
+        }
+        dor_store
+    }
+
+    #[tokio::test]
+    async fn failed_objects_are_all_a_second_pull_retries() {
+        let dir = working_dir("retry");
+        let config = ConfigBuilder::default()
+            .with_working_dir(dir.clone())
+            .build()
+            .unwrap();
+        let root_cid = cid(9);
+        let dor_store = dor_store(&["a", "b", "c"]);
+
+        let mut fetched = Vec::new();
+        let result = pull_into_working_dir(
+            &config,
+            "test".to_string(),
+            &root_cid,
+            &dor_store,
+            PathFilter::default(),
+            PullOutput::Jsonl,
+            true,
+            |object, working_path| {
+                fetched.push(working_path.clone());
+                let fails = object.cid() == &cid(1);
+                async move {
+                    if fails {
+                        Err(PullError::PathIsDirectory(working_path))
+                    } else {
+                        Ok(Some(1))
+                    }
+                }
+            },
+        )
+        .await;
+        assert!(matches!(result, Err(PullError::Incomplete(1))));
+        assert_eq!(fetched.len(), 3);
+        let retry = config.pull_retry().unwrap().unwrap();
+        assert_eq!(retry.paths, BTreeSet::from([PathBuf::from("b")]));
+        // Nothing is saved until every object is in
+        assert!(!dir
+            .join(DEFAULT_LOCAL_DOT_DIR)
+            .join(PUBLISH_JOURNAL_NAME)
+            .exists());
+
+        let mut retried = Vec::new();
+        let result = pull_into_working_dir(
+            &config,
+            "test".to_string(),
+            &root_cid,
+            &dor_store,
+            PathFilter::default(),
+            PullOutput::Jsonl,
+            true,
+            |_object, working_path| {
+                retried.push(working_path.clone());
+                async move { Err(PullError::PathIsDirectory(working_path)) }
+            },
+        )
+        .await;
+        assert!(matches!(result, Err(PullError::Incomplete(1))));
+        assert_eq!(retried, vec![config.data_dir().join("b")]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn cancelled_pulls_leave_state_alone() {
+        let dir = working_dir("cancel");
+        let token = CancellationToken::new();
+        let config = ConfigBuilder::default()
+            .with_working_dir(dir.clone())
+            .with_cancellation_token(token.clone())
+            .build()
+            .unwrap();
+        let old_root_cid = cid(8);
+        let old_dor_store = dor_store(&["a"]);
+        let change_log = ChangeLog::new("test".to_string(), &old_dor_store, &old_root_cid);
+        config.set_change_log(change_log.clone()).unwrap();
+        let dot_dir = dir.join(DEFAULT_LOCAL_DOT_DIR);
+        let state = || {
+            let mut names: Vec<_> = std::fs::read_dir(&dot_dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name())
+                .collect();
+            names.sort();
+            names
+        };
+        let before = state();
+
+        let result = pull_into_working_dir(
+            &config,
+            "test".to_string(),
+            &cid(9),
+            &dor_store(&["a", "b", "c"]),
+            PathFilter::default(),
+            PullOutput::Jsonl,
+            false,
+            |_object, working_path| {
+                // Cancelled midway, once the first object is in
+                token.cancel();
+                async move {
+                    std::fs::write(working_path, b"a")?;
+                    Ok(Some(1))
+                }
+            },
+        )
+        .await;
+        assert!(matches!(result, Err(PullError::Cancelled(_, 1))));
+        assert!(dir.join("a").exists());
+        assert_eq!(state(), before);
+        assert_eq!(config.change_log().unwrap(), change_log);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
@@ -1,24 +1,392 @@
-use std::fs::File;
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 use cid::Cid;
+use ethers::types::Address;
+use ethers::utils::{format_ether, to_checksum};
 
+use crate::cli::audit::AuditRecord;
+use crate::cli::cancel::{self, CancelReason};
+use crate::cli::changes::{ChangeLog, ChangeType, HashCache, PushOptions, PushStage, PushState};
+use crate::cli::config::on_disk_device::PreviousRootCheck;
 use crate::cli::config::{Config, ConfigError};
-use crate::device::{Device, DeviceError};
-use crate::types::DorStore;
+use crate::cli::hooks::{run_post_push_hooks, HookError, PushOutcome};
+use crate::cli::walk::link_data;
+#[cfg(feature = "s3")]
+use crate::device::RootCidStore;
+use crate::device::{
+    AddProgress, BlockStatus, CidParams, Device, DeviceError, RemotePinStatus, RootLayout,
+    RootUpdateReceipt,
+};
+use crate::metrics;
+use crate::types::{DorStore, Object, Replica, ReplicationError};
 
-/// Push a file to the remote ipfs node
-pub async fn push_file(device: &Device, file_path: &PathBuf) -> Result<Cid, PushError> {
-    let file = File::open(file_path)?;
-    let cid = device.write_ipfs_data(file, true).await?;
+/// How long to wait on the remote when checking the root we're replacing is
+/// still there
+const PREVIOUS_ROOT_PROBE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Push a file to an ipfs node
+/// # Args
+/// - remote: whether to push to the remote or local node
+pub async fn push_file(
+    device: &Device,
+    file_path: &PathBuf,
+    remote: bool,
+) -> Result<Cid, PushError> {
+    let cid = device
+        .write_ipfs_file_with_progress(file_path, remote, |progress| match progress {
+            AddProgress::Bytes { name, bytes } => {
+                tracing::debug!("pushing {}: {} bytes", name, bytes)
+            }
+            AddProgress::Added { name, cid } => tracing::debug!("pushed {}: {}", name, cid),
+        })
+        .await?;
     Ok(cid)
 }
 
-pub async fn push(config: &Config) -> Result<(), PushError> {
-    let working_dir = config.working_dir().clone();
+/// Push data under the params of the cid it's kept by, i.e. for objects
+/// re-encoded to params other than ours
+/// # Args
+/// - path: the object's path within the DorStore, for errors
+/// - cid: the cid the data is kept by
+/// - remote: whether to push to the remote or local node
+async fn push_data_with<R>(
+    device: &Device,
+    path: &Path,
+    data: R,
+    cid: &Cid,
+    remote: bool,
+) -> Result<Cid, PushError>
+where
+    R: 'static + Read + Send + Sync + Unpin,
+{
+    let params = CidParams::of(cid);
+    device
+        .write_ipfs_data_with(data, params, remote)
+        .await
+        .map_err(|source| PushError::ObjectPin {
+            path: path.to_path_buf(),
+            cid: *cid,
+            source,
+        })?
+        .ok_or_else(|| PushError::UnsupportedParams(path.to_path_buf(), params))
+}
+
+/// What happened to a single object during a push
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PushedObject {
+    /// The object was pinned, after sending this many bytes of it
+    /// Links and external objects send nothing worth counting -- the node fetches
+    /// external ones itself
+    Pinned(u64),
+    /// The object was already present on the node
+    Skipped,
+    /// The object is still being pinned by the node's pinning service
+    Pending,
+}
+
+/// Make sure a single object is pinned against an ipfs node
+/// Errors carry the path and cid of the object
+/// # Args
+/// - path: the object's path within the DorStore
+/// - file_path: where to read the object from
+/// - object: the object to pin. Links are pinned by their target, not read from file_path.
+///   Objects are added under the params of their cid
+/// - remote: whether to push to the remote or local node
+/// - index: hashes of the working dir. Consulted to catch a file edited since it
+///   was committed before uploading it, and updated once it's pushed
+pub async fn push_object(
+    device: &Device,
+    path: &PathBuf,
+    file_path: &PathBuf,
+    object: &Object,
+    remote: bool,
+    index: &mut HashCache,
+) -> Result<PushedObject, PushError> {
+    let cid = object.cid();
+    let object_pin = |source| PushError::ObjectPin {
+        path: path.clone(),
+        cid: *cid,
+        source,
+    };
+    // See if the cid already exists on the node, or is on its way
+    match device.block_status(cid, remote).await.map_err(object_pin)? {
+        BlockStatus::Present => return Ok(PushedObject::Skipped),
+        BlockStatus::Pending => return Ok(PushedObject::Pending),
+        BlockStatus::Absent => {}
+    }
+    // Content we only know by cid is fetched by the node itself
+    if object.is_external() {
+        device
+            .pin_ipfs_data(cid, remote)
+            .await
+            .map_err(object_pin)?;
+        return Ok(PushedObject::Pinned(0));
+    }
+    let default_params = CidParams::of(cid) == CidParams::default();
+    if let Some(target) = object.link() {
+        let data = Cursor::new(link_data(target));
+        let pushed_cid = if default_params {
+            device
+                .write_ipfs_data(data, remote)
+                .await
+                .map_err(object_pin)?
+        } else {
+            push_data_with(device, path, data, cid, remote).await?
+        };
+        if pushed_cid != *cid {
+            return Err(PushError::CidMismatch {
+                path: path.clone(),
+                expected: *cid,
+                actual: pushed_cid,
+            });
+        }
+        return Ok(PushedObject::Pinned(0));
+    }
+    let metadata = std::fs::metadata(file_path).map_err(|source| PushError::ObjectFile {
+        path: path.clone(),
+        source,
+    })?;
+    if let Some(indexed_cid) = index.get(file_path, &metadata) {
+        if indexed_cid != *cid {
+            return Err(PushError::CidMismatch {
+                path: path.clone(),
+                expected: *cid,
+                actual: indexed_cid,
+            });
+        }
+    }
+    let pushed = if default_params {
+        push_file(device, file_path, remote).await
+    } else {
+        let file = std::fs::File::open(file_path)?;
+        push_data_with(device, path, file, cid, remote).await
+    };
+    let pushed_cid = match pushed {
+        Ok(pushed_cid) => pushed_cid,
+        Err(PushError::Device(source)) => return Err(object_pin(source)),
+        Err(e) => return Err(e),
+    };
+    if pushed_cid != *cid {
+        return Err(PushError::CidMismatch {
+            path: path.clone(),
+            expected: *cid,
+            actual: pushed_cid,
+        });
+    }
+    index.insert(file_path.clone(), &metadata, *cid);
+    Ok(PushedObject::Pinned(metadata.len()))
+}
+
+/// Where a push stopped when cancelled, and what that left behind
+#[derive(Debug, Clone)]
+pub enum CancelledPush {
+    /// Stopped between objects
+    Pinning { pinned: usize, remaining: usize },
+    /// Everything, including the new root, is pinned but the root wasn't published
+    Unpublished { root_cid: Cid },
+    /// The root was published and saved, but post push hooks didn't run
+    Unhooked { root_cid: Cid },
+}
+
+impl std::fmt::Display for CancelledPush {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CancelledPush::Pinning { pinned, remaining } => write!(
+                f,
+                "stopped pinning with {} objects pinned and {} left. Nothing was published \
+                 and local state is unchanged -- push again to pick up where this left off",
+                pinned, remaining
+            ),
+            CancelledPush::Unpublished { root_cid } => write!(
+                f,
+                "root {} is pinned but wasn't published. Local state is unchanged -- push \
+                 again to publish it",
+                root_cid
+            ),
+            CancelledPush::Unhooked { root_cid } => write!(
+                f,
+                "root {} was published and saved, but post push hooks didn't run",
+                root_cid
+            ),
+        }
+    }
+}
+
+/// Build the root a partial push publishes: the base, with only the committed
+/// changes under `prefix` applied. Everything else keeps its existing cid
+fn partial_base(base: &DorStore, next_base: &DorStore, prefix: &Path, root_cid: &Cid) -> DorStore {
+    let mut partial = base.clone();
+    for path in base.objects().keys() {
+        if path.starts_with(prefix) && !next_base.objects().contains_key(path) {
+            partial.remove_object(path);
+        }
+    }
+    for (path, object) in next_base.objects().iter() {
+        if path.starts_with(prefix) {
+            partial.insert_object(path.clone(), object.clone());
+        }
+    }
+    partial.set_previous_root(*root_cid);
+    partial
+}
+
+/// The replicas the device has to copy objects to, the node first
+fn available_replicas(config: &Config, device: &Device) -> Result<Vec<Replica>, PushError> {
+    let mut replicas = vec![Replica::Node];
+    if device.has_pin_service() {
+        replicas.push(Replica::PinService);
+    }
+    if config.on_disk_device()?.s3_mirror().is_some() {
+        replicas.push(Replica::S3);
+    }
+    Ok(replicas)
+}
+
+/// Pick out the objects a replica keeps, by each object's replication policy or,
+/// for objects without one, the device's
+/// Directory roots link every object, so a replica holding the root holds them all
+/// -- policies that leave any replica out are refused under that layout
+/// # Returns the cids of the objects to copy to the replica
+pub(super) fn replicated_on(
+    config: &Config,
+    device: &Device,
+    pushed_base: &DorStore,
+    replica: Replica,
+) -> Result<Vec<Cid>, PushError> {
+    let on_disk_device = config.on_disk_device()?;
+    let available = available_replicas(config, device)?;
+    let default = on_disk_device.replication();
+    let directory = on_disk_device.root_layout() == RootLayout::Directory;
+    let mut cids = Vec::new();
+    for (path, object) in pushed_base.objects().iter() {
+        let replicas = object
+            .replication()
+            .unwrap_or(default)
+            .resolve(&available)
+            .map_err(|source| PushError::Replication {
+                path: path.clone(),
+                source,
+            })?;
+        if directory && replicas.len() < available.len() {
+            return Err(PushError::PartialDirectoryReplication(path.clone()));
+        }
+        if replicas.contains(&replica) {
+            cids.push(*object.cid());
+        }
+    }
+    Ok(cids)
+}
+
+/// Save a published root as our local state, and forget the push that published it
+/// Committed changes a partial push left out are carried over onto the new root
+/// # Args
+/// - change_log: the change log the push was made from
+/// - pushed_base: the manifest the published root points at
+/// - path: the prefix a partial push was limited to
+pub async fn save_published(
+    config: &Config,
+    device: &Device,
+    change_log: &ChangeLog,
+    pushed_base: &DorStore,
+    new_root_cid: &Cid,
+    path: Option<&Path>,
+) -> Result<(), PushError> {
+    let (_next_root_cid, next_base) = change_log.last_version().unwrap();
+    let mut next_change_log = change_log.clone();
+    next_change_log.wipe(pushed_base, new_root_cid);
+
+    // Carry anything we didn't push over onto the new root
+    if let Some(prefix) = path {
+        if pushed_base.objects() != next_base.objects() {
+            let mut log = change_log.log().clone();
+            log.retain(|object_path, (_cid, change_type)| {
+                !(object_path.starts_with(prefix) && change_type == &ChangeType::Removed)
+            });
+            for (object_path, (_cid, change_type)) in log.iter_mut() {
+                if object_path.starts_with(prefix) {
+                    *change_type = ChangeType::Base;
+                }
+            }
+            let mut rest = next_base.clone();
+            rest.set_previous_root(*new_root_cid);
+            let rest_root_cid = device.hash_dor_store(&rest, false).await?;
+            next_change_log.update(&log, &rest, &rest_root_cid);
+        }
+    }
+
+    config.save_published(new_root_cid, pushed_base, next_change_log)?;
+    Ok(())
+}
+
+/// Pin the committed changes and publish the new root
+/// # Args
+/// - local: only pin against the local node and commit the new root locally,
+///   skipping the remote and the root update entirely. A later push publishes it
+/// - yes: skip confirming the root update, even where the remote asks for it
+/// - options:
+///   - fail_fast: stop at the first object that fails to push, rather than
+///     attempting all of them and reporting every failure
+///   - name_pin: name the root's pin using the remote's pin name template
+///   - force: push even if the changes go over the device's push guardrail
+///   - path: only pin and publish changes under this prefix. The rest of the
+///     committed changes are left to a later push
+///   - skip_balance_check: don't check the signer can pay for the root update
+///     before pinning
+///   - allow_orphan_previous: push even if the root we're replacing can't be
+///     fetched from the remote, and the device is set to refuse
+///   - prepare: stop once everything is pinned and the new root is written, leaving
+///     the push prepared for `push commit` to publish later. Ignored with local
+///   - unattended: never prompt -- a push over the guardrail, or a root update
+///     that needs confirming, fails instead
+/// Objects go to the node, and whichever of its pinning service and the S3 mirror
+/// their replication policy picks. Roots go everywhere
+/// If the remote has an MFS path set, the new root is mirrored there once it's saved
+/// Ctrl-C, the config's cancellation token, or its deadline stops the push at the
+/// next safe point: between objects, before the root is published, or before hooks
+/// run. Uploading an object, waiting on the pinning service, and the root update
+/// itself are never interrupted -- once the update is sent, we see it through to
+/// saving local state before stopping
+pub async fn push(
+    config: &Config,
+    local: bool,
+    yes: bool,
+    options: &PushOptions,
+) -> Result<(), PushError> {
+    let PushOptions {
+        fail_fast,
+        name_pin,
+        force,
+        skip_balance_check,
+        allow_orphan_previous,
+        prepare,
+        unattended,
+        ..
+    } = *options;
+    let path = options.path.as_deref();
+    cancel::install();
+    let started = Instant::now();
+    let working_dir = config.data_dir().clone();
     let device = config.device()?;
     let disk_root_cid = config.root_cid()?;
 
+    // Don't leave a stage behind against a root we're about to replace
+    if config.has_stage() {
+        return Err(PushError::UncommittedStage);
+    }
+
+    // Don't send a second root update while an earlier one may still land
+    let previous_state = config.push_state()?;
+    if let Some(state) = &previous_state {
+        if state.stage() == PushStage::Broadcast {
+            return Err(PushError::PendingUpdate(
+                state.tx_hash().cloned().unwrap_or_default(),
+            ));
+        }
+    }
+
     let disk_base = config.base()?;
     let change_log = config.change_log()?;
     let (root_cid, base) = change_log.first_version().unwrap();
@@ -44,48 +412,773 @@ pub async fn push(config: &Config) -> Result<(), PushError> {
         return Err(PushError::NoChanges);
     }
 
-    let objects = next_base.objects();
+    let pushed_base = match path {
+        Some(prefix) => {
+            let partial = partial_base(base, next_base, prefix, root_cid);
+            if partial.objects() == base.objects() {
+                return Err(PushError::NoChanges);
+            }
+            partial
+        }
+        None => next_base.clone(),
+    };
+
+    // Don't publish a root we won't be able to verify
+    device.check_cid_params(&pushed_base)?;
 
-    // Tell the remote to pin all the objects
-    for (path, object) in objects.iter() {
-        // See if the cid already exists on the remote
-        if device.stat_ipfs_data(object.cid(), true).await?.is_some() {
+    // Nor pin anything before finding an object wants replicas we don't have
+    replicated_on(config, &device, &pushed_base, Replica::Node)?;
+
+    // Make sure an unexpectedly large push is on purpose
+    let mut added_objects = 0;
+    let mut added_bytes = 0;
+    for (object_path, object) in pushed_base.objects().iter() {
+        if base.objects().get(object_path).map(|o| o.cid()) != Some(object.cid()) {
+            added_objects += 1;
+            // Only files are uploaded from the working dir. One that's gone fails
+            // its push on its own
+            if object.link().is_none() && !object.is_external() {
+                added_bytes += std::fs::metadata(working_dir.join(object_path))
+                    .map(|m| m.len())
+                    .unwrap_or(0);
+            }
+        }
+    }
+    let guardrail = *config.on_disk_device()?.push_guardrail();
+    if !force && guardrail.exceeded(added_objects, added_bytes) {
+        if unattended {
+            return Err(PushError::TooLarge {
+                added_objects,
+                added_bytes,
+            });
+        }
+        confirm_large_push(added_objects, added_bytes)?;
+    }
+
+    let objects = pushed_base.objects();
+
+    let remote = !local;
+
+    // The new root links back to ours, so make sure ours can still be fetched
+    let previous_root_check = config.on_disk_device()?.previous_root_check();
+    if remote && *root_cid != Cid::default() && previous_root_check != PreviousRootCheck::Off {
+        if let Err(e) = previous_root_reachable(&device, root_cid).await {
+            if previous_root_check == PreviousRootCheck::Error && !allow_orphan_previous {
+                return Err(PushError::UnreachablePreviousRoot(*root_cid, e));
+            }
+            tracing::warn!(
+                "previous root {} can't be fetched, so history stops at the new root: {}",
+                root_cid,
+                e
+            );
+        }
+    }
+
+    // Don't pin everything only to find we can't pay for the root update
+    if remote && !skip_balance_check {
+        let next_root_cid = device.hash_dor_store(&pushed_base, false).await?;
+        device
+            .check_root_cid_update_funds(*root_cid, next_root_cid)
+            .await?;
+    }
+
+    // Pick up where an interrupted push of the same changes left off
+    let mut push_state = match previous_state {
+        Some(state)
+            if state.previous_root_cid() == root_cid && state.options().path == options.path =>
+        {
+            state
+        }
+        _ => PushState::new(*root_cid, options.clone()),
+    };
+
+    // Anything the previous root links to was pinned on the remote when it was
+    // published, so there's no need to ask about it again. Run fsck if the remote
+    // may have dropped pins since
+    let carried_over: HashSet<Cid> = if remote && *root_cid != Cid::default() {
+        base.objects()
+            .values()
+            .map(|object| *object.cid())
+            .collect()
+    } else {
+        HashSet::new()
+    };
+
+    // Tell the node to pin all the objects
+    let mut index = config.hash_cache()?;
+    let mut pinned = 0;
+    let mut carried = 0;
+    let mut uploaded = 0;
+    let mut skipped = 0;
+    let mut pending = 0;
+    let mut failures = Vec::new();
+    let mut unchecked = Vec::new();
+    for (i, (object_path, object)) in objects.iter().enumerate() {
+        if let Err(reason) = config.cancellation().check() {
+            if remote {
+                push_state.stopped(format!("stopped, {}", reason));
+                config.set_push_state(&push_state)?;
+            }
+            return Err(PushError::Cancelled(
+                reason,
+                CancelledPush::Pinning {
+                    pinned: pinned + carried + skipped + pending,
+                    remaining: objects.len() - i,
+                },
+            ));
+        }
+        if carried_over.contains(object.cid()) {
+            carried += 1;
             continue;
-        };
-        let cid = push_file(&device, &working_dir.join(path)).await?;
-        if cid != *object.cid() {
-            return Err(PushError::CidMismatch(cid, *object.cid()));
         }
+        // Already pinned by an earlier attempt at this push
+        if remote && push_state.is_pinned(object_path, object.cid()) {
+            skipped += 1;
+            continue;
+        }
+        // Objects outside a partial push should already be on the remote
+        if let Some(prefix) = path {
+            if !object_path.starts_with(prefix) {
+                unchecked.push((object_path.clone(), *object.cid()));
+                continue;
+            }
+        }
+        let file_path = working_dir.join(object_path);
+        let pushed =
+            push_object(&device, object_path, &file_path, object, remote, &mut index).await;
+        match pushed {
+            Ok(PushedObject::Pinned(bytes)) => {
+                pinned += 1;
+                uploaded += bytes;
+                push_state.pinned(object_path, object.cid());
+                if remote {
+                    config.set_push_state(&push_state)?;
+                }
+            }
+            Ok(PushedObject::Skipped) => {
+                skipped += 1;
+                push_state.pinned(object_path, object.cid());
+            }
+            Ok(PushedObject::Pending) => pending += 1,
+            Err(e) => {
+                tracing::error!("{}", e);
+                push_state.failed(object_path, e.to_string());
+                failures.push(e);
+                if fail_fast {
+                    break;
+                }
+            }
+        }
+    }
+    println!(
+        "pinned: {}, carried over: {}, pending: {}, skipped: {}, failed: {}",
+        pinned,
+        carried,
+        pending,
+        skipped,
+        failures.len()
+    );
+    config.set_hash_cache(&index)?;
+    metrics::increment(metrics::OBJECTS_PINNED_TOTAL, pinned);
+    metrics::increment(metrics::BYTES_UPLOADED_TOTAL, uploaded);
+
+    // Only go on to publish a root whose objects all made it
+    if !failures.is_empty() {
+        let e = PushError::ObjectsFailed(failures);
+        if remote {
+            push_state.stopped(e.to_string());
+            config.set_push_state(&push_state)?;
+        }
+        return Err(e);
+    }
+
+    // Make sure a partial root doesn't point at anything the remote doesn't have
+    let mut missing = Vec::new();
+    for (object_path, cid) in unchecked {
+        if device.block_status(&cid, remote).await? == BlockStatus::Absent {
+            missing.push((object_path, cid));
+        }
+    }
+    if !missing.is_empty() {
+        return Err(PushError::IncompleteRoot(missing));
+    }
+
+    // Write the dor store against the node
+    let new_root_cid = device.write_dor_store(&pushed_base, remote).await?;
+
+    if name_pin {
+        let pin_name = config
+            .on_disk_device()?
+            .ipfs_remote()
+            .pin_name(&new_root_cid);
+        device
+            .name_ipfs_pin(&new_root_cid, &pin_name, remote)
+            .await?;
+    }
+
+    let mfs_path = config.on_disk_device()?.ipfs_remote().mfs_path.clone();
+
+    if local {
+        let mut change_log = change_log.clone();
+        change_log.commit(&new_root_cid);
+        config.set_change_log(change_log)?;
+        config.clear_push_state()?;
+        println!("committed root {} locally", new_root_cid);
+        if let Some(mfs_path) = &mfs_path {
+            device
+                .mirror_to_mfs(&pushed_base, &new_root_cid, mfs_path, remote)
+                .await?;
+        }
+        return Ok(());
     }
 
-    // Write the dor store against the remote
-    let new_root_cid = device.write_dor_store(next_base, true).await?;
+    check_committed_root(&change_log, path, &new_root_cid)?;
+    config
+        .cancellation()
+        .check()
+        .map_err(|reason| unpublished(reason, new_root_cid))?;
+
+    // Never announce a root the pinning service hasn't finished pinning
+    if device.has_pin_service() {
+        let timeout = config.on_disk_device()?.ipfs_remote().pin_wait_timeout_secs;
+        let mut cids = replicated_on(config, &device, &pushed_base, Replica::PinService)?;
+        cids.push(new_root_cid);
+        let unpinned = device
+            .pin_with_service(
+                &cids,
+                Duration::from_secs(timeout),
+                |cid, status| match status {
+                    Some(status) => tracing::info!("pin {}: {:?}", cid, status),
+                    None => tracing::info!("pin {}: not found", cid),
+                },
+            )
+            .await?;
+        if !unpinned.is_empty() {
+            return Err(PushError::Unpinned(unpinned));
+        }
+    }
+
+    if prepare {
+        push_state.prepared(new_root_cid);
+        config.set_push_state(&push_state)?;
+        println!(
+            "prepared root {} -- run `push commit` to publish it",
+            new_root_cid
+        );
+        return Ok(());
+    }
+
+    let ready = ReadyRoot {
+        change_log: &change_log,
+        pushed_base: &pushed_base,
+        previous_root_cid: *root_cid,
+        root_cid: new_root_cid,
+    };
+    publish(config, &device, &mut push_state, &ready, yes, started).await
+}
+
+/// A root whose objects are all on the remote, ready to publish over the one it
+/// was pushed on top of
+pub struct ReadyRoot<'a> {
+    /// The change log the root was pushed from
+    pub change_log: &'a ChangeLog,
+    /// The manifest the root points at
+    pub pushed_base: &'a DorStore,
+    pub previous_root_cid: Cid,
+    pub root_cid: Cid,
+}
+
+/// Send the root update for a ready root, save it locally, mirror it, and run
+/// post push hooks -- the last half of a push, shared with `push commit`
+/// # Args
+/// - push_state: the push the root came from, saved as the update goes out
+/// - yes: skip confirming the root update
+/// - started: when the push started, for metrics
+pub async fn publish(
+    config: &Config,
+    device: &Device,
+    push_state: &mut PushState,
+    ready: &ReadyRoot<'_>,
+    yes: bool,
+    started: Instant,
+) -> Result<(), PushError> {
+    let ReadyRoot {
+        change_log,
+        pushed_base,
+        ..
+    } = *ready;
+    let root_cid = &ready.previous_root_cid;
+    let new_root_cid = ready.root_cid;
+    let (_, base) = change_log.first_version().unwrap();
+    let path = push_state.options().path.clone();
+    let mfs_path = config.on_disk_device()?.ipfs_remote().mfs_path.clone();
+
+    // Warn before sending our update somewhere it can't land
+    let eth_remote = config.on_disk_device()?.eth_remote().clone();
+    if eth_remote.verify_contract {
+        device.contract_deployed().await?;
+    }
+    if eth_remote.check_previous_root {
+        check_previous_root(device, base).await;
+    }
+
+    if !yes && eth_remote.confirm_updates.required(device.chain_id()) {
+        if push_state.options().unattended {
+            return Err(PushError::Unconfirmed);
+        }
+        confirm_update(device, root_cid, &new_root_cid).await?;
+    }
+    config
+        .cancellation()
+        .check()
+        .map_err(|reason| unpublished(reason, new_root_cid))?;
+    let s3_mirrored = mirror_to_s3(config, device, &new_root_cid, pushed_base).await?;
 
     // Push the new root cid to the eth client
-    device.update_root_cid(*root_cid, new_root_cid).await?;
-    let mut change_log = change_log.clone();
-    change_log.wipe(next_base, &new_root_cid);
-    config.set_root_cid(&new_root_cid)?;
-    config.set_base(next_base)?;
-    config.set_change_log(change_log)?;
+    // Past this point we don't stop for cancellation or the deadline until local state is saved
+    push_state.publishing(new_root_cid);
+    config.set_push_state(push_state)?;
+    let receipt = match device.update_root_cid(*root_cid, new_root_cid).await {
+        Ok(receipt) => receipt,
+        Err(e) => {
+            if let Some(tx_hash) = e.pending_transaction() {
+                push_state.broadcast(tx_hash);
+            }
+            push_state.stopped(e.to_string());
+            config.set_push_state(push_state)?;
+            return Err(e.into());
+        }
+    };
+    if let Some(receipt) = &receipt {
+        audit_root_update(
+            config,
+            device.chain_id(),
+            &device.contract_address(),
+            root_cid,
+            &new_root_cid,
+            receipt,
+        )?;
+    }
+    let tx_hash = receipt.map(|receipt| receipt.id);
+    save_published(
+        config,
+        device,
+        change_log,
+        pushed_base,
+        &new_root_cid,
+        path.as_deref(),
+    )
+    .await?;
+    metrics::increment(metrics::PUSHES_TOTAL, 1);
+    metrics::observe(
+        metrics::PUSH_DURATION_SECONDS,
+        started.elapsed().as_secs_f64(),
+    );
+
+    // The root is already published, so a failed mirror only needs retrying
+    if s3_mirrored {
+        publish_to_s3(config, device, root_cid, &new_root_cid).await;
+    }
+    match device.update_mirrors(*root_cid, new_root_cid).await {
+        Ok(failed) => {
+            for (chain_id, e) in failed {
+                tracing::error!(
+                    "failed to set root {} on the mirror on chain {}: {}",
+                    new_root_cid,
+                    chain_id,
+                    e
+                );
+            }
+        }
+        Err(e) => tracing::error!("failed to update mirrors to root {}: {}", new_root_cid, e),
+    }
+    if let Some(mfs_path) = &mfs_path {
+        if let Err(e) = device
+            .mirror_to_mfs(pushed_base, &new_root_cid, mfs_path, true)
+            .await
+        {
+            tracing::error!(
+                "failed to mirror root {} at {}: {}",
+                new_root_cid,
+                mfs_path,
+                e
+            );
+        }
+    }
+
+    if let Err(reason) = config.cancellation().check() {
+        return Err(PushError::Cancelled(
+            reason,
+            CancelledPush::Unhooked {
+                root_cid: new_root_cid,
+            },
+        ));
+    }
+
+    // Hooks run once everything is published and saved -- failing them can't undo that
+    let outcome = PushOutcome {
+        previous_root_cid: *root_cid,
+        root_cid: new_root_cid,
+        tx_hash,
+    };
+    let hooks = config.on_disk_device()?.hooks().clone();
+    run_post_push_hooks(&hooks.post_push_hooks(), &outcome, hooks.fail_on_error)?;
+
+    Ok(())
+}
+
+/// Make sure we're publishing what we committed: a whole push has to publish the
+/// root the change log was committed at
+pub(super) fn check_committed_root(
+    change_log: &ChangeLog,
+    path: Option<&Path>,
+    new_root_cid: &Cid,
+) -> Result<(), PushError> {
+    if let (Some(committed_root_cid), None) = (change_log.committed(), path) {
+        if committed_root_cid != new_root_cid {
+            return Err(PushError::MissmatchedCommittedRoot(
+                *new_root_cid,
+                *committed_root_cid,
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Append a root update that landed to the device's audit log, if it keeps one
+/// Every way a root gets published comes through here -- a push, a resumed push
+/// that had landed, and the seed of a migration
+/// The root's already updated, so losing its record mustn't lose the push: a
+/// failed append is only logged, with the record
+pub(super) fn audit_root_update(
+    config: &Config,
+    chain_id: u16,
+    contract_address: &Address,
+    previous_root_cid: &Cid,
+    root_cid: &Cid,
+    receipt: &RootUpdateReceipt,
+) -> Result<(), PushError> {
+    if let Some(audit_log) = config.on_disk_device()?.audit_log() {
+        let record = AuditRecord::new(
+            chain_id,
+            to_checksum(contract_address, None),
+            *previous_root_cid,
+            *root_cid,
+            receipt,
+        );
+        if let Err(e) = record.append(audit_log) {
+            tracing::error!(
+                "failed to append to audit log {}: {} -- record was {}",
+                audit_log.display(),
+                e,
+                serde_json::to_string(&record).unwrap_or_default()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Bring the device's S3 mirror, if it has one, up to a root that's already
+/// published -- for roots published some other way than a push, i.e. a resumed
+/// push that had landed, or one carried over to a new contract
+pub(super) async fn sync_s3_mirror(
+    config: &Config,
+    device: &Device,
+    pushed_base: &DorStore,
+    previous_root_cid: &Cid,
+    root_cid: &Cid,
+) -> Result<(), PushError> {
+    if mirror_to_s3(config, device, root_cid, pushed_base).await? {
+        publish_to_s3(config, device, previous_root_cid, root_cid).await;
+    }
+    Ok(())
+}
+
+/// Copy a root's dag and every one of its objects' to the device's S3 mirror, if
+/// it has one. Runs before the root update, so nothing the mirror points at is missing
+/// A mirror that can't be written only fails the push if it's `required` --
+/// otherwise it's logged, and the mirror is left behind for a later push to catch up
+/// # Returns whether the root made it to a mirror
+#[cfg(feature = "s3")]
+async fn mirror_to_s3(
+    config: &Config,
+    device: &Device,
+    root_cid: &Cid,
+    pushed_base: &DorStore,
+) -> Result<bool, PushError> {
+    let required = match config.on_disk_device()?.s3_mirror() {
+        Some(s3_remote) => s3_remote.required,
+        None => return Ok(false),
+    };
+    match copy_to_s3(config, device, root_cid, pushed_base).await {
+        Ok(()) => Ok(true),
+        Err(e) if required => Err(e),
+        Err(e) => {
+            tracing::error!("failed to mirror root {} to s3: {}", root_cid, e);
+            Ok(false)
+        }
+    }
+}
 
+#[cfg(feature = "s3")]
+async fn copy_to_s3(
+    config: &Config,
+    device: &Device,
+    root_cid: &Cid,
+    pushed_base: &DorStore,
+) -> Result<(), PushError> {
+    let store = config.s3_store()?;
+    let mut cids = vec![*root_cid];
+    cids.extend(replicated_on(config, device, pushed_base, Replica::S3)?);
+    let uploaded = store
+        .mirror(device.remote_blocks(), &cids)
+        .await
+        .map_err(DeviceError::from)?;
+    tracing::info!("mirrored {} blocks of {} to s3", uploaded, root_cid);
     Ok(())
 }
 
+#[cfg(not(feature = "s3"))]
+async fn mirror_to_s3(
+    config: &Config,
+    _device: &Device,
+    _root_cid: &Cid,
+    _pushed_base: &DorStore,
+) -> Result<bool, PushError> {
+    if config.on_disk_device()?.s3_mirror().is_some() {
+        return Err(ConfigError::S3Disabled.into());
+    }
+    Ok(false)
+}
+
+/// Point the device's S3 mirror at a root we just published
+/// A mirror left behind by pushes it missed is caught up, so long as the root it
+/// holds is one of ours. One holding a root we don't know is left alone
+#[cfg(feature = "s3")]
+async fn publish_to_s3(config: &Config, device: &Device, previous_root_cid: &Cid, root_cid: &Cid) {
+    let store = match config.s3_store() {
+        Ok(store) => store,
+        Err(e) => {
+            tracing::error!("failed to open s3 mirror for root {}: {}", root_cid, e);
+            return;
+        }
+    };
+    let current_cid = match store.read_root().await {
+        Ok(current_cid) => current_cid,
+        Err(e) => {
+            tracing::error!("failed to read the root of the s3 mirror: {}", e);
+            return;
+        }
+    };
+    let from_cid = match current_cid {
+        // Bare buckets take any root
+        None => *previous_root_cid,
+        Some(current_cid) if current_cid == *root_cid => return,
+        Some(current_cid) if current_cid == *previous_root_cid => current_cid,
+        Some(current_cid) => match device.root_history(previous_root_cid).await {
+            Ok(history) if history.contains(&current_cid) => {
+                tracing::info!(
+                    "s3 mirror was behind at root {}, catching it up",
+                    current_cid
+                );
+                current_cid
+            }
+            Ok(_) => {
+                tracing::error!(
+                    "s3 mirror holds root {}, which isn't one of ours -- leaving it be",
+                    current_cid
+                );
+                return;
+            }
+            Err(e) => {
+                tracing::error!("failed to read the history behind root {}: {}", root_cid, e);
+                return;
+            }
+        },
+    };
+    if let Err(e) = store.update(from_cid, *root_cid).await {
+        tracing::error!("failed to set root {} on s3 mirror: {}", root_cid, e);
+    }
+}
+
+#[cfg(not(feature = "s3"))]
+async fn publish_to_s3(
+    _config: &Config,
+    _device: &Device,
+    _previous_root_cid: &Cid,
+    _root_cid: &Cid,
+) {
+}
+
+fn unpublished(reason: CancelReason, root_cid: Cid) -> PushError {
+    PushError::Cancelled(reason, CancelledPush::Unpublished { root_cid })
+}
+
+/// Check the root we're replacing is still on the remote, without fetching it
+/// Its root block being on the node, or pinned or being pinned by its pinning
+/// service, is enough. Gives up after PREVIOUS_ROOT_PROBE_TIMEOUT
+async fn previous_root_reachable(device: &Device, root_cid: &Cid) -> Result<(), String> {
+    match tokio::time::timeout(
+        PREVIOUS_ROOT_PROBE_TIMEOUT,
+        device.block_status(root_cid, true),
+    )
+    .await
+    {
+        Ok(Ok(BlockStatus::Absent)) => {
+            Err("it's not on the node or its pinning service".to_string())
+        }
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err(format!(
+            "the node didn't answer within {:?}",
+            PREVIOUS_ROOT_PROBE_TIMEOUT
+        )),
+    }
+}
+
+/// Warn if the contract's previous root isn't the one our base manifest points back
+/// at -- a sign our history and the chain's have silently diverged
+async fn check_previous_root(device: &Device, base: &DorStore) {
+    match device.read_previous_root_cid().await {
+        Ok(Some(previous_root_cid)) if &previous_root_cid != base.previous_root() => {
+            tracing::warn!(
+                "contract's previous root is {}, but our manifest's is {} -- history may \
+                 have diverged",
+                previous_root_cid,
+                base.previous_root()
+            );
+        }
+        Ok(Some(_)) => {}
+        Ok(None) => tracing::warn!(
+            "contract doesn't report a previous root -- turn off check_previous_root for it"
+        ),
+        Err(e) => tracing::warn!("failed to read the contract's previous root: {}", e),
+    }
+}
+
+/// Report what tripped the push guardrail, and ask before going ahead
+fn confirm_large_push(added_objects: usize, added_bytes: u64) -> Result<(), PushError> {
+    println!(
+        "this push adds {} new or changed objects ({} bytes), over the device's guardrail",
+        added_objects, added_bytes
+    );
+    print!("push anyway? [y/N] ");
+    std::io::stdout().flush()?;
+
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input)?;
+    match input.trim().to_lowercase().as_str() {
+        "y" | "yes" => Ok(()),
+        _ => Err(PushError::TooLarge {
+            added_objects,
+            added_bytes,
+        }),
+    }
+}
+
+/// Show what a root update will do and cost, and ask before going ahead
+async fn confirm_update(
+    device: &Device,
+    root_cid: &Cid,
+    new_root_cid: &Cid,
+) -> Result<(), PushError> {
+    let cost = match device
+        .estimate_root_cid_update(*root_cid, *new_root_cid)
+        .await?
+    {
+        Some(cost) => cost,
+        // Nothing is going on chain
+        None => return Ok(()),
+    };
+    println!("chain id: {}", device.chain_id());
+    println!("contract: {:?}", device.contract_address());
+    println!("old root: {}", root_cid);
+    println!("new root: {}", new_root_cid);
+    println!("estimated cost: {} ETH", format_ether(cost));
+    print!("send update? [y/N] ");
+    std::io::stdout().flush()?;
+
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input)?;
+    match input.trim().to_lowercase().as_str() {
+        "y" | "yes" => Ok(()),
+        _ => Err(PushError::Aborted),
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum PushError {
     #[error("config error")]
     Config(#[from] ConfigError),
     #[error("device error: {0}")]
     Device(#[from] DeviceError),
-    #[error("cid mismatch: {0} != {1}")]
-    CidMismatch(Cid, Cid),
+    #[error("failed to pin {path:?} ({cid}): {source}")]
+    ObjectPin {
+        path: PathBuf,
+        cid: Cid,
+        source: DeviceError,
+    },
+    #[error("can't read {path:?} to push it: {source}")]
+    ObjectFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("cid mismatch for {path:?}: expected {expected}, pushed {actual}")]
+    CidMismatch {
+        path: PathBuf,
+        expected: Cid,
+        actual: Cid,
+    },
+    #[error("root would reference {} objects missing from the remote: {0:?}", .0.len())]
+    IncompleteRoot(Vec<(PathBuf, Cid)>),
+    #[error("root {0} doesn't match the locally committed root {1}")]
+    MissmatchedCommittedRoot(Cid, Cid),
+    #[error("root published, but hooks failed: {0}")]
+    Hook(#[from] HookError),
     #[error("io error: {0}")]
     Io(#[from] std::io::Error),
+    #[error("push stopped, {0}: {1}")]
+    Cancelled(CancelReason, CancelledPush),
     #[error("no changes to push")]
     NoChanges,
-    #[error("missmatched root cid: {0} != {1}")]
+    #[error("root update aborted")]
+    Aborted,
+    #[error("root update needs confirming, but nobody's there to -- pass --yes to skip it")]
+    Unconfirmed,
+    #[error("push adds {added_objects} objects ({added_bytes} bytes), over the guardrail -- use --force to push anyway")]
+    TooLarge {
+        added_objects: usize,
+        added_bytes: u64,
+    },
+    #[error("pinning service didn't pin {} cids: {0:?}", .0.len())]
+    Unpinned(Vec<(Cid, Option<RemotePinStatus>)>),
+    #[error("there are staged changes -- commit or reset them before pushing")]
+    UncommittedStage,
+    #[error("missmatched root cid: change log has {0}, disk has {1}")]
     MissmatchedRootCid(Cid, Cid),
     #[error("missmatched base: {0:?} != {1:?}")]
     MissmatchedBase(DorStore, DorStore),
+    #[error("failed to push {} objects: {}", .0.len(), display_failures(.0))]
+    ObjectsFailed(Vec<PushError>),
+    #[error("root update {0} from an earlier push may still land -- see `push status`")]
+    PendingUpdate(String),
+    #[error("previous root {0} can't be fetched from the remote ({1}) -- pass --allow-orphan-previous to push anyway")]
+    UnreachablePreviousRoot(Cid, String),
+    #[error("can't push {0:?}: the node can't add data under {1}")]
+    UnsupportedParams(PathBuf, CidParams),
+    #[error("can't replicate {path:?}: {source}")]
+    Replication {
+        path: PathBuf,
+        source: ReplicationError,
+    },
+    #[error("{0:?} is kept off some replicas, but directory roots link every object -- use the manifest root layout")]
+    PartialDirectoryReplication(PathBuf),
+}
+
+fn display_failures(failures: &[PushError]) -> String {
+    failures
+        .iter()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join("; ")
 }
@@ -0,0 +1,317 @@
+use std::path::PathBuf;
+use std::time::Instant;
+
+use cid::Cid;
+use futures_util::stream::{StreamExt, TryStreamExt};
+
+use super::push::{
+    audit_root_update, check_committed_root, publish, push, save_published, sync_s3_mirror,
+    PushError, ReadyRoot,
+};
+use crate::cli::args::PushSubcommand;
+use crate::cli::cancel;
+use crate::cli::changes::{PushOptions, PushStage, PushState};
+use crate::cli::config::{Config, ConfigError};
+use crate::device::{BlockStatus, Device, DeviceError, RootUpdateReceipt};
+use crate::types::DorStore;
+
+/// How many pins `push commit` checks at once
+const PIN_CHECK_CONCURRENCY: usize = 16;
+
+/// Whether the root an unfinished push was publishing has landed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Landed {
+    /// The remote holds the push's root
+    Yes,
+    /// The remote still holds the root the push was publishing over
+    No,
+    /// The remote holds some other root -- another device pushed in the meantime
+    Diverged,
+}
+
+async fn landed(device: &Device, push_state: &PushState) -> Result<Landed, DeviceError> {
+    let current_root_cid = device.read_root_cid().await?;
+    if Some(&current_root_cid) == push_state.root_cid() {
+        Ok(Landed::Yes)
+    } else if &current_root_cid == push_state.previous_root_cid() {
+        Ok(Landed::No)
+    } else {
+        Ok(Landed::Diverged)
+    }
+}
+
+/// Show, resume, or clean up a push that was interrupted or failed, or publish
+/// a prepared one. Preparing starts a push of its own
+pub async fn push_subcommand(
+    config: &Config,
+    subcommand: &PushSubcommand,
+) -> Result<(), PushSubcommandError> {
+    match subcommand {
+        PushSubcommand::Prepare {
+            fail_fast,
+            name_pin,
+            force,
+            path,
+            skip_balance_check,
+            allow_orphan_previous,
+        } => {
+            let options = PushOptions {
+                fail_fast: *fail_fast,
+                name_pin: *name_pin,
+                force: *force,
+                path: path.clone(),
+                skip_balance_check: *skip_balance_check,
+                allow_orphan_previous: *allow_orphan_previous,
+                prepare: true,
+                unattended: false,
+            };
+            push(config, false, false, &options).await?;
+        }
+        PushSubcommand::Status => {
+            if let Some(push_state) = unfinished_push(config)? {
+                status(config, &push_state).await?;
+            }
+        }
+        PushSubcommand::Resume { yes } => {
+            if let Some(push_state) = unfinished_push(config)? {
+                resume(config, &push_state, *yes).await?;
+            }
+        }
+        PushSubcommand::Commit { yes } => {
+            if let Some(push_state) = unfinished_push(config)? {
+                commit(config, push_state, *yes).await?;
+            }
+        }
+        PushSubcommand::Abort => {
+            if let Some(push_state) = unfinished_push(config)? {
+                abort(config, &push_state)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The root and objects of a prepared push the remote no longer keeps whole, by path
+/// Pins are checked rather than blocks, since a pin covers the dag below it, and
+/// a block that's only cached goes at the node's next garbage collection
+async fn unpinned(
+    device: &Device,
+    root_cid: &Cid,
+    pushed_base: &DorStore,
+) -> Result<Vec<(PathBuf, Cid)>, DeviceError> {
+    let mut cids = vec![(PathBuf::new(), *root_cid)];
+    cids.extend(
+        pushed_base
+            .objects()
+            .iter()
+            .map(|(path, object)| (path.clone(), *object.cid())),
+    );
+    let statuses: Vec<_> = futures_util::stream::iter(cids)
+        .map(|(path, cid)| async move {
+            let status = device.pin_status(&cid, true).await?;
+            Ok::<_, DeviceError>((path, cid, status))
+        })
+        .buffer_unordered(PIN_CHECK_CONCURRENCY)
+        .try_collect()
+        .await?;
+    let mut missing: Vec<_> = statuses
+        .into_iter()
+        .filter(|(_, _, status)| *status == BlockStatus::Absent)
+        .map(|(path, cid, _)| (path, cid))
+        .collect();
+    missing.sort();
+    Ok(missing)
+}
+
+/// The push left unfinished, if there is one -- saying so if there isn't
+fn unfinished_push(config: &Config) -> Result<Option<PushState>, ConfigError> {
+    let push_state = config.push_state()?;
+    if push_state.is_none() {
+        println!("no unfinished push");
+    }
+    Ok(push_state)
+}
+
+async fn status(config: &Config, push_state: &PushState) -> Result<(), PushSubcommandError> {
+    print!("{}", push_state);
+    // Only a push that got as far as publishing can have landed
+    if push_state.root_cid().is_some() {
+        let device = config.device()?;
+        match landed(&device, push_state).await {
+            Ok(Landed::Yes) => println!("landed: yes -- run `push resume` to save it"),
+            Ok(Landed::No) if push_state.stage() == PushStage::Prepared => {
+                println!("landed: no -- run `push commit` to publish it")
+            }
+            Ok(Landed::No) => println!("landed: no"),
+            Ok(Landed::Diverged) => println!("landed: no, the remote has moved on"),
+            Err(e) => println!("landed: unknown, {}", e),
+        }
+    }
+    Ok(())
+}
+
+async fn resume(
+    config: &Config,
+    push_state: &PushState,
+    yes: bool,
+) -> Result<(), PushSubcommandError> {
+    let device = config.device()?;
+    let landed = match push_state.root_cid() {
+        Some(_) => landed(&device, push_state).await?,
+        None => Landed::No,
+    };
+    match landed {
+        Landed::Yes => {
+            // The update went through -- all that's left is saving it locally
+            let root_cid = *push_state.root_cid().unwrap();
+            let pushed_base = device.read_dor_store(&root_cid, true).await?;
+            let change_log = config.change_log()?;
+            save_published(
+                config,
+                &device,
+                &change_log,
+                &pushed_base,
+                &root_cid,
+                push_state.options().path.as_deref(),
+            )
+            .await?;
+            // Audit the update we can find, or failing that the one we sent
+            let receipt = match device.find_root_update(&root_cid).await {
+                Some(update) => Some(RootUpdateReceipt {
+                    id: update.tx_hash,
+                    block_number: update.block_number,
+                    gas_used: None,
+                }),
+                None => push_state.tx_hash().map(|tx_hash| RootUpdateReceipt {
+                    id: tx_hash.clone(),
+                    block_number: None,
+                    gas_used: None,
+                }),
+            };
+            if let Some(receipt) = &receipt {
+                audit_root_update(
+                    config,
+                    device.chain_id(),
+                    &device.contract_address(),
+                    push_state.previous_root_cid(),
+                    &root_cid,
+                    receipt,
+                )?;
+            }
+            sync_s3_mirror(
+                config,
+                &device,
+                &pushed_base,
+                push_state.previous_root_cid(),
+                &root_cid,
+            )
+            .await?;
+            println!(
+                "root {} had landed, saved it locally. Post push hooks weren't run",
+                root_cid
+            );
+        }
+        Landed::Diverged => return Err(PushSubcommandError::Diverged),
+        Landed::No if push_state.stage() == PushStage::Broadcast => {
+            return Err(PushSubcommandError::StillPending(
+                push_state.tx_hash().cloned().unwrap_or_default(),
+            ));
+        }
+        Landed::No if push_state.stage() == PushStage::Prepared => {
+            return Err(PushSubcommandError::Prepared(
+                *push_state.root_cid().unwrap(),
+            ));
+        }
+        Landed::No => {
+            push(config, false, yes, push_state.options()).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn commit(
+    config: &Config,
+    mut push_state: PushState,
+    yes: bool,
+) -> Result<(), PushSubcommandError> {
+    let root_cid = match (push_state.stage(), push_state.root_cid()) {
+        (PushStage::Prepared, Some(root_cid)) => *root_cid,
+        (stage, _) => return Err(PushSubcommandError::NotPrepared(stage)),
+    };
+    cancel::install();
+    let device = config.device()?;
+    match landed(&device, &push_state).await? {
+        Landed::Yes => return Err(PushSubcommandError::AlreadyLanded(root_cid)),
+        Landed::Diverged => return Err(PushSubcommandError::Diverged),
+        Landed::No => {}
+    }
+
+    // Anything committed or pulled since has to go into a fresh prepare
+    let change_log = config.change_log()?;
+    let (previous_root_cid, _) = change_log.first_version().unwrap();
+    if previous_root_cid != push_state.previous_root_cid() {
+        return Err(PushSubcommandError::Stale(*previous_root_cid));
+    }
+    let path = push_state.options().path.clone();
+    check_committed_root(&change_log, path.as_deref(), &root_cid)?;
+
+    // Hours may have passed -- make sure the remote didn't drop anything
+    let pushed_base = device.read_dor_store(&root_cid, true).await?;
+    let missing = unpinned(&device, &root_cid, &pushed_base).await?;
+    if !missing.is_empty() {
+        return Err(PushError::IncompleteRoot(missing).into());
+    }
+
+    let ready = ReadyRoot {
+        change_log: &change_log,
+        pushed_base: &pushed_base,
+        previous_root_cid: *previous_root_cid,
+        root_cid,
+    };
+    publish(
+        config,
+        &device,
+        &mut push_state,
+        &ready,
+        yes,
+        Instant::now(),
+    )
+    .await?;
+    Ok(())
+}
+
+fn abort(config: &Config, push_state: &PushState) -> Result<(), PushSubcommandError> {
+    if push_state.stage() == PushStage::Broadcast {
+        tracing::warn!(
+            "root update {} may still land -- pull once it does",
+            push_state.tx_hash().cloned().unwrap_or_default()
+        );
+    }
+    config.clear_push_state()?;
+    println!("forgot the unfinished push. Objects it pinned stay pinned");
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PushSubcommandError {
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("device error: {0}")]
+    Device(#[from] DeviceError),
+    #[error("push error: {0}")]
+    Push(#[from] PushError),
+    #[error("root update {0} hasn't landed yet -- check again later, or abort the push")]
+    StillPending(String),
+    #[error("the remote root has moved on since the push started -- abort it and pull")]
+    Diverged,
+    #[error("root {0} is prepared -- run `push commit` to publish it, or abort it")]
+    Prepared(Cid),
+    #[error("the unfinished push is {0}, not prepared -- see `push status`")]
+    NotPrepared(PushStage),
+    #[error("prepared root {0} has already landed -- run `push resume` to save it")]
+    AlreadyLanded(Cid),
+    #[error(
+        "our root has moved on to {0} since the push was prepared -- abort it and prepare again"
+    )]
+    Stale(Cid),
+}
@@ -0,0 +1,439 @@
+use std::fmt::Display;
+use std::path::PathBuf;
+
+use cid::Cid;
+
+use super::diff::{diff, DiffError};
+use super::pull::{pull_object, PullError};
+use crate::cli::changes::{ChangeType, Log};
+use crate::cli::config::{Config, ConfigError};
+use crate::device::DeviceError;
+use crate::types::{DorStore, Object};
+
+/// How one side of a merge changed something relative to the common ancestor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Added,
+    Modified,
+    Removed,
+}
+
+impl Side {
+    fn of<T: PartialEq>(ancestor: Option<&T>, version: Option<&T>) -> Self {
+        match (ancestor, version) {
+            (None, _) => Self::Added,
+            (Some(_), None) => Self::Removed,
+            (Some(_), Some(_)) => Self::Modified,
+        }
+    }
+}
+
+impl Display for Side {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Added => "added",
+            Self::Modified => "modified",
+            Self::Removed => "removed",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Something changed differently on both sides of a merge
+#[derive(Debug, Clone)]
+struct Conflict {
+    /// The object path, or `schema <name>` for a schema entry
+    what: String,
+    local: Side,
+    remote: Side,
+}
+
+/// Which side a three-way merge of a single entry took
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Merged<T> {
+    /// Only we changed it, or both sides made the same change
+    Local(Option<T>),
+    /// Only the remote changed it
+    Remote(Option<T>),
+    Conflict(Side, Side),
+}
+
+/// Three-way merge a single entry against its common ancestor
+fn merge_entry<T: PartialEq + Clone>(
+    ancestor: Option<&T>,
+    local: Option<&T>,
+    remote: Option<&T>,
+) -> Merged<T> {
+    merge_entry_by(ancestor, local, remote, |a, b| a == b)
+}
+
+/// Three-way merge a single entry, counting entries as the same change when `same`
+/// says they are
+fn merge_entry_by<T: Clone>(
+    ancestor: Option<&T>,
+    local: Option<&T>,
+    remote: Option<&T>,
+    same: impl Fn(&T, &T) -> bool,
+) -> Merged<T> {
+    let same_entry = |a: Option<&T>, b: Option<&T>| match (a, b) {
+        (Some(a), Some(b)) => same(a, b),
+        (a, b) => a.is_none() && b.is_none(),
+    };
+    if same_entry(local, remote) || same_entry(remote, ancestor) {
+        Merged::Local(local.cloned())
+    } else if same_entry(local, ancestor) {
+        Merged::Remote(remote.cloned())
+    } else {
+        Merged::Conflict(Side::of(ancestor, local), Side::of(ancestor, remote))
+    }
+}
+
+/// Whether two objects hold the same thing -- the same content, tagged the same
+/// way. When they were staged, and the mtimes of their files, don't matter, so
+/// two devices staging the same file don't conflict
+fn same_object(a: &Object, b: &Object) -> bool {
+    a.cid() == b.cid() && a.metadata() == b.metadata() && a.link() == b.link()
+}
+
+/// The result of merging our committed manifest onto the remote's
+#[derive(Debug, Default)]
+struct Merge {
+    /// Objects the remote changed, which need to be brought into the working dir
+    from_remote: Vec<(PathBuf, Option<Object>)>,
+    /// Paths where our change was kept over the remote's manifest
+    kept_local: usize,
+    conflicts: Vec<Conflict>,
+}
+
+impl Display for Merge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "from remote: {}, kept local: {}, conflicts: {}",
+            self.from_remote.len(),
+            self.kept_local,
+            self.conflicts.len()
+        )?;
+        for conflict in self.conflicts.iter() {
+            writeln!(
+                f,
+                "\x1b[0;31mconflict\x1b[0m {}: {} locally, {} on the remote",
+                conflict.what, conflict.local, conflict.remote
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Three-way merge the local manifest and remote manifest against their ancestor
+/// # Returns the merged manifest, built on top of the remote's, and what went into it
+fn merge(ancestor: &DorStore, local: &DorStore, remote: &DorStore) -> (DorStore, Merge) {
+    let mut merged = remote.clone();
+    let mut report = Merge::default();
+
+    let mut paths: Vec<&PathBuf> = ancestor
+        .objects()
+        .keys()
+        .chain(local.objects().keys())
+        .chain(remote.objects().keys())
+        .collect();
+    paths.sort();
+    paths.dedup();
+    for path in paths {
+        let remote_object = remote.objects().get(path);
+        let same_as_remote = |object: &Option<Object>| match (object, remote_object) {
+            (Some(object), Some(remote_object)) => same_object(object, remote_object),
+            (object, remote_object) => object.is_none() && remote_object.is_none(),
+        };
+        match merge_entry_by(
+            ancestor.objects().get(path),
+            local.objects().get(path),
+            remote_object,
+            same_object,
+        ) {
+            Merged::Local(object) if same_as_remote(&object) => {}
+            Merged::Local(Some(object)) => {
+                report.kept_local += 1;
+                merged.insert_object(path.clone(), object);
+            }
+            Merged::Local(None) => {
+                report.kept_local += 1;
+                merged.remove_object(path);
+            }
+            Merged::Remote(object) => report.from_remote.push((path.clone(), object)),
+            Merged::Conflict(local, remote) => report.conflicts.push(Conflict {
+                what: path.display().to_string(),
+                local,
+                remote,
+            }),
+        }
+    }
+
+    let mut names: Vec<&String> = ancestor
+        .schema()
+        .names()
+        .chain(local.schema().names())
+        .chain(remote.schema().names())
+        .collect();
+    names.sort();
+    names.dedup();
+    for name in names {
+        match merge_entry(
+            ancestor.schema().get(name),
+            local.schema().get(name),
+            remote.schema().get(name),
+        ) {
+            Merged::Local(Some(fields)) => merged.insert_schema_entry(name, &fields),
+            Merged::Local(None) => merged.remove_schema_entry(name),
+            Merged::Remote(_) => {}
+            Merged::Conflict(local, remote) => report.conflicts.push(Conflict {
+                what: format!("schema {}", name),
+                local,
+                remote,
+            }),
+        }
+    }
+
+    (merged, report)
+}
+
+/// Catch up with a remote root another device pushed, keeping our committed changes
+/// The remote's manifest is three-way merged with ours against the root we last
+/// pulled, which must be in the remote root's history. Changes only one side made
+/// are merged, and the remote's are pulled into the working dir. If both sides
+/// changed the same path, the conflicts are listed and nothing is touched. To
+/// resolve one, bring the path back in line with the remote's copy, stage, commit,
+/// and reconcile again -- then redo the local change on top
+/// # Args
+/// - force: pull the remote's changes over edits in the working dir that haven't
+///   been staged, rather than refusing to
+pub async fn reconcile(config: &Config, force: bool) -> Result<(), ReconcileError> {
+    if config.has_stage() {
+        return Err(ReconcileError::UncommittedStage);
+    }
+
+    let device = config.device()?;
+    let change_log = config.change_log()?;
+    // Pulling the remote's objects would clobber edits we haven't staged yet
+    if !force {
+        let unstaged = diff(config, false, None).await?.delta(change_log.log());
+        if let Some(path) = unstaged.keys().next() {
+            return Err(ReconcileError::UnstagedChanges(path.clone()));
+        }
+    }
+    let (base_root_cid, ancestor) = change_log.first_version().unwrap();
+    let (_local_root_cid, local) = change_log.last_version().unwrap();
+
+    let remote_root_cid = device.read_root_cid().await?;
+    if &remote_root_cid == base_root_cid {
+        println!(
+            "remote root is still {}, nothing to reconcile",
+            base_root_cid
+        );
+        return Ok(());
+    }
+    let history = device.root_history(&remote_root_cid).await?;
+    if !history.contains(base_root_cid) {
+        return Err(ReconcileError::NoCommonAncestor(
+            *base_root_cid,
+            remote_root_cid,
+        ));
+    }
+    let remote = device.read_dor_store(&remote_root_cid, true).await?;
+
+    let (mut merged, report) = merge(ancestor, local, &remote);
+    print!("{}", report);
+    if !report.conflicts.is_empty() {
+        return Err(ReconcileError::Conflicts(report.conflicts.len()));
+    }
+
+    // Bring the remote's side of the merge into the working dir
+    for (path, object) in report.from_remote.iter() {
+        let working_path = config.data_dir().join(path);
+        match object {
+            Some(object) => {
+                pull_object(&device, object, &working_path).await?;
+            }
+            None if std::fs::symlink_metadata(&working_path).is_ok() => {
+                std::fs::remove_file(&working_path)?;
+            }
+            None => {}
+        }
+    }
+
+    // Our changes are now committed on top of the remote root
+    let mut next_change_log = change_log.clone();
+    next_change_log.wipe(&remote, &remote_root_cid);
+    if report.kept_local > 0 {
+        merged.set_previous_root(remote_root_cid);
+        let mut log = Log::new();
+        for (path, object) in merged.objects().iter() {
+            let change_type = match remote.objects().get(path) {
+                Some(remote_object) if same_object(remote_object, object) => ChangeType::Base,
+                Some(_) => ChangeType::Modified,
+                None => ChangeType::Added,
+            };
+            log.insert(path.clone(), (*object.cid(), change_type));
+        }
+        for (path, object) in remote.objects().iter() {
+            if !merged.objects().contains_key(path) {
+                log.insert(path.clone(), (*object.cid(), ChangeType::Removed));
+            }
+        }
+        let merged_root_cid = device.hash_dor_store(&merged, false).await?;
+        next_change_log.update(&log, &merged, &merged_root_cid);
+    }
+
+    config.save_pulled(&remote_root_cid, &remote, next_change_log)?;
+    println!("reconciled onto {}", remote_root_cid);
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReconcileError {
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("device error: {0}")]
+    Device(#[from] DeviceError),
+    #[error("pull error: {0}")]
+    Pull(#[from] PullError),
+    #[error("diff error: {0}")]
+    Diff(#[from] DiffError),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("there are staged changes -- commit or reset them before reconciling")]
+    UncommittedStage,
+    #[error("{0:?} has changed in the working dir -- stage and commit it, or pass --force to overwrite it")]
+    UnstagedChanges(PathBuf),
+    #[error("remote root {1} doesn't descend from our root {0} -- pull to start over from it")]
+    NoCommonAncestor(Cid, Cid),
+    #[error("{0} conflicts between local and remote changes -- nothing was changed")]
+    Conflicts(usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::test_utils::cid;
+
+    /// An object for content, as staged at a given time
+    fn object(byte: u8, staged_at: &str) -> Object {
+        let mut value = serde_json::to_value(Object::new(cid(byte))).unwrap();
+        value["created_at"] = json!(staged_at);
+        value["updated_at"] = json!(staged_at);
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn store(objects: &[(&str, &Object)]) -> DorStore {
+        let mut dor_store = DorStore::default();
+        for (path, object) in objects {
+            dor_store.insert_object(PathBuf::from(path), (*object).clone());
+        }
+        dor_store
+    }
+
+    #[test]
+    fn merges_changes_one_side_made() {
+        let a = object(1, "2024-01-01T00:00:00Z");
+        let b = object(2, "2024-01-01T00:00:00Z");
+        let ancestor = store(&[("a", &a), ("b", &b)]);
+
+        // We changed a, the remote removed b and added c
+        let local_a = object(3, "2024-02-01T00:00:00Z");
+        let c = object(4, "2024-02-01T00:00:00Z");
+        let local = store(&[("a", &local_a), ("b", &b)]);
+        let remote = store(&[("a", &a), ("c", &c)]);
+
+        let (merged, report) = merge(&ancestor, &local, &remote);
+        assert!(report.conflicts.is_empty());
+        assert_eq!(report.kept_local, 1);
+        assert_eq!(
+            report.from_remote,
+            vec![
+                (PathBuf::from("b"), None),
+                (PathBuf::from("c"), Some(c.clone()))
+            ]
+        );
+        assert_eq!(merged, store(&[("a", &local_a), ("c", &c)]));
+    }
+
+    #[test]
+    fn the_same_change_on_both_sides_merges() {
+        let a = object(1, "2024-01-01T00:00:00Z");
+        let ancestor = store(&[("a", &a)]);
+
+        // Both sides staged the same new content, at different times
+        let local = store(&[("a", &object(2, "2024-02-01T00:00:00Z"))]);
+        let remote = store(&[("a", &object(2, "2024-03-01T00:00:00Z"))]);
+        let (merged, report) = merge(&ancestor, &local, &remote);
+        assert!(report.conflicts.is_empty());
+        assert_eq!(report.kept_local, 0);
+        assert!(report.from_remote.is_empty());
+        assert_eq!(merged, remote);
+
+        // And both removed it
+        let (merged, report) = merge(&ancestor, &DorStore::default(), &DorStore::default());
+        assert!(report.conflicts.is_empty());
+        assert!(merged.objects().is_empty());
+
+        // A restage of unchanged content on one side isn't a change at all
+        let restaged = store(&[("a", &object(1, "2024-04-01T00:00:00Z"))]);
+        let remote = store(&[("a", &object(5, "2024-02-01T00:00:00Z"))]);
+        let (_, report) = merge(&ancestor, &restaged, &remote);
+        assert!(report.conflicts.is_empty());
+        assert_eq!(report.from_remote.len(), 1);
+    }
+
+    #[test]
+    fn different_changes_on_both_sides_conflict() {
+        let a = object(1, "2024-01-01T00:00:00Z");
+        let ancestor = store(&[("a", &a)]);
+
+        let cases = [
+            // Both modified it differently
+            (
+                store(&[("a", &object(2, "2024-02-01T00:00:00Z"))]),
+                store(&[("a", &object(3, "2024-02-01T00:00:00Z"))]),
+                Side::Modified,
+                Side::Modified,
+            ),
+            // We modified it, the remote removed it
+            (
+                store(&[("a", &object(2, "2024-02-01T00:00:00Z"))]),
+                DorStore::default(),
+                Side::Modified,
+                Side::Removed,
+            ),
+        ];
+        for (local, remote, local_side, remote_side) in cases {
+            let (_, report) = merge(&ancestor, &local, &remote);
+            assert_eq!(report.conflicts.len(), 1);
+            assert_eq!(report.conflicts[0].local, local_side);
+            assert_eq!(report.conflicts[0].remote, remote_side);
+        }
+
+        // Both added the path, with different content
+        let local = store(&[("b", &object(2, "2024-02-01T00:00:00Z"))]);
+        let remote = store(&[("b", &object(3, "2024-02-01T00:00:00Z"))]);
+        let (_, report) = merge(&DorStore::default(), &local, &remote);
+        assert_eq!(report.conflicts[0].local, Side::Added);
+        assert_eq!(report.conflicts[0].remote, Side::Added);
+    }
+
+    #[test]
+    fn retagging_is_a_change() {
+        let a = object(1, "2024-01-01T00:00:00Z");
+        let ancestor = store(&[("a", &a)]);
+        let tag = |value: Value| {
+            let mut object = a.clone();
+            object.tag(&"content".to_string(), &value);
+            object
+        };
+        let local = store(&[("a", &tag(json!({ "size": "1" })))]);
+        let remote = store(&[("a", &tag(json!({ "size": "2" })))]);
+        let (_, report) = merge(&ancestor, &local, &remote);
+        assert_eq!(report.conflicts.len(), 1);
+    }
+}
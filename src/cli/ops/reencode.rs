@@ -0,0 +1,271 @@
+use std::fs::File;
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use cid::Cid;
+
+use super::diff::{diff, DiffError};
+use super::pull::{pull_object, PullError};
+use super::push::{push, PushError};
+use crate::cli::changes::{ChangeType, Log, PushOptions};
+use crate::cli::config::{Config, ConfigError};
+use crate::cli::walk::link_data;
+use crate::device::{CidParams, DeviceError};
+use crate::types::{hash_code, DorStore};
+
+/// How many old cids to unpin at once
+const UNPIN_CONCURRENCY: usize = 16;
+/// How long unpinning the old cids gets in all
+const UNPIN_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// The params to re-encode to
+/// # Args
+/// - to_hash: the name of the hash function, i.e. `blake3` or `sha2-256`
+/// - cid_version: 0 or 1. Cidv0 only holds sha2-256
+pub fn target_params(to_hash: &str, cid_version: u64) -> Result<CidParams, ReencodeError> {
+    let unsupported =
+        || ReencodeError::UnsupportedTarget(format!("cidv{}/{}", cid_version, to_hash));
+    let hash_code = hash_code(to_hash).ok_or_else(unsupported)?;
+    let version = cid::Version::try_from(cid_version).map_err(|_| unsupported())?;
+    if version == cid::Version::V0 && to_hash != "sha2-256" {
+        return Err(unsupported());
+    }
+    Ok(CidParams { version, hash_code })
+}
+
+/// The objects of a store not already under the target params
+fn to_reencode(base: &DorStore, target: CidParams) -> Vec<PathBuf> {
+    base.objects()
+        .iter()
+        .filter(|(_, object)| CidParams::of(object.cid()) != target)
+        .map(|(path, _)| path.clone())
+        .collect()
+}
+
+/// Re-add every object that isn't under the target params, i.e. from a store
+/// written with sha2 and cidv0, and push a root pointing at the new cids
+/// Each such object is pulled by its old cid, re-added, and moved over to its new
+/// cid with its path and metadata intact. Objects already on the target params
+/// are left as they are. Pulling overwrites the working dir, so it has to be clean
+/// Pushes add objects under their cids' own params, and diffs hash back to them,
+/// but objects staged later are hashed with ours again
+/// # Args
+/// - target: the params to re-encode to
+/// - unpin_old: unpin the old cids from the remote once the new root has landed
+/// - yes: skip confirming the root update before it's sent
+pub async fn reencode(
+    config: &Config,
+    target: CidParams,
+    unpin_old: bool,
+    yes: bool,
+) -> Result<(), ReencodeError> {
+    if config.has_stage() {
+        return Err(ReencodeError::UncommittedStage);
+    }
+
+    let device = config.device()?;
+    let change_log = config.change_log()?;
+    let (root_cid, base) = change_log.first_version().unwrap();
+    let (next_root_cid, _) = change_log.last_version().unwrap();
+    if root_cid != next_root_cid {
+        return Err(ReencodeError::UnpushedChanges);
+    }
+    // Pulling objects by their old cids would clobber edits we haven't staged yet
+    let unstaged = diff(config, false, None).await?.delta(change_log.log());
+    if let Some(path) = unstaged.keys().next() {
+        return Err(ReencodeError::UnstagedChanges(path.clone()));
+    }
+
+    let mut next_base = base.clone();
+    let mut log = Log::new();
+    let mut index = config.hash_cache()?;
+    let mut old_cids: Vec<Cid> = Vec::new();
+    let paths = to_reencode(base, target);
+    for (path, object) in base.objects().iter() {
+        if !paths.contains(path) {
+            log.insert(path.clone(), (*object.cid(), ChangeType::Base));
+            continue;
+        }
+
+        // Bring in the object as the remote has it, then hash it under the target params
+        let working_path = config.data_dir().join(path);
+        pull_object(&device, object, &working_path).await?;
+        let new_cid = match object.link() {
+            Some(link_target) => {
+                let data = Cursor::new(link_data(link_target));
+                device.hash_ipfs_data_with(data, target, false).await?
+            }
+            None => {
+                let file = File::open(&working_path)?;
+                device.hash_ipfs_data_with(file, target, false).await?
+            }
+        };
+        let new_cid =
+            new_cid.ok_or_else(|| ReencodeError::UnsupportedTarget(target.to_string()))?;
+        if object.link().is_none() {
+            let metadata = std::fs::metadata(&working_path)?;
+            index.insert(working_path.clone(), &metadata, new_cid);
+        }
+        tracing::info!("{}: {} -> {}", path.display(), object.cid(), new_cid);
+
+        let mut next_object = object.clone();
+        next_object.update(new_cid);
+        next_base.insert_object(path.clone(), next_object);
+        log.insert(path.clone(), (new_cid, ChangeType::Modified));
+        old_cids.push(*object.cid());
+    }
+    config.set_hash_cache(&index)?;
+
+    if old_cids.is_empty() {
+        println!("every object is already {}", target);
+        return Ok(());
+    }
+    println!("re-encoded {} objects to {}", old_cids.len(), target);
+
+    // Commit the rewritten manifest, and push it like any other change
+    next_base.set_previous_root(*root_cid);
+    let next_root_cid = device.hash_dor_store(&next_base, false).await?;
+    let mut next_change_log = change_log.clone();
+    next_change_log.update(&log, &next_base, &next_root_cid);
+    config.set_change_log(next_change_log)?;
+    push(config, false, yes, &PushOptions::default()).await?;
+
+    if unpin_old {
+        let report = device
+            .unpin_many(&old_cids, true, UNPIN_CONCURRENCY, UNPIN_TIMEOUT)
+            .await;
+        for (cid, e) in report.failed.iter() {
+            tracing::warn!("failed to unpin {}: {}", cid, e);
+        }
+        for cid in report.timed_out.iter() {
+            tracing::warn!("timed out unpinning {}", cid);
+        }
+        println!(
+            "unpinned {} old cids, {} failed, {} timed out",
+            report.unpinned.len(),
+            report.failed.len(),
+            report.timed_out.len()
+        );
+    }
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReencodeError {
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("device error: {0}")]
+    Device(#[from] DeviceError),
+    #[error("diff error: {0}")]
+    Diff(#[from] DiffError),
+    #[error("pull error: {0}")]
+    Pull(#[from] PullError),
+    #[error("push error: {0}")]
+    Push(#[from] PushError),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error(
+        "can't re-encode to {0} -- use cidv1 with a hash the node knows, or cidv0 with sha2-256"
+    )]
+    UnsupportedTarget(String),
+    #[error("there are staged changes -- commit or reset them before re-encoding")]
+    UncommittedStage,
+    #[error("there are committed changes that haven't been pushed -- push them first")]
+    UnpushedChanges,
+    #[error("{0:?} has changed in the working dir -- stage and commit it, or reset it, before re-encoding")]
+    UnstagedChanges(PathBuf),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::cid_with_hash;
+    use crate::types::Object;
+
+    const SHA2_256: u64 = 0x12;
+    const BLAKE3: u64 = 0x1e;
+
+    /// A store of one object, and one link, under a hash
+    fn store(hash_code: u64) -> DorStore {
+        let mut store = DorStore::default();
+        store.insert_object(PathBuf::from("a"), Object::new(cid_with_hash(hash_code, 1)));
+        let mut link = Object::new(cid_with_hash(hash_code, 2));
+        link.set_link(Some(PathBuf::from("a")));
+        store.insert_object(PathBuf::from("b"), link);
+        store
+    }
+
+    fn cids(store: &DorStore) -> Vec<Cid> {
+        store
+            .objects()
+            .values()
+            .map(|object| *object.cid())
+            .collect()
+    }
+
+    /// What re-encoding a store does to it, with made up digests
+    fn reencoded(store: &DorStore, target: CidParams) -> DorStore {
+        let mut next = store.clone();
+        for path in to_reencode(store, target) {
+            let mut object = store.get_object(&path).unwrap().clone();
+            let byte = object.cid().hash().digest()[0];
+            object.update(cid_with_hash(target.hash_code, byte));
+            next.insert_object(path, object);
+        }
+        next
+    }
+
+    #[test]
+    fn stores_round_trip_between_hashes() {
+        let sha2 = target_params("sha2-256", 1).unwrap();
+        let blake3 = target_params("blake3", 1).unwrap();
+        assert_eq!(blake3, CidParams::default());
+        let paths = vec![PathBuf::from("a"), PathBuf::from("b")];
+
+        // sha2-256 -> blake3, and back
+        let start = store(SHA2_256);
+        assert!(to_reencode(&start, sha2).is_empty());
+        assert_eq!(to_reencode(&start, blake3), paths);
+        let there = reencoded(&start, blake3);
+        assert_eq!(cids(&there), cids(&store(BLAKE3)));
+        assert!(to_reencode(&there, blake3).is_empty());
+        assert_eq!(to_reencode(&there, sha2), paths);
+        assert_eq!(cids(&reencoded(&there, sha2)), cids(&start));
+
+        // blake3 -> sha2-256, and back
+        let start = store(BLAKE3);
+        let there = reencoded(&start, sha2);
+        assert_eq!(cids(&there), cids(&store(SHA2_256)));
+        assert!(to_reencode(&there, sha2).is_empty());
+        assert_eq!(cids(&reencoded(&there, blake3)), cids(&start));
+
+        // Links keep their target through it
+        assert_eq!(
+            there.get_object(&PathBuf::from("b")).unwrap().link(),
+            Some(&PathBuf::from("a"))
+        );
+    }
+
+    #[test]
+    fn targets_parse_from_flags() {
+        assert_eq!(
+            target_params("sha2-256", 0).unwrap(),
+            CidParams {
+                version: cid::Version::V0,
+                hash_code: SHA2_256
+            }
+        );
+        for (to_hash, cid_version) in [("md5", 1), ("blake3", 0), ("blake3", 2)] {
+            assert!(
+                matches!(
+                    target_params(to_hash, cid_version),
+                    Err(ReencodeError::UnsupportedTarget(_))
+                ),
+                "cidv{}/{}",
+                cid_version,
+                to_hash
+            );
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use std::path::PathBuf;
+
+use crate::cli::config::{Config, ConfigError};
+use crate::device::DeviceError;
+use crate::types::Replication;
+
+/// Set which of the device's replicas pushes copy an object to -- the node and
+/// its pinning service, and the S3 mirror. The policy is kept on the object in the
+/// manifest, so it's staged, and holds for every device pushing the store
+/// # Args
+/// - path: the object's path
+/// - replication: the policy to keep it under, or None to go back to the device's default
+pub async fn replicate(
+    config: &Config,
+    path: &PathBuf,
+    replication: Option<&Replication>,
+) -> Result<(), ReplicateError> {
+    let device = config.device()?;
+    let mut change_log = config.stage()?;
+    let (_cid, staged) = change_log.last_version().unwrap();
+    let mut dor_store = staged.clone();
+
+    let object = dor_store
+        .get_object_mut(path)
+        .ok_or_else(|| ReplicateError::NotFound(path.clone()))?;
+    if object.replication() == replication {
+        println!("{} is already kept that way", path.display());
+        return Ok(());
+    }
+    object.set_replication(replication.cloned());
+
+    let cid = device.hash_dor_store(&dor_store, false).await?;
+    let log = change_log.log().clone();
+    change_log.update(&log, &dor_store, &cid);
+    config.set_stage(change_log)?;
+    match replication {
+        Some(replication) => println!("replicating {} on {}", path.display(), replication),
+        None => println!("replicating {} on the device default", path.display()),
+    }
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReplicateError {
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("device error: {0}")]
+    Device(#[from] DeviceError),
+    #[error("no object at {0:?} -- stage it first")]
+    NotFound(PathBuf),
+}
@@ -0,0 +1,17 @@
+use crate::cli::config::{Config, ConfigError};
+
+/// Discard the staged copy of the change log, leaving the change log as it was
+/// Does not touch the working dir
+pub fn reset(config: &Config) -> Result<(), ResetError> {
+    if !config.has_stage() {
+        tracing::info!("nothing to reset");
+    }
+    config.reset_stage()?;
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ResetError {
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+}
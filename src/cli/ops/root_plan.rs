@@ -0,0 +1,96 @@
+use std::fmt::Display;
+
+use cid::Cid;
+use ethers::types::{Address, Bytes};
+use serde::Serialize;
+
+use crate::cli::config::{Config, ConfigError};
+use crate::device::{DeviceError, RootTarget};
+
+/// Signature of the contract call a push makes to publish a root
+const UPDATE_SIGNATURE: &str = "update(bytes32[2],bytes32[2])";
+
+/// How to print a root plan
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum RootPlanOutput {
+    #[default]
+    Text,
+    Json,
+}
+
+/// The root update a push would make, as it would be sent
+#[derive(Debug, Serialize)]
+struct RootPlan {
+    root_target: RootTarget,
+    chain_id: u16,
+    contract: Address,
+    function: &'static str,
+    old_root: String,
+    new_root: String,
+    /// None if the root is only published over ipns
+    calldata: Option<Bytes>,
+}
+
+impl Display for RootPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "root target: {:?}", self.root_target)?;
+        writeln!(f, "chain id: {}", self.chain_id)?;
+        writeln!(f, "contract: {:?}", self.contract)?;
+        writeln!(f, "call: update({}, {})", self.old_root, self.new_root)?;
+        match &self.calldata {
+            Some(calldata) => write!(f, "calldata: {}", calldata),
+            None => write!(f, "calldata: none, the root is only published over ipns"),
+        }
+    }
+}
+
+/// Show the root update the next push would make, without pinning or sending anything
+/// The next root is hashed from the committed manifest, the same way a push writes it
+pub async fn root_plan(config: &Config, output: RootPlanOutput) -> Result<(), RootPlanError> {
+    let device = config.device()?;
+    let change_log = config.change_log()?;
+    let (root_cid, base) = change_log.first_version().unwrap();
+    let (_, next_base) = change_log.last_version().unwrap();
+    if base == next_base {
+        return Err(RootPlanError::NoChanges);
+    }
+
+    let next_root_cid = device.hash_dor_store(next_base, false).await?;
+    if let Some(committed_root_cid) = change_log.committed() {
+        if committed_root_cid != &next_root_cid {
+            return Err(RootPlanError::MissmatchedCommittedRoot(
+                next_root_cid,
+                *committed_root_cid,
+            ));
+        }
+    }
+
+    let plan = RootPlan {
+        root_target: config.on_disk_device()?.root_target(),
+        chain_id: device.chain_id(),
+        contract: device.contract_address(),
+        function: UPDATE_SIGNATURE,
+        old_root: root_cid.to_string(),
+        new_root: next_root_cid.to_string(),
+        calldata: device.root_cid_update_calldata(*root_cid, next_root_cid)?,
+    };
+    match output {
+        RootPlanOutput::Text => println!("{}", plan),
+        RootPlanOutput::Json => println!("{}", serde_json::to_string_pretty(&plan)?),
+    }
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RootPlanError {
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("device error: {0}")]
+    Device(#[from] DeviceError),
+    #[error("serde error: {0}")]
+    Serde(#[from] serde_json::Error),
+    #[error("no committed changes -- a push wouldn't update the root")]
+    NoChanges,
+    #[error("next root hashes to {0}, but {1} was committed -- commit again")]
+    MissmatchedCommittedRoot(Cid, Cid),
+}
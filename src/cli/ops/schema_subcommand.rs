@@ -11,7 +11,7 @@ pub async fn schema_subcommand(
 ) -> Result<(), SchemaSubcommandError> {
     // load the dor_store schema
     let device = config.device()?;
-    let mut change_log = config.change_log()?;
+    let mut change_log = config.stage()?;
     let (_cid, base_dor_store) = change_log.last_version().unwrap();
     let mut dor_store = base_dor_store.clone();
 
@@ -66,7 +66,7 @@ pub async fn schema_subcommand(
         let log = wtf_log.log();
         change_log.update(log, &dor_store, &cid);
 
-        config.set_change_log(change_log)?;
+        config.set_stage(change_log)?;
     }
     Ok(())
 }
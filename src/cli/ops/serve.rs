@@ -0,0 +1,449 @@
+use std::net::SocketAddr;
+
+use crate::cli::config::{Config, ConfigError};
+use crate::device::DeviceError;
+
+#[cfg(feature = "serve")]
+use std::path::{Component, PathBuf};
+
+#[cfg(feature = "serve")]
+use cid::Cid;
+#[cfg(feature = "serve")]
+use futures_util::stream::{BoxStream, FuturesUnordered, StreamExt, TryStreamExt};
+#[cfg(feature = "serve")]
+use tokio::io::AsyncWriteExt;
+#[cfg(feature = "serve")]
+use tokio::net::TcpStream;
+
+#[cfg(feature = "serve")]
+use super::export::sniff;
+#[cfg(feature = "serve")]
+use crate::cli::cancel;
+#[cfg(feature = "serve")]
+use crate::device::{CidParams, Device, FileHasher};
+#[cfg(feature = "serve")]
+use crate::http::{read_request, Request, Response};
+#[cfg(feature = "serve")]
+use crate::types::{DorStore, Object};
+
+/// Metadata fields, under any schema, naming an object's content type
+#[cfg(feature = "serve")]
+const CONTENT_TYPE_FIELDS: [&str; 3] = ["content_type", "content-type", "mime"];
+/// Served for objects whose type we can't tell
+#[cfg(feature = "serve")]
+const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// Serve objects over http, reading through the cache and gateway
+/// - `GET /<path>` serves the object at path in the current base. What's at a path
+///   changes as roots do, so these are revalidated against the object's cid
+/// - `GET /ipfs/<cid>` serves an object in the current base by its cid. These never
+///   change, so they're cached as immutable. Cids the base doesn't hold aren't served
+/// Objects are streamed, and hashed as they go out. The last bytes of a response are
+/// held back until the whole object has hashed to its cid -- if it doesn't, we hang
+/// up short, so clients see an incomplete response rather than a wrong one. Single
+/// byte ranges are served the same way, sending only the bytes asked for
+/// Runs until ctrl-c. Requires the `serve` feature
+/// # Args
+/// - addr: the address to listen on
+#[cfg(feature = "serve")]
+pub async fn serve(config: &Config, addr: SocketAddr) -> Result<(), ServeError> {
+    let device = config.device()?;
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    println!("serving objects on http://{}", addr);
+
+    cancel::install();
+    let mut connections = FuturesUnordered::new();
+    loop {
+        tokio::select! {
+            conn = listener.accept() => match conn {
+                Ok((stream, _)) => connections.push(handle(config, &device, stream)),
+                Err(e) => tracing::warn!("failed to accept connection: {}", e),
+            },
+            Some(()) = connections.next(), if !connections.is_empty() => {}
+            _ = config.cancellation().cancelled() => break,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "serve"))]
+pub async fn serve(_config: &Config, addr: SocketAddr) -> Result<(), ServeError> {
+    Err(ServeError::ServeDisabled(addr))
+}
+
+#[cfg(feature = "serve")]
+async fn handle(config: &Config, device: &Device, mut stream: TcpStream) {
+    let result = match read_request(&mut stream).await {
+        Ok(Some(request)) => {
+            let result = respond(config, device, &request, &mut stream).await;
+            if let Ok(status) = result {
+                tracing::info!("{} {} {}", request.method, request.target, status);
+            }
+            result.map(|_| ())
+        }
+        Ok(None) => {
+            Response::error(400, "Bad Request", "malformed request")
+                .write(&mut stream)
+                .await
+        }
+        Err(e) => {
+            tracing::debug!("failed to read request: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = result {
+        tracing::debug!("failed to write response: {}", e);
+    }
+}
+
+/// What a request asks for
+#[cfg(feature = "serve")]
+enum Target {
+    /// The object at a path in the current base
+    Path(PathBuf),
+    /// An object by its cid
+    Cid(Cid),
+}
+
+#[cfg(feature = "serve")]
+fn parse_target(target: &str) -> Option<Target> {
+    let path = target.split(['?', '#']).next().unwrap_or_default();
+    let path = percent_decode(path.strip_prefix('/')?)?;
+    if let Some(cid) = path.strip_prefix("ipfs/") {
+        return cid.trim_end_matches('/').parse().ok().map(Target::Cid);
+    }
+    let path = PathBuf::from(path);
+    // Only plain paths within the store
+    if path.as_os_str().is_empty() || !path.components().all(|c| matches!(c, Component::Normal(_)))
+    {
+        return None;
+    }
+    Some(Target::Path(path))
+}
+
+#[cfg(feature = "serve")]
+fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut iter = s.bytes();
+    while let Some(b) = iter.next() {
+        if b == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            let hex = std::str::from_utf8(&hex).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+        } else {
+            bytes.push(b);
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// An object in the base a request resolved to
+#[cfg(feature = "serve")]
+struct Route {
+    object: Object,
+    cache_control: &'static str,
+}
+
+/// Find the object in the base a request's target names
+/// # Returns the response to send instead if there isn't one
+#[cfg(feature = "serve")]
+fn route(base: &DorStore, target: &str) -> Result<Route, Response> {
+    match parse_target(target) {
+        Some(Target::Path(path)) => match base.get_object(&path) {
+            Some(object) => Ok(Route {
+                object: object.clone(),
+                cache_control: "no-cache",
+            }),
+            None => Err(Response::error(404, "Not Found", "no object at that path")),
+        },
+        // Only what the base holds -- we're not an open gateway
+        Some(Target::Cid(cid)) => match base.objects().values().find(|o| *o.cid() == cid) {
+            Some(object) => Ok(Route {
+                object: object.clone(),
+                cache_control: "public, max-age=31536000, immutable",
+            }),
+            None => Err(Response::error(404, "Not Found", "no object with that cid")),
+        },
+        None => Err(Response::error(
+            404,
+            "Not Found",
+            "not an object path or /ipfs/<cid>",
+        )),
+    }
+}
+
+/// Answer a request, writing the response to the stream
+/// # Returns the status we answered with
+#[cfg(feature = "serve")]
+async fn respond(
+    config: &Config,
+    device: &Device,
+    request: &Request,
+    stream: &mut TcpStream,
+) -> std::io::Result<u16> {
+    let head = match request.method.as_str() {
+        "GET" => false,
+        "HEAD" => true,
+        _ => {
+            let mut response = Response::error(405, "Method Not Allowed", "only GET and HEAD");
+            response.header("Allow", "GET, HEAD".to_string());
+            return send(response, stream).await;
+        }
+    };
+    let base = match config.base() {
+        Ok(base) => base,
+        Err(e) => {
+            let response = Response::error(500, "Internal Server Error", &e.to_string());
+            return send(response, stream).await;
+        }
+    };
+    let route = match route(&base, &request.target) {
+        Ok(route) => route,
+        Err(response) => return send(response, stream).await,
+    };
+    let cid = *route.object.cid();
+
+    let etag = format!("\"{}\"", cid);
+    if request.header("If-None-Match") == Some(etag.as_str()) {
+        let mut response = Response::new(304, "Not Modified", Vec::new());
+        cache_headers(&mut response, etag, &route);
+        response.head = head;
+        return send(response, stream).await;
+    }
+    let opened = match open_object(device, &cid).await {
+        Ok(opened) => opened,
+        Err(e) => return send(Response::error(502, "Bad Gateway", &e), stream).await,
+    };
+    let (mut response, bounds) = match ranged(request.header("Range"), opened.size) {
+        Some(ranged) => ranged,
+        None => {
+            let mut response = Response::new(416, "Range Not Satisfiable", Vec::new());
+            response.header("Content-Range", format!("bytes */{}", opened.size));
+            cache_headers(&mut response, etag, &route);
+            response.head = head;
+            return send(response, stream).await;
+        }
+    };
+    let content_type = content_type(&route.object)
+        .or_else(|| sniff(&opened.first).map(|(mime, _)| mime))
+        .unwrap_or(DEFAULT_CONTENT_TYPE.to_string());
+    response.header("Content-Type", content_type);
+    cache_headers(&mut response, etag, &route);
+    response.write_head(stream, bounds.1 - bounds.0).await?;
+    if !head {
+        // Hanging up is how a bad object gets refused once its headers are out
+        if let Err(e) = send_verified(&cid, opened, bounds, stream).await {
+            tracing::warn!("stopped serving {}: {}", cid, e);
+            return Ok(response.status);
+        }
+    }
+    stream.flush().await?;
+    Ok(response.status)
+}
+
+#[cfg(feature = "serve")]
+fn cache_headers(response: &mut Response, etag: String, route: &Route) {
+    response.header("ETag", etag);
+    response.header("Cache-Control", route.cache_control.to_string());
+    response.header("Accept-Ranges", "bytes".to_string());
+}
+
+#[cfg(feature = "serve")]
+async fn send(response: Response, stream: &mut TcpStream) -> std::io::Result<u16> {
+    response.write(stream).await?;
+    Ok(response.status)
+}
+
+/// An object we've started reading through the cache and gateway
+#[cfg(feature = "serve")]
+struct Opened {
+    size: u64,
+    /// The first chunk, already read off the stream to sniff its type from
+    first: Vec<u8>,
+    rest: BoxStream<'static, Result<Vec<u8>, DeviceError>>,
+}
+
+#[cfg(feature = "serve")]
+async fn open_object(device: &Device, cid: &Cid) -> Result<Opened, String> {
+    // We can only reproduce cids made with the params we hash with
+    if CidParams::of(cid) != CidParams::default() {
+        return Err(format!(
+            "can't verify {}: it's {}, not {} -- re-encode it to serve it",
+            cid,
+            CidParams::of(cid),
+            CidParams::default()
+        ));
+    }
+    let size = device
+        .object_size(cid, true)
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut rest = device.stream_object(cid).await.map_err(|e| e.to_string())?;
+    let first = rest
+        .try_next()
+        .await
+        .map_err(|e| e.to_string())?
+        .unwrap_or_default();
+    Ok(Opened { size, first, rest })
+}
+
+/// Start a response with the part of an object a `Range` header asks for
+/// Only single ranges are honored -- anything else gets the whole object
+/// # Returns the response, without a body, and the start, inclusive, and end,
+///  exclusive, of the bytes to fill it with. None if the range can't be satisfied
+#[cfg(feature = "serve")]
+fn ranged(range: Option<&str>, len: u64) -> Option<(Response, (u64, u64))> {
+    let spec = match range.and_then(|range| range.trim().strip_prefix("bytes=")) {
+        Some(spec) if !spec.contains(',') => spec,
+        _ => return Some((Response::new(200, "OK", Vec::new()), (0, len))),
+    };
+    let (start, end) = byte_range(spec, len)?;
+    let mut response = Response::new(206, "Partial Content", Vec::new());
+    response.header("Content-Range", format!("bytes {}-{}/{}", start, end, len));
+    Some((response, (start, end + 1)))
+}
+
+/// Bounds, inclusive, of a single `start-end`, `start-`, or `-suffix` byte range
+/// # Returns None if the range can't be satisfied
+#[cfg(feature = "serve")]
+fn byte_range(spec: &str, len: u64) -> Option<(u64, u64)> {
+    let (start, end) = spec.split_once('-')?;
+    let last = len.checked_sub(1)?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", "") => return None,
+        // The last n bytes
+        ("", suffix) => {
+            let n: u64 = suffix.parse().ok()?;
+            if n == 0 {
+                return None;
+            }
+            (len.saturating_sub(n), last)
+        }
+        (start, "") => (start.parse().ok()?, last),
+        (start, end) => (start.parse().ok()?, end.parse::<u64>().ok()?.min(last)),
+    };
+    (start <= end).then_some((start, end))
+}
+/// Send the bytes of an object within bounds as it streams in, hashing all of it
+/// The last bytes we'd send are held back until the whole object has hashed to
+/// its cid, so a bad object never goes out complete
+#[cfg(feature = "serve")]
+async fn send_verified(
+    cid: &Cid,
+    opened: Opened,
+    bounds: (u64, u64),
+    stream: &mut TcpStream,
+) -> Result<(), String> {
+    let Opened {
+        size,
+        first,
+        mut rest,
+    } = opened;
+    let (start, end) = bounds;
+    let mut hasher = FileHasher::new();
+    let mut offset = 0u64;
+    let mut held: Option<Vec<u8>> = None;
+    let mut chunk = Some(first);
+    while let Some(data) = chunk {
+        hasher.update(&data).map_err(|e| e.to_string())?;
+        let chunk_start = offset;
+        offset += data.len() as u64;
+        let (from, to) = (start.max(chunk_start), end.min(offset));
+        if from < to {
+            let part = data[(from - chunk_start) as usize..(to - chunk_start) as usize].to_vec();
+            if let Some(previous) = held.replace(part) {
+                stream
+                    .write_all(&previous)
+                    .await
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+        chunk = rest.try_next().await.map_err(|e| e.to_string())?;
+    }
+    let hash = hasher.finish().map_err(|e| e.to_string())?;
+    if hash != *cid {
+        return Err(format!("{} hashes to {}", cid, hash));
+    }
+    if offset != size {
+        return Err(format!("{} holds {} bytes, not {}", cid, offset, size));
+    }
+    if let Some(last) = held {
+        stream.write_all(&last).await.map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// The content type an object's metadata names, under any schema
+#[cfg(feature = "serve")]
+fn content_type(object: &Object) -> Option<String> {
+    object.metadata().values().find_map(|value| {
+        CONTENT_TYPE_FIELDS
+            .iter()
+            .find_map(|field| value.get(field)?.as_str())
+            .map(str::to_string)
+    })
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ServeError {
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("device error: {0}")]
+    Device(#[from] DeviceError),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[cfg(not(feature = "serve"))]
+    #[error("can't serve on {0}: built without the `serve` feature")]
+    ServeDisabled(SocketAddr),
+}
+
+#[cfg(all(test, feature = "serve"))]
+mod tests {
+
+    use super::*;
+    use crate::test_utils::cid;
+
+    fn routed(base: &DorStore, target: &str) -> Result<(Cid, &'static str), u16> {
+        route(base, target)
+            .map(|route| (*route.object.cid(), route.cache_control))
+            .map_err(|response| response.status)
+    }
+
+    #[test]
+    fn routes_only_what_the_base_holds() {
+        let mut base = DorStore::default();
+        base.insert_object(PathBuf::from("docs/a b.txt"), Object::new(cid(1)));
+
+        assert_eq!(
+            routed(&base, "/docs/a%20b.txt?x=1"),
+            Ok((cid(1), "no-cache"))
+        );
+        assert_eq!(
+            routed(&base, &format!("/ipfs/{}/", cid(1))),
+            Ok((cid(1), "public, max-age=31536000, immutable"))
+        );
+        assert_eq!(routed(&base, &format!("/ipfs/{}", cid(2))), Err(404));
+        assert_eq!(routed(&base, "/docs/missing.txt"), Err(404));
+        assert_eq!(routed(&base, "/docs/../docs/a%20b.txt"), Err(404));
+        assert_eq!(routed(&base, "/ipfs/not-a-cid"), Err(404));
+        assert_eq!(routed(&base, "/"), Err(404));
+        assert_eq!(routed(&base, "relative"), Err(404));
+    }
+
+    #[test]
+    fn ranges_pick_the_bytes_to_send() {
+        let bounds = |range: Option<&str>| ranged(range, 10).map(|(r, b)| (r.status, b));
+        assert_eq!(bounds(None), Some((200, (0, 10))));
+        assert_eq!(bounds(Some("bytes=2-4")), Some((206, (2, 5))));
+        assert_eq!(bounds(Some("bytes=7-")), Some((206, (7, 10))));
+        assert_eq!(bounds(Some("bytes=-3")), Some((206, (7, 10))));
+        assert_eq!(bounds(Some("bytes=5-100")), Some((206, (5, 10))));
+        // Multiple ranges get the whole object
+        assert_eq!(bounds(Some("bytes=0-1,4-5")), Some((200, (0, 10))));
+        assert_eq!(bounds(Some("bytes=10-")), None);
+        assert_eq!(bounds(Some("bytes=4-2")), None);
+        assert_eq!(bounds(Some("bytes=-0")), None);
+        assert_eq!(ranged(Some("bytes=0-"), 0).map(|(r, _)| r.status), None);
+        assert_eq!(ranged(None, 0).map(|(_, b)| b), Some((0, 0)));
+    }
+}
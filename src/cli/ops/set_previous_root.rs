@@ -0,0 +1,78 @@
+use cid::Cid;
+
+use crate::cli::cid_arg::{parse_root_arg, CidArgError, RootArg};
+use crate::cli::config::{Config, ConfigError};
+use crate::device::{is_root_cid, DeviceError};
+
+/// Parse a root cid or tag argument, taking `none` to mean no root
+pub fn parse_previous_root(s: &str) -> Result<RootArg, CidArgError> {
+    if s.eq_ignore_ascii_case("none") {
+        return Ok(RootArg::Cid(Cid::default()));
+    }
+    parse_root_arg(s)
+}
+
+/// Advanced: re-point the staged manifest's previous_root, i.e. to repair history
+/// after a compaction
+/// Only the stage changes -- the on-chain root is left alone until the change is
+/// committed and pushed. Staging again points previous_root back at the last
+/// staged version, so run this right before committing
+/// # Args
+/// - root: the root or tag to point at, or the default cid to clear it
+/// - resolve: also check the root reads as a manifest against the remote
+pub async fn set_previous_root(
+    config: &Config,
+    root: &RootArg,
+    resolve: bool,
+) -> Result<(), SetPreviousRootError> {
+    let root = &config.resolve_root(root)?;
+    let unset = *root == Cid::default();
+    if !unset && !is_root_cid(root) {
+        return Err(SetPreviousRootError::NotARoot(*root));
+    }
+
+    let device = config.device()?;
+    if !unset && resolve {
+        device
+            .read_dor_store(root, true)
+            .await
+            .map_err(|e| SetPreviousRootError::Unresolvable(*root, e))?;
+    }
+
+    let mut change_log = config.stage()?;
+    let (_cid, last_dor_store) = change_log.last_version().unwrap();
+    let previous_root = *last_dor_store.previous_root();
+    if previous_root == *root {
+        tracing::info!("previous root is already {}", root);
+        return Ok(());
+    }
+
+    let mut dor_store = last_dor_store.clone();
+    dor_store.set_previous_root(*root);
+    let cid = device.hash_dor_store(&dor_store, false).await?;
+    let log = change_log.log().clone();
+    change_log.update(&log, &dor_store, &cid);
+    config.set_stage(change_log)?;
+
+    let show = |cid: Cid| {
+        if cid == Cid::default() {
+            "none".to_string()
+        } else {
+            cid.to_string()
+        }
+    };
+    println!("previous root {} -> {}", show(previous_root), show(*root));
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SetPreviousRootError {
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("device error: {0}")]
+    Device(#[from] DeviceError),
+    #[error("{0} isn't a manifest or directory cid we could have published")]
+    NotARoot(Cid),
+    #[error("could not resolve {0} as a root: {1}")]
+    Unresolvable(Cid, DeviceError),
+}
@@ -0,0 +1,57 @@
+use super::push::{push, PushError};
+use crate::cli::changes::{ChangeType, Log, PushOptions};
+use crate::cli::config::{Config, ConfigError};
+use crate::device::DeviceError;
+
+/// Collapse the chain of delta manifests behind our root into one whole manifest
+/// A new root is pushed with the same objects and a whole manifest, pointing back
+/// at the current root. Readers then stop walking the chain at it
+/// # Args
+/// - yes: skip confirming the root update before it's sent
+pub async fn squash(config: &Config, yes: bool) -> Result<(), SquashError> {
+    if config.has_stage() {
+        return Err(SquashError::UncommittedStage);
+    }
+    let device = config.device()?;
+    let change_log = config.change_log()?;
+    let (root_cid, base) = change_log.first_version().unwrap();
+    let (next_root_cid, _) = change_log.last_version().unwrap();
+    if root_cid != next_root_cid {
+        return Err(SquashError::UnpushedChanges);
+    }
+
+    let depth = device.delta_depth(root_cid, true).await?;
+    if depth == 0 {
+        println!("root {} already has a whole manifest", root_cid);
+        return Ok(());
+    }
+    println!("squashing {} deltas behind root {}", depth, root_cid);
+
+    // Changing no objects is what makes the manifest get written whole
+    let mut next_base = base.clone();
+    next_base.set_previous_root(*root_cid);
+    let mut log = Log::new();
+    for (path, object) in next_base.objects().iter() {
+        log.insert(path.clone(), (*object.cid(), ChangeType::Base));
+    }
+    let next_root_cid = device.hash_dor_store(&next_base, false).await?;
+    let mut next_change_log = change_log.clone();
+    next_change_log.update(&log, &next_base, &next_root_cid);
+    config.set_change_log(next_change_log)?;
+    push(config, false, yes, &PushOptions::default()).await?;
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SquashError {
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("device error: {0}")]
+    Device(#[from] DeviceError),
+    #[error("push error: {0}")]
+    Push(#[from] PushError),
+    #[error("there are staged changes -- commit or reset them before squashing")]
+    UncommittedStage,
+    #[error("there are committed changes that haven't been pushed -- push them first")]
+    UnpushedChanges,
+}
@@ -1,12 +1,15 @@
 use std::fs::File;
-use std::path::PathBuf;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
 
+use chrono::{DateTime, Utc};
 use cid::Cid;
 
 use super::diff::{diff, DiffError};
 
 use crate::cli::changes::ChangeType;
 use crate::cli::config::{Config, ConfigError};
+use crate::cli::walk::{link_data, SymlinkPolicy};
 use crate::device::{Device, DeviceError};
 use crate::types::Object;
 
@@ -18,11 +21,52 @@ pub async fn stage_file(device: &Device, file_path: &PathBuf) -> Result<Cid, Sta
     Ok(cid)
 }
 
-pub async fn stage(config: &Config) -> Result<(), StageError> {
+/// Stage a symlink against the local ipfs node, by its target
+pub async fn stage_link(device: &Device, target: &Path) -> Result<Cid, StageError> {
+    let data = Cursor::new(link_data(target));
+    let cid = device.write_ipfs_data(data, false).await?;
+    Ok(cid)
+}
+
+/// The target of a path in the working dir, if we're storing it as a link
+pub fn stored_link(
+    working_path: &Path,
+    symlink_policy: SymlinkPolicy,
+) -> Result<Option<PathBuf>, std::io::Error> {
+    if symlink_policy != SymlinkPolicy::StoreAsLink
+        || !std::fs::symlink_metadata(working_path)?
+            .file_type()
+            .is_symlink()
+    {
+        return Ok(None);
+    }
+    Ok(Some(std::fs::read_link(working_path)?))
+}
+
+/// The modification time of a file in the working dir, to record in its object
+pub fn file_mtime(working_path: &Path) -> Result<DateTime<Utc>, std::io::Error> {
+    let mtime = std::fs::metadata(working_path)?.modified()?;
+    Ok(mtime.into())
+}
+
+/// Stage changes in the working dir
+/// # Args
+/// - no_cache: re-hash every file, rather than trusting cached hashes
+/// - allow_large: stage new or changed files even if they're over the device's max
+///   object size
+pub async fn stage(config: &Config, no_cache: bool, allow_large: bool) -> Result<(), StageError> {
     let device = config.device()?;
-    let working_dir = config.working_dir().clone();
-    let updates = diff(config).await?;
-    let mut change_log = config.change_log()?;
+    let on_disk_device = config.on_disk_device()?;
+    let symlink_policy = on_disk_device.symlink_policy();
+    let record_mtimes = on_disk_device.record_mtimes();
+    let max_object_size = if allow_large {
+        None
+    } else {
+        on_disk_device.max_object_size()
+    };
+    let working_dir = config.data_dir().clone();
+    let updates = diff(config, no_cache, max_object_size).await?;
+    let mut change_log = config.stage()?;
     let base_dor_store = config.base()?;
     let (last_root_cid, last_dor_store) = change_log.last_version().unwrap().clone();
     let mut update_dor_store = base_dor_store.clone();
@@ -37,21 +81,45 @@ pub async fn stage(config: &Config) -> Result<(), StageError> {
         // updates.insert(path.clone(), (cid.clone(), ChangeType::Staged));
 
         let working_path = working_dir.join(path);
+        // External objects have no file of ours to stage -- carry them over as they
+        // are, whether or not they've been pulled. Diff only gives their cid for a
+        // file if it still hashes back to it
+        if let Some(object) = last_dor_store
+            .get_object(path)
+            .filter(|object| object.is_external() && object.cid() == cid)
+        {
+            update_dor_store.insert_object(path.clone(), object.clone());
+            continue;
+        }
         if diff_type == &ChangeType::Added || diff_type == &ChangeType::Modified {
-            // Add the file to the local ipfs node
-            let added_cid = stage_file(&device, &working_path).await?;
+            // Add the file to the local ipfs node -- or the link target, for links
+            let link = stored_link(&working_path, symlink_policy)?;
+            let added_cid = match &link {
+                Some(target) => stage_link(&device, target).await?,
+                None => stage_file(&device, &working_path).await?,
+            };
             // Make sure the cid matches the one in the change_log
             if added_cid != *cid {
                 return Err(StageError::CidMismatch(added_cid, *cid));
             }
+            // Links keep no mtime -- there's no portable way to restore one
+            let mtime = if record_mtimes && link.is_none() {
+                Some(file_mtime(&working_path)?)
+            } else {
+                None
+            };
             // Insert the file into the DorStore
             if diff_type == &ChangeType::Added {
-                let object = Object::new(added_cid);
+                let mut object = Object::new(added_cid);
+                object.set_link(link);
+                object.set_mtime(mtime);
                 update_dor_store.insert_object(path.clone(), object.clone());
             } else if diff_type == &ChangeType::Modified {
                 let mut d = update_dor_store.clone();
-                let object = d.update_object(path, added_cid);
-                update_dor_store.insert_object(path.clone(), object.clone());
+                let mut object = d.update_object(path, added_cid).clone();
+                object.set_link(link);
+                object.set_mtime(mtime);
+                update_dor_store.insert_object(path.clone(), object);
             }
         }
 
@@ -74,7 +142,7 @@ pub async fn stage(config: &Config) -> Result<(), StageError> {
 
     change_log.update(&updates, &update_dor_store, &update_root_cid);
 
-    config.set_change_log(change_log)?;
+    config.set_stage(change_log)?;
 
     Ok(())
 }
@@ -0,0 +1,36 @@
+use super::diff::{diff, DiffError};
+
+use crate::cli::changes::DisplayableLog;
+use crate::cli::config::{Config, ConfigError};
+
+/// Show committed, staged, and unstaged changes
+/// - committed: changes in the change log, published on the next push
+/// - staged: changes in the stage, not yet committed
+/// - unstaged: changes in the working dir, not yet staged
+/// # Args
+/// - no_cache: re-hash every file, rather than trusting cached hashes
+pub async fn stat(config: &Config, no_cache: bool) -> Result<(), StatError> {
+    let change_log = config.change_log()?;
+    let stage = config.stage()?;
+    let updates = diff(config, no_cache, None).await?;
+
+    println!("committed:\n{}", change_log.displayable());
+    println!(
+        "staged:\n{}",
+        DisplayableLog(stage.log().delta(change_log.log()))
+    );
+    println!("unstaged:\n{}", DisplayableLog(updates.delta(stage.log())));
+
+    if let Some(committed_root_cid) = change_log.committed() {
+        println!("committed locally: {}", committed_root_cid);
+    }
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum StatError {
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("diff error: {0}")]
+    Diff(#[from] DiffError),
+}
@@ -0,0 +1,99 @@
+use cid::Cid;
+
+use crate::cli::args::TagSubcommand;
+use crate::cli::cid_arg::RootArg;
+use crate::cli::config::{Config, ConfigError};
+use crate::device::is_root_cid;
+use crate::types::{is_tag_name, Tag};
+
+/// Give a root a human name, i.e. `v1.0`, usable anywhere a root cid is
+/// Tags are kept per device, and never leave this machine
+/// # Args
+/// - name: the name to tag the root with
+/// - root: the root to tag, or another tag's -- defaults to our current root
+/// - force: move the tag if it already points at another root
+pub fn tag(
+    config: &Config,
+    name: &str,
+    root: Option<&RootArg>,
+    force: bool,
+) -> Result<(), TagError> {
+    if !is_tag_name(name) {
+        return Err(TagError::InvalidName(name.to_string()));
+    }
+    let root_cid = match root {
+        Some(root) => config.resolve_root(root)?,
+        None => config.root_cid()?,
+    };
+    if root_cid == Cid::default() {
+        return Err(TagError::NoRoot);
+    }
+    if !is_root_cid(&root_cid) {
+        return Err(TagError::NotARoot(root_cid));
+    }
+
+    let mut tags = config.tags()?;
+    if let Some(existing) = tags.get(name) {
+        if existing.root() == &root_cid {
+            println!("{} already tags {}", name, root_cid);
+            return Ok(());
+        }
+        if !force {
+            return Err(TagError::Exists(name.to_string(), *existing.root()));
+        }
+    }
+    tags.insert(name.to_string(), Tag::new(root_cid));
+    config.set_tags(&tags)?;
+    println!("tagged {} as {}", root_cid, name);
+    Ok(())
+}
+
+/// List the device's tags, oldest first
+pub fn tags(config: &Config) -> Result<(), TagError> {
+    let tags = config.tags()?;
+    if tags.is_empty() {
+        println!("no tags");
+        return Ok(());
+    }
+    let mut tags: Vec<(&String, &Tag)> = tags.iter().collect();
+    tags.sort_by_key(|(_, tag)| *tag.created_at());
+    for (name, tag) in tags {
+        println!(
+            "{}\t{}\t{}",
+            name,
+            tag.root(),
+            tag.created_at().to_rfc3339()
+        );
+    }
+    Ok(())
+}
+
+pub fn tag_subcommand(config: &Config, subcommand: &TagSubcommand) -> Result<(), TagError> {
+    match subcommand {
+        TagSubcommand::Rm { name } => {
+            let mut tags = config.tags()?;
+            let tag = tags
+                .remove(name)
+                .ok_or_else(|| TagError::NotFound(name.clone()))?;
+            config.set_tags(&tags)?;
+            println!("removed tag {} ({})", name, tag.root());
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TagError {
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("{0:?} isn't a valid tag name -- use letters, digits, '.', '_', and '-'")]
+    InvalidName(String),
+    #[error("no root to tag -- pull first, or name one")]
+    NoRoot,
+    #[error("{0} isn't a root cid")]
+    NotARoot(Cid),
+    #[error("tag {0} already points at {1} -- pass --force to move it")]
+    Exists(String, Cid),
+    #[error("no tag named {0}")]
+    NotFound(String),
+}
@@ -0,0 +1,128 @@
+use std::fmt::Display;
+use std::io::Cursor;
+use std::path::PathBuf;
+
+use cid::Cid;
+
+use crate::cli::cancel::CancelReason;
+use crate::cli::config::{Config, ConfigError};
+use crate::device::{CidParams, Device, DeviceError};
+
+/// Fetch every object of the current root and recompute its cid as it streams in,
+/// checking the content we'd serve is the content we published. Memory stays flat
+/// however big the objects are
+/// Objects made with other cid params than ours are read whole and rehashed by the
+/// local node with their own params. Any it can't hash with are unverified, and
+/// fail the run like corrupt or unreadable ones
+/// Stops between objects once the config's cancellation says so
+pub async fn verify(config: &Config) -> Result<(), VerifyError> {
+    let device = config.device()?;
+    let base = config.base()?;
+    let mut report = VerifyReport::default();
+
+    for (path, object) in base.objects().iter() {
+        if let Err(reason) = config.cancellation().check() {
+            println!("{}", report);
+            return Err(VerifyError::Cancelled(reason));
+        }
+        let cid = object.cid();
+        let rehashed = if CidParams::of(cid) == CidParams::default() {
+            device.hash_object_stream(cid).await.map(Some)
+        } else {
+            hash_with_own_params(&device, cid).await
+        };
+        match rehashed {
+            Ok(Some((actual, bytes))) if actual == *cid => {
+                report.verified += 1;
+                report.bytes += bytes;
+            }
+            Ok(Some((actual, _))) => report.corrupt.push((path.clone(), *cid, actual)),
+            Ok(None) => report.unverified.push((path.clone(), *cid)),
+            Err(e) => {
+                tracing::error!("failed to read {} ({}): {}", path.display(), cid, e);
+                report.unreadable.push((path.clone(), *cid));
+            }
+        }
+    }
+
+    println!("{}", report);
+
+    if !report.corrupt.is_empty() || !report.unreadable.is_empty() || !report.unverified.is_empty()
+    {
+        return Err(VerifyError::Failed(
+            report.corrupt.len(),
+            report.unreadable.len(),
+            report.unverified.len(),
+        ));
+    }
+    Ok(())
+}
+
+/// Recompute the cid of an object made with other cid params than ours, as the
+/// local node would add it with them
+/// # Returns the recomputed cid and how many bytes the object held, or None if the
+///  node can't hash with its params
+async fn hash_with_own_params(
+    device: &Device,
+    cid: &Cid,
+) -> Result<Option<(Cid, u64)>, DeviceError> {
+    let data = device.read_object(cid).await?;
+    let bytes = data.len() as u64;
+    let actual = device
+        .hash_ipfs_data_with(Cursor::new(data), CidParams::of(cid), false)
+        .await?;
+    Ok(actual.map(|actual| (actual, bytes)))
+}
+
+#[derive(Default)]
+struct VerifyReport {
+    verified: usize,
+    bytes: u64,
+    /// Objects whose cids the node can't recompute
+    unverified: Vec<(PathBuf, Cid)>,
+    /// Objects whose content hashed to another cid, with the cid it hashed to
+    corrupt: Vec<(PathBuf, Cid, Cid)>,
+    unreadable: Vec<(PathBuf, Cid)>,
+}
+
+impl Display for VerifyReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "verified: {} ({} bytes), unverified: {}, corrupt: {}, unreadable: {}",
+            self.verified,
+            self.bytes,
+            self.unverified.len(),
+            self.corrupt.len(),
+            self.unreadable.len()
+        )?;
+        for (path, cid) in self.unverified.iter() {
+            writeln!(f, "unverified {} ({})", path.display(), cid)?;
+        }
+        for (path, cid, actual) in self.corrupt.iter() {
+            writeln!(
+                f,
+                "corrupt {} ({} hashes to {})",
+                path.display(),
+                cid,
+                actual
+            )?;
+        }
+        for (path, cid) in self.unreadable.iter() {
+            writeln!(f, "unreadable {} ({})", path.display(), cid)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum VerifyError {
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("device error: {0}")]
+    Device(#[from] DeviceError),
+    #[error("{0} objects are corrupt, {1} couldn't be read, and {2} couldn't be verified")]
+    Failed(usize, usize, usize),
+    #[error("verify stopped, {0}")]
+    Cancelled(CancelReason),
+}
@@ -0,0 +1,162 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+use cid::Cid;
+
+use crate::device::{
+    block_links, check_delta_chain, decode_dor_store, decode_manifest, find_pb_link,
+    read_unixfs_file, verify_block, BlockSource, BlockSourceError, CarBlockSource, CidVerifier,
+    DeviceError, Manifest, DAG_PB_CODEC, MANIFEST_NAME,
+};
+use crate::types::DorStore;
+
+/// Check a CAR file holds every block of a root, its manifest, and the objects it
+/// references, and that each block hashes to its cid under its own hash function.
+/// Blocks hashed with a function we can't check fail the run too. Entirely offline
+/// # Args
+/// - car: the CARv1 file to check
+/// - root: the root cid the CAR was exported for
+/// - manifest: read the manifest from this file instead of from the CAR
+pub async fn verify_car(
+    car: &PathBuf,
+    root: &Cid,
+    manifest: Option<&PathBuf>,
+) -> Result<(), VerifyCarError> {
+    let source = CarBlockSource::open(car)?;
+    let mut report = VerifyCarReport::default();
+
+    // The root covers the manifest, plus the object tree of directory roots
+    verify_dag(&source, Path::new(""), root, &mut report).await;
+    let dor_store = match manifest {
+        Some(manifest) => {
+            let data = std::fs::read(manifest)?;
+            decode_dor_store(&data)?
+        }
+        None => read_manifest(&source, root).await?,
+    };
+
+    for (path, object) in dor_store.objects().iter() {
+        verify_dag(&source, path, object.cid(), &mut report).await;
+    }
+
+    println!("{}", report);
+
+    if !report.missing.is_empty() || !report.corrupt.is_empty() || !report.unverified.is_empty() {
+        return Err(VerifyCarError::Incomplete(
+            report.missing.len(),
+            report.corrupt.len(),
+            report.unverified.len(),
+        ));
+    }
+    Ok(())
+}
+
+/// Read the manifest a root points at out of the CAR
+/// Directory roots are followed to their manifest, and delta manifests are applied
+/// on top of their previous roots', which the CAR then has to hold too
+async fn read_manifest(source: &CarBlockSource, root: &Cid) -> Result<DorStore, VerifyCarError> {
+    let start = *root;
+    let mut deltas = Vec::new();
+    let mut seen = HashSet::new();
+    let mut root = *root;
+    let dor_store = loop {
+        check_delta_chain(&start, &root, &mut seen)?;
+        let mut manifest_cid = root;
+        if root.codec() == DAG_PB_CODEC {
+            let block = source.get_block(&root).await?;
+            if let Some(cid) = find_pb_link(&block, MANIFEST_NAME)? {
+                manifest_cid = cid;
+            }
+        }
+        let data = read_unixfs_file(source, &manifest_cid, &CidVerifier).await?;
+        match decode_manifest(&data)? {
+            Manifest::Full(dor_store) => break dor_store,
+            Manifest::Delta(delta) => {
+                root = *delta.delta_of();
+                deltas.push(delta);
+            }
+        }
+    };
+    Ok(deltas
+        .iter()
+        .rev()
+        .fold(dor_store, |dor_store, delta| delta.apply(&dor_store)))
+}
+
+/// Check every block below a cid, noting what's missing or doesn't hash right
+/// Blocks already checked under another path are skipped
+async fn verify_dag(source: &CarBlockSource, path: &Path, cid: &Cid, report: &mut VerifyCarReport) {
+    let mut stack = vec![*cid];
+    while let Some(cid) = stack.pop() {
+        if !report.seen.insert(cid) {
+            continue;
+        }
+        let block = match source.get_block(&cid).await {
+            Ok(block) => block,
+            Err(_) => {
+                report.missing.push((path.to_path_buf(), cid));
+                continue;
+            }
+        };
+        report.checked += 1;
+        match verify_block(&cid, &block) {
+            Some(true) => {}
+            Some(false) => {
+                report.corrupt.push((path.to_path_buf(), cid));
+                continue;
+            }
+            None => report.unverified.push((path.to_path_buf(), cid)),
+        }
+        match block_links(&cid, &block) {
+            Ok(links) => stack.extend(links),
+            Err(_) => report.corrupt.push((path.to_path_buf(), cid)),
+        }
+    }
+}
+
+#[derive(Default)]
+struct VerifyCarReport {
+    /// Blocks we've looked at, so shared blocks are only checked once
+    seen: HashSet<Cid>,
+    checked: usize,
+    /// Blocks hashed with a function we can't check
+    unverified: Vec<(PathBuf, Cid)>,
+    missing: Vec<(PathBuf, Cid)>,
+    corrupt: Vec<(PathBuf, Cid)>,
+}
+
+impl Display for VerifyCarReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "checked: {}, unverified: {}, missing: {}, corrupt: {}",
+            self.checked,
+            self.unverified.len(),
+            self.missing.len(),
+            self.corrupt.len()
+        )?;
+        for (path, cid) in self.missing.iter() {
+            writeln!(f, "missing {} ({})", path.display(), cid)?;
+        }
+        for (path, cid) in self.corrupt.iter() {
+            writeln!(f, "corrupt {} ({})", path.display(), cid)?;
+        }
+        for (path, cid) in self.unverified.iter() {
+            writeln!(f, "unverified {} ({})", path.display(), cid)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum VerifyCarError {
+    #[error("block source error: {0}")]
+    BlockSource(#[from] BlockSourceError),
+    #[error("device error: {0}")]
+    Device(#[from] DeviceError),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("car failed verification: {0} missing, {1} corrupt, and {2} uncheckable blocks")]
+    Incomplete(usize, usize, usize),
+}
@@ -0,0 +1,226 @@
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use notify::{Event, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+use super::commit::{commit, CommitError};
+use super::push::{push, PushError};
+use super::stage::{stage, StageError};
+
+use crate::cli::cancel;
+use crate::cli::changes::PushOptions;
+use crate::cli::config::{Config, ConfigError, LockMode, DEFAULT_LOCAL_DOT_DIR};
+use crate::cli::filter::{FilterError, PathFilter};
+
+/// Longest we back off for before retrying a push that failed
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+
+/// Watch the data dir, staging, committing, and pushing changes as they settle
+/// Rapid successive edits are batched into a single push
+/// The working dir is only locked while a push is underway, so other ops can run
+/// in between. If one holds it when changes settle, we try again a debounce later.
+/// Pushes that fail otherwise are retried too, backing off each time they fail
+/// Errors from the watcher itself are logged, and we keep watching
+/// # Args
+/// - debounce: how long the data dir must be quiet before we push
+/// - include: glob patterns of paths whose changes trigger a push (all if empty)
+/// - exclude: glob patterns of paths whose changes are ignored
+/// - local: only pin against the local node and commit roots locally, skipping
+///   the remote and root update until a regular push
+/// - yes: send root updates without confirming them. We never prompt, so without
+///   it, updates that need confirming fail the push
+/// - metrics_addr: serve prometheus metrics on this address while we watch
+pub async fn watch(
+    config: &Config,
+    debounce: Duration,
+    include: &[String],
+    exclude: &[String],
+    local: bool,
+    yes: bool,
+    metrics_addr: Option<SocketAddr>,
+) -> Result<(), WatchError> {
+    let filter = PathFilter::new(include.to_vec(), exclude.to_vec())?;
+    if let Some(addr) = metrics_addr {
+        #[cfg(feature = "metrics")]
+        {
+            crate::metrics::serve(addr).await?;
+            println!("serving metrics on http://{}/metrics", addr);
+        }
+        #[cfg(not(feature = "metrics"))]
+        return Err(WatchError::MetricsDisabled(addr));
+    }
+    let data_dir = config.data_dir().canonicalize()?;
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        // The receiver only goes away when we're done watching
+        let _ = tx.send(event);
+    })?;
+    watcher.watch(&data_dir, RecursiveMode::Recursive)?;
+    println!("watching {}", data_dir.display());
+
+    cancel::install();
+    // Changes we couldn't push yet, because another op held the working dir or
+    // the push failed
+    let mut pending = false;
+    // Pushes that have failed in a row, other than for the lock
+    let mut failures = 0;
+    loop {
+        if !pending {
+            let event = tokio::select! {
+                event = rx.recv() => event,
+                _ = config.cancellation().cancelled() => None,
+            };
+            match event {
+                Some(Ok(event)) if is_relevant(&event, &data_dir, &filter) => {}
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => {
+                    tracing::error!("watch error: {}", e);
+                    continue;
+                }
+                None => break,
+            }
+        }
+
+        // Wait for things to settle, soaking up anything else that comes in. Only
+        // changes we'd push keep us waiting
+        let wait = retry_delay(debounce, failures);
+        let mut settled_at = Instant::now() + wait;
+        loop {
+            match tokio::time::timeout_at(settled_at, rx.recv()).await {
+                Ok(Some(Ok(event))) => {
+                    if is_relevant(&event, &data_dir, &filter) {
+                        settled_at = Instant::now() + wait;
+                    }
+                }
+                Ok(Some(Err(e))) => tracing::error!("watch error: {}", e),
+                Ok(None) => return Ok(()),
+                Err(_) => break,
+            }
+        }
+        // Changes that came in as we were cancelled are left for the next run
+        if config.cancellation().check().is_err() {
+            break;
+        }
+
+        match sync(config, local, yes).await {
+            Ok(()) => {
+                pending = false;
+                failures = 0;
+            }
+            Err(WatchError::Config(ConfigError::Locked(_))) => {
+                tracing::warn!(
+                    "working dir is busy, trying again in {:?}",
+                    retry_delay(debounce, failures)
+                );
+                pending = true;
+            }
+            Err(e) => {
+                failures += 1;
+                tracing::error!(
+                    "failed to push changes, trying again in {:?}: {}",
+                    retry_delay(debounce, failures),
+                    e
+                );
+                pending = true;
+            }
+        }
+    }
+
+    println!("stopped watching");
+    Ok(())
+}
+
+/// Whether an event touches a path we care about, outside of the dot dir
+fn is_relevant(event: &Event, data_dir: &Path, filter: &PathFilter) -> bool {
+    let dot_dir = PathBuf::from(DEFAULT_LOCAL_DOT_DIR);
+    event.paths.iter().any(|path| {
+        let path = path.strip_prefix(data_dir).unwrap_or(path);
+        !path.starts_with(&dot_dir) && filter.matches(path)
+    })
+}
+
+/// How long to wait before pushing, after some pushes failed in a row
+/// Doubles with each failure, from the debounce up to MAX_RETRY_DELAY
+fn retry_delay(debounce: Duration, failures: u32) -> Duration {
+    debounce
+        .saturating_mul(2u32.saturating_pow(failures))
+        .min(MAX_RETRY_DELAY)
+        .max(debounce)
+}
+
+/// Stage, commit, and push whatever has changed, holding the working dir while we do
+async fn sync(config: &Config, local: bool, yes: bool) -> Result<(), WatchError> {
+    let _lock = config.lock(LockMode::Exclusive)?;
+    stage(config, false, false).await?;
+    commit(config)?;
+    let options = PushOptions {
+        unattended: true,
+        ..PushOptions::default()
+    };
+    match push(config, local, yes, &options).await {
+        Ok(()) => Ok(()),
+        // Edits that cancel out leave nothing to push
+        Err(PushError::NoChanges) => {
+            tracing::info!("no changes to push");
+            Ok(())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum WatchError {
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("filter error: {0}")]
+    Filter(#[from] FilterError),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("watch error: {0}")]
+    Notify(#[from] notify::Error),
+    #[error("stage error: {0}")]
+    Stage(#[from] StageError),
+    #[error("commit error: {0}")]
+    Commit(#[from] CommitError),
+    #[error("push error: {0}")]
+    Push(#[from] PushError),
+    #[cfg(not(feature = "metrics"))]
+    #[error("can't serve metrics on {0}: built without the `metrics` feature")]
+    MetricsDisabled(SocketAddr),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failed_pushes_back_off() {
+        let debounce = Duration::from_secs(2);
+        assert_eq!(retry_delay(debounce, 0), debounce);
+        assert_eq!(retry_delay(debounce, 1), Duration::from_secs(4));
+        assert_eq!(retry_delay(debounce, 3), Duration::from_secs(16));
+        assert_eq!(retry_delay(debounce, 40), MAX_RETRY_DELAY);
+        // A debounce longer than the cap is still waited out
+        let slow = Duration::from_secs(600);
+        assert_eq!(retry_delay(slow, 2), slow);
+    }
+
+    #[test]
+    fn only_filtered_paths_outside_the_dot_dir_are_relevant() {
+        let data_dir = Path::new("/data");
+        let filter = PathFilter::new(vec![], vec!["*.tmp".to_string()]).unwrap();
+        let event = |path: &str| Event::default().add_path(data_dir.join(path));
+
+        assert!(is_relevant(&event("notes.txt"), data_dir, &filter));
+        assert!(!is_relevant(&event("notes.tmp"), data_dir, &filter));
+        assert!(!is_relevant(
+            &event(&format!("{}/stage.json", DEFAULT_LOCAL_DOT_DIR)),
+            data_dir,
+            &filter
+        ));
+    }
+}
@@ -0,0 +1,226 @@
+use std::fmt::Display;
+use std::path::PathBuf;
+
+use cid::Cid;
+use url::Url;
+
+use crate::cli::cancel::CancelReason;
+use crate::cli::cid_arg::RootArg;
+use crate::cli::config::{Config, ConfigError};
+use crate::device::{BlockStatus, Device, DeviceError, IpfsGateway, IpfsRemote};
+
+/// Where a block can be found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Presence {
+    Present,
+    /// The remote's pinning service is still working on it
+    Pending,
+    Absent,
+    /// We stopped asking after the holder failed on an earlier block
+    Unknown,
+}
+
+impl Presence {
+    fn mark(&self) -> char {
+        match self {
+            Self::Present => '+',
+            Self::Pending => '~',
+            Self::Absent => '-',
+            Self::Unknown => '?',
+        }
+    }
+}
+
+/// One of the places we ask for blocks
+enum Holder<'a> {
+    /// Our local node
+    Local(&'a Device),
+    /// Our remote node, and its pinning service if it has one
+    Remote(&'a Device),
+    /// Our configured gateway
+    Gateway(&'a Device),
+    /// Any other gateway
+    Public(Url, IpfsGateway),
+}
+
+impl Holder<'_> {
+    fn name(&self) -> String {
+        match self {
+            Self::Local(_) => "local".to_string(),
+            Self::Remote(_) => "remote".to_string(),
+            Self::Gateway(_) => "gateway".to_string(),
+            Self::Public(url, _) => url.host_str().unwrap_or(url.as_str()).to_string(),
+        }
+    }
+
+    async fn presence(&self, cid: &Cid) -> Result<Presence, DeviceError> {
+        let present = |has: bool| {
+            if has {
+                Presence::Present
+            } else {
+                Presence::Absent
+            }
+        };
+        let presence = match self {
+            Self::Local(device) => present(device.stat_ipfs_data(cid, false).await?.is_some()),
+            Self::Remote(device) => match device.block_status(cid, true).await? {
+                BlockStatus::Present => Presence::Present,
+                BlockStatus::Pending => Presence::Pending,
+                BlockStatus::Absent => Presence::Absent,
+            },
+            Self::Gateway(device) => present(device.gateway_has_block(cid).await?),
+            Self::Public(_, gateway) => present(gateway.has_block(cid).await?),
+        };
+        Ok(presence)
+    }
+}
+
+/// Check which of our nodes, and any public gateways given, hold a root's manifest
+/// and every one of its objects, printing where each block was found and how
+/// complete each holder is
+/// Only the top block of each object is asked for, so an object counts as held
+/// even if blocks below it have gone missing -- run fsck or verify for those
+/// A holder that fails is reported as unreachable, and isn't asked again
+/// # Args
+/// - root: the root to look for, the base root if None
+/// - gateways: public gateways to ask as well
+pub async fn where_pinned(
+    config: &Config,
+    root: Option<&RootArg>,
+    gateways: &[Url],
+) -> Result<(), WhereError> {
+    let device = config.device()?;
+    let change_log = config.change_log()?;
+    let (base_root, base) = change_log.first_version().unwrap();
+    let (root, dor_store) = match root {
+        Some(root) => {
+            let root = config.resolve_root(root)?;
+            if root == *base_root {
+                (root, base.clone())
+            } else {
+                (root, device.read_dor_store(&root, true).await?)
+            }
+        }
+        None => (*base_root, base.clone()),
+    };
+
+    let mut holders = vec![
+        Holder::Local(&device),
+        Holder::Remote(&device),
+        Holder::Gateway(&device),
+    ];
+    for url in gateways {
+        let gateway = IpfsGateway::from(IpfsRemote {
+            gateway_url: url.clone(),
+            ..Default::default()
+        });
+        holders.push(Holder::Public(url.clone(), gateway));
+    }
+
+    let mut blocks = vec![(PathBuf::from("<manifest>"), root)];
+    blocks.extend(
+        dor_store
+            .objects()
+            .iter()
+            .map(|(path, object)| (path.clone(), *object.cid())),
+    );
+
+    let mut matrix = PresenceMatrix {
+        holders: holders.iter().map(Holder::name).collect(),
+        errors: vec![None; holders.len()],
+        rows: Vec::new(),
+    };
+    for (path, cid) in blocks {
+        if let Err(reason) = config.cancellation().check() {
+            println!("{}", matrix);
+            return Err(WhereError::Cancelled(reason));
+        }
+        let mut row = Vec::new();
+        for (holder, error) in holders.iter().zip(matrix.errors.iter_mut()) {
+            if error.is_some() {
+                row.push(Presence::Unknown);
+                continue;
+            }
+            match holder.presence(&cid).await {
+                Ok(presence) => row.push(presence),
+                Err(e) => {
+                    tracing::warn!("failed to ask {} for {}: {}", holder.name(), cid, e);
+                    *error = Some(e.to_string());
+                    row.push(Presence::Unknown);
+                }
+            }
+        }
+        matrix.rows.push((path, row));
+    }
+
+    println!("root: {}", root);
+    println!("{}", matrix);
+    Ok(())
+}
+
+/// Which holders had each block
+struct PresenceMatrix {
+    holders: Vec<String>,
+    /// What took each holder out, if one did
+    errors: Vec<Option<String>>,
+    rows: Vec<(PathBuf, Vec<Presence>)>,
+}
+
+impl PresenceMatrix {
+    /// How many blocks a holder had, and how many it's still pinning
+    fn held(&self, holder: usize) -> (usize, usize) {
+        let count = |presence: Presence| {
+            self.rows
+                .iter()
+                .filter(|(_, row)| row[holder] == presence)
+                .count()
+        };
+        (count(Presence::Present), count(Presence::Pending))
+    }
+}
+
+impl Display for PresenceMatrix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", self.holders.join(" "))?;
+        for (path, row) in self.rows.iter() {
+            for (name, presence) in self.holders.iter().zip(row.iter()) {
+                write!(f, "{:<width$} ", presence.mark(), width = name.len())?;
+            }
+            writeln!(f, "{}", path.display())?;
+        }
+
+        let total = self.rows.len();
+        for (holder, name) in self.holders.iter().enumerate() {
+            if let Some(error) = &self.errors[holder] {
+                writeln!(f, "{}: unreachable ({})", name, error)?;
+                continue;
+            }
+            let (present, pending) = self.held(holder);
+            // Rounded down, so only a holder with everything shows 100%
+            let percent = if total > 0 {
+                present * 100 / total
+            } else {
+                100
+            };
+            write!(f, "{}: {}%", name, percent)?;
+            if present < total {
+                write!(f, " -- {} blocks missing", total - present)?;
+            }
+            if pending > 0 {
+                write!(f, " ({} still pinning)", pending)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum WhereError {
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("device error: {0}")]
+    Device(#[from] DeviceError),
+    #[error("where stopped, {0}")]
+    Cancelled(CancelReason),
+}
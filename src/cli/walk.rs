@@ -0,0 +1,175 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// What to do with symlinks found walking the working dir
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum SymlinkPolicy {
+    /// Leave symlinks out, as if they weren't there
+    #[default]
+    Skip,
+    /// Walk through symlinks as if they were what they point at
+    /// Links back into a directory we're already walking are left out, so cycles end
+    Follow,
+    /// Track the link itself by its target, without following it
+    StoreAsLink,
+}
+
+/// Something found walking the working dir
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WalkEntry {
+    Directory,
+    File,
+    /// A symlink kept as is, with its target
+    Link(PathBuf),
+}
+
+/// What we store for a link -- its target path
+pub fn link_data(target: &Path) -> Vec<u8> {
+    target.to_string_lossy().into_owned().into_bytes()
+}
+
+/// Create a symlink at path pointing at target
+#[cfg(unix)]
+pub fn create_link(target: &Path, path: &Path) -> Result<(), std::io::Error> {
+    std::os::unix::fs::symlink(target, path)
+}
+
+/// Create a symlink at path pointing at target
+#[cfg(not(unix))]
+pub fn create_link(_target: &Path, _path: &Path) -> Result<(), std::io::Error> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "can't create links on this platform",
+    ))
+}
+
+/// Walk everything below a directory
+/// Followed links are walked through their path in the tree, so entries below
+/// them open as they would through the link itself
+/// # Args
+/// - root: the directory to walk
+/// - skip: a name to leave out at the top level, i.e. the local dot dir
+/// - policy: what to do with symlinks
+/// # Returns every entry by its path relative to the root, in path order
+pub fn walk(
+    root: &Path,
+    skip: &Path,
+    policy: SymlinkPolicy,
+) -> Result<BTreeMap<PathBuf, WalkEntry>, std::io::Error> {
+    let mut entries = BTreeMap::new();
+    let mut ancestors = vec![root.canonicalize()?];
+    walk_dir(
+        root,
+        &PathBuf::new(),
+        Some(skip),
+        policy,
+        &mut ancestors,
+        &mut entries,
+    )?;
+    Ok(entries)
+}
+
+fn walk_dir(
+    dir: &Path,
+    relative: &Path,
+    skip: Option<&Path>,
+    policy: SymlinkPolicy,
+    ancestors: &mut Vec<PathBuf>,
+    entries: &mut BTreeMap<PathBuf, WalkEntry>,
+) -> Result<(), std::io::Error> {
+    for dir_entry in std::fs::read_dir(dir)? {
+        let dir_entry = dir_entry?;
+        let name = dir_entry.file_name();
+        if skip.map(|skip| skip.as_os_str() == name).unwrap_or(false) {
+            continue;
+        }
+        let path = dir_entry.path();
+        let relative = relative.join(&name);
+        let file_type = dir_entry.file_type()?;
+
+        let is_dir = if file_type.is_symlink() {
+            match policy {
+                SymlinkPolicy::Skip => continue,
+                SymlinkPolicy::StoreAsLink => {
+                    entries.insert(relative, WalkEntry::Link(std::fs::read_link(&path)?));
+                    continue;
+                }
+                SymlinkPolicy::Follow => match std::fs::metadata(&path) {
+                    Ok(metadata) => metadata.is_dir(),
+                    Err(_) => {
+                        tracing::warn!("skipping broken symlink {}", relative.display());
+                        continue;
+                    }
+                },
+            }
+        } else {
+            file_type.is_dir()
+        };
+
+        if !is_dir {
+            entries.insert(relative, WalkEntry::File);
+            continue;
+        }
+
+        let canonical = path.canonicalize()?;
+        if ancestors.contains(&canonical) {
+            tracing::warn!("skipping symlink cycle at {}", relative.display());
+            continue;
+        }
+        entries.insert(relative.clone(), WalkEntry::Directory);
+        ancestors.push(canonical);
+        walk_dir(&path, &relative, None, policy, ancestors, entries)?;
+        ancestors.pop();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    /// A fresh directory holding `a/file` and `a/loop`, a link back up to `a`
+    fn tree_with_cycle(name: &str) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("dor-store-walk-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("a")).unwrap();
+        std::fs::write(root.join("a/file"), b"hello").unwrap();
+        symlink(root.join("a"), root.join("a/loop")).unwrap();
+        root
+    }
+
+    #[test]
+    fn follow_stops_at_cycles() {
+        let root = tree_with_cycle("follow");
+        let entries = walk(&root, Path::new(".fs"), SymlinkPolicy::Follow).unwrap();
+        let paths: Vec<&PathBuf> = entries.keys().collect();
+        assert_eq!(paths, vec![Path::new("a"), Path::new("a/file")]);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn skip_leaves_links_out() {
+        let root = tree_with_cycle("skip");
+        let entries = walk(&root, Path::new(".fs"), SymlinkPolicy::Skip).unwrap();
+        assert!(!entries.contains_key(Path::new("a/loop")));
+        assert_eq!(entries.len(), 2);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn store_as_link_keeps_targets() {
+        let root = tree_with_cycle("link");
+        let entries = walk(&root, Path::new(".fs"), SymlinkPolicy::StoreAsLink).unwrap();
+        assert_eq!(
+            entries.get(Path::new("a/loop")),
+            Some(&WalkEntry::Link(root.join("a")))
+        );
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
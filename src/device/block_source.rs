@@ -0,0 +1,356 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use cid::Cid;
+use futures_util::stream::TryStreamExt;
+use sha2::{Digest, Sha256};
+
+use super::block_verifier::BlockVerifier;
+use super::ipfs::{IpfsApi, IpfsClient, IpfsClientError, IpfsError, IpfsGateway};
+#[cfg(feature = "s3")]
+use super::s3::S3StoreError;
+
+/// Multicodec code for raw blocks
+const RAW_CODEC: u64 = 0x55;
+/// Multicodec code for dag-pb blocks
+const DAG_PB_CODEC: u64 = 0x70;
+/// Multihash codes of the hash functions we can check blocks against: blake3, which
+/// we hash with, and sha2-256, which older stores were written with
+const BLAKE3_CODE: u64 = 0x1e;
+const SHA2_256_CODE: u64 = 0x12;
+
+/// Somewhere we can read blocks from by their cid
+/// Lets read-only workflows run against exported blocks, without a live ipfs node
+#[async_trait]
+pub trait BlockSource: Send + Sync {
+    /// Read a single block by its cid
+    async fn get_block(&self, cid: &Cid) -> Result<Vec<u8>, BlockSourceError>;
+}
+
+#[async_trait]
+impl BlockSource for IpfsClient {
+    async fn get_block(&self, cid: &Cid) -> Result<Vec<u8>, BlockSourceError> {
+        let data = self
+            .block_get(&cid.to_string())
+            .map_ok(|chunk| chunk.to_vec())
+            .try_concat()
+            .await?;
+        Ok(data)
+    }
+}
+
+/// Blocks fetched one at a time from a gateway, so each can be checked on its own
+#[async_trait]
+impl BlockSource for IpfsGateway {
+    async fn get_block(&self, cid: &Cid) -> Result<Vec<u8>, BlockSourceError> {
+        Ok(IpfsGateway::get_block(self, cid).await?)
+    }
+}
+
+/// Open a local block source at a path
+/// Directories are read as a DirBlockSource, files as a CarBlockSource
+pub fn open_block_source(path: &Path) -> Result<Box<dyn BlockSource>, BlockSourceError> {
+    if path.is_dir() {
+        Ok(Box::new(DirBlockSource::new(path)))
+    } else {
+        Ok(Box::new(CarBlockSource::open(path)?))
+    }
+}
+
+/// A flat directory of blocks, each stored in a file named by its cid
+pub struct DirBlockSource {
+    path: PathBuf,
+}
+
+impl DirBlockSource {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+}
+
+#[async_trait]
+impl BlockSource for DirBlockSource {
+    async fn get_block(&self, cid: &Cid) -> Result<Vec<u8>, BlockSourceError> {
+        let block_path = self.path.join(cid.to_string());
+        if !block_path.exists() {
+            return Err(BlockSourceError::MissingBlock(*cid));
+        }
+        Ok(std::fs::read(block_path)?)
+    }
+}
+
+/// Blocks within a CARv1 file
+/// The file is indexed on open, and blocks are read from it as requested
+pub struct CarBlockSource {
+    path: PathBuf,
+    /// Offset and length of each block's data within the file
+    index: HashMap<Cid, (u64, usize)>,
+}
+
+impl CarBlockSource {
+    /// Open and index a CARv1 file
+    pub fn open(path: &Path) -> Result<Self, BlockSourceError> {
+        let file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+
+        // Skip over the header -- we don't care about its roots
+        let header_len = read_varint(&mut reader)?
+            .ok_or(BlockSourceError::InvalidCar("missing header".to_string()))?;
+        reader.seek(SeekFrom::Current(header_len as i64))?;
+
+        let mut index = HashMap::new();
+        while let Some(section_len) = read_varint(&mut reader)? {
+            let section_start = reader.stream_position()?;
+            let cid = Cid::read_bytes(&mut reader)?;
+            let data_start = reader.stream_position()?;
+            let cid_len = data_start - section_start;
+            if cid_len > section_len || section_start + section_len > file_len {
+                return Err(BlockSourceError::InvalidCar(format!(
+                    "truncated section for {}",
+                    cid
+                )));
+            }
+            let data_len = (section_len - cid_len) as usize;
+            index.insert(cid, (data_start, data_len));
+            reader.seek(SeekFrom::Start(data_start + data_len as u64))?;
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            index,
+        })
+    }
+}
+
+#[async_trait]
+impl BlockSource for CarBlockSource {
+    async fn get_block(&self, cid: &Cid) -> Result<Vec<u8>, BlockSourceError> {
+        let (offset, len) = self
+            .index
+            .get(cid)
+            .ok_or(BlockSourceError::MissingBlock(*cid))?;
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(*offset))?;
+        let mut data = vec![0u8; *len];
+        file.read_exact(&mut data)?;
+        Ok(data)
+    }
+}
+
+/// Read the full contents of a unix-fs file from a block source
+/// Follows the links of dag-pb nodes in order, concatenating their data
+/// Every block is passed by the verifier before it's followed
+pub async fn read_unixfs_file(
+    source: &dyn BlockSource,
+    cid: &Cid,
+    verifier: &dyn BlockVerifier,
+) -> Result<Vec<u8>, BlockSourceError> {
+    let mut data = Vec::new();
+    // Depth first, in link order
+    let mut stack = vec![(*cid, 0)];
+    while let Some((cid, depth)) = stack.pop() {
+        let block = source.get_block(&cid).await?;
+        verifier
+            .verify(&cid, &block, depth)
+            .map_err(|reason| BlockSourceError::Rejected(cid, reason))?;
+        match cid.codec() {
+            RAW_CODEC => data.extend(block),
+            DAG_PB_CODEC => {
+                let (links, unixfs_data) = decode_pb_node(&block)?;
+                // Inline data comes before any linked data
+                if let Some(unixfs_data) = unixfs_data {
+                    if let Some(inline) = decode_unixfs_data(&unixfs_data)? {
+                        data.extend(inline);
+                    }
+                }
+                stack.extend(links.into_iter().rev().map(|link| (link, depth + 1)));
+            }
+            codec => {
+                return Err(BlockSourceError::InvalidUnixfs(format!(
+                    "unsupported codec 0x{:x} for {}",
+                    codec, cid
+                )))
+            }
+        }
+    }
+    Ok(data)
+}
+
+/// Check a block's data hashes to its cid
+/// # Returns None if the cid uses a hash function we can't check
+pub fn verify_block(cid: &Cid, data: &[u8]) -> Option<bool> {
+    let hash = cid.hash();
+    match hash.code() {
+        BLAKE3_CODE => Some(blake3::hash(data).as_bytes().as_slice() == hash.digest()),
+        SHA2_256_CODE => Some(Sha256::digest(data).as_slice() == hash.digest()),
+        _ => None,
+    }
+}
+
+/// The cids a block links to -- none for raw blocks
+pub fn block_links(cid: &Cid, block: &[u8]) -> Result<Vec<Cid>, BlockSourceError> {
+    match cid.codec() {
+        RAW_CODEC => Ok(Vec::new()),
+        DAG_PB_CODEC => Ok(decode_pb_node(block)?.0),
+        codec => Err(BlockSourceError::InvalidUnixfs(format!(
+            "unsupported codec 0x{:x} for {}",
+            codec, cid
+        ))),
+    }
+}
+
+/* Minimal protobuf decoding for dag-pb and unix-fs nodes */
+
+/// Read an unsigned LEB128 varint, returning None at a clean end of input
+fn read_varint<R: Read>(reader: &mut R) -> Result<Option<u64>, BlockSourceError> {
+    let mut value = 0u64;
+    let mut shift = 0;
+    let mut byte = [0u8; 1];
+    loop {
+        if reader.read(&mut byte)? == 0 {
+            if shift == 0 {
+                return Ok(None);
+            }
+            return Err(BlockSourceError::InvalidUnixfs(
+                "truncated varint".to_string(),
+            ));
+        }
+        if shift >= 64 {
+            return Err(BlockSourceError::InvalidUnixfs(
+                "varint overflow".to_string(),
+            ));
+        }
+        value |= ((byte[0] & 0x7f) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+        shift += 7;
+    }
+}
+
+/// A single protobuf field -- we only care about varints and length delimited bytes
+enum PbField<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+}
+
+/// Split a protobuf message into its (field number, value) pairs
+fn pb_fields(mut buf: &[u8]) -> Result<Vec<(u64, PbField<'_>)>, BlockSourceError> {
+    let truncated = || BlockSourceError::InvalidUnixfs("truncated field".to_string());
+    let mut fields = Vec::new();
+    while let Some(key) = read_varint(&mut buf)? {
+        let field = match key & 0x7 {
+            0 => PbField::Varint(read_varint(&mut buf)?.ok_or_else(truncated)?),
+            2 => {
+                let len = read_varint(&mut buf)?.ok_or_else(truncated)? as usize;
+                if len > buf.len() {
+                    return Err(truncated());
+                }
+                let (bytes, rest) = buf.split_at(len);
+                buf = rest;
+                PbField::Bytes(bytes)
+            }
+            wire_type => {
+                return Err(BlockSourceError::InvalidUnixfs(format!(
+                    "unsupported wire type {}",
+                    wire_type
+                )))
+            }
+        };
+        fields.push((key >> 3, field));
+    }
+    Ok(fields)
+}
+
+/// Decode a dag-pb node into its link cids and data
+fn decode_pb_node(block: &[u8]) -> Result<(Vec<Cid>, Option<Vec<u8>>), BlockSourceError> {
+    let mut links = Vec::new();
+    let mut data = None;
+    for (number, field) in pb_fields(block)? {
+        match (number, field) {
+            (1, PbField::Bytes(bytes)) => data = Some(bytes.to_vec()),
+            (2, PbField::Bytes(link)) => {
+                for (number, field) in pb_fields(link)? {
+                    if let (1, PbField::Bytes(hash)) = (number, field) {
+                        links.push(Cid::try_from(hash)?);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok((links, data))
+}
+
+/// Find the cid of a named link within a dag-pb node, i.e. an entry of a unix-fs directory
+pub fn find_pb_link(block: &[u8], name: &str) -> Result<Option<Cid>, BlockSourceError> {
+    for (number, field) in pb_fields(block)? {
+        if let (2, PbField::Bytes(link)) = (number, field) {
+            let mut hash = None;
+            let mut link_name = None;
+            for (number, field) in pb_fields(link)? {
+                match (number, field) {
+                    (1, PbField::Bytes(bytes)) => hash = Some(bytes),
+                    (2, PbField::Bytes(bytes)) => link_name = Some(bytes),
+                    _ => {}
+                }
+            }
+            if let (Some(hash), Some(link_name)) = (hash, link_name) {
+                if link_name == name.as_bytes() {
+                    return Ok(Some(Cid::try_from(hash)?));
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Decode the inline file data of a unix-fs Data message
+/// Errors if the node isn't a file
+fn decode_unixfs_data(data: &[u8]) -> Result<Option<Vec<u8>>, BlockSourceError> {
+    let mut inline = None;
+    for (number, field) in pb_fields(data)? {
+        match (number, field) {
+            // Raw = 0, File = 2
+            (1, PbField::Varint(0)) | (1, PbField::Varint(2)) => {}
+            (1, PbField::Varint(typ)) => {
+                return Err(BlockSourceError::InvalidUnixfs(format!(
+                    "not a file, unix-fs type {}",
+                    typ
+                )))
+            }
+            (2, PbField::Bytes(bytes)) => inline = Some(bytes.to_vec()),
+            _ => {}
+        }
+    }
+    Ok(inline)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BlockSourceError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("cid error: {0}")]
+    Cid(#[from] cid::Error),
+    #[error("ipfs error: {0}")]
+    IpfsClient(#[from] IpfsClientError),
+    #[error("gateway error: {0}")]
+    Gateway(#[from] IpfsError),
+    #[error("block not found: {0}")]
+    MissingBlock(Cid),
+    #[error("invalid car file: {0}")]
+    InvalidCar(String),
+    #[error("invalid unix-fs data: {0}")]
+    InvalidUnixfs(String),
+    #[error("block {0} rejected: {1}")]
+    Rejected(Cid, String),
+    #[cfg(feature = "s3")]
+    #[error("s3 error: {0}")]
+    S3(#[from] S3StoreError),
+}
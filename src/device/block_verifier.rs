@@ -0,0 +1,107 @@
+use std::fmt::Debug;
+
+use cid::Cid;
+
+use super::block_source::verify_block;
+
+/// Policy checked against every block we fetch by its cid, before it's used
+/// Lets callers enforce more than the block matching its cid, i.e. a max dag
+/// depth, codecs they don't trust, or a cap on block size
+pub trait BlockVerifier: Debug + Send + Sync {
+    /// Accept a block, or reject it with a reason
+    /// # Args
+    /// - cid: the cid the block was fetched by
+    /// - data: the block
+    /// - depth: how many links below the top of the dag being read the block
+    ///   is -- 0 for the top block itself
+    fn verify(&self, cid: &Cid, data: &[u8], depth: usize) -> Result<(), String>;
+}
+
+/// Only checks a block's data hashes to its cid
+/// Blocks hashed with a function we can't check are let through
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CidVerifier;
+
+impl BlockVerifier for CidVerifier {
+    fn verify(&self, cid: &Cid, data: &[u8], _depth: usize) -> Result<(), String> {
+        match verify_block(cid, data) {
+            Some(false) => Err("data doesn't hash to its cid".to_string()),
+            Some(true) | None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::device::block_source::{read_unixfs_file, BlockSource, BlockSourceError};
+    use crate::device::ipfs::{file_dag, file_leaf};
+
+    /// Blocks held in memory
+    struct MemoryBlockSource(HashMap<Cid, Vec<u8>>);
+
+    #[async_trait]
+    impl BlockSource for MemoryBlockSource {
+        async fn get_block(&self, cid: &Cid) -> Result<Vec<u8>, BlockSourceError> {
+            self.0
+                .get(cid)
+                .cloned()
+                .ok_or(BlockSourceError::MissingBlock(*cid))
+        }
+    }
+
+    /// Rejects anything deeper than its limit
+    #[derive(Debug)]
+    struct MaxDepth(usize);
+
+    impl BlockVerifier for MaxDepth {
+        fn verify(&self, _cid: &Cid, _data: &[u8], depth: usize) -> Result<(), String> {
+            if depth > self.0 {
+                return Err(format!("deeper than {}", self.0));
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn verifiers_see_every_block_read() {
+        let chunks: [&[u8]; 2] = [b"hello ", b"world"];
+        let mut blocks = HashMap::new();
+        let mut leaves = Vec::new();
+        for chunk in chunks {
+            let leaf = file_leaf(chunk).unwrap();
+            blocks.insert(leaf.cid, chunk.to_vec());
+            leaves.push(leaf);
+        }
+        let first_leaf = leaves[0].cid;
+        let (nodes, root) = file_dag(leaves).unwrap();
+        blocks.extend(nodes);
+        let mut source = MemoryBlockSource(blocks);
+
+        let data = read_unixfs_file(&source, &root, &CidVerifier)
+            .await
+            .unwrap();
+        assert_eq!(data, b"hello world");
+        assert!(read_unixfs_file(&source, &root, &MaxDepth(1)).await.is_ok());
+        match read_unixfs_file(&source, &root, &MaxDepth(0)).await {
+            Err(BlockSourceError::Rejected(cid, reason)) => {
+                assert_eq!(cid, first_leaf);
+                assert_eq!(reason, "deeper than 0");
+            }
+            result => panic!("expected a rejection, got {:?}", result),
+        }
+
+        // A block that doesn't match its cid never makes it into the file
+        source.0.insert(first_leaf, b"HELLO ".to_vec());
+        assert!(matches!(
+            read_unixfs_file(&source, &root, &CidVerifier).await,
+            Err(BlockSourceError::Rejected(cid, _)) if cid == first_leaf
+        ));
+        let sha2 = Cid::try_from("QmWATWQ7fVPP2EFGu71UkfnqhYXDYH566qy47CnJDgvs8u").unwrap();
+        assert!(CidVerifier.verify(&sha2, b"anything", 0).is_ok());
+    }
+}
@@ -0,0 +1,406 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::fs::{create_dir_all, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use cid::Cid;
+use serde::{Deserialize, Serialize};
+
+/// Name of the file holding cache metadata, within the cache directory
+const CACHE_INDEX_NAME: &str = "index.json";
+/// How many reads' worth of hits, misses, and access times to hold in memory
+/// before writing the index out. Whatever's left is written when the cache is dropped
+const READS_PER_SAVE: u64 = 64;
+
+/// When to evict objects from a ContentCache
+/// Content is immutable, so eviction is purely about space -- never correctness
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachePolicy {
+    /// Whether to cache objects read from the gateway at all
+    #[serde(default)]
+    pub enabled: bool,
+    /// Cap on the total size of cached objects, evicting the least recently used first
+    #[serde(default)]
+    pub max_bytes: Option<u64>,
+    /// Evict objects that haven't been read in this many seconds
+    #[serde(default)]
+    pub max_age_secs: Option<u64>,
+}
+
+/// Metadata about a single cached object
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    size: u64,
+    /// Unix timestamp of the last time the object was read or written
+    last_access: u64,
+}
+
+/// Cache metadata, persisted alongside the objects so stats survive restarts
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct CacheIndex {
+    /// Entries keyed by the string form of their cid
+    entries: HashMap<String, CacheEntry>,
+    hits: u64,
+    misses: u64,
+    /// Reads since the index was last written out
+    #[serde(skip)]
+    unsaved_reads: u64,
+}
+
+impl CacheIndex {
+    fn bytes(&self) -> u64 {
+        self.entries.values().map(|entry| entry.size).sum()
+    }
+}
+
+/// Summary of a ContentCache
+#[derive(Debug, Clone, Copy)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub bytes: u64,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl CacheStats {
+    /// Fraction of reads served from the cache
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / total as f64
+    }
+}
+
+impl Display for CacheStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "entries: {}, bytes: {}, hits: {}, misses: {}, hit rate: {:.1}%",
+            self.entries,
+            self.bytes,
+            self.hits,
+            self.misses,
+            self.hit_rate() * 100.0
+        )
+    }
+}
+
+/// What a prune removed
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PruneStats {
+    pub removed: usize,
+    pub freed: u64,
+}
+
+impl Display for PruneStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "removed: {}, freed: {} bytes", self.removed, self.freed)
+    }
+}
+
+/// On disk cache of objects by their cid
+/// Objects are stored in files named by their cid, next to an index of their metadata
+pub struct ContentCache {
+    path: PathBuf,
+    policy: CachePolicy,
+    index: Mutex<CacheIndex>,
+}
+
+impl ContentCache {
+    /// Open a cache at a directory, creating it if it doesn't exist
+    /// An index that can't be parsed, i.e. one cut short by a crash, is only a
+    /// cache: it's warned about, and the cache starts over empty
+    pub fn open(path: &Path, policy: CachePolicy) -> Result<Self, CacheError> {
+        create_dir_all(path)?;
+        let index_path = path.join(CACHE_INDEX_NAME);
+        let index = if index_path.exists() {
+            let index_str = std::fs::read_to_string(&index_path)?;
+            match serde_json::from_str(&index_str) {
+                Ok(index) => index,
+                Err(e) => {
+                    tracing::warn!(
+                        "cache index at {} is unreadable, starting over empty: {}",
+                        index_path.display(),
+                        e
+                    );
+                    clear_dir(path)?;
+                    CacheIndex::default()
+                }
+            }
+        } else {
+            CacheIndex::default()
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            policy,
+            index: Mutex::new(index),
+        })
+    }
+
+    /// Read an object from the cache, if it's there and hasn't expired
+    pub fn get(&self, cid: &Cid) -> Result<Option<Vec<u8>>, CacheError> {
+        let mut index = self.index.lock().unwrap();
+        let key = cid.to_string();
+        let object_path = self.path.join(&key);
+        let now = now();
+        let live = match index.entries.get(&key) {
+            Some(entry) => !self.expired(entry, now) && object_path.exists(),
+            None => false,
+        };
+        if !live {
+            index.misses += 1;
+            self.save_read(&mut index)?;
+            return Ok(None);
+        }
+
+        let data = std::fs::read(object_path)?;
+        if let Some(entry) = index.entries.get_mut(&key) {
+            entry.last_access = now;
+        }
+        index.hits += 1;
+        self.save_read(&mut index)?;
+        Ok(Some(data))
+    }
+
+    /// Write out whatever reads haven't been saved yet
+    pub fn flush(&self) -> Result<(), CacheError> {
+        let mut index = self.index.lock().unwrap();
+        if index.unsaved_reads > 0 {
+            self.save(&mut index)?;
+        }
+        Ok(())
+    }
+
+    /// Write an object to the cache, evicting others if it goes over its policy
+    pub fn put(&self, cid: &Cid, data: &[u8]) -> Result<(), CacheError> {
+        let mut index = self.index.lock().unwrap();
+        let key = cid.to_string();
+        let mut file = File::create(self.path.join(&key))?;
+        file.write_all(data)?;
+        index.entries.insert(
+            key,
+            CacheEntry {
+                size: data.len() as u64,
+                last_access: now(),
+            },
+        );
+        self.evict(&mut index)?;
+        self.save(&mut index)?;
+        Ok(())
+    }
+
+    /// Evict everything the policy no longer allows
+    pub fn prune(&self) -> Result<PruneStats, CacheError> {
+        let mut index = self.index.lock().unwrap();
+        let stats = self.evict(&mut index)?;
+        self.save(&mut index)?;
+        Ok(stats)
+    }
+
+    /// Remove every object whose cid isn't in the live set, whatever the policy says
+    pub fn retain(&self, live: &HashSet<Cid>) -> Result<PruneStats, CacheError> {
+        let mut index = self.index.lock().unwrap();
+        let live: HashSet<String> = live.iter().map(|cid| cid.to_string()).collect();
+        let dead: Vec<String> = index
+            .entries
+            .keys()
+            .filter(|key| !live.contains(*key))
+            .cloned()
+            .collect();
+
+        let mut stats = PruneStats::default();
+        for key in dead {
+            if let Some(entry) = index.entries.remove(&key) {
+                let object_path = self.path.join(&key);
+                if object_path.exists() {
+                    std::fs::remove_file(object_path)?;
+                }
+                stats.removed += 1;
+                stats.freed += entry.size;
+            }
+        }
+        self.save(&mut index)?;
+        Ok(stats)
+    }
+
+    /// Report the size and hit rate of the cache
+    pub fn stats(&self) -> CacheStats {
+        let index = self.index.lock().unwrap();
+        CacheStats {
+            entries: index.entries.len(),
+            bytes: index.bytes(),
+            hits: index.hits,
+            misses: index.misses,
+        }
+    }
+
+    fn expired(&self, entry: &CacheEntry, now: u64) -> bool {
+        match self.policy.max_age_secs {
+            Some(max_age_secs) => now.saturating_sub(entry.last_access) > max_age_secs,
+            None => false,
+        }
+    }
+
+    /// Drop expired entries, then the least recently used until we're under max_bytes
+    fn evict(&self, index: &mut CacheIndex) -> Result<PruneStats, CacheError> {
+        let now = now();
+        let mut evicted: Vec<String> = index
+            .entries
+            .iter()
+            .filter(|(_, entry)| self.expired(entry, now))
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        if let Some(max_bytes) = self.policy.max_bytes {
+            let mut by_access: Vec<(&String, &CacheEntry)> = index
+                .entries
+                .iter()
+                .filter(|(key, _)| !evicted.contains(key))
+                .collect();
+            by_access.sort_by_key(|(_, entry)| entry.last_access);
+            let mut bytes: u64 = by_access.iter().map(|(_, entry)| entry.size).sum();
+            for (key, entry) in by_access {
+                if bytes <= max_bytes {
+                    break;
+                }
+                bytes -= entry.size;
+                evicted.push(key.clone());
+            }
+        }
+
+        let mut stats = PruneStats::default();
+        for key in evicted {
+            if let Some(entry) = index.entries.remove(&key) {
+                let object_path = self.path.join(&key);
+                if object_path.exists() {
+                    std::fs::remove_file(object_path)?;
+                }
+                stats.removed += 1;
+                stats.freed += entry.size;
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Count a read, saving the index once enough have built up
+    fn save_read(&self, index: &mut CacheIndex) -> Result<(), CacheError> {
+        index.unsaved_reads += 1;
+        if index.unsaved_reads >= READS_PER_SAVE {
+            self.save(index)?;
+        }
+        Ok(())
+    }
+
+    /// Write the index through a temp file, so a crash never leaves half of one
+    fn save(&self, index: &mut CacheIndex) -> Result<(), CacheError> {
+        let index_str = serde_json::to_string(index)?;
+        let index_path = self.path.join(CACHE_INDEX_NAME);
+        let tmp_path = self.path.join(format!("{}.tmp", CACHE_INDEX_NAME));
+        let mut tmp_file = File::create(&tmp_path)?;
+        tmp_file.write_all(index_str.as_bytes())?;
+        tmp_file.sync_all()?;
+        std::fs::rename(tmp_path, index_path)?;
+        index.unsaved_reads = 0;
+        Ok(())
+    }
+}
+
+impl Drop for ContentCache {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            tracing::warn!("failed to save cache index: {}", e);
+        }
+    }
+}
+
+/// Remove the objects left in a cache directory
+fn clear_dir(path: &Path) -> Result<(), CacheError> {
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            std::fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CacheError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("serde error: {0}")]
+    Serde(#[from] serde_json::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::cid;
+
+    fn cache_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("dor-store-cache-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn saved_index(dir: &Path) -> CacheIndex {
+        let index_str = std::fs::read_to_string(dir.join(CACHE_INDEX_NAME)).unwrap();
+        serde_json::from_str(&index_str).unwrap()
+    }
+
+    #[test]
+    fn reads_are_saved_in_batches() {
+        let dir = cache_dir("batches");
+        let policy = CachePolicy {
+            enabled: true,
+            ..Default::default()
+        };
+        let cache = ContentCache::open(&dir, policy).unwrap();
+        cache.put(&cid(1), b"data").unwrap();
+
+        for _ in 0..READS_PER_SAVE - 1 {
+            assert_eq!(cache.get(&cid(1)).unwrap(), Some(b"data".to_vec()));
+        }
+        assert_eq!(saved_index(&dir).hits, 0);
+        cache.get(&cid(2)).unwrap();
+        assert_eq!(saved_index(&dir).hits, READS_PER_SAVE - 1);
+        assert_eq!(saved_index(&dir).misses, 1);
+
+        // Whatever's left is saved on drop
+        cache.get(&cid(1)).unwrap();
+        drop(cache);
+        assert_eq!(saved_index(&dir).hits, READS_PER_SAVE);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn unreadable_indexes_start_over_empty() {
+        let dir = cache_dir("unreadable");
+        let cache = ContentCache::open(&dir, CachePolicy::default()).unwrap();
+        cache.put(&cid(1), b"data").unwrap();
+        drop(cache);
+        std::fs::write(dir.join(CACHE_INDEX_NAME), b"{\"entries\": {").unwrap();
+
+        let cache = ContentCache::open(&dir, CachePolicy::default()).unwrap();
+        assert_eq!(cache.stats().entries, 0);
+        assert_eq!(cache.get(&cid(1)).unwrap(), None);
+        assert!(!dir.join(cid(1).to_string()).exists());
+
+        drop(cache);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
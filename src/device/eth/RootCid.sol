@@ -10,7 +10,11 @@ import "@openzeppelin/contracts/access/AccessControl.sol";
 contract RootCid is AccessControl {
   // We're opting for 512 bit Cids, so we'll need two bytes32
   bytes32[2] public cid;
+  // The cid held before the current one, zeroed until the first update
+  bytes32[2] private prior_cid;
   bytes32 public constant WRITER_ROLE = keccak256("WRITER_ROLE");
+  // Other RootCid contracts trusted to pass on who is updating through updateMany
+  bytes32 public constant BATCHER_ROLE = keccak256("BATCHER_ROLE");
 
   constructor(bytes32[2] memory _cid) {
     _grantRole(DEFAULT_ADMIN_ROLE, msg.sender);
@@ -27,17 +31,61 @@ contract RootCid is AccessControl {
     _grantRole(WRITER_ROLE, account);
   }
 
+  function grantBatcher(address account) public {
+    require(hasRole(DEFAULT_ADMIN_ROLE, msg.sender));
+    _grantRole(BATCHER_ROLE, account);
+  }
+
   /* CRUD ops */
 
   function read() public view returns (bytes32[2] memory) {
     return cid;
   }
 
+  function previousCid() public view returns (bytes32[2] memory) {
+    return prior_cid;
+  }
+
   // Set the CID of the blog - restricted to owner
   function update(bytes32[2] memory previous_cid, bytes32[2] memory _cid) public {
     require(hasRole(WRITER_ROLE, msg.sender));
+    _update(previous_cid, _cid);
+  }
+
+  // Update several RootCid contracts in one transaction, each as the sender of
+  // this one. Any other than this contract has to have granted it BATCHER_ROLE,
+  // and any failed update reverts them all
+  function updateMany(
+    address[] memory roots,
+    bytes32[2][] memory previous_cids,
+    bytes32[2][] memory cids
+  ) public {
+    require(roots.length == previous_cids.length);
+    require(roots.length == cids.length);
+    for (uint256 i = 0; i < roots.length; i++) {
+      if (roots[i] == address(this)) {
+        update(previous_cids[i], cids[i]);
+      } else {
+        RootCid(roots[i]).updateFor(msg.sender, previous_cids[i], cids[i]);
+      }
+    }
+  }
+
+  // An update passed on by a batcher, checked against who it came from
+  function updateFor(
+    address writer,
+    bytes32[2] memory previous_cid,
+    bytes32[2] memory _cid
+  ) public {
+    require(hasRole(BATCHER_ROLE, msg.sender));
+    require(hasRole(WRITER_ROLE, writer));
+    _update(previous_cid, _cid);
+  }
+
+  function _update(bytes32[2] memory previous_cid, bytes32[2] memory _cid) internal {
     require(previous_cid[0] == cid[0]);
     require(previous_cid[1] == cid[1]);
+    prior_cid = cid;
     cid = _cid;
     emit updated(cid);
   }
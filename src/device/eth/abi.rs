@@ -0,0 +1,199 @@
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use ethers::abi::{Abi, ParamType};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::EthClientError;
+
+/// The abi of RootCid.sol as first deployed, bundled so we never depend on build output
+const ROOT_CID_V1_ABI: &str = include_str!("abi/root_cid_v1.json");
+/// The abi of RootCid.sol as it is now: keeping its previous cid, read with
+/// `previousCid`, and batching updates with `updateMany`
+const ROOT_CID_V2_ABI: &str = include_str!("abi/root_cid_v2.json");
+
+/// Functions we call on a root cid contract, as `(name, signature)`
+const ROOT_CID_FUNCTIONS: &[(&str, &str)] = &[
+    ("read", "read()"),
+    ("update", "update(bytes32[2],bytes32[2])"),
+];
+/// Event we look through for the transactions behind updates
+const ROOT_CID_EVENT: (&str, &str) = ("updated", "updated(bytes32[2])");
+/// Function updating several RootCid contracts in one transaction, for abis that have it
+pub(super) const ROOT_CID_BATCH_FUNCTION: (&str, &str) = (
+    "updateMany",
+    "updateMany(address[],bytes32[2][],bytes32[2][])",
+);
+
+/// Which abi to talk to the root cid contract with
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RootCidAbi {
+    /// The RootCid contract in this repo, as first deployed
+    #[default]
+    V1,
+    /// The RootCid contract in this repo, keeping its previous cid and able to
+    /// batch updates to others
+    V2,
+    /// An abi on disk, either bare or as a forge artifact with an `abi` key
+    Path(PathBuf),
+}
+
+impl RootCidAbi {
+    /// Load the abi, and check it has the functions and event we use with the
+    /// signatures we encode them with
+    pub fn load(&self) -> Result<Abi, EthClientError> {
+        let abi = match self {
+            Self::V1 => parse_abi(ROOT_CID_V1_ABI)?,
+            Self::V2 => parse_abi(ROOT_CID_V2_ABI)?,
+            Self::Path(path) => {
+                let json = std::fs::read_to_string(path).map_err(|e| {
+                    EthClientError::Abi(format!("failed to read {}: {}", path.display(), e))
+                })?;
+                parse_abi(&json)?
+            }
+        };
+        check_root_cid_abi(&abi).map_err(|e| EthClientError::Abi(format!("{}: {}", self, e)))?;
+        Ok(abi)
+    }
+}
+
+impl FromStr for RootCidAbi {
+    type Err = std::convert::Infallible;
+
+    /// `v1` or `v2` for a bundled version, anything else is a path
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "v1" => Self::V1,
+            "v2" => Self::V2,
+            path => Self::Path(PathBuf::from(path)),
+        })
+    }
+}
+
+impl Display for RootCidAbi {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::V1 => write!(f, "v1"),
+            Self::V2 => write!(f, "v2"),
+            Self::Path(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Parse an abi, bare or from the `abi` key of a build artifact
+fn parse_abi(json: &str) -> Result<Abi, EthClientError> {
+    let value: Value =
+        serde_json::from_str(json).map_err(|e| EthClientError::Abi(e.to_string()))?;
+    let value = match value {
+        Value::Object(mut artifact) => artifact
+            .remove("abi")
+            .ok_or(EthClientError::Abi("artifact has no abi".to_string()))?,
+        value => value,
+    };
+    serde_json::from_value(value).map_err(|e| EthClientError::Abi(e.to_string()))
+}
+
+/// Whether an abi can batch root updates, i.e. has `updateMany` with the selector
+/// we encode it by
+pub(super) fn has_batch_update(abi: &Abi) -> bool {
+    let (name, signature) = ROOT_CID_BATCH_FUNCTION;
+    let selector = ethers::utils::id(signature);
+    abi.functions_by_name(name).is_ok_and(|functions| {
+        functions
+            .iter()
+            .any(|function| function.short_signature() == selector)
+    })
+}
+
+/// Check an abi has every function and the event we use, with the selectors we
+/// encode them by, and that `read` returns a cid
+fn check_root_cid_abi(abi: &Abi) -> Result<(), String> {
+    for (name, signature) in ROOT_CID_FUNCTIONS {
+        let selector = ethers::utils::id(signature);
+        let function = abi
+            .functions_by_name(name)
+            .ok()
+            .and_then(|functions| {
+                functions
+                    .iter()
+                    .find(|function| function.short_signature() == selector)
+            })
+            .ok_or(format!("no function {}", signature))?;
+        let cid_kind = ParamType::FixedArray(Box::new(ParamType::FixedBytes(32)), 2);
+        let outputs = function.outputs.iter().map(|o| &o.kind).collect::<Vec<_>>();
+        if *name == "read" && outputs != [&cid_kind] {
+            return Err(format!("{} doesn't return a bytes32[2]", signature));
+        }
+    }
+
+    let (name, signature) = ROOT_CID_EVENT;
+    let topic = ethers::utils::keccak256(signature);
+    abi.events_by_name(name)
+        .ok()
+        .and_then(|events| {
+            events
+                .iter()
+                .find(|event| event.signature().as_bytes() == topic)
+        })
+        .ok_or(format!("no event {}", signature))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_abi_has_what_we_call() {
+        let abi = RootCidAbi::V1.load().unwrap();
+        assert!(abi.function("grantWriter").is_ok());
+        assert!(!has_batch_update(&abi));
+        assert!(abi.function("previousCid").is_err());
+        let abi = RootCidAbi::V2.load().unwrap();
+        assert!(abi.function("grantBatcher").is_ok());
+        assert!(abi.function("previousCid").is_ok());
+        assert!(has_batch_update(&abi));
+        assert_eq!("v1".parse::<RootCidAbi>().unwrap(), RootCidAbi::V1);
+        assert_eq!("v2".parse::<RootCidAbi>().unwrap(), RootCidAbi::V2);
+        assert_eq!(
+            "out/RootCid.json".parse::<RootCidAbi>().unwrap(),
+            RootCidAbi::Path(PathBuf::from("out/RootCid.json"))
+        );
+    }
+
+    #[test]
+    fn abis_missing_what_we_call_are_rejected() {
+        let full: Value = serde_json::from_str(ROOT_CID_V1_ABI).unwrap();
+        let without = |name: &str| {
+            let entries = full
+                .as_array()
+                .unwrap()
+                .iter()
+                .filter(|entry| entry["name"] != name)
+                .cloned()
+                .collect::<Vec<_>>();
+            serde_json::from_value::<Abi>(Value::Array(entries)).unwrap()
+        };
+        for name in ["read", "update", "updated"] {
+            assert!(check_root_cid_abi(&without(name)).is_err(), "{}", name);
+        }
+
+        // Same name, different selector
+        let mut renamed = full.clone();
+        for entry in renamed.as_array_mut().unwrap() {
+            if entry["name"] == "update" {
+                entry["inputs"][1]["type"] = Value::String("bytes32".to_string());
+                entry["inputs"][1]["internalType"] = Value::String("bytes32".to_string());
+            }
+        }
+        let renamed = serde_json::from_value::<Abi>(renamed).unwrap();
+        assert!(check_root_cid_abi(&renamed).is_err());
+
+        // Forge artifacts carry the abi under a key
+        let artifact = format!(r#"{{"abi":{},"bytecode":{{}}}}"#, ROOT_CID_V1_ABI);
+        check_root_cid_abi(&parse_abi(&artifact).unwrap()).unwrap();
+    }
+}
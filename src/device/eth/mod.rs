@@ -1,5 +1,7 @@
 use std::fmt::Display;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use ethers::{
     abi::Abi,
@@ -8,28 +10,142 @@ use ethers::{
     providers::{Http, Provider},
     signers::LocalWallet,
     types::Address,
+    utils::format_ether,
 };
 use serde::{Deserialize, Serialize};
 use url::Url;
 
+mod abi;
 mod cid_token;
 mod root_cid;
 
-pub use root_cid::{RootCid, RootCidError};
+pub use abi::RootCidAbi;
+pub use root_cid::{RootCid, RootCidError, RootUpdate};
 
 /// Connection to an HTTP RPC API for an EVM-based chain
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EthRemote {
     pub rpc_url: Url,
     pub chain_id: u16,
+    /// Address for the contract publishing our root cid
+    #[serde(default)]
+    pub contract_address: Address,
+    /// Whether to check there's a deployed contract at the address before using it
+    #[serde(default)]
+    pub verify_contract: bool,
+    /// When to ask for confirmation before sending a root update
+    #[serde(default)]
+    pub confirm_updates: ConfirmUpdates,
+    /// Seconds to wait between checks for the receipt of a transaction we sent
+    #[serde(default = "default_receipt_poll_interval_secs")]
+    pub receipt_poll_interval_secs: u64,
+    /// How many times to check for a receipt before giving up on waiting for it
+    #[serde(default = "default_receipt_max_attempts")]
+    pub receipt_max_attempts: u32,
+    /// Whether to cross-check the contract's previous root against our manifest's
+    /// before pushing. Only for contracts that keep one, as ours does from v2 on
+    #[serde(default)]
+    pub check_previous_root: bool,
+    /// Abi to encode calls to the contract with: a version we bundle, or one on disk
+    #[serde(default)]
+    pub contract_abi: RootCidAbi,
+}
+
+/// Seconds between receipt checks -- the same cadence ethers polls at over http
+pub const DEFAULT_RECEIPT_POLL_INTERVAL_SECS: u64 = 7;
+/// Receipt checks before giving up, a bit over ten minutes at the default interval
+pub const DEFAULT_RECEIPT_MAX_ATTEMPTS: u32 = 90;
+
+fn default_receipt_poll_interval_secs() -> u64 {
+    DEFAULT_RECEIPT_POLL_INTERVAL_SECS
+}
+
+fn default_receipt_max_attempts() -> u32 {
+    DEFAULT_RECEIPT_MAX_ATTEMPTS
+}
+
+/// Chain ids of mainnets we know about, where transactions cost real money
+/// Ethereum, Optimism, BNB, Gnosis, Polygon, Filecoin, Base, Arbitrum One
+pub const MAINNET_CHAIN_IDS: &[u16] = &[1, 10, 56, 100, 137, 314, 8453, 42161];
+
+/// When to ask for confirmation before sending a root update
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfirmUpdates {
+    /// Only on chains in MAINNET_CHAIN_IDS
+    #[default]
+    Mainnet,
+    /// On every chain
+    Always,
+    /// Never
+    Never,
+}
+
+impl ConfirmUpdates {
+    /// Whether an update on the given chain needs confirming
+    pub fn required(&self, chain_id: u16) -> bool {
+        match self {
+            Self::Mainnet => MAINNET_CHAIN_IDS.contains(&chain_id),
+            Self::Always => true,
+            Self::Never => false,
+        }
+    }
 }
 
 impl Display for EthRemote {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "rpc: {}, chain_id: {}", self.rpc_url, self.chain_id)
+        write!(
+            f,
+            "rpc: {}, chain_id: {}, contract_address: {:?}",
+            self.rpc_url, self.chain_id, self.contract_address
+        )
     }
 }
 
+/// Parse a `chain_id,address,rpc_url` mirror, or `chain_id,address` to stop
+/// mirroring that contract
+pub fn parse_eth_mirror(s: &str) -> Result<(u16, Address, Option<Url>), EthClientError> {
+    let invalid = |reason: &str| EthClientError::InvalidMirror(s.to_string(), reason.to_string());
+    let mut parts = s.splitn(3, ',');
+    let chain_id = parts
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .parse()
+        .map_err(|_| invalid("expected a chain id"))?;
+    let address = parse_address(
+        parts
+            .next()
+            .ok_or_else(|| invalid("expected an address"))?
+            .trim(),
+    )?;
+    let rpc_url = match parts.next().map(str::trim) {
+        None | Some("") => None,
+        Some(rpc_url) => Some(Url::parse(rpc_url).map_err(|_| invalid("not an rpc url"))?),
+    };
+    Ok((chain_id, address, rpc_url))
+}
+
+/// Parse a hex address, validating its EIP-55 checksum if it is mixed case
+pub fn parse_address(s: &str) -> Result<Address, EthClientError> {
+    let hex = s
+        .strip_prefix("0x")
+        .ok_or(EthClientError::InvalidAddress(s.to_string()))?;
+    if hex.len() != 40 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(EthClientError::InvalidAddress(s.to_string()));
+    }
+    let address =
+        Address::from_str(hex).map_err(|_| EthClientError::InvalidAddress(s.to_string()))?;
+
+    // All lower or all upper case addresses carry no checksum
+    let has_lower = hex.chars().any(|c| c.is_ascii_lowercase());
+    let has_upper = hex.chars().any(|c| c.is_ascii_uppercase());
+    if has_lower && has_upper && ethers::utils::to_checksum(&address, None) != s {
+        return Err(EthClientError::InvalidChecksum(s.to_string()));
+    }
+    Ok(address)
+}
+
 /// Client for interacting with the EVM over Http
 #[derive(Debug, Clone)]
 pub struct EthClient {
@@ -37,6 +153,9 @@ pub struct EthClient {
     chain_id: u16,
     contract: Option<Contract<ethers::providers::Provider<Http>>>,
     signer: Option<SignerMiddleware<Provider<Http>, LocalWallet>>,
+    receipt_poll_interval: Duration,
+    receipt_max_attempts: u32,
+    root_cid_abi: Abi,
 }
 
 impl TryFrom<EthRemote> for EthClient {
@@ -45,11 +164,16 @@ impl TryFrom<EthRemote> for EthClient {
     fn try_from(remote: EthRemote) -> Result<Self, Self::Error> {
         let provider = Provider::<Http>::try_from(remote.rpc_url.to_string())
             .map_err(|e| EthClientError::Default(e.to_string()))?;
+        // Loaded up front, so a bad abi fails here rather than on the first call
+        let root_cid_abi = remote.contract_abi.load()?;
         Ok(Self {
             provider,
             chain_id: remote.chain_id,
             contract: None,
             signer: None,
+            receipt_poll_interval: Duration::from_secs(remote.receipt_poll_interval_secs),
+            receipt_max_attempts: remote.receipt_max_attempts,
+            root_cid_abi,
         })
     }
 }
@@ -65,11 +189,63 @@ impl EthClient {
         self.contract.clone()
     }
 
+    /// Get the abi configured for the root cid contract, checked when it was loaded
+    pub fn root_cid_abi(&self) -> &Abi {
+        &self.root_cid_abi
+    }
+
     /// Get the signer from the implementing struct
     pub fn signer(&self) -> Option<SignerMiddleware<Provider<Http>, LocalWallet>> {
         self.signer.clone()
     }
 
+    /// Check whether there is deployed bytecode at an address
+    /// Returns false for EOAs and empty addresses
+    pub async fn has_code(&self, address: Address) -> Result<bool, EthClientError> {
+        let code = self
+            .provider
+            .get_code(address, None)
+            .await
+            .map_err(|e| EthClientError::Default(e.to_string()))?;
+        Ok(!code.is_empty())
+    }
+
+    /// Get the balance of an account, in wei
+    pub async fn balance(&self, address: Address) -> Result<U256, EthClientError> {
+        self.provider
+            .get_balance(address, None)
+            .await
+            .map_err(|e| EthClientError::Default(e.to_string()))
+    }
+
+    /// Wait for the receipt of a transaction we sent, checking for it on our
+    /// configured interval
+    /// Errors with the transaction hash once we've checked as many times as we're
+    /// configured to, so the caller can check on it later rather than block forever
+    pub async fn wait_for_receipt(
+        &self,
+        tx_hash: TxHash,
+    ) -> Result<TransactionReceipt, EthClientError> {
+        for attempt in 0..self.receipt_max_attempts {
+            if attempt > 0 {
+                tokio::time::sleep(self.receipt_poll_interval).await;
+            }
+            let receipt = self
+                .provider
+                .get_transaction_receipt(tx_hash)
+                .await
+                .map_err(|e| EthClientError::Default(e.to_string()))?;
+            if let Some(receipt) = receipt {
+                return Ok(receipt);
+            }
+            tracing::debug!("no receipt yet for {:?}, attempt {}", tx_hash, attempt + 1);
+        }
+        Err(EthClientError::TransactionPending(
+            tx_hash,
+            self.receipt_max_attempts,
+        ))
+    }
+
     // TODO: no result needed
     /// Attach SignerMiddleware to the client
     pub fn with_signer(mut self, wallet: LocalWallet) -> Self {
@@ -93,4 +269,66 @@ impl EthClient {
 pub enum EthClientError {
     #[error("default error: {0}")]
     Default(String),
+    #[error("malformed address, expected 0x followed by 40 hex characters: {0}")]
+    InvalidAddress(String),
+    #[error("address does not match its checksum: {0}")]
+    InvalidChecksum(String),
+    #[error("transaction {0:?} is still pending after {1} receipt checks -- check on it later")]
+    TransactionPending(TxHash, u32),
+    #[error(
+        "signer can't pay for the update: it needs about {} ETH, but has {} ETH",
+        format_ether(*needed),
+        format_ether(*available)
+    )]
+    InsufficientFunds { needed: U256, available: U256 },
+    #[error("contract abi error: {0}")]
+    Abi(String),
+    #[error("invalid eth mirror {0:?}: {1}")]
+    InvalidMirror(String, String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eth_mirrors_parse_with_or_without_a_url() {
+        let address = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+        let (chain_id, parsed, rpc_url) =
+            parse_eth_mirror(&format!("10,{},https://rpc.example/v1", address)).unwrap();
+        assert_eq!(chain_id, 10);
+        assert_eq!(parsed, parse_address(address).unwrap());
+        assert_eq!(rpc_url.unwrap().as_str(), "https://rpc.example/v1");
+        assert_eq!(
+            parse_eth_mirror(&format!("10,{}", address)).unwrap().2,
+            None
+        );
+        assert!(matches!(
+            parse_eth_mirror(address),
+            Err(EthClientError::InvalidMirror(_, _))
+        ));
+        assert!(matches!(
+            parse_eth_mirror(&format!("10,{},not a url", address)),
+            Err(EthClientError::InvalidMirror(_, _))
+        ));
+    }
+
+    #[test]
+    fn parse_address_checksum() {
+        let checksummed = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+        let address = parse_address(checksummed).unwrap();
+        assert_eq!(parse_address(&checksummed.to_lowercase()).unwrap(), address);
+        assert!(matches!(
+            parse_address("0x5aaeb6053F3E94C9b9A09f33669435E7Ef1BeAed"),
+            Err(EthClientError::InvalidChecksum(_))
+        ));
+        assert!(matches!(
+            parse_address("5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"),
+            Err(EthClientError::InvalidAddress(_))
+        ));
+        assert!(matches!(
+            parse_address("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeA"),
+            Err(EthClientError::InvalidAddress(_))
+        ));
+    }
 }
@@ -1,16 +1,32 @@
+use async_trait::async_trait;
 use cid::Cid;
 use ethers::{
-    abi::Abi,
+    abi::{ParamType, Token, Tokenizable},
     prelude::*,
     signers::LocalWallet,
     types::{Address, TransactionRequest},
 };
-use serde_json::Value;
 
+use super::abi::{has_batch_update, ROOT_CID_BATCH_FUNCTION};
 use super::cid_token::CidToken;
 use super::{EthClient, EthClientError};
+use crate::device::root_cid_store::{RootCidStore, RootCidStoreError, RootUpdateReceipt};
+use crate::metrics;
 
-const ABI_STRING: &str = include_str!("../../../out/RootCid.sol/RootCid.json");
+/// How many blocks back to look for the transaction behind an already applied update
+const UPDATE_LOOKBACK_BLOCKS: u64 = 1000;
+/// Getter that contracts keeping their previous root expose it by, as a bytes32[2]
+/// Our RootCid contract keeps one from v2 on -- v1 deployments don't
+const PREVIOUS_ROOT_SIGNATURE: &str = "previousCid()";
+
+/// One update in a batch: move the RootCid contract at `contract` from
+/// `previous_cid` to `cid`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootUpdate {
+    pub contract: Address,
+    pub previous_cid: Cid,
+    pub cid: Cid,
+}
 
 /// Wrapper around an EthClient for interacting with our RootCid contract
 pub struct RootCid(EthClient);
@@ -25,8 +41,7 @@ impl RootCid {
             Some(signer) => eth_client.with_signer(signer),
             None => eth_client,
         };
-        let abi_value: Value = serde_json::from_str(ABI_STRING)?;
-        let abi: Abi = serde_json::from_value(abi_value["abi"].clone())?;
+        let abi = eth_client.root_cid_abi().clone();
 
         let client = eth_client.with_contract(address, abi);
         let client = client.clone();
@@ -83,20 +98,122 @@ impl RootCid {
         Ok(cid)
     }
 
+    /// Read the current value of the contract as it's stored, without parsing it
+    async fn read_token(&self) -> Result<Token, RootCidError> {
+        // TODO: This is janky, but we should have the contract available by now
+        let contract = self.0.contract().unwrap();
+
+        contract
+            .method::<_, Token>("read", ())
+            .map_err(|e| RootCidError::Default(e.to_string()))?
+            .call()
+            .await
+            .map_err(|e| RootCidError::Default(e.to_string()))
+    }
+
+    /// Read the current cid from the contract, or None if it was deployed without one
+    pub async fn read_seed(&self) -> Result<Option<Cid>, RootCidError> {
+        seeded_cid(self.read_token().await?)
+    }
+
+    /// Read the cid the contract held before its current one, for contracts that keep it
+    /// Called by selector, so it works whichever abi the device is configured with
+    /// # Returns None if the contract gave an empty reply, i.e. doesn't expose one.
+    ///   A contract that has only ever held one cid reports the default cid
+    pub async fn read_previous_root(&self) -> Result<Option<Cid>, RootCidError> {
+        // TODO: This is janky, but we should have the contract available by now
+        let contract = self.0.contract().unwrap();
+        let tx = TransactionRequest::new()
+            .to(contract.address())
+            .data(ethers::utils::id(PREVIOUS_ROOT_SIGNATURE).to_vec());
+        let data = contract
+            .client()
+            .call(&tx.into(), None)
+            .await
+            .map_err(|e| RootCidError::Default(e.to_string()))?;
+        if data.is_empty() {
+            return Ok(None);
+        }
+        let param = ParamType::FixedArray(Box::new(ParamType::FixedBytes(32)), 2);
+        let token = ethers::abi::decode(&[param], &data)?
+            .into_iter()
+            .next()
+            .unwrap_or_else(unset_token);
+        if token == unset_token() {
+            return Ok(Some(Cid::default()));
+        }
+        let cid_token =
+            CidToken::from_token(token).map_err(|e| RootCidError::Default(e.to_string()))?;
+        Ok(Some(cid_token.into()))
+    }
+
+    /// Give an unset contract its first cid
+    /// The contract checks the previous cid of an update against what it holds, so
+    /// whichever unset value it was deployed with is passed back as is
+    /// Requires a signer
+    pub async fn seed(&self, cid: Cid) -> Result<Option<TransactionReceipt>, RootCidError> {
+        let previous = self.read_token().await?;
+        if let Some(current_cid) = seeded_cid(previous.clone())? {
+            return Err(RootCidError::AlreadySeeded(current_cid));
+        }
+        let signer = match self.0.signer() {
+            Some(signer) => signer,
+            None => return Err(RootCidError::MissingSigner),
+        };
+        // TODO: This is janky, but we should have the contract available by now
+        let contract = self.0.contract().unwrap();
+        let data = contract
+            .encode("update", (previous, CidToken::from(cid)))
+            .map_err(|e| RootCidError::Default(e.to_string()))?;
+        let tx = TransactionRequest::new()
+            .to(contract.address())
+            .data(data)
+            .chain_id(self.0.chain_id());
+        let signed_tx = signer
+            .send_transaction(tx, None)
+            .await
+            .map_err(|e| RootCidError::Default(e.to_string()))?;
+        let reciept = self.0.wait_for_receipt(signed_tx.tx_hash()).await?;
+        Ok(Some(reciept))
+    }
+
     /// Update the current cid in the contract
     /// Requires a signer
+    /// Idempotent: if the contract already points at `cid` -- i.e. a retried update
+    /// whose original transaction landed -- nothing is submitted, and the receipt of
+    /// the transaction that set it is returned if it can be found
     pub async fn update(
         &self,
         previous_cid: Cid,
         cid: Cid,
     ) -> Result<Option<TransactionReceipt>, RootCidError> {
+        let current_cid = self.read().await?;
+        if current_cid == cid {
+            tracing::info!("root cid is already {}, skipping update", cid);
+            return Ok(self.find_update(cid).await);
+        }
+        if current_cid != previous_cid {
+            return Err(RootCidError::MissmatchedRootCid(previous_cid, current_cid));
+        }
+
+        match self.submit_update(previous_cid, cid).await {
+            Ok(reciept) => Ok(reciept),
+            // The transaction may have landed even if we didn't hear back
+            Err(e) => match self.read().await {
+                Ok(current_cid) if current_cid == cid => {
+                    tracing::warn!("update errored but landed: {}", e);
+                    Ok(self.find_update(cid).await)
+                }
+                _ => Err(e),
+            },
+        }
+    }
+
+    /// Build the transaction updating the contract from `previous_cid` to `cid`
+    fn update_tx(&self, previous_cid: Cid, cid: Cid) -> Result<TransactionRequest, RootCidError> {
         // TODO: This is janky, but we should have the contract available by now
         let contract = self.0.contract().unwrap();
         let chain_id = self.0.chain_id();
-        let signer = match self.0.signer() {
-            Some(signer) => signer,
-            None => return Err(RootCidError::MissingSigner),
-        };
         let data = contract
             .encode(
                 "update",
@@ -107,15 +224,241 @@ impl RootCid {
             .to(contract.address())
             .data(data)
             .chain_id(chain_id);
+        Ok(tx)
+    }
+
+    /// The calldata of the `update` call moving the contract from `previous_cid` to `cid`
+    /// Doesn't need a signer, or touch the chain
+    pub fn update_calldata(&self, previous_cid: Cid, cid: Cid) -> Result<Bytes, RootCidError> {
+        let tx = self.update_tx(previous_cid, cid)?;
+        Ok(tx.data.unwrap_or_default())
+    }
+
+    /// Estimate the cost in wei of updating the contract at the current gas price
+    /// Requires a signer
+    pub async fn estimate_update(&self, previous_cid: Cid, cid: Cid) -> Result<U256, RootCidError> {
+        let signer = match self.0.signer() {
+            Some(signer) => signer,
+            None => return Err(RootCidError::MissingSigner),
+        };
+        let tx = self.update_tx(previous_cid, cid)?;
+        let gas = signer
+            .estimate_gas(&tx.from(signer.address()).into(), None)
+            .await
+            .map_err(|e| RootCidError::Default(e.to_string()))?;
+        let gas_price = signer
+            .get_gas_price()
+            .await
+            .map_err(|e| RootCidError::Default(e.to_string()))?;
+        Ok(gas * gas_price)
+    }
+
+    /// Check the signer can pay for updating the contract at the current gas price
+    /// Requires a signer
+    pub async fn check_update_funds(
+        &self,
+        previous_cid: Cid,
+        cid: Cid,
+    ) -> Result<(), RootCidError> {
+        let signer = match self.0.signer() {
+            Some(signer) => signer,
+            None => return Err(RootCidError::MissingSigner),
+        };
+        let needed = self.estimate_update(previous_cid, cid).await?;
+        let available = self.0.balance(signer.address()).await?;
+        if available < needed {
+            return Err(EthClientError::InsufficientFunds { needed, available }.into());
+        }
+        Ok(())
+    }
+
+    /// Submit an update transaction and wait for its receipt
+    async fn submit_update(
+        &self,
+        previous_cid: Cid,
+        cid: Cid,
+    ) -> Result<Option<TransactionReceipt>, RootCidError> {
+        let signer = match self.0.signer() {
+            Some(signer) => signer,
+            None => return Err(RootCidError::MissingSigner),
+        };
+        let tx = self.update_tx(previous_cid, cid)?;
         let signed_tx = signer
             .send_transaction(tx, None)
             .await
             .map_err(|e| RootCidError::Default(e.to_string()))?;
-        let reciept = signed_tx
+        let reciept = self.0.wait_for_receipt(signed_tx.tx_hash()).await?;
+        Ok(Some(reciept))
+    }
+
+    /// Whether the contract can batch updates, going by the abi we talk to it with
+    pub fn can_batch(&self) -> bool {
+        has_batch_update(self.0.root_cid_abi())
+    }
+
+    /// Update several RootCid contracts on this chain in one transaction, through
+    /// this contract's `updateMany`. Any but this one has to have granted it the
+    /// batcher role, and the signer has to be a writer on each
+    /// Requires a signer
+    /// Every update is checked before anything is sent: each contract has to be
+    /// named once and hold the update's previous cid. One bad update fails the
+    /// batch here rather than on chain
+    /// # Returns the receipt of the batch transaction
+    pub async fn update_many(
+        &self,
+        updates: &[RootUpdate],
+    ) -> Result<TransactionReceipt, RootCidError> {
+        if !self.can_batch() {
+            return Err(RootCidError::BatchUnsupported);
+        }
+        let signer = match self.0.signer() {
+            Some(signer) => signer,
+            None => return Err(RootCidError::MissingSigner),
+        };
+        let tokens = batch_tokens(updates)?;
+        for update in updates {
+            let target = RootCid::new(self.0.clone(), update.contract, None)?;
+            let current_cid = target
+                .read()
+                .await
+                .map_err(|e| RootCidError::InvalidBatchUpdate(update.contract, e.to_string()))?;
+            if current_cid != update.previous_cid {
+                let e = RootCidError::MissmatchedRootCid(update.previous_cid, current_cid);
+                return Err(RootCidError::InvalidBatchUpdate(
+                    update.contract,
+                    e.to_string(),
+                ));
+            }
+        }
+
+        // TODO: This is janky, but we should have the contract available by now
+        let contract = self.0.contract().unwrap();
+        let (name, _) = ROOT_CID_BATCH_FUNCTION;
+        let data = self
+            .0
+            .root_cid_abi()
+            .function(name)?
+            .encode_input(&tokens)?;
+        let tx = TransactionRequest::new()
+            .to(contract.address())
+            .data(data)
+            .chain_id(self.0.chain_id());
+        let signed_tx = signer
+            .send_transaction(tx, None)
             .await
             .map_err(|e| RootCidError::Default(e.to_string()))?;
+        let reciept = self.0.wait_for_receipt(signed_tx.tx_hash()).await?;
+        if let Some(gas_used) = reciept.gas_used {
+            metrics::increment(metrics::GAS_USED_TOTAL, gas_used.low_u64());
+        }
         Ok(reciept)
     }
+
+    /// Look through recent `updated` events for the transaction that set `cid`
+    /// Best effort -- returns None if it can't be found
+    pub async fn find_update(&self, cid: Cid) -> Option<TransactionReceipt> {
+        // TODO: This is janky, but we should have the contract available by now
+        let contract = self.0.contract().unwrap();
+        let provider = contract.client();
+        let latest = provider.get_block_number().await.ok()?;
+        let filter = Filter::new()
+            .address(contract.address())
+            .event("updated(bytes32[2])")
+            .from_block(latest.saturating_sub(U64::from(UPDATE_LOOKBACK_BLOCKS)));
+        let logs = provider.get_logs(&filter).await.ok()?;
+
+        let data = ethers::abi::encode(&[CidToken::from(cid).into_token()]);
+        let log = logs.iter().rev().find(|log| log.data.to_vec() == data)?;
+        provider
+            .get_transaction_receipt(log.transaction_hash?)
+            .await
+            .ok()
+            .flatten()
+    }
+
+    /// Seconds since the epoch at which a block was produced
+    /// Best effort -- returns None if the block can't be read
+    pub async fn block_timestamp(&self, block_number: U64) -> Option<u64> {
+        let contract = self.0.contract()?;
+        let block = contract.client().get_block(block_number).await.ok()??;
+        Some(block.timestamp.as_u64())
+    }
+}
+
+/// The arguments to `updateMany` for a batch: the contracts, their previous cids,
+/// and their next cids
+fn batch_tokens(updates: &[RootUpdate]) -> Result<Vec<Token>, RootCidError> {
+    if updates.is_empty() {
+        return Err(RootCidError::EmptyBatch);
+    }
+    let mut contracts = Vec::new();
+    let mut previous_cids = Vec::new();
+    let mut cids = Vec::new();
+    for update in updates {
+        let contract = Token::Address(update.contract);
+        if contracts.contains(&contract) {
+            return Err(RootCidError::InvalidBatchUpdate(
+                update.contract,
+                "named more than once".to_string(),
+            ));
+        }
+        contracts.push(contract);
+        previous_cids.push(CidToken::from(update.previous_cid).into_token());
+        cids.push(CidToken::from(update.cid).into_token());
+    }
+    Ok(vec![
+        Token::Array(contracts),
+        Token::Array(previous_cids),
+        Token::Array(cids),
+    ])
+}
+
+/// What an unset contract holds: two zeroed words
+fn unset_token() -> Token {
+    Token::FixedArray(vec![
+        Token::FixedBytes(vec![0; 32]),
+        Token::FixedBytes(vec![0; 32]),
+    ])
+}
+
+/// The cid a contract's stored value holds, or None if it's unset
+/// Contracts are deployed either with all zeros, which don't parse as a cid, or
+/// with the default cid as a sentinel, as `bin/reset_dev_env.sh` does
+fn seeded_cid(token: Token) -> Result<Option<Cid>, RootCidError> {
+    if token == unset_token() {
+        return Ok(None);
+    }
+    let cid: Cid = CidToken::from_token(token)
+        .map_err(|e| RootCidError::Default(e.to_string()))?
+        .into();
+    if cid == Cid::default() {
+        return Ok(None);
+    }
+    Ok(Some(cid))
+}
+
+#[async_trait]
+impl RootCidStore for RootCid {
+    async fn read(&self) -> Result<Cid, RootCidStoreError> {
+        Ok(RootCid::read(self).await?)
+    }
+
+    async fn update(
+        &self,
+        previous_cid: Cid,
+        cid: Cid,
+    ) -> Result<Option<RootUpdateReceipt>, RootCidStoreError> {
+        // TODO: maybe should wait for emitted event and check for a valid update
+        let maybe_txn_reciept = RootCid::update(self, previous_cid, cid).await?;
+        if let Some(gas_used) = maybe_txn_reciept.as_ref().and_then(|r| r.gas_used) {
+            metrics::increment(metrics::GAS_USED_TOTAL, gas_used.low_u64());
+        }
+        Ok(maybe_txn_reciept.map(|reciept| RootUpdateReceipt {
+            id: format!("{:?}", reciept.transaction_hash),
+            block_number: reciept.block_number.map(|number| number.as_u64()),
+            gas_used: reciept.gas_used.map(|gas_used| gas_used.low_u64()),
+        }))
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -124,6 +467,16 @@ pub enum RootCidError {
     EthClient(#[from] EthClientError),
     #[error("No signer")]
     MissingSigner,
+    #[error("contract already holds root {0}")]
+    AlreadySeeded(Cid),
+    #[error("contract abi has no {}", ROOT_CID_BATCH_FUNCTION.1)]
+    BatchUnsupported,
+    #[error("no updates to batch")]
+    EmptyBatch,
+    #[error("can't batch the update of {0:?}: {1}")]
+    InvalidBatchUpdate(Address, String),
+    #[error("missmatched root cid: expected {0}, contract has {1}")]
+    MissmatchedRootCid(Cid, Cid),
     #[error("abi error: {0}")]
     Abi(#[from] ethers::abi::Error),
     #[error("serde json error: {0}")]
@@ -131,3 +484,53 @@ pub enum RootCidError {
     #[error("default error: {0}")]
     Default(String),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::eth::RootCidAbi;
+    use crate::test_utils::cid;
+
+    #[test]
+    fn zeroed_and_default_contracts_are_unseeded() {
+        assert!(seeded_cid(unset_token()).unwrap().is_none());
+        assert!(seeded_cid(CidToken::from(Cid::default()).into_token())
+            .unwrap()
+            .is_none());
+
+        let cid = Cid::try_from("QmWATWQ7fVPP2EFGu71UkfnqhYXDYH566qy47CnJDgvs8u").unwrap();
+        assert_eq!(
+            seeded_cid(CidToken::from(cid).into_token()).unwrap(),
+            Some(cid)
+        );
+    }
+
+    #[test]
+    fn batches_encode_as_update_many() {
+        let update = |contract: u8| RootUpdate {
+            contract: Address::from_low_u64_be(contract as u64),
+            previous_cid: cid(contract),
+            cid: cid(contract + 10),
+        };
+        let updates = vec![update(1), update(2)];
+
+        let abi = RootCidAbi::V2.load().unwrap();
+        let function = abi.function(ROOT_CID_BATCH_FUNCTION.0).unwrap();
+        let data = function
+            .encode_input(&batch_tokens(&updates).unwrap())
+            .unwrap();
+        assert_eq!(data[..4], ethers::utils::id(ROOT_CID_BATCH_FUNCTION.1)[..]);
+        let decoded = function.decode_input(&data[4..]).unwrap();
+        assert_eq!(decoded, batch_tokens(&updates).unwrap());
+
+        // Bad batches are turned away before they're encoded
+        assert!(matches!(batch_tokens(&[]), Err(RootCidError::EmptyBatch)));
+        let mut twice = vec![update(1), update(1)];
+        twice[1].previous_cid = twice[0].cid;
+        assert!(matches!(
+            batch_tokens(&twice),
+            Err(RootCidError::InvalidBatchUpdate(contract, _))
+                if contract == Address::from_low_u64_be(1)
+        ));
+    }
+}
@@ -0,0 +1,183 @@
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bytes::Bytes;
+use common_multipart_rfc7578::client::multipart::Form;
+use http::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, PROXY_AUTHORIZATION};
+use http::StatusCode;
+use hyper::client::HttpConnector;
+use hyper::{Body, Client};
+use hyper_tls::HttpsConnector;
+use ipfs_api_backend_hyper::{Error as HyperError, IpfsClient as HyperIpfsClient};
+use ipfs_api_prelude::{ApiRequest, Backend, BoxStream};
+
+use super::{BasicAuth, Secret};
+
+/// The credential headers every request to the api carries, whichever backend
+/// sends it
+/// Basic auth goes as `Authorization` on its own, or as `Proxy-Authorization`
+/// when a bearer token is also set, since the two can't share a header. The bearer
+/// token is always sent as `Authorization`. Both are marked sensitive, so they're
+/// masked in logs
+#[derive(Clone, Default)]
+pub struct ApiAuth(HeaderMap);
+
+impl ApiAuth {
+    pub fn new(
+        basic_auth: Option<&BasicAuth>,
+        bearer_token: Option<&Secret>,
+    ) -> Result<Self, http::header::InvalidHeaderValue> {
+        let mut headers = HeaderMap::new();
+        if let Some(basic_auth) = basic_auth {
+            let credential = STANDARD.encode(format!(
+                "{}:{}",
+                basic_auth.username,
+                basic_auth.password.expose()
+            ));
+            let header = match bearer_token {
+                Some(_) => PROXY_AUTHORIZATION,
+                None => AUTHORIZATION,
+            };
+            headers.insert(header, sensitive(&format!("Basic {}", credential))?);
+        }
+        if let Some(bearer_token) = bearer_token {
+            headers.insert(
+                AUTHORIZATION,
+                sensitive(&format!("Bearer {}", bearer_token.expose()))?,
+            );
+        }
+        Ok(Self(headers))
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        &self.0
+    }
+}
+
+fn sensitive(value: &str) -> Result<HeaderValue, http::header::InvalidHeaderValue> {
+    let mut value = HeaderValue::from_str(value)?;
+    value.set_sensitive(true);
+    Ok(value)
+}
+
+/// The Hyper backend, with our credentials attached to every request
+/// The backend itself only knows basic auth, so the headers are set on each
+/// request it builds, and requests it would send itself are sent from here
+#[derive(Clone)]
+pub struct AuthorizedBackend {
+    backend: HyperIpfsClient,
+    client: Client<HttpsConnector<HttpConnector>, Body>,
+    auth: ApiAuth,
+}
+
+impl AuthorizedBackend {
+    pub fn new(backend: HyperIpfsClient, auth: ApiAuth) -> Self {
+        Self {
+            backend,
+            client: Client::builder().build(HttpsConnector::new()),
+            auth,
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl Backend for AuthorizedBackend {
+    type HttpRequest = http::Request<Body>;
+    type HttpResponse = http::Response<Body>;
+    type Error = HyperError;
+
+    fn with_credentials<U, P>(self, username: U, password: P) -> Self
+    where
+        U: Into<String>,
+        P: Into<String>,
+    {
+        Self {
+            backend: self.backend.with_credentials(username, password),
+            ..self
+        }
+    }
+
+    fn build_base_request<Req>(
+        &self,
+        req: Req,
+        form: Option<Form<'static>>,
+    ) -> Result<Self::HttpRequest, Self::Error>
+    where
+        Req: ApiRequest,
+    {
+        let mut request = self.backend.build_base_request(req, form)?;
+        for (name, value) in self.auth.headers().iter() {
+            request.headers_mut().insert(name, value.clone());
+        }
+        Ok(request)
+    }
+
+    fn get_header(res: &Self::HttpResponse, key: HeaderName) -> Option<&HeaderValue> {
+        HyperIpfsClient::get_header(res, key)
+    }
+
+    async fn request_raw<Req>(
+        &self,
+        req: Req,
+        form: Option<Form<'static>>,
+    ) -> Result<(StatusCode, Bytes), Self::Error>
+    where
+        Req: ApiRequest,
+    {
+        let request = self.build_base_request(req, form)?;
+        let response = self.client.request(request).await?;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        Ok((status, body))
+    }
+
+    fn response_to_byte_stream(res: Self::HttpResponse) -> BoxStream<Bytes, Self::Error> {
+        HyperIpfsClient::response_to_byte_stream(res)
+    }
+
+    fn request_stream<Res, F>(
+        &self,
+        req: Self::HttpRequest,
+        process: F,
+    ) -> BoxStream<Res, Self::Error>
+    where
+        F: 'static + Send + Fn(Self::HttpResponse) -> BoxStream<Res, Self::Error>,
+    {
+        // Already carries our headers, from build_base_request
+        self.backend.request_stream(req, process)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ipfs_api_backend_hyper::request;
+
+    use super::*;
+
+    #[test]
+    fn backends_send_the_same_headers() {
+        let basic_auth = BasicAuth {
+            username: "proxy".to_string(),
+            password: Secret::from("hunter2".to_string()),
+        };
+        let bearer_token = Secret::from("s3cr3t".to_string());
+
+        let auth = ApiAuth::new(Some(&basic_auth), Some(&bearer_token)).unwrap();
+        let backend = AuthorizedBackend::new(HyperIpfsClient::default(), auth);
+        let request = backend.build_base_request(request::Version, None).unwrap();
+        let headers = request.headers();
+        assert_eq!(
+            headers.get(PROXY_AUTHORIZATION).unwrap(),
+            "Basic cHJveHk6aHVudGVyMg=="
+        );
+        assert_eq!(headers.get(AUTHORIZATION).unwrap(), "Bearer s3cr3t");
+        assert!(headers.get(AUTHORIZATION).unwrap().is_sensitive());
+
+        let auth = ApiAuth::new(Some(&basic_auth), None).unwrap();
+        assert_eq!(
+            auth.headers().get(AUTHORIZATION).unwrap(),
+            "Basic cHJveHk6aHVudGVyMg=="
+        );
+        assert!(auth.headers().get(PROXY_AUTHORIZATION).is_none());
+    }
+}
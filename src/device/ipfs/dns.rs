@@ -0,0 +1,173 @@
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+
+use reqwest::ClientBuilder;
+use serde::{Deserialize, Serialize};
+use url::{Host, Url};
+
+use super::IpfsError;
+
+/// Addresses to reach hosts at in place of looking them up, i.e. where hosts can
+/// only be resolved through a resolver the system doesn't use
+/// Only affects dor-store's own requests to the api and gateway -- the node, and
+/// anything else on the machine, resolve hosts as they always would
+/// Subdomains of an overridden host are overridden with it, so subdomain gateway
+/// urls reach the gateway they're under
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct DnsOverrides(BTreeMap<String, IpAddr>);
+
+impl From<BTreeMap<String, IpAddr>> for DnsOverrides {
+    fn from(overrides: BTreeMap<String, IpAddr>) -> Self {
+        Self(overrides)
+    }
+}
+
+impl DnsOverrides {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Override a host, or stop overriding it with None
+    pub fn set(&mut self, host: String, ip: Option<IpAddr>) {
+        let host = host.to_ascii_lowercase();
+        match ip {
+            Some(ip) => self.0.insert(host, ip),
+            None => self.0.remove(&host),
+        };
+    }
+
+    /// Check every overridden host is a plain domain name
+    pub fn validate(&self) -> Result<(), IpfsError> {
+        self.0.keys().try_for_each(|host| validate_host(host))
+    }
+
+    /// The address overriding a host, if any: its own, or that of the closest
+    /// overridden domain it's under
+    pub fn lookup(&self, host: &str) -> Option<IpAddr> {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        let mut domain = host.as_str();
+        loop {
+            if let Some((_, ip)) = self
+                .0
+                .iter()
+                .find(|(overridden, _)| overridden.eq_ignore_ascii_case(domain))
+            {
+                return Some(*ip);
+            }
+            domain = domain.split_once('.')?.1;
+        }
+    }
+
+    /// Where to connect for a url, if its host is overridden
+    pub fn socket_addr(&self, url: &Url) -> Option<SocketAddr> {
+        let ip = match url.host()? {
+            Host::Domain(domain) => self.lookup(domain)?,
+            _ => return None,
+        };
+        Some(SocketAddr::new(ip, url.port_or_known_default()?))
+    }
+
+    /// Have a client reach a url's host at its override, if it has one
+    pub fn apply(&self, builder: ClientBuilder, url: &Url) -> ClientBuilder {
+        match (url.host_str(), self.socket_addr(url)) {
+            (Some(host), Some(addr)) => builder.resolve(host, addr),
+            _ => builder,
+        }
+    }
+}
+
+/// Check a host is a plain domain name
+/// Addresses, ports, and urls can't be looked up, so overriding them is a mistake
+fn validate_host(host: &str) -> Result<(), IpfsError> {
+    let invalid = |reason: &str| Err(IpfsError::DnsOverride(host.to_string(), reason.to_string()));
+    if host.contains(':') || host.contains('/') {
+        return invalid("not a plain domain name");
+    }
+    match Host::parse(host) {
+        Ok(Host::Domain(domain)) if domain == host.to_ascii_lowercase() => Ok(()),
+        Ok(Host::Domain(_)) => invalid("not a plain domain name"),
+        Ok(_) => invalid("already an address"),
+        Err(_) => invalid("not a valid domain name"),
+    }
+}
+
+/// Parse a `host=ip` override, or `host=` to stop overriding the host
+pub fn parse_dns_override(s: &str) -> Result<(String, Option<IpAddr>), IpfsError> {
+    let invalid = |reason: &str| IpfsError::DnsOverride(s.to_string(), reason.to_string());
+    let (host, ip) = s
+        .split_once('=')
+        .ok_or_else(|| invalid("expected host=ip"))?;
+    let ip = match ip.trim() {
+        "" => None,
+        ip => Some(ip.parse().map_err(|_| invalid("not an ip address"))?),
+    };
+    let host = host.trim().to_string();
+    validate_host(&host)?;
+    Ok((host, ip))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overrides(entries: &[(&str, &str)]) -> DnsOverrides {
+        DnsOverrides::from(
+            entries
+                .iter()
+                .map(|(host, ip)| (host.to_string(), ip.parse().unwrap()))
+                .collect::<BTreeMap<_, _>>(),
+        )
+    }
+
+    #[test]
+    fn overrides_cover_subdomains() {
+        let overrides = overrides(&[
+            ("gateway.internal", "10.0.0.1"),
+            ("api.gateway.internal", "10.0.0.2"),
+        ]);
+        assert!(overrides.validate().is_ok());
+        let ip = |s: &str| s.parse::<IpAddr>().ok();
+        assert_eq!(overrides.lookup("gateway.internal"), ip("10.0.0.1"));
+        assert_eq!(overrides.lookup("Gateway.Internal."), ip("10.0.0.1"));
+        assert_eq!(
+            overrides.lookup("bafy.ipfs.gateway.internal"),
+            ip("10.0.0.1")
+        );
+        assert_eq!(overrides.lookup("api.gateway.internal"), ip("10.0.0.2"));
+        assert_eq!(overrides.lookup("other.internal"), None);
+        assert_eq!(overrides.lookup("notgateway.internal"), None);
+
+        let url = Url::parse("https://bafy.ipfs.gateway.internal/file").unwrap();
+        assert_eq!(
+            overrides.socket_addr(&url),
+            Some("10.0.0.1:443".parse().unwrap())
+        );
+        let url = Url::parse("http://10.0.0.9:5001").unwrap();
+        assert_eq!(overrides.socket_addr(&url), None);
+    }
+
+    #[test]
+    fn invalid_overrides_are_errors() {
+        for host in [
+            "127.0.0.1",
+            "[::1]",
+            "api.internal:5001",
+            "http://api.internal",
+            "",
+        ] {
+            assert!(
+                matches!(
+                    overrides(&[(host, "10.0.0.1")]).validate(),
+                    Err(IpfsError::DnsOverride(_, _))
+                ),
+                "{} should be rejected",
+                host
+            );
+        }
+        assert!(parse_dns_override("api.internal=10.0.0.1").is_ok());
+        assert!(matches!(parse_dns_override("api.internal="), Ok((_, None))));
+        assert!(parse_dns_override("api.internal=nowhere").is_err());
+        assert!(parse_dns_override("api.internal").is_err());
+    }
+}
@@ -0,0 +1,164 @@
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use cid::Cid;
+use serde::{Deserialize, Serialize};
+
+use super::{IpfsApi, IpfsClient, IpfsClientError, IpfsError, IpfsRemote};
+use crate::device::root_cid_store::{RootCidStore, RootCidStoreError, RootUpdateReceipt};
+
+/// Default lifetime of published IPNS records
+/// Records need to be republished before they expire -- kubo does this
+/// automatically for keys it holds, so we can afford to be generous
+const DEFAULT_IPNS_LIFETIME: &str = "8760h";
+/// Default time to wait on publishing or resolving a name
+/// These hit the DHT, and can be very slow
+const DEFAULT_IPNS_TIMEOUT_SECS: u64 = 120;
+
+/// Configuration for publishing a root cid against an IPNS key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IpnsRemote {
+    /// Name of the key on the IPFS api to publish against
+    pub key: String,
+    /// Lifetime of published records (i.e. "24h")
+    #[serde(default = "default_lifetime")]
+    pub lifetime: String,
+    /// Optional ttl hint for resolvers caching our records
+    #[serde(default)]
+    pub ttl: Option<String>,
+    /// How long to wait on publishing or resolving before giving up
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_lifetime() -> String {
+    DEFAULT_IPNS_LIFETIME.to_string()
+}
+
+fn default_timeout_secs() -> u64 {
+    DEFAULT_IPNS_TIMEOUT_SECS
+}
+
+impl IpnsRemote {
+    pub fn new(key: String, lifetime: Option<String>) -> Self {
+        Self {
+            key,
+            lifetime: lifetime.unwrap_or_else(default_lifetime),
+            ttl: None,
+            timeout_secs: default_timeout_secs(),
+        }
+    }
+}
+
+impl Display for IpnsRemote {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "key: {}, lifetime: {}", self.key, self.lifetime)
+    }
+}
+
+/// A RootCidStore backed by an IPNS key on an IPFS api
+#[derive(Clone)]
+pub struct IpnsRootStore {
+    client: IpfsClient,
+    remote: IpnsRemote,
+}
+
+impl IpnsRootStore {
+    pub fn new(ipfs_remote: IpfsRemote, remote: IpnsRemote) -> Result<Self, IpfsError> {
+        let client = IpfsClient::try_from(ipfs_remote)?;
+        Ok(Self { client, remote })
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(self.remote.timeout_secs)
+    }
+
+    /// Look up the IPNS name (key id) for our configured key
+    async fn name(&self) -> Result<String, IpnsError> {
+        let keys = self.client.key_list().await?;
+        let key = keys
+            .keys
+            .into_iter()
+            .find(|k| k.name == self.remote.key)
+            .ok_or(IpnsError::MissingKey(self.remote.key.clone()))?;
+        Ok(key.id)
+    }
+
+    /// Resolve the root cid currently published under our key
+    /// Returns the default cid if nothing has been published yet
+    pub async fn read(&self) -> Result<Cid, IpnsError> {
+        let name = self.name().await?;
+        // Skip the cache -- we always want the latest record for comparing roots
+        let resolve = self.client.name_resolve(Some(&name), true, true);
+        let resolved = match tokio::time::timeout(self.timeout(), resolve).await {
+            Ok(Ok(resolved)) => resolved,
+            Ok(Err(IpfsClientError::Api(api_error)))
+                if api_error.message.contains("could not resolve name") =>
+            {
+                return Ok(Cid::default());
+            }
+            Ok(Err(e)) => return Err(e.into()),
+            Err(_) => return Err(IpnsError::Timeout(name)),
+        };
+        let cid_str = resolved.path.trim_start_matches("/ipfs/");
+        let cid = Cid::from_str(cid_str)?;
+        Ok(cid)
+    }
+
+    /// Publish a new root cid under our key
+    /// Refuses to do so unless previous_cid is what is currently published
+    pub async fn update(&self, previous_cid: Cid, cid: Cid) -> Result<(), IpnsError> {
+        let current_cid = self.read().await?;
+        if current_cid != previous_cid {
+            return Err(IpnsError::MissmatchedRootCid(previous_cid, current_cid));
+        }
+
+        let path = format!("/ipfs/{}", cid);
+        // Don't ask the node to resolve the path -- we already pinned it, and
+        // resolution is just another slow network round trip
+        let publish = self.client.name_publish(
+            &path,
+            false,
+            Some(&self.remote.lifetime),
+            self.remote.ttl.as_deref(),
+            Some(&self.remote.key),
+        );
+        match tokio::time::timeout(self.timeout(), publish).await {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(e)) => Err(e.into()),
+            Err(_) => Err(IpnsError::Timeout(self.remote.key.clone())),
+        }
+    }
+}
+
+#[async_trait]
+impl RootCidStore for IpnsRootStore {
+    async fn read(&self) -> Result<Cid, RootCidStoreError> {
+        Ok(IpnsRootStore::read(self).await?)
+    }
+
+    async fn update(
+        &self,
+        previous_cid: Cid,
+        cid: Cid,
+    ) -> Result<Option<RootUpdateReceipt>, RootCidStoreError> {
+        IpnsRootStore::update(self, previous_cid, cid).await?;
+        Ok(None)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum IpnsError {
+    #[error("ipfs error: {0}")]
+    IpfsClient(#[from] IpfsClientError),
+    #[error("cid error: {0}")]
+    Cid(#[from] cid::Error),
+    #[error("key not found on remote: {0}")]
+    MissingKey(String),
+    #[error("timed out waiting on ipns: {0}")]
+    Timeout(String),
+    #[error("missmatched root cid: expected {0}, name has {1}")]
+    MissmatchedRootCid(Cid, Cid),
+}
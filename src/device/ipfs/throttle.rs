@@ -0,0 +1,79 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures_util::stream::Stream;
+use tokio::time::{sleep, Instant, Sleep};
+
+/// Limits the throughput of a stream of byte chunks to roughly a number of bytes per second
+/// Chunks are passed through whole -- each one is just held back until the running
+/// average is back under the limit. Without a limit the stream is passed through as is
+pub struct Throttle<S: Stream> {
+    inner: Pin<Box<S>>,
+    bytes_per_sec: Option<u64>,
+    start: Instant,
+    sent: u64,
+    /// A chunk waiting on the delay before it can be passed on
+    held: Option<(S::Item, Pin<Box<Sleep>>)>,
+}
+
+impl<S: Stream> Throttle<S> {
+    pub fn new(inner: S, bytes_per_sec: Option<u64>) -> Self {
+        Self {
+            inner: Box::pin(inner),
+            bytes_per_sec: bytes_per_sec.filter(|bps| *bps > 0),
+            start: Instant::now(),
+            sent: 0,
+            held: None,
+        }
+    }
+}
+
+// The inner stream and delay are boxed, and held chunks are never pinned
+impl<S: Stream> Unpin for Throttle<S> {}
+
+impl<S, T, E> Stream for Throttle<S>
+where
+    S: Stream<Item = Result<T, E>>,
+    T: AsRef<[u8]>,
+{
+    type Item = Result<T, E>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+
+        if let Some((chunk, mut delay)) = this.held.take() {
+            if delay.as_mut().poll(cx).is_pending() {
+                this.held = Some((chunk, delay));
+                return Poll::Pending;
+            }
+            return Poll::Ready(Some(chunk));
+        }
+
+        let chunk = match this.inner.as_mut().poll_next(cx) {
+            Poll::Ready(Some(chunk)) => chunk,
+            other => return other,
+        };
+        let bytes_per_sec = match this.bytes_per_sec {
+            Some(bps) => bps,
+            None => return Poll::Ready(Some(chunk)),
+        };
+        if let Ok(bytes) = &chunk {
+            this.sent += bytes.as_ref().len() as u64;
+        }
+
+        // How long sending this much should have taken us
+        let due = Duration::from_secs_f64(this.sent as f64 / bytes_per_sec as f64);
+        let elapsed = this.start.elapsed();
+        if due <= elapsed {
+            return Poll::Ready(Some(chunk));
+        }
+        let mut delay = Box::pin(sleep(due - elapsed));
+        if delay.as_mut().poll(cx).is_ready() {
+            return Poll::Ready(Some(chunk));
+        }
+        this.held = Some((chunk, delay));
+        Poll::Pending
+    }
+}
@@ -0,0 +1,332 @@
+use std::collections::BTreeMap;
+use std::path::{Component, PathBuf};
+
+use cid::multihash::Multihash;
+use cid::Cid;
+
+use super::{hash_offline, IpfsError, DEFAULT_CHUNK_SIZE, DEFAULT_HASH_CODE};
+
+/// Multicodec code for dag-pb nodes
+const DAG_PB_CODEC: u64 = 0x70;
+/// Most links the node puts in a single unix-fs file node -- kubo's balanced layout default
+const MAX_LINKS: usize = 174;
+/// Unix-fs data type of a directory node
+const UNIXFS_DIRECTORY_TYPE: u64 = 1;
+/// Unix-fs data type of a file node
+const UNIXFS_FILE_TYPE: u64 = 2;
+
+/// A block within a unix-fs file dag, as seen by its parent
+#[derive(Debug, Clone, Copy)]
+pub struct DagLink {
+    pub cid: Cid,
+    /// Size of the block plus everything below it
+    pub tsize: u64,
+    /// Bytes of file content below the block
+    pub filesize: u64,
+}
+
+/// Describe a chunk of file content as a raw leaf
+pub fn leaf(chunk: &[u8]) -> Result<DagLink, IpfsError> {
+    Ok(DagLink {
+        cid: hash_offline(chunk)?,
+        tsize: chunk.len() as u64,
+        filesize: chunk.len() as u64,
+    })
+}
+
+/// Assemble raw leaves into a balanced unix-fs file dag, the same way the node lays
+/// out a file it adds with raw leaves
+/// A single leaf is its own root
+/// # Args
+/// - leaves: the file's chunks, in order. Must not be empty
+/// # Returns the encoded intermediate nodes, lowest level first, and the root of the dag
+pub fn file_dag(leaves: Vec<DagLink>) -> Result<(Vec<(Cid, Vec<u8>)>, Cid), IpfsError> {
+    let mut nodes = Vec::new();
+    let mut level = leaves;
+    while level.len() > 1 {
+        let mut next_level = Vec::new();
+        for children in level.chunks(MAX_LINKS) {
+            let (block, link) = file_node(children)?;
+            nodes.push((link.cid, block));
+            next_level.push(link);
+        }
+        level = next_level;
+    }
+    let root = level.first().ok_or(IpfsError::EmptyDag)?.cid;
+    Ok((nodes, root))
+}
+
+/// Works out the cid the node gives a file as its content arrives, without
+/// holding more than a chunk of it
+/// Links are folded into their parent as soon as a node's worth of them is in, so
+/// only the unfinished node at each level of the dag is held -- a few KiB for
+/// every 174x the file grows, however big it gets
+#[derive(Debug, Default)]
+pub struct FileHasher {
+    /// Content not yet filling a whole chunk
+    pending: Vec<u8>,
+    /// Links not yet folded into a parent, by level -- leaves first
+    levels: Vec<Vec<DagLink>>,
+}
+
+impl FileHasher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed the next bytes of the file in
+    pub fn update(&mut self, mut data: &[u8]) -> Result<(), IpfsError> {
+        while !data.is_empty() {
+            let take = (DEFAULT_CHUNK_SIZE - self.pending.len()).min(data.len());
+            self.pending.extend_from_slice(&data[..take]);
+            data = &data[take..];
+            if self.pending.len() == DEFAULT_CHUNK_SIZE {
+                let leaf = leaf(&self.pending)?;
+                self.push_link(0, leaf)?;
+                self.pending.clear();
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a link at a level, folding the level into a parent once it's full
+    /// Full nodes come out exactly as file_dag's, which chunks each level from the left
+    fn push_link(&mut self, level: usize, link: DagLink) -> Result<(), IpfsError> {
+        if self.levels.len() == level {
+            self.levels.push(Vec::new());
+        }
+        self.levels[level].push(link);
+        if self.levels[level].len() == MAX_LINKS {
+            let children = std::mem::take(&mut self.levels[level]);
+            let (_block, parent) = file_node(&children)?;
+            self.push_link(level + 1, parent)?;
+        }
+        Ok(())
+    }
+
+    /// How many links are held, waiting on the rest of their node
+    pub fn held_links(&self) -> usize {
+        self.levels.iter().map(Vec::len).sum()
+    }
+
+    /// The cid of everything fed in
+    pub fn finish(mut self) -> Result<Cid, IpfsError> {
+        // An empty file is still a single, empty leaf
+        if !self.pending.is_empty() || self.levels.is_empty() {
+            let leaf = leaf(&self.pending)?;
+            self.push_link(0, leaf)?;
+        }
+        // Fold what's left bottom up. A lone link on the top level is the root; below
+        // it, even a single link gets a parent, as it would in file_dag
+        let mut level = 0;
+        loop {
+            let top = level + 1 == self.levels.len();
+            let links = std::mem::take(&mut self.levels[level]);
+            match links.len() {
+                0 if top => return Err(IpfsError::EmptyDag),
+                0 => {}
+                1 if top => return Ok(links[0].cid),
+                _ => {
+                    let (_block, parent) = file_node(&links)?;
+                    if top {
+                        self.levels.push(Vec::new());
+                    }
+                    self.levels[level + 1].push(parent);
+                }
+            }
+            level += 1;
+        }
+    }
+}
+
+/// An entry within a directory being assembled
+enum DirectoryEntry {
+    File(Cid),
+    Directory(BTreeMap<String, DirectoryEntry>),
+}
+
+/// Assemble a unix-fs directory dag linking each cid at its path, so the paths
+/// resolve through a gateway as `<root>/<path>`
+/// Links don't carry a Tsize, so the dag can be built without knowing how big
+/// the linked dags are. Directories aren't sharded, so very large ones may
+/// produce blocks bigger than some nodes accept
+/// # Args
+/// - entries: cids by their relative path. A path can't be both a file and a directory
+/// # Returns the encoded directory nodes, deepest first, and the root of the dag
+pub fn directory_dag(
+    entries: &BTreeMap<PathBuf, Cid>,
+) -> Result<(Vec<(Cid, Vec<u8>)>, Cid), IpfsError> {
+    let mut root = BTreeMap::new();
+    for (path, cid) in entries {
+        let names: Vec<String> = path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+                _ => None,
+            })
+            .collect();
+        let (file_name, dir_names) = names
+            .split_last()
+            .ok_or(IpfsError::InvalidDirectoryEntry(path.clone()))?;
+        let mut dir = &mut root;
+        for name in dir_names {
+            let entry = dir
+                .entry(name.clone())
+                .or_insert_with(|| DirectoryEntry::Directory(BTreeMap::new()));
+            dir = match entry {
+                DirectoryEntry::Directory(dir) => dir,
+                DirectoryEntry::File(_) => {
+                    return Err(IpfsError::InvalidDirectoryEntry(path.clone()))
+                }
+            };
+        }
+        if dir
+            .insert(file_name.clone(), DirectoryEntry::File(*cid))
+            .is_some()
+        {
+            return Err(IpfsError::InvalidDirectoryEntry(path.clone()));
+        }
+    }
+
+    let mut nodes = Vec::new();
+    let cid = directory_node(&root, &mut nodes)?;
+    Ok((nodes, cid))
+}
+
+/// Encode a directory node, after the directories below it
+fn directory_node(
+    entries: &BTreeMap<String, DirectoryEntry>,
+    nodes: &mut Vec<(Cid, Vec<u8>)>,
+) -> Result<Cid, IpfsError> {
+    // Links are sorted by name, which a BTreeMap of Strings gives us already
+    let mut block = Vec::new();
+    for (name, entry) in entries {
+        let cid = match entry {
+            DirectoryEntry::File(cid) => *cid,
+            DirectoryEntry::Directory(dir) => directory_node(dir, nodes)?,
+        };
+        let mut link = Vec::new();
+        put_bytes_field(&mut link, 1, &cid.to_bytes());
+        put_bytes_field(&mut link, 2, name.as_bytes());
+        put_bytes_field(&mut block, 2, &link);
+    }
+    let mut data = Vec::new();
+    put_varint_field(&mut data, 1, UNIXFS_DIRECTORY_TYPE);
+    put_bytes_field(&mut block, 1, &data);
+
+    let digest = blake3::hash(&block);
+    let hash = Multihash::wrap(DEFAULT_HASH_CODE, digest.as_bytes())?;
+    let cid = Cid::new_v1(DAG_PB_CODEC, hash);
+    nodes.push((cid, block));
+    Ok(cid)
+}
+
+/// Encode a dag-pb file node linking to its children
+fn file_node(children: &[DagLink]) -> Result<(Vec<u8>, DagLink), IpfsError> {
+    let filesize: u64 = children.iter().map(|child| child.filesize).sum();
+
+    // Unix-fs Data message: Type, filesize, then a blocksize per child
+    let mut data = Vec::new();
+    put_varint_field(&mut data, 1, UNIXFS_FILE_TYPE);
+    put_varint_field(&mut data, 3, filesize);
+    for child in children {
+        put_varint_field(&mut data, 4, child.filesize);
+    }
+
+    // PBNode: Links come before Data in the canonical encoding
+    let mut block = Vec::new();
+    for child in children {
+        let mut link = Vec::new();
+        put_bytes_field(&mut link, 1, &child.cid.to_bytes());
+        put_bytes_field(&mut link, 2, &[]);
+        put_varint_field(&mut link, 3, child.tsize);
+        put_bytes_field(&mut block, 2, &link);
+    }
+    put_bytes_field(&mut block, 1, &data);
+
+    let digest = blake3::hash(&block);
+    let hash = Multihash::wrap(DEFAULT_HASH_CODE, digest.as_bytes())?;
+    let tsize = block.len() as u64 + children.iter().map(|child| child.tsize).sum::<u64>();
+    let link = DagLink {
+        cid: Cid::new_v1(DAG_PB_CODEC, hash),
+        tsize,
+        filesize,
+    };
+    Ok((block, link))
+}
+
+fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn put_varint_field(buf: &mut Vec<u8>, field: u64, value: u64) {
+    put_varint(buf, field << 3);
+    put_varint(buf, value);
+}
+
+fn put_bytes_field(buf: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    put_varint(buf, (field << 3) | 2);
+    put_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A stand in for a leaf, so dags many levels deep don't need gigabytes of content
+    fn fake_leaf(i: u32) -> DagLink {
+        DagLink {
+            cid: hash_offline(&i.to_be_bytes()).unwrap(),
+            tsize: DEFAULT_CHUNK_SIZE as u64,
+            filesize: DEFAULT_CHUNK_SIZE as u64,
+        }
+    }
+
+    #[test]
+    fn streamed_dags_match_file_dag_in_bounded_memory() {
+        // Enough leaves for three levels of nodes, with partial nodes on each
+        let count = (MAX_LINKS * MAX_LINKS + MAX_LINKS + 3) as u32;
+        for count in [1, 2, MAX_LINKS as u32, MAX_LINKS as u32 + 1, count] {
+            let leaves = (0..count).map(fake_leaf).collect::<Vec<_>>();
+            let mut hasher = FileHasher::new();
+            for leaf in leaves.iter() {
+                hasher.push_link(0, *leaf).unwrap();
+                assert!(hasher.held_links() < MAX_LINKS * hasher.levels.len());
+            }
+            let (_nodes, expected) = file_dag(leaves).unwrap();
+            assert_eq!(hasher.finish().unwrap(), expected, "{} leaves", count);
+        }
+    }
+
+    #[test]
+    fn streamed_content_matches_however_it_arrives() {
+        let data = (0..DEFAULT_CHUNK_SIZE * 3 + 17)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let leaves = data
+            .chunks(DEFAULT_CHUNK_SIZE)
+            .map(|chunk| leaf(chunk).unwrap())
+            .collect();
+        let (_nodes, expected) = file_dag(leaves).unwrap();
+
+        let mut whole = FileHasher::new();
+        whole.update(&data).unwrap();
+        let mut pieces = FileHasher::new();
+        for piece in data.chunks(1000) {
+            pieces.update(piece).unwrap();
+        }
+        assert_eq!(whole.finish().unwrap(), expected);
+        assert_eq!(pieces.finish().unwrap(), expected);
+
+        assert_eq!(hash_offline(&data).unwrap(), expected);
+
+        let mut empty = FileHasher::new();
+        empty.update(&[]).unwrap();
+        assert_eq!(empty.finish().unwrap(), hash_offline(&[]).unwrap());
+    }
+}
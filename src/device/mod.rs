@@ -1,19 +1,285 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
+use std::future::Future;
 use std::io::Cursor;
 use std::io::Read;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
+use chrono::{DateTime, TimeZone, Utc};
 use cid::Cid;
 use ethers::signers::LocalWallet;
-use ethers::types::Address;
-use futures_util::stream::TryStreamExt;
+use ethers::types::{Address, Bytes, U256};
+use futures_util::stream::{BoxStream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
 
+mod block_source;
+mod block_verifier;
+mod cache;
 mod eth;
 mod ipfs;
+mod root_cid_store;
+mod s3;
 
-pub use eth::{EthClient, EthClientError, EthRemote, RootCid};
-pub use ipfs::{IpfsApi, IpfsClient, IpfsClientError, IpfsError, IpfsGateway, IpfsRemote};
+pub use block_source::{
+    block_links, find_pb_link, open_block_source, read_unixfs_file, verify_block, BlockSource,
+    BlockSourceError, CarBlockSource, DirBlockSource,
+};
+pub use block_verifier::{BlockVerifier, CidVerifier};
+pub use cache::{CacheError, CachePolicy, ContentCache};
+pub use eth::{
+    parse_address, parse_eth_mirror, ConfirmUpdates, EthClient, EthClientError, EthRemote, RootCid,
+    RootCidAbi, RootCidError, RootUpdate, DEFAULT_RECEIPT_MAX_ATTEMPTS,
+    DEFAULT_RECEIPT_POLL_INTERVAL_SECS,
+};
+pub use ipfs::{
+    hash_offline, parse_dns_override, AddProgress, CidParams, DirEntry, DnsOverrides, FileHasher,
+    IpfsApi, IpfsClient, IpfsClientError, IpfsError, IpfsGateway, IpfsRemote, IpnsError,
+    IpnsRemote, IpnsRootStore, RemotePinStatus,
+};
+pub use root_cid_store::{
+    FileRootStore, RootCidStore, RootCidStoreError, RootFallback, RootReconcile, RootTarget,
+    RootUpdateReceipt,
+};
+pub use s3::S3Remote;
+#[cfg(feature = "s3")]
+pub use s3::{S3Store, S3StoreError};
 
-use crate::types::DorStore;
+use crate::types::{migrate_manifest, DeltaManifest, DorStore, ManifestVersionError};
+
+/// How far back to look through a root's history when reconciling diverging roots
+const ROOT_HISTORY_DEPTH: usize = 64;
+/// Name of the manifest within a directory root
+pub const MANIFEST_NAME: &str = ".dor-store.json";
+/// Name of the link from a delta root to the root its manifest is a delta on top of
+pub const DELTA_OF_NAME: &str = ".dor-store.delta-of";
+/// How many deltas deep a manifest can be written -- the next one is written whole
+pub const MAX_DELTA_DEPTH: usize = 64;
+/// Multicodec code for dag-pb blocks
+pub const DAG_PB_CODEC: u64 = 0x70;
+/// Multicodec code for raw blocks
+const RAW_CODEC: u64 = 0x55;
+/// How many times to retry putting a single block during a resumable upload
+const UPLOAD_RETRIES: u32 = 4;
+/// How long to wait before the first block retry, doubling on each one after
+const UPLOAD_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Leading bytes of a zstd frame, which no json manifest starts with
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+/// Compression level for zstd manifests -- the library's default
+const ZSTD_LEVEL: i32 = 3;
+
+/// Whether a cid could be a root we published: a manifest or a directory, hashed
+/// with the params we add data with
+pub fn is_root_cid(cid: &Cid) -> bool {
+    CidParams::of(cid) == CidParams::default()
+        && (cid.codec() == RAW_CODEC || cid.codec() == DAG_PB_CODEC)
+}
+
+/// What a root cid points at
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum RootLayout {
+    /// The json manifest itself
+    #[default]
+    Manifest,
+    /// A unix-fs directory linking every object at its path, with the manifest
+    /// alongside them as MANIFEST_NAME. Any gateway can serve objects by path
+    Directory,
+}
+
+/// How manifests are encoded before they're added
+/// Cids of compressed manifests are of the compressed bytes. Either encoding can
+/// always be read -- compressed manifests are told apart by their frame header
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum ManifestCompression {
+    /// Plain json
+    #[default]
+    None,
+    /// A zstd frame of the json
+    Zstd,
+}
+
+impl std::fmt::Display for ManifestCompression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::None => "none",
+            Self::Zstd => "zstd",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// A manifest as it was added: whole, or as a delta on top of its previous root's
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Manifest {
+    Full(DorStore),
+    Delta(DeltaManifest),
+}
+
+/// Decode a manifest as it was added, compressed or not, whole or a delta
+/// Manifests written with older schema versions are migrated, and ones written with
+/// newer versions than we know are refused rather than mis-read
+pub fn decode_manifest(data: &[u8]) -> Result<Manifest, DeviceError> {
+    let value: serde_json::Value = if data.starts_with(&ZSTD_MAGIC) {
+        let data = zstd::decode_all(data)?;
+        serde_json::from_slice(&data)?
+    } else {
+        serde_json::from_slice(data)?
+    };
+    let (value, _) = migrate_manifest(value)?;
+    if value.get("delta_of").is_some() {
+        return Ok(Manifest::Delta(serde_json::from_value(value)?));
+    }
+    Ok(Manifest::Full(serde_json::from_value(value)?))
+}
+
+/// Decode a whole manifest as it was added, compressed or not
+/// Deltas can't be read on their own -- read them by root, see Device::read_dor_store
+pub fn decode_dor_store(data: &[u8]) -> Result<DorStore, DeviceError> {
+    match decode_manifest(data)? {
+        Manifest::Full(dor_store) => Ok(dor_store),
+        Manifest::Delta(delta) => Err(DeviceError::UnresolvedDelta(*delta.delta_of())),
+    }
+}
+
+/// Note the next manifest along a chain of deltas behind `root`, refusing chains
+/// that loop or run deeper than MAX_DELTA_DEPTH
+pub fn check_delta_chain(
+    root: &Cid,
+    cid: &Cid,
+    seen: &mut HashSet<Cid>,
+) -> Result<(), DeviceError> {
+    if seen.len() > MAX_DELTA_DEPTH {
+        return Err(DeviceError::DeltaChainTooLong(*root, MAX_DELTA_DEPTH));
+    }
+    if !seen.insert(*cid) {
+        return Err(DeviceError::DeltaCycle(*root, *cid));
+    }
+    Ok(())
+}
+
+impl std::fmt::Display for RootLayout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Manifest => "manifest",
+            Self::Directory => "directory",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// A root carried over from one RootCid contract to another
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RootMigration {
+    pub root_cid: Cid,
+    pub from_chain_id: u16,
+    pub from_contract_address: Address,
+    /// The transaction that set the root on the old contract, if it could be found
+    pub from_tx_hash: Option<String>,
+    pub to_chain_id: u16,
+    pub to_contract_address: Address,
+    /// The transaction that seeded the new contract, if it could be found
+    pub to_tx_hash: Option<String>,
+}
+
+impl std::fmt::Display for RootMigration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "root: {}\nfrom: chain {} contract {:?} (tx: {})\nto: chain {} contract {:?} (tx: {})",
+            self.root_cid,
+            self.from_chain_id,
+            self.from_contract_address,
+            self.from_tx_hash.as_deref().unwrap_or("unknown"),
+            self.to_chain_id,
+            self.to_contract_address,
+            self.to_tx_hash.as_deref().unwrap_or("unknown"),
+        )
+    }
+}
+
+/// The on chain update that set a root, as found in the contract's events
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootUpdateLog {
+    pub tx_hash: String,
+    pub block_number: Option<u64>,
+    /// When the update's block was produced
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+/// Whether a block is available on an ipfs node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockStatus {
+    /// The block is on the node, or pinned by its pinning service
+    Present,
+    /// The node's pinning service is still pinning the block
+    Pending,
+    /// The block isn't on the node, and isn't being pinned
+    Absent,
+}
+
+/// How a bulk unpin went
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UnpinReport {
+    /// Cids no longer pinned, including any that weren't pinned to begin with
+    pub unpinned: Vec<Cid>,
+    /// Cids the node failed to unpin, with why
+    pub failed: Vec<(Cid, String)>,
+    /// Cids we never heard back about before the timeout
+    pub timed_out: Vec<Cid>,
+}
+
+impl UnpinReport {
+    /// Whether every cid was unpinned
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty() && self.timed_out.is_empty()
+    }
+}
+
+/// Run an unpin for each distinct cid, a bounded number at a time, sorting how each went
+/// # Args
+/// - unpin: unpins a single cid
+async fn unpin_each<F, Fut, E>(
+    cids: &[Cid],
+    concurrency: usize,
+    timeout: Duration,
+    unpin: F,
+) -> UnpinReport
+where
+    F: Fn(Cid) -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+    E: std::fmt::Display,
+{
+    let mut seen = HashSet::new();
+    let cids: Vec<Cid> = cids
+        .iter()
+        .copied()
+        .filter(|cid| seen.insert(*cid))
+        .collect();
+    let mut outstanding: HashSet<Cid> = cids.iter().copied().collect();
+    let mut report = UnpinReport::default();
+
+    let deadline = tokio::time::Instant::now() + timeout;
+    let unpin = &unpin;
+    let mut unpins = futures_util::stream::iter(cids.iter().copied())
+        .map(|cid| async move { (cid, unpin(cid).await) })
+        .buffer_unordered(concurrency.max(1));
+    while let Ok(Some((cid, result))) = tokio::time::timeout_at(deadline, unpins.next()).await {
+        outstanding.remove(&cid);
+        match result {
+            Ok(()) => report.unpinned.push(cid),
+            Err(e) => report.failed.push((cid, e.to_string())),
+        }
+    }
+    report.timed_out = cids
+        .into_iter()
+        .filter(|cid| outstanding.contains(cid))
+        .collect();
+    report
+}
 
 /// Union of IPFS and Ethereum clients for coordinating pushing and pulling
 /// dor-store updates to and from remote infrastructure.
@@ -33,6 +299,64 @@ pub struct Device {
     eth: EthClient,
     /// LocalWallet for signing RootCid updates
     wallet: LocalWallet,
+    /// Optional IPNS key to publish our root cid against
+    ipns: Option<IpnsRootStore>,
+    /// Which RootCidStore(s) to read and publish our root cid against
+    root_target: RootTarget,
+    /// Our RootCid contract on other chains, by client and address. Read alongside
+    /// it, and brought up to roots we publish
+    mirrors: Vec<(EthClient, Address)>,
+    /// How to pick a root when our mirrors disagree
+    root_reconcile: RootReconcile,
+    /// Optional store to read our root cid from when the primary can't be read
+    fallback: Option<Box<dyn RootCidStore>>,
+    /// Optional offline source to read manifests and objects from, in place of
+    /// the remote and gateway
+    block_source: Option<Box<dyn BlockSource>>,
+    /// Policy every block we read by its cid has to pass
+    block_verifier: Arc<dyn BlockVerifier>,
+    /// Whether to read objects from the gateway block by block, so each block goes
+    /// through block_verifier -- set along with a verifier of our own
+    verify_gateway_blocks: bool,
+    /// Optional on disk cache of objects read from the gateway
+    cache: Option<ContentCache>,
+    /// What the roots we write point at
+    root_layout: RootLayout,
+    /// How the manifests we write are encoded
+    manifest_compression: ManifestCompression,
+    /// Whether to write manifests as deltas on top of their previous root's
+    delta_manifests: bool,
+    /// A root and its whole manifest, so writing a delta on top of it doesn't have
+    /// to read it back
+    delta_base: Option<(Cid, DorStore)>,
+}
+
+/// Read the root cid from each of a set of stores, skipping any that can't be read
+/// # Args
+/// - stores: each store, by a name to warn about it with
+/// # Returns the roots read, or why the first store couldn't be if none could
+async fn read_roots(
+    stores: Vec<(String, Box<dyn RootCidStore>)>,
+) -> Result<Vec<Cid>, RootCidStoreError> {
+    let mut roots = Vec::new();
+    let mut first_error = None;
+    for (name, store) in stores {
+        match store.read().await {
+            Ok(root_cid) => roots.push(root_cid),
+            Err(e) => {
+                tracing::warn!(
+                    "failed to read the root cid from {}, skipping it: {}",
+                    name,
+                    e
+                );
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    match first_error {
+        Some(e) if roots.is_empty() => Err(e),
+        _ => Ok(roots),
+    }
 }
 
 /// One stop shop for coordinating interactions with a given remote configuration
@@ -52,6 +376,19 @@ impl Device {
             ipfs_client,
             ipfs_gateway,
             wallet,
+            ipns: None,
+            root_target: RootTarget::default(),
+            mirrors: Vec::new(),
+            root_reconcile: RootReconcile::default(),
+            fallback: None,
+            block_source: None,
+            block_verifier: Arc::new(CidVerifier),
+            verify_gateway_blocks: false,
+            cache: None,
+            root_layout: RootLayout::default(),
+            manifest_compression: ManifestCompression::default(),
+            delta_manifests: false,
+            delta_base: None,
         }
     }
 
@@ -61,16 +398,231 @@ impl Device {
         self
     }
 
+    /// Set the IPNS key and which RootCidStore(s) the device should target
+    pub fn with_ipns(mut self, ipns: Option<IpnsRootStore>, root_target: RootTarget) -> Self {
+        self.ipns = ipns;
+        self.root_target = root_target;
+        self
+    }
+
+    /// Read the root cid from mirrors as well, reconciling what they report, and
+    /// publish our roots to them
+    pub fn with_mirrors(
+        mut self,
+        mirrors: Vec<(EthClient, Address)>,
+        root_reconcile: RootReconcile,
+    ) -> Self {
+        self.mirrors = mirrors;
+        self.root_reconcile = root_reconcile;
+        self
+    }
+
+    /// Read the root cid from a fallback store when the primary can't be read
+    pub fn with_fallback(mut self, fallback: Option<Box<dyn RootCidStore>>) -> Self {
+        self.fallback = fallback;
+        self
+    }
+
+    /// Read manifests and objects from an offline BlockSource, rather than the remote
+    pub fn with_block_source(mut self, block_source: Option<Box<dyn BlockSource>>) -> Self {
+        self.block_source = block_source;
+        self
+    }
+
+    /// Check every block read by its cid against a verifier, rather than just its hash
+    /// Covers blocks read from the node, from our BlockSource, and from the gateway --
+    /// which objects are then fetched from a block at a time, rather than whole
+    pub fn with_block_verifier(mut self, block_verifier: Arc<dyn BlockVerifier>) -> Self {
+        self.block_verifier = block_verifier;
+        self.verify_gateway_blocks = true;
+        self
+    }
+
+    /// Cache objects read from the gateway on disk
+    pub fn with_cache(mut self, cache: Option<ContentCache>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Set what the roots we write point at
+    /// Roots of either layout can always be read
+    pub fn with_root_layout(mut self, root_layout: RootLayout) -> Self {
+        self.root_layout = root_layout;
+        self
+    }
+
+    /// Set how the manifests we write are encoded
+    /// Manifests of either encoding can always be read
+    pub fn with_manifest_compression(mut self, manifest_compression: ManifestCompression) -> Self {
+        self.manifest_compression = manifest_compression;
+        self
+    }
+
+    /// Write manifests as deltas on top of their previous root's manifest
+    /// Either can always be read
+    pub fn with_delta_manifests(mut self, delta_manifests: bool) -> Self {
+        self.delta_manifests = delta_manifests;
+        self
+    }
+
+    /// Set a root whose whole manifest we already hold, so deltas on top of it don't
+    /// read it back. Only saves a read -- what we write is the same without it
+    pub fn with_delta_base(mut self, delta_base: Option<(Cid, DorStore)>) -> Self {
+        self.delta_base = delta_base;
+        self
+    }
+
+    /// Encode a manifest the way we add it, along with the root it's a delta on top
+    /// of, if it's written as one
+    /// Only the manifest and its previous root decide this, so every device writing
+    /// deltas encodes a manifest the same way. It's written whole when it has no
+    /// previous root, when its previous root is already MAX_DELTA_DEPTH deltas deep,
+    /// or when it changes no objects, which is how a chain of deltas is squashed
+    async fn encode_dor_store(
+        &self,
+        dor_store: &DorStore,
+        remote: bool,
+    ) -> Result<(Vec<u8>, Option<Cid>), DeviceError> {
+        let delta = match self.delta_parent(dor_store, remote).await? {
+            Some((parent, depth)) => {
+                Some(dor_store.delta_from(&parent, depth + 1)).filter(|delta| !delta.is_empty())
+            }
+            None => None,
+        };
+        let (data, delta_of) = match delta {
+            Some(delta) => (serde_json::to_vec(&delta)?, Some(*delta.delta_of())),
+            None => (serde_json::to_vec(dor_store)?, None),
+        };
+        let data = match self.manifest_compression {
+            ManifestCompression::None => data,
+            ManifestCompression::Zstd => zstd::encode_all(data.as_slice(), ZSTD_LEVEL)?,
+        };
+        Ok((data, delta_of))
+    }
+
+    /// The whole manifest of the root a DorStore would be written as a delta on top
+    /// of, and how many deltas deep that root already is
+    async fn delta_parent(
+        &self,
+        dor_store: &DorStore,
+        remote: bool,
+    ) -> Result<Option<(Cow<'_, DorStore>, usize)>, DeviceError> {
+        let previous_root = dor_store.previous_root();
+        if !self.delta_manifests || *previous_root == Cid::default() {
+            return Ok(None);
+        }
+        let depth = self.manifest_depth(previous_root, remote).await?;
+        if depth >= MAX_DELTA_DEPTH {
+            return Ok(None);
+        }
+        let parent = match &self.delta_base {
+            Some((root_cid, base)) if root_cid == previous_root => Cow::Borrowed(base),
+            _ => Cow::Owned(self.read_dor_store(previous_root, remote).await?),
+        };
+        Ok(Some((parent, depth)))
+    }
+
     /* Dor Store Helpers */
 
     /// Read a Block by its Cid as a DorStore from Ipfs
+    /// Reads from our BlockSource instead, if one is set
+    /// Directory roots are followed to their manifest, and deltas are applied on top
+    /// of the nearest whole manifest along the previous_root chain
     /// # Args
     /// - cid: The cid of the DorStore object
     /// - remote: whether to read against the remote of local IPFS client
     pub async fn read_dor_store(&self, cid: &Cid, remote: bool) -> Result<DorStore, DeviceError> {
-        let dor_store_data = self.read_ipfs_data(cid, remote).await?;
-        let dor_store = serde_json::from_slice(&dor_store_data)?;
-        Ok(dor_store)
+        let root = *cid;
+        let mut deltas = Vec::new();
+        let mut seen = HashSet::new();
+        let mut cid = *cid;
+        let dor_store = loop {
+            check_delta_chain(&root, &cid, &mut seen)?;
+            match self.read_manifest(&cid, remote).await? {
+                Manifest::Full(dor_store) => break dor_store,
+                Manifest::Delta(delta) => {
+                    cid = *delta.delta_of();
+                    deltas.push(delta);
+                }
+            }
+        };
+        Ok(deltas
+            .iter()
+            .rev()
+            .fold(dor_store, |dor_store, delta| delta.apply(&dor_store)))
+    }
+
+    /// How many deltas a root's manifest is stacked on top of its nearest whole one,
+    /// counting its own -- 0 for a root with a whole manifest
+    pub async fn delta_depth(&self, cid: &Cid, remote: bool) -> Result<usize, DeviceError> {
+        let root = *cid;
+        let mut depth = 0;
+        let mut seen = HashSet::new();
+        let mut cid = *cid;
+        loop {
+            check_delta_chain(&root, &cid, &mut seen)?;
+            match self.read_manifest(&cid, remote).await? {
+                Manifest::Full(_) => return Ok(depth),
+                Manifest::Delta(delta) => {
+                    cid = *delta.delta_of();
+                    depth += 1;
+                }
+            }
+        }
+    }
+
+    /// How many deltas deep a root's manifest says it is, without walking the chain
+    /// -- 0 for a root with a whole manifest
+    async fn manifest_depth(&self, cid: &Cid, remote: bool) -> Result<usize, DeviceError> {
+        match self.read_manifest(cid, remote).await? {
+            Manifest::Full(_) => Ok(0),
+            Manifest::Delta(delta) => Ok(delta.depth()),
+        }
+    }
+
+    /// Follow a root to the manifest it points at, and the root it links as the one
+    /// its manifest is a delta on top of, if any
+    /// Roots that are just a manifest point at themselves
+    async fn resolve_root(
+        &self,
+        cid: &Cid,
+        remote: bool,
+    ) -> Result<(Cid, Option<Cid>), DeviceError> {
+        if cid.codec() != DAG_PB_CODEC {
+            return Ok((*cid, None));
+        }
+        let block = match &self.block_source {
+            Some(block_source) => {
+                let block = block_source.get_block(cid).await?;
+                self.check_block(cid, &block)?;
+                block
+            }
+            None => self.read_ipfs_data(cid, remote).await?,
+        };
+        // Large manifests added whole are dag-pb too, and link neither
+        let manifest_cid = find_pb_link(&block, MANIFEST_NAME)?.unwrap_or(*cid);
+        let delta_of = find_pb_link(&block, DELTA_OF_NAME)?;
+        Ok((manifest_cid, delta_of))
+    }
+
+    /// Read the manifest a root points at, as it was added
+    /// # Args
+    /// - cid: the root cid
+    /// - remote: whether to read against the remote of local IPFS client
+    pub async fn read_manifest(&self, cid: &Cid, remote: bool) -> Result<Manifest, DeviceError> {
+        let (manifest_cid, _) = self.resolve_root(cid, remote).await?;
+        let dor_store_data = match &self.block_source {
+            Some(block_source) => {
+                read_unixfs_file(
+                    block_source.as_ref(),
+                    &manifest_cid,
+                    self.block_verifier.as_ref(),
+                )
+                .await?
+            }
+            None => self.read_ipfs_data(&manifest_cid, remote).await?,
+        };
+        decode_manifest(&dor_store_data)
     }
 
     /// Write a DorStore as a block on Ipfs
@@ -82,10 +634,26 @@ impl Device {
         dor_store: &DorStore,
         remote: bool,
     ) -> Result<Cid, DeviceError> {
-        let dor_store_data = serde_json::to_vec(&dor_store)?;
-        let dor_store_data = Cursor::new(dor_store_data);
-        let cid = self.write_ipfs_data(dor_store_data, remote).await?;
-        Ok(cid)
+        let (dor_store_data, delta_of) = self.encode_dor_store(dor_store, remote).await?;
+        let cid = self
+            .write_ipfs_data(Cursor::new(dor_store_data), remote)
+            .await?;
+        if self.root_layout == RootLayout::Manifest && delta_of.is_none() {
+            return Ok(cid);
+        }
+
+        let objects = self.root_layout == RootLayout::Directory;
+        let (nodes, root_cid) = Self::root_directory(dor_store, cid, delta_of, objects)?;
+        for (node_cid, node) in nodes {
+            self.put_block(&node_cid, node, "dag-pb", remote).await?;
+        }
+        let client = if remote {
+            &self.ipfs_client
+        } else {
+            &self.local_ipfs_client
+        };
+        client.pin_add(&root_cid.to_string(), true).await?;
+        Ok(root_cid)
     }
 
     /// Hash a DorStore object against Ipfs
@@ -98,10 +666,109 @@ impl Device {
         dor_store: &DorStore,
         remote: bool,
     ) -> Result<Cid, DeviceError> {
-        let dor_store_data = serde_json::to_vec(&dor_store)?;
-        let dor_store_data = Cursor::new(dor_store_data);
-        let cid = self.hash_ipfs_data(dor_store_data, remote).await?;
-        Ok(cid)
+        let (dor_store_data, delta_of) = self.encode_dor_store(dor_store, remote).await?;
+        let cid = self
+            .hash_ipfs_data(Cursor::new(dor_store_data), remote)
+            .await?;
+        if self.root_layout == RootLayout::Manifest && delta_of.is_none() {
+            return Ok(cid);
+        }
+        let objects = self.root_layout == RootLayout::Directory;
+        let (_nodes, root_cid) = Self::root_directory(dor_store, cid, delta_of, objects)?;
+        Ok(root_cid)
+    }
+
+    /// Build the directory root of a DorStore, given its manifest's cid
+    /// Roots of delta manifests are always directories, linking the root they're a
+    /// delta on top of as DELTA_OF_NAME so pinning one keeps the whole chain around
+    /// # Args
+    /// - delta_of: the root the manifest is a delta on top of, if it's a delta
+    /// - objects: whether to link every object at its path
+    fn root_directory(
+        dor_store: &DorStore,
+        manifest_cid: Cid,
+        delta_of: Option<Cid>,
+        objects: bool,
+    ) -> Result<(Vec<(Cid, Vec<u8>)>, Cid), DeviceError> {
+        let manifest_path = PathBuf::from(MANIFEST_NAME);
+        let delta_of_path = PathBuf::from(DELTA_OF_NAME);
+        for path in [&manifest_path, &delta_of_path] {
+            if dor_store.objects().contains_key(path) {
+                return Err(DeviceError::ReservedPath(path.clone()));
+            }
+        }
+        let mut entries: BTreeMap<PathBuf, Cid> = BTreeMap::new();
+        if objects {
+            entries.extend(
+                dor_store
+                    .objects()
+                    .iter()
+                    .map(|(path, object)| (path.clone(), *object.cid())),
+            );
+        }
+        entries.insert(manifest_path, manifest_cid);
+        if let Some(delta_of) = delta_of {
+            entries.insert(delta_of_path, delta_of);
+        }
+        Ok(ipfs::directory_dag(&entries)?)
+    }
+
+    /// Mirror a written root into MFS, so it can be browsed by path on the node
+    /// Roots that don't link their objects get a directory of their objects and
+    /// manifest built for them, which isn't pinned -- MFS keeps it around. The
+    /// root cid itself is unaffected
+    /// # Args
+    /// - dor_store: the DorStore the root was written from
+    /// - root_cid: the root cid written for it
+    /// - path: the MFS path to place the directory at, replacing what's there
+    /// - remote: whether to mirror against the remote or local IPFS client
+    pub async fn mirror_to_mfs(
+        &self,
+        dor_store: &DorStore,
+        root_cid: &Cid,
+        path: &str,
+        remote: bool,
+    ) -> Result<(), DeviceError> {
+        let directory_cid = match self.root_layout {
+            RootLayout::Directory => *root_cid,
+            RootLayout::Manifest => {
+                let (manifest_cid, delta_of) = self.resolve_root(root_cid, remote).await?;
+                let (nodes, directory_cid) =
+                    Self::root_directory(dor_store, manifest_cid, delta_of, true)?;
+                for (node_cid, node) in nodes {
+                    self.put_block(&node_cid, node, "dag-pb", remote).await?;
+                }
+                directory_cid
+            }
+        };
+        let client = if remote {
+            &self.ipfs_client
+        } else {
+            &self.local_ipfs_client
+        };
+        client.mfs_replace(&directory_cid, path).await?;
+        Ok(())
+    }
+
+    /// Check every object in a DorStore was produced with the cid params we add data with
+    /// Reports the first object that wasn't. External objects are pinned as they are,
+    /// never hashed by us, so they're left out
+    pub fn check_cid_params(&self, dor_store: &DorStore) -> Result<(), DeviceError> {
+        let expected = CidParams::default();
+        for (path, object) in dor_store.objects().iter() {
+            if object.is_external() {
+                continue;
+            }
+            let actual = CidParams::of(object.cid());
+            if actual != expected {
+                return Err(DeviceError::CidParamsMismatch {
+                    path: path.clone(),
+                    expected,
+                    actual,
+                });
+            }
+        }
+        Ok(())
     }
 
     /* Eth Helpers */
@@ -111,33 +778,377 @@ impl Device {
         self.eth.chain_id()
     }
 
-    /// Read the root cid from the eth remote
-    pub async fn read_root_cid(&self) -> Result<Cid, DeviceError> {
+    /// Get the address of the RootCid contract in use
+    pub fn contract_address(&self) -> Address {
+        self.contract_address
+    }
+
+    /// Check there's deployed bytecode at our contract address
+    /// Warns if the address looks like an EOA
+    pub async fn contract_deployed(&self) -> Result<bool, DeviceError> {
+        let deployed = self.eth.has_code(self.contract_address).await?;
+        if !deployed {
+            tracing::warn!(
+                "no contract deployed at {:?} -- is this an EOA?",
+                self.contract_address
+            );
+        }
+        Ok(deployed)
+    }
+
+    /// Read the root our RootCid contract held before its current one, if it keeps one
+    /// Ipns roots never do
+    pub async fn read_previous_root_cid(&self) -> Result<Option<Cid>, DeviceError> {
+        if self.root_target == RootTarget::Ipns {
+            return Ok(None);
+        }
         let root_cid = RootCid::new(self.eth.clone(), self.contract_address, None)?;
-        let root_cid = root_cid.read().await?;
-        Ok(root_cid)
+        Ok(root_cid.read_previous_root().await?)
     }
 
-    /// Update the root cid against the eth remote
+    /// Get the RootCidStores configured by our root target, with the target each is
+    /// The first store is the one we read from
     /// # Args
-    /// - previous_root_cid: the previously known root cid of the remote
-    /// - next_root_cid: the root cid to overwrite it with
-    pub async fn update_root_cid(
+    /// - signed: whether the stores should be able to publish updates
+    fn root_cid_stores(
+        &self,
+        signed: bool,
+    ) -> Result<Vec<(RootTarget, Box<dyn RootCidStore>)>, DeviceError> {
+        let mut stores: Vec<(RootTarget, Box<dyn RootCidStore>)> = Vec::new();
+        if self.root_target == RootTarget::Ipns {
+            let ipns = self.ipns.clone().ok_or(DeviceError::MissingIpns)?;
+            stores.push((RootTarget::Ipns, Box::new(ipns)));
+            return Ok(stores);
+        }
+
+        let signer = if signed {
+            Some(self.wallet.clone())
+        } else {
+            None
+        };
+        let root_cid = RootCid::new(self.eth.clone(), self.contract_address, signer)?;
+        stores.push((RootTarget::Eth, Box::new(root_cid)));
+        if self.root_target == RootTarget::Both {
+            let ipns = self.ipns.clone().ok_or(DeviceError::MissingIpns)?;
+            stores.push((RootTarget::Ipns, Box::new(ipns)));
+        }
+        Ok(stores)
+    }
+
+    /// Seed a freshly deployed RootCid contract with our current root, so the root's
+    /// history carries on from it
+    /// The new contract must be unset, or already hold our root -- i.e. a migration
+    /// that landed but wasn't recorded -- in which case nothing is submitted
+    /// # Args
+    /// - to: the remote hosting the new contract
+    pub async fn migrate_root_cid(&self, to: EthRemote) -> Result<RootMigration, DeviceError> {
+        let root_cid = self.read_root_cid().await?;
+
+        // Find the update behind our root, if it came from our contract
+        let from_tx_hash = if self.root_target == RootTarget::Ipns {
+            None
+        } else {
+            RootCid::new(self.eth.clone(), self.contract_address, None)?
+                .find_update(root_cid)
+                .await
+                .map(|reciept| format!("{:?}", reciept.transaction_hash))
+        };
+
+        let to_eth = EthClient::try_from(to.clone())?;
+        if !to_eth.has_code(to.contract_address).await? {
+            return Err(DeviceError::ContractNotDeployed(to.contract_address));
+        }
+        let to_root_cid = RootCid::new(to_eth, to.contract_address, Some(self.wallet.clone()))?;
+        let reciept = match to_root_cid.read_seed().await? {
+            None => to_root_cid.seed(root_cid).await?,
+            Some(seeded_cid) if seeded_cid == root_cid => {
+                tracing::info!("new contract already holds {}, skipping seed", root_cid);
+                to_root_cid.find_update(root_cid).await
+            }
+            Some(seeded_cid) => {
+                return Err(DeviceError::RootCid(RootCidError::AlreadySeeded(
+                    seeded_cid,
+                )))
+            }
+        };
+
+        Ok(RootMigration {
+            root_cid,
+            from_chain_id: self.chain_id(),
+            from_contract_address: self.contract_address,
+            from_tx_hash,
+            to_chain_id: to.chain_id,
+            to_contract_address: to.contract_address,
+            to_tx_hash: reciept.map(|reciept| format!("{:?}", reciept.transaction_hash)),
+        })
+    }
+
+    /// Look through the contract's recent `updated` events for the update that set
+    /// a root, with the block it landed in
+    /// Best effort -- None if we don't publish on chain, or the root was set too
+    /// long ago to find
+    pub async fn find_root_update(&self, root_cid: &Cid) -> Option<RootUpdateLog> {
+        if self.root_target == RootTarget::Ipns {
+            return None;
+        }
+        let root_cid_contract = RootCid::new(self.eth.clone(), self.contract_address, None).ok()?;
+        let receipt = root_cid_contract.find_update(*root_cid).await?;
+        let timestamp = match receipt.block_number {
+            Some(block_number) => root_cid_contract
+                .block_timestamp(block_number)
+                .await
+                .and_then(|secs| Utc.timestamp_opt(secs as i64, 0).single()),
+            None => None,
+        };
+        Some(RootUpdateLog {
+            tx_hash: format!("{:?}", receipt.transaction_hash),
+            block_number: receipt
+                .block_number
+                .map(|block_number| block_number.as_u64()),
+            timestamp,
+        })
+    }
+
+    /// Estimate the cost in wei of updating the root cid on chain
+    /// # Returns None if our root target doesn't publish on chain
+    pub async fn estimate_root_cid_update(
+        &self,
+        previous_root_cid: Cid,
+        next_root_cid: Cid,
+    ) -> Result<Option<U256>, DeviceError> {
+        if self.root_target == RootTarget::Ipns {
+            return Ok(None);
+        }
+        let root_cid = RootCid::new(
+            self.eth.clone(),
+            self.contract_address,
+            Some(self.wallet.clone()),
+        )?;
+        let cost = root_cid
+            .estimate_update(previous_root_cid, next_root_cid)
+            .await?;
+        Ok(Some(cost))
+    }
+
+    /// Check our wallet can pay for a root update before it's sent
+    /// Passes without checking if roots are only published over ipns
+    pub async fn check_root_cid_update_funds(
         &self,
         previous_root_cid: Cid,
         next_root_cid: Cid,
     ) -> Result<(), DeviceError> {
+        if self.root_target == RootTarget::Ipns {
+            return Ok(());
+        }
         let root_cid = RootCid::new(
             self.eth.clone(),
             self.contract_address,
             Some(self.wallet.clone()),
         )?;
+        root_cid
+            .check_update_funds(previous_root_cid, next_root_cid)
+            .await?;
+        Ok(())
+    }
 
-        let _maybe_txn_reciept = root_cid.update(previous_root_cid, next_root_cid).await?;
+    /// The calldata a root update would send the RootCid contract, without sending it
+    /// # Returns None if roots are only published over ipns
+    pub fn root_cid_update_calldata(
+        &self,
+        previous_root_cid: Cid,
+        next_root_cid: Cid,
+    ) -> Result<Option<Bytes>, DeviceError> {
+        if self.root_target == RootTarget::Ipns {
+            return Ok(None);
+        }
+        let root_cid = RootCid::new(self.eth.clone(), self.contract_address, None)?;
+        let calldata = root_cid.update_calldata(previous_root_cid, next_root_cid)?;
+        Ok(Some(calldata))
+    }
 
-        // TODO: maybe should wait for emitted event and check for a valid update
+    /// Read the root cid from our primary RootCidStore
+    /// If we have mirrors, their roots are read and reconciled with it. Any store
+    /// that can't be read is skipped, and the roots of the rest reconciled
+    /// If none can be read and we have a fallback, its root is used as is
+    pub async fn read_root_cid(&self) -> Result<Cid, DeviceError> {
+        // There is always at least one store configured
+        let (_, store) = self.root_cid_stores(false)?.remove(0);
+        let mut stores = vec![("the primary store".to_string(), store)];
+        for (mirror, (eth, _)) in self.mirror_stores(false)?.into_iter().zip(&self.mirrors) {
+            let name = format!("the mirror on chain {}", eth.chain_id());
+            stores.push((name, Box::new(mirror)));
+        }
+        let roots = match (read_roots(stores).await, &self.fallback) {
+            (Ok(roots), _) => roots,
+            (Err(e), Some(fallback)) => {
+                tracing::warn!("failed to read the root cid, using the fallback: {}", e);
+                return Ok(fallback.read().await?);
+            }
+            (Err(e), None) => return Err(e.into()),
+        };
+        self.reconcile_roots(roots).await
+    }
 
-        Ok(())
+    /// Our mirrors' RootCid contracts
+    /// # Args
+    /// - signed: whether they should be able to publish updates
+    fn mirror_stores(&self, signed: bool) -> Result<Vec<RootCid>, DeviceError> {
+        let mut stores = Vec::new();
+        for (eth, contract_address) in self.mirrors.iter() {
+            let signer = if signed {
+                Some(self.wallet.clone())
+            } else {
+                None
+            };
+            stores.push(RootCid::new(eth.clone(), *contract_address, signer)?);
+        }
+        Ok(stores)
+    }
+
+    /// Bring our mirrors up to a root just published to our primary stores
+    /// A mirror behind on roots of ours is moved straight to the new one. A mirror
+    /// that can't be read or updated, or holds a root we don't know, is left as it
+    /// is -- pulls reconcile it, and later updates catch it up
+    /// If our contract can batch updates, mirrors on its chain are updated through
+    /// it in one transaction, falling back to one each if the batch can't be sent
+    /// # Returns what went wrong with each mirror left behind, by chain id
+    pub async fn update_mirrors(
+        &self,
+        previous_root_cid: Cid,
+        next_root_cid: Cid,
+    ) -> Result<Vec<(u16, DeviceError)>, DeviceError> {
+        let mut failed = Vec::new();
+        if self.root_target == RootTarget::Ipns {
+            return Ok(failed);
+        }
+        let primary = RootCid::new(
+            self.eth.clone(),
+            self.contract_address,
+            Some(self.wallet.clone()),
+        )?;
+        let mut batched = Vec::new();
+        // Only read if a mirror turns out to be behind
+        let mut history: Option<Vec<Cid>> = None;
+        for (mirror, (eth, contract_address)) in
+            self.mirror_stores(true)?.into_iter().zip(&self.mirrors)
+        {
+            let current_root_cid = match mirror.read().await {
+                Ok(current_root_cid) => current_root_cid,
+                Err(e) => {
+                    failed.push((eth.chain_id(), e.into()));
+                    continue;
+                }
+            };
+            if current_root_cid == next_root_cid {
+                continue;
+            }
+            if current_root_cid != previous_root_cid {
+                if history.is_none() {
+                    history = Some(self.root_history(&previous_root_cid).await?);
+                }
+                if !history
+                    .as_ref()
+                    .is_some_and(|history| history.contains(&current_root_cid))
+                {
+                    let roots = vec![current_root_cid, previous_root_cid];
+                    failed.push((eth.chain_id(), DeviceError::RootDivergence(roots)));
+                    continue;
+                }
+            }
+            if primary.can_batch() && eth.chain_id() == self.eth.chain_id() {
+                let update = RootUpdate {
+                    contract: *contract_address,
+                    previous_cid: current_root_cid,
+                    cid: next_root_cid,
+                };
+                batched.push((mirror, update));
+                continue;
+            }
+            if let Err(e) = RootCidStore::update(&mirror, current_root_cid, next_root_cid).await {
+                failed.push((eth.chain_id(), e.into()));
+            }
+        }
+
+        if batched.is_empty() {
+            return Ok(failed);
+        }
+        let updates: Vec<RootUpdate> = batched.iter().map(|(_, update)| update.clone()).collect();
+        match primary.update_many(&updates).await {
+            Ok(_) => return Ok(failed),
+            Err(e) => tracing::warn!("failed to batch mirror updates, sending each: {}", e),
+        }
+        for (mirror, update) in batched {
+            if let Err(e) = RootCidStore::update(&mirror, update.previous_cid, update.cid).await {
+                failed.push((self.eth.chain_id(), e.into()));
+            }
+        }
+        Ok(failed)
+    }
+
+    /// Pick a single root from what our stores reported
+    async fn reconcile_roots(&self, roots: Vec<Cid>) -> Result<Cid, DeviceError> {
+        let mut distinct = roots.clone();
+        distinct.sort();
+        distinct.dedup();
+        if distinct.len() == 1 {
+            return Ok(distinct[0]);
+        }
+        if self.root_reconcile == RootReconcile::Agree {
+            return Err(DeviceError::RootDivergence(roots));
+        }
+
+        for candidate in distinct.iter() {
+            let history = self.root_history(candidate).await?;
+            if distinct
+                .iter()
+                .all(|root| root == candidate || history.contains(root))
+            {
+                tracing::info!("reconciled diverging roots {:?} to {}", roots, candidate);
+                return Ok(*candidate);
+            }
+        }
+        Err(DeviceError::RootDivergence(roots))
+    }
+
+    /// Walk back the previous roots of a root, up to ROOT_HISTORY_DEPTH of them
+    pub async fn root_history(&self, root_cid: &Cid) -> Result<Vec<Cid>, DeviceError> {
+        let mut history = Vec::new();
+        let mut cid = *root_cid;
+        while history.len() < ROOT_HISTORY_DEPTH && cid != Cid::default() {
+            let dor_store = self.read_dor_store(&cid, true).await?;
+            cid = *dor_store.previous_root();
+            history.push(cid);
+        }
+        Ok(history)
+    }
+
+    /// Update the root cid against all of our RootCidStores
+    /// # Args
+    /// - previous_root_cid: the previously known root cid of the remote
+    /// - next_root_cid: the root cid to overwrite it with
+    /// # Returns the receipt of the first update a store reported, i.e. of a transaction
+    ///  Stores are updated in turn, so a failure says which target it was, and which
+    ///  ones already hold the new root
+    pub async fn update_root_cid(
+        &self,
+        previous_root_cid: Cid,
+        next_root_cid: Cid,
+    ) -> Result<Option<RootUpdateReceipt>, DeviceError> {
+        let mut receipt = None;
+        let mut published = Vec::new();
+        for (target, store) in self.root_cid_stores(true)? {
+            let store_receipt = store
+                .update(previous_root_cid, next_root_cid)
+                .await
+                .map_err(|source| DeviceError::RootPublish {
+                    target,
+                    published: published.clone(),
+                    source,
+                })?;
+            receipt = receipt.or(store_receipt);
+            published.push(target);
+        }
+        Ok(receipt)
     }
 
     /* Ipfs Helpers */
@@ -172,9 +1183,91 @@ impl Device {
             .map_ok(|chunk| chunk.to_vec())
             .try_concat()
             .await?;
+        self.check_block(cid, &block_data)?;
         Ok(block_data)
     }
 
+    /// Pass a block read on its own through our verifier, as the top of its dag
+    fn check_block(&self, cid: &Cid, block: &[u8]) -> Result<(), DeviceError> {
+        self.block_verifier
+            .verify(cid, block, 0)
+            .map_err(|reason| BlockSourceError::Rejected(*cid, reason).into())
+    }
+
+    /// Read the full contents of a unix-fs file by its cid against the configured IpfsClients
+    /// # Args
+    /// - cid: the cid to read
+    /// - remote: whether to do so against a remote or local instance
+    pub async fn cat_ipfs_data(&self, cid: &Cid, remote: bool) -> Result<Vec<u8>, DeviceError> {
+        let cat_stream = if remote {
+            self.ipfs_client.cat(&cid.to_string())
+        } else {
+            self.local_ipfs_client.cat(&cid.to_string())
+        };
+        let data = cat_stream
+            .map_ok(|chunk| chunk.to_vec())
+            .try_concat()
+            .await?;
+        Ok(data)
+    }
+
+    /// List the entries of a unix-fs directory against the configured IpfsClients
+    /// # Args
+    /// - cid: the cid of the directory. File cids list as a single unnamed entry
+    /// - remote: whether to do so against a remote or local instance
+    pub async fn list_ipfs_dir(
+        &self,
+        cid: &Cid,
+        remote: bool,
+    ) -> Result<Vec<DirEntry>, DeviceError> {
+        let entries = if remote {
+            self.ipfs_client.list(cid)
+        } else {
+            self.local_ipfs_client.list(cid)
+        }
+        .await?;
+        Ok(entries)
+    }
+
+    /// Size of an object's content, read from its root block alone -- a raw block's
+    /// own size, or the file size a unix-fs root records -- without fetching the rest
+    /// # Args
+    /// - cid: the object
+    /// - remote: whether to ask the remote or local instance
+    pub async fn object_size(&self, cid: &Cid, remote: bool) -> Result<u64, DeviceError> {
+        if cid.codec() == RAW_CODEC {
+            return self
+                .stat_ipfs_data(cid, remote)
+                .await?
+                .ok_or(DeviceError::MissingBlock(*cid));
+        }
+        let entries = self.list_ipfs_dir(cid, remote).await?;
+        match entries.as_slice() {
+            [entry] if entry.name.is_empty() => Ok(entry.size),
+            _ => Err(DeviceError::NotAFile(*cid)),
+        }
+    }
+
+    /// Read a single node within a dag, by its path from the dag's root
+    /// # Args
+    /// - cid: the root of the dag
+    /// - path: an ipld path from the root
+    /// - remote: whether to do so against a remote or local instance
+    pub async fn read_dag_path(
+        &self,
+        cid: &Cid,
+        path: &str,
+        remote: bool,
+    ) -> Result<serde_json::Value, DeviceError> {
+        let node = if remote {
+            self.ipfs_client.dag_get(cid, path)
+        } else {
+            self.local_ipfs_client.dag_get(cid, path)
+        }
+        .await?;
+        Ok(node)
+    }
+
     /// Read a Cid from the configured Ipfs Gateway
     /// # Args
     /// - cid: the cid to read
@@ -188,6 +1281,76 @@ impl Device {
         Ok(data)
     }
 
+    /// Ask the configured Ipfs Gateway whether it can serve a block
+    pub async fn gateway_has_block(&self, cid: &Cid) -> Result<bool, DeviceError> {
+        Ok(self.ipfs_gateway.has_block(cid).await?)
+    }
+
+    /// Read the full contents of an object by its cid
+    /// Reads from our BlockSource if one is set, otherwise from the cache or the gateway
+    pub async fn read_object(&self, cid: &Cid) -> Result<Vec<u8>, DeviceError> {
+        let verifier = self.block_verifier.as_ref();
+        if let Some(block_source) = &self.block_source {
+            return Ok(read_unixfs_file(block_source.as_ref(), cid, verifier).await?);
+        }
+        // The cache is best effort -- fall back to the gateway if it fails
+        if let Some(cache) = &self.cache {
+            match cache.get(cid) {
+                Ok(Some(data)) => return Ok(data),
+                Ok(None) => {}
+                Err(e) => tracing::warn!("failed to read {} from cache: {}", cid, e),
+            }
+        }
+        let data = if self.verify_gateway_blocks {
+            read_unixfs_file(&self.ipfs_gateway, cid, verifier).await?
+        } else {
+            self.read_ipfs_gateway_data(cid, None).await?
+        };
+        if let Some(cache) = &self.cache {
+            if let Err(e) = cache.put(cid, &data) {
+                tracing::warn!("failed to cache {}: {}", cid, e);
+            }
+        }
+        Ok(data)
+    }
+
+    /// Stream the contents of an object by its cid, rather than reading it whole
+    /// Objects from our BlockSource or the cache, or checked block by block, come as
+    /// a single chunk. Ones streamed from the gateway aren't cached
+    pub async fn stream_object(
+        &self,
+        cid: &Cid,
+    ) -> Result<BoxStream<'static, Result<Vec<u8>, DeviceError>>, DeviceError> {
+        let cached = match &self.cache {
+            Some(cache) => cache.get(cid).ok().flatten(),
+            None => None,
+        };
+        if self.block_source.is_some() || self.verify_gateway_blocks || cached.is_some() {
+            let data = match cached {
+                Some(data) => data,
+                None => self.read_object(cid).await?,
+            };
+            return Ok(futures_util::stream::once(async move { Ok(data) }).boxed());
+        }
+        let stream = self.ipfs_gateway.get_stream(cid, None).await?;
+        Ok(stream.map_err(DeviceError::from).boxed())
+    }
+
+    /// Recompute the cid of an object from its content as it streams in, so even
+    /// the largest objects are checked in constant memory
+    /// Only gives back the cid the node would for objects added with our cid params
+    /// # Returns the recomputed cid, and how many bytes the object held
+    pub async fn hash_object_stream(&self, cid: &Cid) -> Result<(Cid, u64), DeviceError> {
+        let mut stream = self.stream_object(cid).await?;
+        let mut hasher = FileHasher::new();
+        let mut bytes = 0;
+        while let Some(chunk) = stream.try_next().await? {
+            hasher.update(&chunk)?;
+            bytes += chunk.len() as u64;
+        }
+        Ok((hasher.finish()?, bytes))
+    }
+
     /// Write data against the configured IpfsClients
     /// # Args
     /// - data: the data to write
@@ -210,6 +1373,170 @@ impl Device {
         Ok(cid)
     }
 
+    /// Write data against the configured IpfsClients with other cid params
+    /// # Returns None if the node can't be asked for the params' hash function
+    pub async fn write_ipfs_data_with<R>(
+        &self,
+        data: R,
+        params: CidParams,
+        remote: bool,
+    ) -> Result<Option<Cid>, DeviceError>
+    where
+        R: 'static + Read + Send + Sync + Unpin,
+    {
+        let request = match ipfs::add_data_request_with(params) {
+            Some(request) => request,
+            None => return Ok(None),
+        };
+        let add_response = if remote {
+            self.ipfs_client.add_with_options(data, request)
+        } else {
+            self.local_ipfs_client.add_with_options(data, request)
+        }
+        .await?;
+        Ok(Some(Cid::try_from(add_response.hash)?))
+    }
+
+    /// Write a file against the configured IpfsClients, reporting the node's add progress
+    /// Use write_ipfs_data if you don't care about progress
+    /// # Args
+    /// - path: the file to write
+    /// - remote: whether to do so against a remote or local instance
+    /// - on_progress: called with bytes added and cids produced as the node reports them
+    /// # Returns the cid of the wrote file
+    pub async fn write_ipfs_file_with_progress<F>(
+        &self,
+        path: &Path,
+        remote: bool,
+        on_progress: F,
+    ) -> Result<Cid, DeviceError>
+    where
+        F: FnMut(AddProgress),
+    {
+        let client = if remote {
+            &self.ipfs_client
+        } else {
+            &self.local_ipfs_client
+        };
+        let size = std::fs::metadata(path)?.len();
+        if client.is_resumable(size) {
+            return self
+                .write_ipfs_file_resumable(path, remote, on_progress)
+                .await;
+        }
+        let cid = client
+            .add_file_with_progress(path, ipfs::add_data_request(), on_progress)
+            .await?;
+        Ok(cid)
+    }
+
+    /// Write a file block by block, assembling its dag ourselves
+    /// Blocks are content addressed, so any the node already has -- i.e. from an
+    /// earlier attempt that failed part way -- are skipped, and the rest are retried
+    /// individually. Produces the same cid as adding the file in one request
+    /// # Args
+    /// - path: the file to write
+    /// - remote: whether to do so against a remote or local instance
+    /// - on_progress: called with bytes written, and the cid of the file once pinned
+    /// # Returns the cid of the wrote file
+    pub async fn write_ipfs_file_resumable<F>(
+        &self,
+        path: &Path,
+        remote: bool,
+        mut on_progress: F,
+    ) -> Result<Cid, DeviceError>
+    where
+        F: FnMut(AddProgress),
+    {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let mut file = std::fs::File::open(path)?;
+        let mut leaves = Vec::new();
+        let mut bytes = 0;
+        loop {
+            let mut chunk = Vec::with_capacity(ipfs::UPLOAD_CHUNK_SIZE);
+            (&mut file)
+                .take(ipfs::UPLOAD_CHUNK_SIZE as u64)
+                .read_to_end(&mut chunk)?;
+            // An empty file is still a single, empty leaf
+            if chunk.is_empty() && !leaves.is_empty() {
+                break;
+            }
+            let full = chunk.len() == ipfs::UPLOAD_CHUNK_SIZE;
+            let leaf = ipfs::file_leaf(&chunk)?;
+            bytes += leaf.filesize;
+            self.put_block(&leaf.cid, chunk, "raw", remote).await?;
+            on_progress(AddProgress::Bytes {
+                name: name.clone(),
+                bytes,
+            });
+            leaves.push(leaf);
+            if !full {
+                break;
+            }
+        }
+
+        let (nodes, cid) = ipfs::file_dag(leaves)?;
+        for (node_cid, node) in nodes {
+            self.put_block(&node_cid, node, "dag-pb", remote).await?;
+        }
+        let client = if remote {
+            &self.ipfs_client
+        } else {
+            &self.local_ipfs_client
+        };
+        client.pin_add(&cid.to_string(), true).await?;
+        on_progress(AddProgress::Added { name, cid });
+        Ok(cid)
+    }
+
+    /// Put a single block of a resumable upload, unless the node already has it
+    /// Failed puts are retried with backoff
+    async fn put_block(
+        &self,
+        cid: &Cid,
+        data: Vec<u8>,
+        codec: &str,
+        remote: bool,
+    ) -> Result<(), DeviceError> {
+        if self.stat_ipfs_data(cid, remote).await?.is_some() {
+            tracing::debug!("block {} already on the node, skipping", cid);
+            return Ok(());
+        }
+        let client = if remote {
+            &self.ipfs_client
+        } else {
+            &self.local_ipfs_client
+        };
+        let mut delay = UPLOAD_RETRY_DELAY;
+        let mut attempt = 0;
+        loop {
+            match client.block_put(data.clone(), codec).await {
+                Ok(put_cid) if put_cid == *cid => return Ok(()),
+                Ok(put_cid) => {
+                    return Err(DeviceError::BlockMismatch {
+                        expected: *cid,
+                        actual: put_cid,
+                    })
+                }
+                Err(e) if attempt < UPLOAD_RETRIES => {
+                    tracing::warn!(
+                        "failed to put block {}, retrying in {:?}: {}",
+                        cid,
+                        delay,
+                        e
+                    );
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
     /// Hash data against the configured IpfsClients
     /// # Args
     /// - data: the data to write
@@ -232,6 +1559,141 @@ impl Device {
         Ok(cid)
     }
 
+    /// Hash data against the configured IpfsClients as it would be added with other
+    /// cid params, without adding it
+    /// # Returns None if the node can't be asked for the params' hash function
+    pub async fn hash_ipfs_data_with<R>(
+        &self,
+        data: R,
+        params: CidParams,
+        remote: bool,
+    ) -> Result<Option<Cid>, DeviceError>
+    where
+        R: 'static + Read + Send + Sync + Unpin,
+    {
+        let request = match ipfs::hash_data_request_with(params) {
+            Some(request) => request,
+            None => return Ok(None),
+        };
+        let add_response = if remote {
+            self.ipfs_client.add_with_options(data, request)
+        } else {
+            self.local_ipfs_client.add_with_options(data, request)
+        }
+        .await?;
+        Ok(Some(Cid::try_from(add_response.hash)?))
+    }
+
+    /// Name the pin of a cid against the configured IpfsClients
+    /// # Args
+    /// - cid: the cid to name
+    /// - name: the name to give its pin
+    /// - remote: whether to do so against a remote or local instance
+    pub async fn name_ipfs_pin(
+        &self,
+        cid: &Cid,
+        name: &str,
+        remote: bool,
+    ) -> Result<(), DeviceError> {
+        if remote {
+            self.ipfs_client.pin_add_named(cid, name)
+        } else {
+            self.local_ipfs_client.pin_add_named(cid, name)
+        }
+        .await?;
+        Ok(())
+    }
+
+    /// Pin a cid against the configured IpfsClients, having the node fetch whatever
+    /// of it it doesn't already hold
+    /// # Args
+    /// - cid: the cid to pin
+    /// - remote: whether to do so against a remote or local instance
+    pub async fn pin_ipfs_data(&self, cid: &Cid, remote: bool) -> Result<(), DeviceError> {
+        if remote {
+            self.ipfs_client.pin_add(cid)
+        } else {
+            self.local_ipfs_client.pin_add(cid)
+        }
+        .await?;
+        Ok(())
+    }
+
+    /// Unpin a cid against the configured IpfsClients
+    /// On our remote, any pin our pinning service holds for it is dropped too, so
+    /// the service doesn't keep its blocks alive after the node lets go of them
+    /// # Args
+    /// - cid: the cid to unpin
+    /// - remote: whether to do so against a remote or local instance
+    pub async fn unpin_ipfs_data(&self, cid: &Cid, remote: bool) -> Result<(), DeviceError> {
+        if remote {
+            self.ipfs_client.pin_rm(cid).await?;
+            self.ipfs_client.remote_pin_rm(cid).await?;
+        } else {
+            self.local_ipfs_client.pin_rm(cid).await?;
+        }
+        Ok(())
+    }
+
+    /// Unpin many cids at once against the configured IpfsClients
+    /// Each cid is unpinned on its own, so one failing doesn't stop the rest, and
+    /// cids that aren't pinned count as unpinned -- re-running after a partial
+    /// failure only has work left for what failed
+    /// # Args
+    /// - cids: the cids to unpin
+    /// - remote: whether to do so against a remote or local instance
+    /// - concurrency: how many unpins to have in flight at once
+    /// - timeout: how long to give the whole batch, after which anything unfinished
+    ///   is reported as timed out
+    pub async fn unpin_many(
+        &self,
+        cids: &[Cid],
+        remote: bool,
+        concurrency: usize,
+        timeout: Duration,
+    ) -> UnpinReport {
+        unpin_each(cids, concurrency, timeout, |cid| async move {
+            self.unpin_ipfs_data(&cid, remote).await
+        })
+        .await
+    }
+
+    /// Our remote node, read as a BlockSource -- whatever BlockSource we read
+    /// manifests and objects from otherwise
+    pub fn remote_blocks(&self) -> &dyn BlockSource {
+        &self.ipfs_client
+    }
+
+    /// Whether our remote has a pinning service configured
+    pub fn has_pin_service(&self) -> bool {
+        self.ipfs_client.has_pin_service()
+    }
+
+    /// Ask our remote's pinning service to pin cids, and wait for it to finish
+    /// # Args
+    /// - cids: the cids to pin
+    /// - timeout: how long to wait on the service before giving up
+    /// - on_status: called as each cid's pin moves to a new status
+    /// # Returns the cids that failed or didn't finish in time, with their last known status
+    pub async fn pin_with_service<F>(
+        &self,
+        cids: &[Cid],
+        timeout: Duration,
+        on_status: F,
+    ) -> Result<Vec<(Cid, Option<RemotePinStatus>)>, DeviceError>
+    where
+        F: FnMut(&Cid, Option<RemotePinStatus>),
+    {
+        for cid in cids {
+            self.ipfs_client.remote_pin_add(cid).await?;
+        }
+        let unpinned = self
+            .ipfs_client
+            .wait_pinned(cids, timeout, on_status)
+            .await?;
+        Ok(unpinned)
+    }
+
     /// Stat the presence of a block against the configured IpfsClients
     /// # Args
     /// - cid: the cid to check
@@ -261,6 +1723,53 @@ impl Device {
             Err(e) => Err(DeviceError::IpfsClient(e)),
         }
     }
+
+    /// Check whether a cid's whole dag is kept by the configured IpfsClients: pinned
+    /// recursively on the node, or pinned by the client's pinning service
+    /// Unlike block_status, a block that's only cached doesn't count -- it's lost
+    /// to the node's next garbage collection
+    /// # Args
+    /// - cid: the cid to check
+    /// - remote: whether to do so against a remote or local instance
+    pub async fn pin_status(&self, cid: &Cid, remote: bool) -> Result<BlockStatus, DeviceError> {
+        let client = if remote {
+            &self.ipfs_client
+        } else {
+            &self.local_ipfs_client
+        };
+        if client.is_pinned(cid).await? {
+            return Ok(BlockStatus::Present);
+        }
+        let status = match client.remote_pin_status(cid).await? {
+            Some(RemotePinStatus::Pinned) => BlockStatus::Present,
+            Some(RemotePinStatus::Queued) | Some(RemotePinStatus::Pinning) => BlockStatus::Pending,
+            Some(RemotePinStatus::Failed) | None => BlockStatus::Absent,
+        };
+        Ok(status)
+    }
+
+    /// Check the status of a block against the configured IpfsClients, taking
+    /// in-progress pins on the client's pinning service into account
+    /// Blocks still being pinned are Pending rather than Absent, and shouldn't be re-added
+    /// # Args
+    /// - cid: the cid to check
+    /// - remote: whether to do so against a remote or local instance
+    pub async fn block_status(&self, cid: &Cid, remote: bool) -> Result<BlockStatus, DeviceError> {
+        if self.stat_ipfs_data(cid, remote).await?.is_some() {
+            return Ok(BlockStatus::Present);
+        }
+        let client = if remote {
+            &self.ipfs_client
+        } else {
+            &self.local_ipfs_client
+        };
+        let status = match client.remote_pin_status(cid).await? {
+            Some(RemotePinStatus::Pinned) => BlockStatus::Present,
+            Some(RemotePinStatus::Queued) | Some(RemotePinStatus::Pinning) => BlockStatus::Pending,
+            Some(RemotePinStatus::Failed) | None => BlockStatus::Absent,
+        };
+        Ok(status)
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -275,6 +1784,180 @@ pub enum DeviceError {
     EthClient(#[from] EthClientError),
     #[error("root cid error: {0}")]
     RootCid(#[from] eth::RootCidError),
+    #[error("root cid store error: {0}")]
+    RootCidStore(#[from] RootCidStoreError),
+    #[error("failed to publish the root to {target}{}: {source}", already_published(.published))]
+    RootPublish {
+        target: RootTarget,
+        /// Targets that took the new root before this one failed
+        published: Vec<RootTarget>,
+        source: RootCidStoreError,
+    },
+    #[error("block source error: {0}")]
+    BlockSource(#[from] BlockSourceError),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("node stored block {expected} as {actual}")]
+    BlockMismatch { expected: Cid, actual: Cid },
+    #[error("{0:?} is reserved for the manifest of a directory root")]
+    ReservedPath(PathBuf),
+    #[error("no contract deployed at {0:?}")]
+    ContractNotDeployed(Address),
+    #[error("root cid stores disagree on the root: {0:?}")]
+    RootDivergence(Vec<Cid>),
+    #[error("root target requires an ipns key, but none is configured")]
+    MissingIpns,
+    #[error("object {path:?} has cid params {actual}, expected {expected}")]
+    CidParamsMismatch {
+        path: PathBuf,
+        expected: CidParams,
+        actual: CidParams,
+    },
     #[error("serde error: {0}")]
     Serde(#[from] serde_json::Error),
+    #[error("manifest is a delta on top of root {0} -- read it by its root instead")]
+    UnresolvedDelta(Cid),
+    #[error("manifest version error: {0}")]
+    ManifestVersion(#[from] ManifestVersionError),
+    #[error("node has no block {0}")]
+    MissingBlock(Cid),
+    #[error("{0} is a directory, not a file")]
+    NotAFile(Cid),
+    #[error("delta manifests behind root {0} loop back to {1}")]
+    DeltaCycle(Cid, Cid),
+    #[error("delta manifests behind root {0} run more than {1} deep")]
+    DeltaChainTooLong(Cid, usize),
+}
+
+/// Note the targets a root was published to before publishing it elsewhere failed
+fn already_published(published: &[RootTarget]) -> String {
+    if published.is_empty() {
+        return String::new();
+    }
+    let targets: Vec<String> = published.iter().map(|t| t.to_string()).collect();
+    format!(" (it's already on {})", targets.join(", "))
+}
+
+impl DeviceError {
+    /// The hash of a root update that was sent but never seen landing, if that's
+    /// what this error is
+    pub fn pending_transaction(&self) -> Option<String> {
+        let eth_client_error = match self {
+            Self::EthClient(e) => e,
+            Self::RootCid(RootCidError::EthClient(e)) => e,
+            Self::RootCidStore(RootCidStoreError::RootCid(RootCidError::EthClient(e))) => e,
+            Self::RootPublish {
+                source: RootCidStoreError::RootCid(RootCidError::EthClient(e)),
+                ..
+            } => e,
+            _ => return None,
+        };
+        match eth_client_error {
+            EthClientError::TransactionPending(tx_hash, _) => Some(format!("{:?}", tx_hash)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use super::*;
+    use crate::test_utils::{cid, http_response, StubServer};
+
+    /// The cid a request to the node was about
+    fn arg(request: &str) -> String {
+        request
+            .split("arg=")
+            .nth(1)
+            .and_then(|rest| rest.split([' ', '&']).next())
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    /// Serve kubo's answers to `pin/rm`: unpinned for cid(1), not pinned for cid(2),
+    /// an error for cid(3), and no answer at all for anything else
+    fn stub_node() -> StubServer {
+        StubServer::start(|request| {
+            let error = |message: &str| {
+                let body = serde_json::json!({ "Message": message, "Code": 0 });
+                http_response(
+                    "500 Internal Server Error",
+                    "application/json",
+                    &body.to_string(),
+                )
+            };
+            match arg(request) {
+                arg if arg == cid(1).to_string() => {
+                    Some(http_response("200 OK", "application/json", "{}"))
+                }
+                arg if arg == cid(2).to_string() => Some(error("not pinned or pinned indirectly")),
+                arg if arg == cid(3).to_string() => Some(error("repo is locked")),
+                _ => None,
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn bulk_unpins_sort_out_each_cid_once() {
+        let node = stub_node();
+        let client = IpfsClient::try_from(IpfsRemote {
+            api_url: Url::parse(node.url()).unwrap(),
+            ..IpfsRemote::default()
+        })
+        .unwrap();
+        let cids = [cid(1), cid(2), cid(3), cid(4), cid(1), cid(2)];
+        let mut report = unpin_each(&cids, 2, Duration::from_millis(500), |cid| {
+            let client = &client;
+            async move { client.pin_rm(&cid).await }
+        })
+        .await;
+
+        // Not being pinned to begin with counts as unpinned
+        report.unpinned.sort();
+        let mut unpinned = vec![cid(1), cid(2)];
+        unpinned.sort();
+        assert_eq!(report.unpinned, unpinned);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].0, cid(3));
+        assert!(report.failed[0].1.contains("repo is locked"));
+        assert_eq!(report.timed_out, vec![cid(4)]);
+        assert!(!report.is_complete());
+
+        // Each cid went to the node once
+        let mut asked: Vec<String> = node.requests().iter().map(|request| arg(request)).collect();
+        asked.sort();
+        let mut distinct: Vec<String> = (1..=4).map(|byte| cid(byte).to_string()).collect();
+        distinct.sort();
+        assert_eq!(asked, distinct);
+    }
+
+    #[tokio::test]
+    async fn dead_mirrors_are_skipped() {
+        let dir = std::env::temp_dir().join(format!("dor-store-read-roots-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("primary"), cid(1).to_string()).unwrap();
+        std::fs::write(dir.join("mirror"), cid(2).to_string()).unwrap();
+        let stores = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| {
+                    let store: Box<dyn RootCidStore> = Box::new(FileRootStore::new(dir.join(name)));
+                    (name.to_string(), store)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Only the roots that could be read are compared
+        let roots = read_roots(stores(&["primary", "dead", "mirror"])).await;
+        assert_eq!(roots.unwrap(), vec![cid(1), cid(2)]);
+        let roots = read_roots(stores(&["dead", "mirror"])).await;
+        assert_eq!(roots.unwrap(), vec![cid(2)]);
+
+        // With nothing read, the first failure is reported
+        let roots = read_roots(stores(&["dead", "also-dead"])).await;
+        assert!(matches!(roots, Err(RootCidStoreError::Io(_))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
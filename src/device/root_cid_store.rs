@@ -0,0 +1,156 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use cid::Cid;
+use serde::{Deserialize, Serialize};
+
+use super::eth::RootCidError;
+use super::ipfs::IpnsError;
+#[cfg(feature = "s3")]
+use super::s3::S3StoreError;
+
+/// Which RootCidStore(s) a device reads and publishes its root cid against
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum RootTarget {
+    /// Only the RootCid contract
+    #[default]
+    Eth,
+    /// Only an IPNS key
+    Ipns,
+    /// Publish to both, reading from the RootCid contract
+    Both,
+}
+
+impl std::fmt::Display for RootTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Eth => "eth",
+            Self::Ipns => "ipns",
+            Self::Both => "both",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// How to pick a root cid when our primary store and its mirrors disagree
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum RootReconcile {
+    /// Every store must report the same root
+    #[default]
+    Agree,
+    /// Take the root whose history includes every other reported root --
+    /// i.e. a mirror that simply hasn't caught up yet
+    Latest,
+}
+
+impl std::fmt::Display for RootReconcile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Agree => "agree",
+            Self::Latest => "latest",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Where to read the root cid from when our primary store can't be read, i.e.
+/// during an eth rpc outage
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum RootFallback {
+    /// The device's IPNS key
+    Ipns,
+    /// A file holding the root cid, i.e. synced from somewhere well known
+    File { path: PathBuf },
+}
+
+impl std::fmt::Display for RootFallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ipns => write!(f, "ipns"),
+            Self::File { path } => write!(f, "file {}", path.display()),
+        }
+    }
+}
+
+/// What a store reports of an update it made
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootUpdateReceipt {
+    /// An id for the update, i.e. a transaction hash
+    pub id: String,
+    /// The block the update landed in, for stores on chain
+    pub block_number: Option<u64>,
+    /// Gas the update used, for stores on chain
+    pub gas_used: Option<u64>,
+}
+
+/// Somewhere a root cid can be read from and published to
+#[async_trait]
+pub trait RootCidStore: Send + Sync {
+    /// Read the current root cid
+    async fn read(&self) -> Result<Cid, RootCidStoreError>;
+
+    /// Update the root cid
+    /// Implementations should refuse the update if previous_cid is not the current root
+    /// # Returns a receipt for the update if the store has one, i.e. of a transaction
+    async fn update(
+        &self,
+        previous_cid: Cid,
+        cid: Cid,
+    ) -> Result<Option<RootUpdateReceipt>, RootCidStoreError>;
+}
+
+/// A root cid kept in a file as its string form
+pub struct FileRootStore {
+    path: PathBuf,
+}
+
+impl FileRootStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+#[async_trait]
+impl RootCidStore for FileRootStore {
+    async fn read(&self) -> Result<Cid, RootCidStoreError> {
+        let root = std::fs::read_to_string(&self.path)?;
+        Ok(Cid::from_str(root.trim())?)
+    }
+
+    async fn update(
+        &self,
+        previous_cid: Cid,
+        cid: Cid,
+    ) -> Result<Option<RootUpdateReceipt>, RootCidStoreError> {
+        let current_cid = self.read().await?;
+        if current_cid != previous_cid {
+            return Err(RootCidStoreError::MissmatchedRootCid(
+                previous_cid,
+                current_cid,
+            ));
+        }
+        std::fs::write(&self.path, cid.to_string())?;
+        Ok(None)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RootCidStoreError {
+    #[error("root cid error: {0}")]
+    RootCid(#[from] RootCidError),
+    #[error("ipns error: {0}")]
+    Ipns(#[from] IpnsError),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("cid error: {0}")]
+    Cid(#[from] cid::Error),
+    #[error("missmatched root cid: expected {0}, store has {1}")]
+    MissmatchedRootCid(Cid, Cid),
+    #[cfg(feature = "s3")]
+    #[error("s3 error: {0}")]
+    S3(#[from] S3StoreError),
+}
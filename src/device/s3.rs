@@ -0,0 +1,470 @@
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::ipfs::Secret;
+
+#[cfg(feature = "s3")]
+use std::collections::HashSet;
+#[cfg(feature = "s3")]
+use std::str::FromStr;
+
+#[cfg(feature = "s3")]
+use async_trait::async_trait;
+#[cfg(feature = "s3")]
+use cid::Cid;
+#[cfg(feature = "s3")]
+use futures_util::stream::{self, StreamExt, TryStreamExt};
+#[cfg(feature = "s3")]
+use s3::{bucket::Bucket, creds::Credentials, error::S3Error, region::Region};
+
+#[cfg(feature = "s3")]
+use super::block_source::{block_links, verify_block, BlockSource, BlockSourceError};
+#[cfg(feature = "s3")]
+use super::root_cid_store::{RootCidStore, RootCidStoreError, RootUpdateReceipt};
+
+/// How many blocks to check the bucket for at once while mirroring
+#[cfg(feature = "s3")]
+const MIRROR_CONCURRENCY: usize = 16;
+
+/// Region to sign requests for when none is configured -- what MinIO expects
+fn default_region() -> String {
+    "us-east-1".to_string()
+}
+
+/// Connection to an S3 compatible bucket we mirror blocks and our root cid to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct S3Remote {
+    /// Endpoint of the service, i.e. `https://s3.us-east-1.amazonaws.com` or a MinIO url
+    pub endpoint: Url,
+    #[serde(default = "default_region")]
+    pub region: String,
+    pub bucket: String,
+    /// Prefix for every key we write, so a bucket can be shared, i.e. `dor-store/`
+    #[serde(default)]
+    pub prefix: String,
+    /// Address the bucket in the path rather than the host, as MinIO does by default
+    #[serde(default)]
+    pub path_style: bool,
+    /// Credentials for the bucket -- read from the usual AWS environment variables
+    /// and profile when left unset
+    #[serde(default)]
+    pub access_key_id: Option<String>,
+    #[serde(default)]
+    pub secret_access_key: Option<Secret>,
+    /// Fail a push when the mirror can't be written, rather than logging it and
+    /// leaving the mirror behind until a later push catches it up
+    #[serde(default)]
+    pub required: bool,
+}
+
+impl S3Remote {
+    /// A bucket at an endpoint, with everything else left at its defaults
+    pub fn new(endpoint: Url, bucket: String) -> Self {
+        Self {
+            endpoint,
+            region: default_region(),
+            bucket,
+            prefix: String::new(),
+            path_style: false,
+            access_key_id: None,
+            secret_access_key: None,
+            required: false,
+        }
+    }
+
+    /// Key a block is kept under
+    #[cfg(feature = "s3")]
+    fn block_key(&self, cid: &Cid) -> String {
+        format!("{}blocks/{}", self.prefix, cid)
+    }
+
+    /// Key our root cid is kept under
+    #[cfg(feature = "s3")]
+    fn root_key(&self) -> String {
+        format!("{}root", self.prefix)
+    }
+}
+
+impl std::fmt::Display for S3Remote {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}/{}", self.endpoint, self.bucket, self.prefix)
+    }
+}
+
+/// Blocks, and our root cid, kept in an S3 compatible bucket
+/// Blocks are stored whole under their cid, so anything pulled back is checked
+/// against its cid like any other BlockSource
+#[cfg(feature = "s3")]
+pub struct S3Store {
+    remote: S3Remote,
+    bucket: Bucket,
+}
+
+#[cfg(feature = "s3")]
+impl S3Store {
+    pub fn new(remote: &S3Remote) -> Result<Self, S3StoreError> {
+        let region = Region::Custom {
+            region: remote.region.clone(),
+            endpoint: remote.endpoint.as_str().trim_end_matches('/').to_string(),
+        };
+        let credentials = match (&remote.access_key_id, &remote.secret_access_key) {
+            (Some(access_key_id), Some(secret_access_key)) => Credentials::new(
+                Some(access_key_id),
+                Some(secret_access_key.expose()),
+                None,
+                None,
+                None,
+            ),
+            _ => Credentials::default(),
+        }
+        .map_err(|e| S3StoreError::Credentials(e.to_string()))?;
+        let bucket = Bucket::new(&remote.bucket, region, credentials)?;
+        let bucket = if remote.path_style {
+            bucket.with_path_style()
+        } else {
+            bucket
+        };
+        Ok(Self {
+            remote: remote.clone(),
+            bucket,
+        })
+    }
+
+    /// Read the root the bucket holds, if it holds one yet
+    pub async fn read_root(&self) -> Result<Option<Cid>, S3StoreError> {
+        match self.get(&self.remote.root_key()).await? {
+            Some(root) => Ok(Some(Cid::from_str(String::from_utf8_lossy(&root).trim())?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Read an object, if it's there
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, S3StoreError> {
+        match self.bucket.get_object(key).await {
+            Ok(response) => match response.status_code() {
+                200 => Ok(Some(response.bytes().to_vec())),
+                404 => Ok(None),
+                status => Err(S3StoreError::Status(key.to_string(), status)),
+            },
+            Err(S3Error::HttpFailWithBody(404, _)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Whether an object is there, without reading it
+    async fn exists(&self, key: &str) -> Result<bool, S3StoreError> {
+        match self.bucket.head_object(key).await {
+            Ok((_, 200)) => Ok(true),
+            Ok((_, 404)) => Ok(false),
+            Ok((_, status)) => Err(S3StoreError::Status(key.to_string(), status)),
+            Err(S3Error::HttpFailWithBody(404, _)) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn put(&self, key: &str, data: &[u8]) -> Result<(), S3StoreError> {
+        let response = self.bucket.put_object(key, data).await?;
+        match response.status_code() {
+            200 => Ok(()),
+            status => Err(S3StoreError::Status(key.to_string(), status)),
+        }
+    }
+
+    /// Whether the bucket holds a block
+    pub async fn has_block(&self, cid: &Cid) -> Result<bool, S3StoreError> {
+        self.exists(&self.remote.block_key(cid)).await
+    }
+
+    /// Write a block under its cid
+    pub async fn put_block(&self, cid: &Cid, data: &[u8]) -> Result<(), S3StoreError> {
+        self.put(&self.remote.block_key(cid), data).await
+    }
+
+    /// Copy the whole dags below some cids from a source into the bucket
+    /// See mirror_blocks
+    /// # Returns how many blocks were uploaded
+    pub async fn mirror(
+        &self,
+        source: &dyn BlockSource,
+        cids: &[Cid],
+    ) -> Result<usize, BlockSourceError> {
+        mirror_blocks(self, source, cids).await
+    }
+}
+
+/// Somewhere blocks are mirrored to
+#[cfg(feature = "s3")]
+#[async_trait]
+trait BlockSink: Sync {
+    async fn has_block(&self, cid: &Cid) -> Result<bool, S3StoreError>;
+    async fn put_block(&self, cid: &Cid, data: &[u8]) -> Result<(), S3StoreError>;
+}
+
+#[cfg(feature = "s3")]
+#[async_trait]
+impl BlockSink for S3Store {
+    async fn has_block(&self, cid: &Cid) -> Result<bool, S3StoreError> {
+        S3Store::has_block(self, cid).await
+    }
+
+    async fn put_block(&self, cid: &Cid, data: &[u8]) -> Result<(), S3StoreError> {
+        S3Store::put_block(self, cid, data).await
+    }
+}
+
+/// Copy the whole dags below some cids from a source into a sink
+/// A block's links are uploaded before it is, so a block in the sink means
+/// everything below it is too -- those are skipped without being read, which
+/// keeps mirroring a root that only changed a little cheap, and makes an
+/// interrupted mirror safe to re-run. The cids themselves are mostly there already
+/// on a push, so they're checked for MIRROR_CONCURRENCY at a time up front
+#[cfg(feature = "s3")]
+async fn mirror_blocks(
+    sink: &dyn BlockSink,
+    source: &dyn BlockSource,
+    cids: &[Cid],
+) -> Result<usize, BlockSourceError> {
+    let mut roots = Vec::new();
+    let mut listed = HashSet::new();
+    for cid in cids {
+        if listed.insert(*cid) {
+            roots.push(*cid);
+        }
+    }
+    let present: Vec<bool> = stream::iter(roots.iter().map(|cid| sink.has_block(cid)))
+        .buffered(MIRROR_CONCURRENCY)
+        .try_collect()
+        .await?;
+    let missing: HashSet<Cid> = roots
+        .iter()
+        .zip(present)
+        .filter(|(_, present)| !present)
+        .map(|(cid, _)| *cid)
+        .collect();
+
+    let mut uploaded = 0;
+    let mut seen = HashSet::new();
+    // Blocks come off the stack once to expand, and again once their links are up
+    let mut stack: Vec<(Cid, Option<Vec<u8>>)> = roots
+        .iter()
+        .rev()
+        .filter(|cid| missing.contains(cid))
+        .map(|cid| (*cid, None))
+        .collect();
+    while let Some((cid, block)) = stack.pop() {
+        match block {
+            Some(block) => {
+                sink.put_block(&cid, &block).await?;
+                uploaded += 1;
+            }
+            None => {
+                if !seen.insert(cid) {
+                    continue;
+                }
+                if !missing.contains(&cid) && sink.has_block(&cid).await? {
+                    continue;
+                }
+                let block = source.get_block(&cid).await?;
+                // Never mirror a block we couldn't read back
+                if verify_block(&cid, &block) == Some(false) {
+                    return Err(BlockSourceError::Rejected(
+                        cid,
+                        "data doesn't hash to its cid".to_string(),
+                    ));
+                }
+                let links = block_links(&cid, &block)?;
+                stack.push((cid, Some(block)));
+                stack.extend(links.into_iter().rev().map(|link| (link, None)));
+            }
+        }
+    }
+    Ok(uploaded)
+}
+
+#[cfg(feature = "s3")]
+#[async_trait]
+impl BlockSource for S3Store {
+    async fn get_block(&self, cid: &Cid) -> Result<Vec<u8>, BlockSourceError> {
+        self.get(&self.remote.block_key(cid))
+            .await?
+            .ok_or(BlockSourceError::MissingBlock(*cid))
+    }
+}
+
+#[cfg(feature = "s3")]
+#[async_trait]
+impl RootCidStore for S3Store {
+    async fn read(&self) -> Result<Cid, RootCidStoreError> {
+        Ok(self.read_root().await?.ok_or(S3StoreError::MissingRoot)?)
+    }
+
+    /// A bucket with no root yet takes any update, so a mirror can be added to a
+    /// device that's already pushed
+    async fn update(
+        &self,
+        previous_cid: Cid,
+        cid: Cid,
+    ) -> Result<Option<RootUpdateReceipt>, RootCidStoreError> {
+        if let Some(current_cid) = self.read_root().await? {
+            if current_cid != previous_cid {
+                return Err(RootCidStoreError::MissmatchedRootCid(
+                    previous_cid,
+                    current_cid,
+                ));
+            }
+        }
+        self.put(&self.remote.root_key(), cid.to_string().as_bytes())
+            .await?;
+        Ok(None)
+    }
+}
+
+#[cfg(feature = "s3")]
+#[derive(Debug, thiserror::Error)]
+pub enum S3StoreError {
+    #[error("s3 error: {0}")]
+    S3(#[from] S3Error),
+    #[error("invalid s3 credentials: {0}")]
+    Credentials(String),
+    #[error("s3 responded to {0} with status {1}")]
+    Status(String, u16),
+    #[error("no root in the bucket yet")]
+    MissingRoot,
+    #[error("cid error: {0}")]
+    Cid(#[from] cid::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn s3_remotes_default_to_minio_friendly_settings() {
+        let remote: S3Remote =
+            serde_json::from_str(r#"{"endpoint": "http://127.0.0.1:9000", "bucket": "backups"}"#)
+                .unwrap();
+        assert_eq!(remote.region, "us-east-1");
+        assert_eq!(remote.prefix, "");
+        assert!(!remote.path_style);
+        assert!(remote.access_key_id.is_none());
+        assert!(remote.secret_access_key.is_none());
+        assert!(!remote.required);
+
+        let remote: S3Remote = serde_json::from_str(
+            r#"{"endpoint": "http://127.0.0.1:9000", "bucket": "backups", "secret_access_key": "hunter2"}"#,
+        )
+        .unwrap();
+        assert_eq!(format!("{:?}", remote.secret_access_key), "Some(***)");
+    }
+
+    #[cfg(feature = "s3")]
+    #[test]
+    fn s3_keys_are_prefixed() {
+        let remote = S3Remote {
+            endpoint: Url::parse("http://127.0.0.1:9000").unwrap(),
+            region: default_region(),
+            bucket: "backups".to_string(),
+            prefix: "dor-store/".to_string(),
+            path_style: true,
+            access_key_id: None,
+            secret_access_key: None,
+            required: false,
+        };
+        let cid = Cid::default();
+        assert_eq!(remote.block_key(&cid), format!("dor-store/blocks/{}", cid));
+        assert_eq!(remote.root_key(), "dor-store/root");
+    }
+
+    /// Blocks held in memory, counting how often the sink is asked for one
+    #[cfg(feature = "s3")]
+    #[derive(Default)]
+    struct MemoryBlocks {
+        blocks: std::sync::Mutex<std::collections::HashMap<Cid, Vec<u8>>>,
+        checks: std::sync::atomic::AtomicUsize,
+    }
+
+    #[cfg(feature = "s3")]
+    #[async_trait]
+    impl BlockSource for MemoryBlocks {
+        async fn get_block(&self, cid: &Cid) -> Result<Vec<u8>, BlockSourceError> {
+            self.blocks
+                .lock()
+                .unwrap()
+                .get(cid)
+                .cloned()
+                .ok_or(BlockSourceError::MissingBlock(*cid))
+        }
+    }
+
+    #[cfg(feature = "s3")]
+    #[async_trait]
+    impl BlockSink for MemoryBlocks {
+        async fn has_block(&self, cid: &Cid) -> Result<bool, S3StoreError> {
+            self.checks
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(self.blocks.lock().unwrap().contains_key(cid))
+        }
+
+        async fn put_block(&self, cid: &Cid, data: &[u8]) -> Result<(), S3StoreError> {
+            self.blocks.lock().unwrap().insert(*cid, data.to_vec());
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "s3")]
+    #[tokio::test]
+    async fn mirrors_copy_whole_dags_once() {
+        use crate::device::ipfs::{file_dag, file_leaf};
+
+        let source = MemoryBlocks::default();
+        let mut leaves = Vec::new();
+        for chunk in [b"hello ".as_slice(), b"world".as_slice()] {
+            let leaf = file_leaf(chunk).unwrap();
+            source
+                .blocks
+                .lock()
+                .unwrap()
+                .insert(leaf.cid, chunk.to_vec());
+            leaves.push(leaf);
+        }
+        let (nodes, file) = file_dag(leaves).unwrap();
+        source.blocks.lock().unwrap().extend(nodes);
+        let other = file_leaf(b"other").unwrap().cid;
+        source
+            .blocks
+            .lock()
+            .unwrap()
+            .insert(other, b"other".to_vec());
+
+        let sink = MemoryBlocks::default();
+        // Listed twice, copied once
+        let uploaded = mirror_blocks(&sink, &source, &[file, other, file])
+            .await
+            .unwrap();
+        assert_eq!(uploaded, 4);
+        assert_eq!(*sink.blocks.lock().unwrap(), *source.blocks.lock().unwrap());
+
+        // Everything's there, so nothing is read or uploaded, and only the listed
+        // cids are checked
+        sink.checks.store(0, std::sync::atomic::Ordering::SeqCst);
+        let uploaded = mirror_blocks(&sink, &MemoryBlocks::default(), &[file, other])
+            .await
+            .unwrap();
+        assert_eq!(uploaded, 0);
+        assert_eq!(sink.checks.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        // A block that doesn't match its cid is never mirrored
+        let corrupt = MemoryBlocks::default();
+        corrupt
+            .blocks
+            .lock()
+            .unwrap()
+            .insert(other, b"tampered".to_vec());
+        let sink = MemoryBlocks::default();
+        assert!(matches!(
+            mirror_blocks(&sink, &corrupt, &[other]).await,
+            Err(BlockSourceError::Rejected(cid, _)) if cid == other
+        ));
+        assert!(sink.blocks.lock().unwrap().is_empty());
+    }
+}
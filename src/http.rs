@@ -0,0 +1,184 @@
+// Just enough HTTP/1.1 for the servers we run -- `serve`, and metrics for `watch`
+// One request per connection: read its head, answer, and hang up
+
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Largest request head we'll read -- we only look at the request line and a
+/// couple of headers
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+/// How long a client has to send its whole request head, so ones trickling it in
+/// can't hold connections open
+pub const REQUEST_HEAD_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct Request {
+    pub method: String,
+    pub target: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Read a request's head off a stream, giving up after REQUEST_HEAD_TIMEOUT
+/// # Returns None if it isn't a request we can make sense of
+pub async fn read_request<S>(stream: &mut S) -> std::io::Result<Option<Request>>
+where
+    S: AsyncRead + Unpin,
+{
+    match tokio::time::timeout(REQUEST_HEAD_TIMEOUT, read_head(stream)).await {
+        Ok(result) => result,
+        Err(_) => Err(std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            "timed out reading request head",
+        )),
+    }
+}
+
+async fn read_head<S>(stream: &mut S) -> std::io::Result<Option<Request>>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 || buf.len() + n > MAX_REQUEST_HEAD {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+
+    let head = String::from_utf8_lossy(&buf);
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let (method, target) = match (request_line.next(), request_line.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target.to_string()),
+        _ => return Ok(None),
+    };
+    let headers = lines
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect();
+    Ok(Some(Request {
+        method,
+        target,
+        headers,
+    }))
+}
+
+pub struct Response {
+    pub status: u16,
+    reason: &'static str,
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+    /// Answering a HEAD request -- send the headers without the body
+    pub head: bool,
+}
+
+impl Response {
+    pub fn new(status: u16, reason: &'static str, body: Vec<u8>) -> Self {
+        Self {
+            status,
+            reason,
+            headers: Vec::new(),
+            body,
+            head: false,
+        }
+    }
+
+    pub fn error(status: u16, reason: &'static str, message: &str) -> Self {
+        let mut response = Self::new(status, reason, format!("{}\n", message).into_bytes());
+        response.header("Content-Type", "text/plain; charset=utf-8".to_string());
+        response
+    }
+
+    pub fn header(&mut self, name: &'static str, value: String) {
+        self.headers.push((name, value));
+    }
+
+    /// Send the response, body and all
+    pub async fn write<S>(&self, stream: &mut S) -> std::io::Result<()>
+    where
+        S: AsyncWrite + Unpin,
+    {
+        self.write_head(stream, self.body.len() as u64).await?;
+        if !self.head {
+            stream.write_all(&self.body).await?;
+        }
+        stream.flush().await
+    }
+
+    /// Send only the status line and headers, for a body the caller streams itself
+    /// # Args
+    /// - content_length: how many bytes of body will follow
+    pub async fn write_head<S>(&self, stream: &mut S, content_length: u64) -> std::io::Result<()>
+    where
+        S: AsyncWrite + Unpin,
+    {
+        let mut head = format!("HTTP/1.1 {} {}\r\n", self.status, self.reason);
+        for (name, value) in self.headers.iter() {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str(&format!(
+            "Content-Length: {}\r\nConnection: close\r\n\r\n",
+            content_length
+        ));
+        stream.write_all(head.as_bytes()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn request(raw: &[u8]) -> std::io::Result<Option<Request>> {
+        let (mut client, mut server) = tokio::io::duplex(MAX_REQUEST_HEAD * 2);
+        client.write_all(raw).await?;
+        drop(client);
+        read_request(&mut server).await
+    }
+
+    #[tokio::test]
+    async fn reads_request_heads() {
+        let request = request(b"GET /a%20b.txt HTTP/1.1\r\nhost: x\r\nRANGE: bytes=0-1\r\n\r\n")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.target, "/a%20b.txt");
+        assert_eq!(request.header("Range"), Some("bytes=0-1"));
+        assert_eq!(request.header("If-None-Match"), None);
+
+        // Cut off, or too big
+        assert!(request(b"GET / HTTP/1.1\r\n").await.unwrap().is_none());
+        let mut huge = b"GET / HTTP/1.1\r\nX: ".to_vec();
+        huge.extend(std::iter::repeat(b'x').take(MAX_REQUEST_HEAD));
+        huge.extend_from_slice(b"\r\n\r\n");
+        assert!(request(&huge).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn writes_responses() {
+        let mut response = Response::error(404, "Not Found", "nope");
+        let mut out = Vec::new();
+        response.write(&mut out).await.unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "HTTP/1.1 404 Not Found\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: 5\r\nConnection: close\r\n\r\nnope\n"
+        );
+
+        response.head = true;
+        let mut out = Vec::new();
+        response.write(&mut out).await.unwrap();
+        assert!(String::from_utf8(out).unwrap().ends_with("\r\n\r\n"));
+    }
+}
@@ -1,8 +1,25 @@
 mod cli;
 mod device;
+#[cfg(any(feature = "metrics", feature = "serve"))]
+mod http;
+mod metrics;
+#[cfg(test)]
+mod test_utils;
 mod types;
 
 pub mod prelude {
     #[cfg(not(target_arch = "wasm32"))]
     pub use crate::cli::App;
+    #[cfg(not(target_arch = "wasm32"))]
+    pub use crate::cli::{
+        commit, fsck, health, init, pull, push, reset, stage, stat, verify, CommitError, FsckError,
+        HealthError, InitError, PullError, PullOutput, PullTarget, PushError, PushOptions,
+        ResetError, StageError, StatError, VerifyError,
+    };
+    #[cfg(not(target_arch = "wasm32"))]
+    pub use crate::cli::{Config, ConfigBuilder, ConfigError, OnDiskDevice, WalletSource};
+    pub use crate::device::{
+        BlockVerifier, CidVerifier, EthRemote, IpfsRemote, IpnsRemote, RootTarget,
+    };
+    pub use crate::types::{CidInfo, Object};
 }
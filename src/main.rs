@@ -1,9 +1,9 @@
 #[cfg(not(target_arch = "wasm32"))]
 #[tokio::main]
-async fn main() {
+async fn main() -> std::process::ExitCode {
     use dor_store::prelude::App;
 
-    App::run().await;
+    App::run().await
 }
 
 #[cfg(target_arch = "wasm32")]
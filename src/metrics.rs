@@ -0,0 +1,156 @@
+// Counters and timings for long running modes, i.e. `watch`
+// Measurements go to a global recorder. Without the `metrics` feature there isn't
+// one, and recording compiles down to nothing
+
+#[cfg(feature = "metrics")]
+use std::collections::BTreeMap;
+#[cfg(feature = "metrics")]
+use std::net::SocketAddr;
+#[cfg(feature = "metrics")]
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Successful pushes
+pub const PUSHES_TOTAL: &str = "dor_store_pushes_total";
+/// Time taken by successful pushes
+pub const PUSH_DURATION_SECONDS: &str = "dor_store_push_duration_seconds";
+/// Objects pinned by pushes, not counting ones the node already had
+pub const OBJECTS_PINNED_TOTAL: &str = "dor_store_objects_pinned_total";
+/// Bytes of objects uploaded by pushes
+pub const BYTES_UPLOADED_TOTAL: &str = "dor_store_bytes_uploaded_total";
+/// Gas used by root updates
+pub const GAS_USED_TOTAL: &str = "dor_store_gas_used_total";
+/// Requests made against the gateway, including retries
+pub const GATEWAY_REQUESTS_TOTAL: &str = "dor_store_gateway_requests_total";
+/// Gateway requests that failed
+pub const GATEWAY_ERRORS_TOTAL: &str = "dor_store_gateway_errors_total";
+
+/// Something that takes measurements
+#[cfg(feature = "metrics")]
+pub trait MetricsRecorder: Send + Sync {
+    /// Add to a counter
+    fn increment(&self, name: &'static str, value: u64);
+
+    /// Record a single observation of a value, i.e. a duration
+    fn observe(&self, name: &'static str, value: f64);
+}
+
+#[cfg(feature = "metrics")]
+static RECORDER: OnceLock<Arc<dyn MetricsRecorder>> = OnceLock::new();
+
+/// Install the global recorder
+/// Only the first recorder installed takes effect
+/// # Returns whether the recorder was installed
+#[cfg(feature = "metrics")]
+pub fn set_recorder(recorder: Arc<dyn MetricsRecorder>) -> bool {
+    RECORDER.set(recorder).is_ok()
+}
+
+/// Add to a counter on the global recorder, if there is one
+#[inline]
+pub fn increment(name: &'static str, value: u64) {
+    #[cfg(feature = "metrics")]
+    if let Some(recorder) = RECORDER.get() {
+        recorder.increment(name, value);
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = (name, value);
+}
+
+/// Record an observation on the global recorder, if there is one
+#[inline]
+pub fn observe(name: &'static str, value: f64) {
+    #[cfg(feature = "metrics")]
+    if let Some(recorder) = RECORDER.get() {
+        recorder.observe(name, value);
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = (name, value);
+}
+
+/// In memory recorder, rendered in the prometheus text format
+#[cfg(feature = "metrics")]
+#[derive(Default)]
+pub struct Registry {
+    counters: Mutex<BTreeMap<&'static str, u64>>,
+    /// Sum and count of observations, by name
+    summaries: Mutex<BTreeMap<&'static str, (f64, u64)>>,
+}
+
+#[cfg(feature = "metrics")]
+impl MetricsRecorder for Registry {
+    fn increment(&self, name: &'static str, value: u64) {
+        *self.counters.lock().unwrap().entry(name).or_default() += value;
+    }
+
+    fn observe(&self, name: &'static str, value: f64) {
+        let mut summaries = self.summaries.lock().unwrap();
+        let (sum, count) = summaries.entry(name).or_default();
+        *sum += value;
+        *count += 1;
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl Registry {
+    /// Render everything recorded so far in the prometheus text format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for (name, value) in self.counters.lock().unwrap().iter() {
+            out.push_str(&format!("# TYPE {} counter\n{} {}\n", name, name, value));
+        }
+        for (name, (sum, count)) in self.summaries.lock().unwrap().iter() {
+            out.push_str(&format!(
+                "# TYPE {} summary\n{}_sum {}\n{}_count {}\n",
+                name, name, sum, name, count
+            ));
+        }
+        out
+    }
+}
+
+/// Install a Registry as the global recorder, and serve it at `/metrics`
+/// The server runs in the background for as long as the runtime does
+/// # Args
+/// - addr: the address to listen on
+#[cfg(feature = "metrics")]
+pub async fn serve(addr: SocketAddr) -> std::io::Result<()> {
+    use crate::http::{read_request, Response};
+
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    let registry = Arc::new(Registry::default());
+    if !set_recorder(registry.clone()) {
+        tracing::warn!("a metrics recorder is already installed, serving an empty registry");
+    }
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = match listener.accept().await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    tracing::warn!("failed to accept metrics connection: {}", e);
+                    continue;
+                }
+            };
+            let registry = registry.clone();
+            tokio::spawn(async move {
+                let response = match read_request(&mut stream).await {
+                    Ok(Some(request))
+                        if request.method == "GET" && request.target == "/metrics" =>
+                    {
+                        let mut response = Response::new(200, "OK", registry.render().into_bytes());
+                        response.header("Content-Type", "text/plain; version=0.0.4".to_string());
+                        response
+                    }
+                    Ok(_) => Response::new(404, "Not Found", Vec::new()),
+                    Err(e) => {
+                        tracing::debug!("failed to read metrics request: {}", e);
+                        return;
+                    }
+                };
+                if let Err(e) = response.write(&mut stream).await {
+                    tracing::debug!("failed to write metrics response: {}", e);
+                }
+            });
+        }
+    });
+    Ok(())
+}
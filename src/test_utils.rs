@@ -0,0 +1,119 @@
+// Fixtures shared by our tests
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use cid::multihash::Multihash;
+use cid::Cid;
+
+/// Multicodec code for raw blocks
+const RAW_CODEC: u64 = 0x55;
+/// Multihash code for BLAKE3, the hash we make cids with by default
+const BLAKE3: u64 = 0x1e;
+
+/// A raw block cid, with a made up BLAKE3 digest -- distinct for each byte
+pub fn cid(byte: u8) -> Cid {
+    cid_with_hash(BLAKE3, byte)
+}
+
+/// A raw block cid with a made up digest, under some other hash
+/// # Args
+/// - hash_code: the multihash code to claim the digest is under
+/// - byte: what to fill the digest with
+pub fn cid_with_hash(hash_code: u64, byte: u8) -> Cid {
+    Cid::new_v1(RAW_CODEC, Multihash::wrap(hash_code, &[byte; 32]).unwrap())
+}
+
+/// An http server on a local port, answering requests the way a test tells it to
+/// Connections it doesn't answer are held open until it's dropped, so clients time
+/// out on them rather than the server sleeping
+pub struct StubServer {
+    url: String,
+    requests: Receiver<String>,
+    held: Arc<Mutex<Vec<TcpStream>>>,
+}
+
+impl StubServer {
+    /// Start serving, each connection on its own thread
+    /// # Args
+    /// - respond: the raw http response to a request, or None to leave it unanswered
+    pub fn start<F>(respond: F) -> Self
+    where
+        F: Fn(&str) -> Option<String> + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (sender, requests) = mpsc::channel();
+        let held = Arc::new(Mutex::new(Vec::new()));
+        let holding = held.clone();
+        let respond = Arc::new(respond);
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let sender = sender.clone();
+                let holding = holding.clone();
+                let respond = respond.clone();
+                std::thread::spawn(move || {
+                    let mut buf = [0u8; 4096];
+                    let n = stream.read(&mut buf).unwrap_or(0);
+                    let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                    let response = respond(&request);
+                    // Nobody may be listening for requests any more
+                    let _ = sender.send(request);
+                    match response {
+                        Some(response) => {
+                            let _ = stream.write_all(response.as_bytes());
+                        }
+                        None => holding.lock().unwrap().push(stream),
+                    }
+                });
+            }
+        });
+        Self {
+            url,
+            requests,
+            held,
+        }
+    }
+
+    /// Answer every request with the same response
+    pub fn always(response: &'static str) -> Self {
+        Self::start(move |_| Some(response.to_string()))
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The next request the server got, waiting for it if need be
+    pub fn request(&self) -> String {
+        self.requests.recv_timeout(Duration::from_secs(5)).unwrap()
+    }
+
+    /// Every request the server has got so far and not handed out
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.try_iter().collect()
+    }
+}
+
+impl Drop for StubServer {
+    fn drop(&mut self) {
+        self.held.lock().unwrap().clear();
+    }
+}
+
+/// A raw http response, closing the connection after it
+/// # Args
+/// - status: the status line's code and reason, i.e. "200 OK"
+pub fn http_response(status: &str, content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
@@ -0,0 +1,85 @@
+use std::fmt::Display;
+
+use cid::Cid;
+use serde::Serialize;
+
+/// Structured info about how a Cid was produced -- for interop debugging
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct CidInfo {
+    /// Cid version -- 0 or 1
+    pub version: u64,
+    /// Multicodec code of the content, i.e. 0x55 for raw or 0x70 for dag-pb
+    pub codec: u64,
+    /// Multihash code of the hash function, i.e. 0x12 for sha2-256 or 0x1e for blake3
+    pub hash_code: u64,
+    /// Length of the digest in bytes
+    pub hash_len: u8,
+}
+
+impl CidInfo {
+    /// Decode the info of a Cid
+    pub fn of(cid: &Cid) -> Self {
+        Self {
+            version: cid.version().into(),
+            codec: cid.codec(),
+            hash_code: cid.hash().code(),
+            hash_len: cid.hash().size(),
+        }
+    }
+
+    /// Human-readable name of the content codec, if we know it
+    pub fn codec_name(&self) -> Option<&'static str> {
+        codec_name(self.codec)
+    }
+
+    /// Human-readable name of the hash function, if we know it
+    pub fn hash_name(&self) -> Option<&'static str> {
+        hash_name(self.hash_code)
+    }
+}
+
+/// Human-readable name of a multicodec content code, if we know it
+pub fn codec_name(code: u64) -> Option<&'static str> {
+    match code {
+        0x55 => Some("raw"),
+        0x70 => Some("dag-pb"),
+        0x71 => Some("dag-cbor"),
+        0x0129 => Some("dag-json"),
+        0x0200 => Some("json"),
+        _ => None,
+    }
+}
+
+/// Multihash function code of a human-readable name, if we know it
+pub fn hash_code(name: &str) -> Option<u64> {
+    [0x00, 0x12, 0x13, 0x1e, 0xb220]
+        .into_iter()
+        .find(|code| hash_name(*code) == Some(name))
+}
+
+/// Human-readable name of a multihash function code, if we know it
+pub fn hash_name(code: u64) -> Option<&'static str> {
+    match code {
+        0x00 => Some("identity"),
+        0x12 => Some("sha2-256"),
+        0x13 => Some("sha2-512"),
+        0x1e => Some("blake3"),
+        0xb220 => Some("blake2b-256"),
+        _ => None,
+    }
+}
+
+impl Display for CidInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "cidv{} ", self.version)?;
+        match self.codec_name() {
+            Some(name) => write!(f, "{} ", name)?,
+            None => write!(f, "0x{:x} ", self.codec)?,
+        }
+        match self.hash_name() {
+            Some(name) => write!(f, "{}", name)?,
+            None => write!(f, "0x{:x}", self.hash_code)?,
+        }
+        write!(f, " ({} bytes)", self.hash_len)
+    }
+}
@@ -0,0 +1,92 @@
+use std::fmt;
+use std::str::FromStr;
+
+use cid::Cid;
+use serde::de::{self, SeqAccess, Visitor};
+use serde::{Deserializer, Serializer};
+
+/// Serialize a cid as its canonical string -- base32 for v1 cids
+/// Use with `#[serde(with = "cid_serde")]`, so manifests read the same to non-Rust
+/// consumers as they do to us
+pub fn serialize<S>(cid: &Cid, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&cid.to_string())
+}
+
+/// Deserialize a cid from its string form
+/// Also accepts the raw bytes older manifests were written with
+pub fn deserialize<'de, D>(deserializer: D) -> Result<Cid, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_any(CidVisitor)
+}
+
+struct CidVisitor;
+
+impl<'de> Visitor<'de> for CidVisitor {
+    type Value = Cid;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a cid string, or its bytes")
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<Cid, E> {
+        Cid::from_str(s).map_err(E::custom)
+    }
+
+    fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Cid, E> {
+        Cid::try_from(bytes).map_err(E::custom)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Cid, A::Error> {
+        let mut bytes = Vec::new();
+        while let Some(byte) = seq.next_element::<u8>()? {
+            bytes.push(byte);
+        }
+        self.visit_bytes(&bytes)
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D) -> Result<Cid, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_utils::cid;
+    use crate::types::Object;
+
+    #[test]
+    fn object_cid_serializes_as_base32_string() {
+        let cid = cid(7);
+        let object_json = json!({
+            "created_at": "2024-01-01T00:00:00Z",
+            "updated_at": "2024-01-01T00:00:00Z",
+            "cid": cid.to_string(),
+            "metadata": {},
+        });
+        let object: Object = serde_json::from_value(object_json.clone()).unwrap();
+        assert_eq!(object.cid(), &cid);
+        assert!(cid.to_string().starts_with('b'));
+        assert_eq!(serde_json::to_value(&object).unwrap(), object_json);
+    }
+
+    #[test]
+    fn object_cid_deserializes_from_legacy_bytes() {
+        let cid = cid(7);
+        let object_json = json!({
+            "created_at": "2024-01-01T00:00:00Z",
+            "updated_at": "2024-01-01T00:00:00Z",
+            "cid": cid.to_bytes(),
+            "metadata": {},
+        });
+        let object: Object = serde_json::from_value(object_json).unwrap();
+        assert_eq!(object.cid(), &cid);
+    }
+}
@@ -1,8 +1,12 @@
+use chrono::{DateTime, Utc};
 use cid::Cid;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::BTreeSet;
 use std::path::PathBuf;
 
+use super::manifest_version::current_schema_version;
+use super::normalize::normalize_path;
 use super::object::{Object, ObjectSet};
 use super::schema::Schema;
 
@@ -12,33 +16,68 @@ use super::schema::Schema;
 /// - previous_root: the cid of the previous root of the DorFS
 /// - version: the version of the DorFS
 /// - schemas: the schemas of the DorFS -- map of unique schema names to schema definitions, which are just JSON objects
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+/// - schema_version: the version of the manifest's own shape, see manifest_version.rs
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct DorStore {
     object_set: ObjectSet,
+    #[serde(with = "super::cid_serde")]
     previous_root: Cid,
     version: Version,
     schema: Schema,
+    /// Manifests read off the network are migrated before they get here, so this
+    /// default only covers our own state on disk
+    /// Always written, even though it's the current version: readers tell
+    /// pre-versioning manifests apart by its absence. It's part of what a root hashes,
+    /// so the first push after upgrading changes the root cid even if nothing else did
+    #[serde(default = "current_schema_version")]
+    schema_version: u64,
+}
+
+impl Default for DorStore {
+    fn default() -> Self {
+        Self {
+            object_set: ObjectSet::default(),
+            previous_root: Cid::default(),
+            version: Version::default(),
+            schema: Schema::default(),
+            schema_version: current_schema_version(),
+        }
+    }
 }
 
 impl DorStore {
+    /// The version of the manifest schema this was written with
+    pub fn schema_version(&self) -> u64 {
+        self.schema_version
+    }
+
     pub fn set_previous_root(&mut self, cid: Cid) {
         self.previous_root = cid;
     }
 
+    pub fn previous_root(&self) -> &Cid {
+        &self.previous_root
+    }
+
     pub fn objects(&self) -> &ObjectSet {
         &self.object_set
     }
 
+    /// Insert an object, normalizing its path
     pub fn insert_object(&mut self, path: PathBuf, object: Object) {
-        self.object_set.insert(path, object);
+        self.object_set.insert(normalize_path(&path), object);
     }
 
     pub fn remove_object(&mut self, path: &PathBuf) {
-        self.object_set.remove(path);
+        self.object_set.remove(&normalize_path(path));
+    }
+
+    pub fn get_object(&self, path: &PathBuf) -> Option<&Object> {
+        self.object_set.get(&normalize_path(path))
     }
 
     pub fn get_object_mut(&mut self, path: &PathBuf) -> Option<&mut Object> {
-        self.object_set.get_mut(path)
+        self.object_set.get_mut(&normalize_path(path))
     }
 
     pub fn update_object(&mut self, path: &PathBuf, cid: Cid) -> &Object {
@@ -57,6 +96,10 @@ impl DorStore {
         &self.schema
     }
 
+    pub fn set_schema(&mut self, schema: Schema) {
+        self.schema = schema;
+    }
+
     pub fn insert_schema_entry(&mut self, name: &String, fields: &Vec<String>) {
         self.schema.insert(name.clone(), fields.clone());
     }
@@ -68,6 +111,177 @@ impl DorStore {
     pub fn get_schema_entry(&mut self, name: &String) -> Option<&Vec<String>> {
         self.schema.get(name)
     }
+
+    /// Describe this manifest as what changed on top of the manifest of its previous
+    /// root, `parent`
+    /// - depth: how many deltas deep this one is, counting itself
+    pub fn delta_from(&self, parent: &DorStore, depth: usize) -> DeltaManifest {
+        let changed = self
+            .object_set
+            .iter()
+            .filter(|(path, object)| parent.object_set.get(*path) != Some(*object))
+            .map(|(path, object)| (path.clone(), object.clone()))
+            .collect();
+        let removed = parent
+            .object_set
+            .keys()
+            .filter(|path| !self.object_set.contains_key(*path))
+            .cloned()
+            .collect();
+        DeltaManifest {
+            delta_of: self.previous_root,
+            depth,
+            schema_version: self.schema_version,
+            version: self.version.clone(),
+            schema: self.schema.clone(),
+            changed: ObjectSet::from(changed),
+            removed,
+        }
+    }
+
+    /// Compare the objects of this manifest to those of `next`, keeping changes to
+    /// what objects hold apart from changes to what they're tagged with
+    pub fn diff(&self, next: &DorStore) -> ManifestDiff {
+        let mut diff = ManifestDiff::default();
+        for (path, object) in next.object_set.iter() {
+            match self.object_set.get(path) {
+                Some(previous) => diff.compare(path, previous, object),
+                None => diff.added.push((path.clone(), *object.cid())),
+            }
+        }
+        for (path, object) in self.object_set.iter() {
+            if !next.object_set.contains_key(path) {
+                diff.removed.push((path.clone(), *object.cid()));
+            }
+        }
+        diff
+    }
+}
+
+/// How the objects of two manifests differ, by path, grouped by kind of change
+/// An object whose content and metadata both changed shows up under both
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ManifestDiff {
+    pub added: Vec<(PathBuf, Cid)>,
+    pub removed: Vec<(PathBuf, Cid)>,
+    /// Objects whose content changed, with their cids before and after
+    pub content: Vec<(PathBuf, Cid, Cid)>,
+    /// Changes to the metadata of objects in both manifests
+    pub metadata: Vec<(PathBuf, MetadataChange)>,
+}
+
+impl ManifestDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.content.is_empty()
+            && self.metadata.is_empty()
+    }
+
+    /// Note how an object at a path in both manifests changed, if it did
+    pub fn compare(&mut self, path: &PathBuf, previous: &Object, object: &Object) {
+        if previous.cid() != object.cid() {
+            self.content
+                .push((path.clone(), *previous.cid(), *object.cid()));
+        }
+        let schema_names: BTreeSet<&String> = previous
+            .metadata()
+            .keys()
+            .chain(object.metadata().keys())
+            .collect();
+        for schema_name in schema_names {
+            let from = previous.metadata_entry(schema_name);
+            let to = object.metadata_entry(schema_name);
+            if from != to {
+                let change = MetadataChange::Entry {
+                    schema_name: schema_name.clone(),
+                    from: from.cloned(),
+                    to: to.cloned(),
+                };
+                self.metadata.push((path.clone(), change));
+            }
+        }
+        if previous.mtime() != object.mtime() {
+            let change = MetadataChange::Mtime {
+                from: previous.mtime().cloned(),
+                to: object.mtime().cloned(),
+            };
+            self.metadata.push((path.clone(), change));
+        }
+    }
+}
+
+/// A change to one piece of an object's metadata
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetadataChange {
+    /// A schema's entry was set, edited, or cleared
+    Entry {
+        schema_name: String,
+        from: Option<Value>,
+        to: Option<Value>,
+    },
+    /// The recorded modification time changed
+    Mtime {
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    },
+}
+
+/// A manifest stored as only the entries that changed since its previous root's
+/// Readers rebuild the full manifest by applying deltas, oldest first, on top of
+/// the nearest full manifest along the previous_root chain. Schemas are small, so
+/// each delta carries them whole
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DeltaManifest {
+    /// The root this applies on top of -- always the previous root
+    #[serde(with = "super::cid_serde")]
+    delta_of: Cid,
+    /// How many deltas deep this is along the chain, counting itself, so writers can
+    /// tell when to start over with a whole manifest without walking the chain
+    #[serde(default = "first_delta")]
+    depth: usize,
+    #[serde(default = "current_schema_version")]
+    schema_version: u64,
+    version: Version,
+    schema: Schema,
+    /// Objects added or changed since the previous root
+    changed: ObjectSet,
+    removed: Vec<PathBuf>,
+}
+
+fn first_delta() -> usize {
+    1
+}
+
+impl DeltaManifest {
+    pub fn delta_of(&self) -> &Cid {
+        &self.delta_of
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Whether the delta changes any objects at all
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.removed.is_empty()
+    }
+
+    /// Rebuild the full manifest, given the one of the root this applies on top of
+    pub fn apply(&self, parent: &DorStore) -> DorStore {
+        let mut dor_store = parent.clone();
+        for path in self.removed.iter() {
+            dor_store.remove_object(path);
+        }
+        for (path, object) in self.changed.iter() {
+            dor_store.insert_object(path.clone(), object.clone());
+        }
+        dor_store.previous_root = self.delta_of;
+        dor_store.schema_version = self.schema_version;
+        dor_store.version = self.version.clone();
+        dor_store.schema = self.schema.clone();
+        dor_store
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -94,3 +308,98 @@ impl Version {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_utils::cid;
+
+    #[test]
+    fn deltas_rebuild_the_manifest() {
+        let mut parent = DorStore::default();
+        parent.insert_object(PathBuf::from("kept"), Object::new(cid(1)));
+        parent.insert_object(PathBuf::from("changed"), Object::new(cid(2)));
+        parent.insert_object(PathBuf::from("removed"), Object::new(cid(3)));
+
+        let mut next = parent.clone();
+        next.set_previous_root(cid(9));
+        next.update_object(&PathBuf::from("changed"), cid(4));
+        next.remove_object(&PathBuf::from("removed"));
+        next.insert_object(PathBuf::from("added"), Object::new(cid(5)));
+        next.insert_schema_entry(&"schema".to_string(), &vec!["field".to_string()]);
+
+        let delta = next.delta_from(&parent, 3);
+        assert_eq!(delta.delta_of(), &cid(9));
+        assert_eq!(delta.depth(), 3);
+        assert_eq!(delta.changed.len(), 2);
+        assert_eq!(delta.removed, vec![PathBuf::from("removed")]);
+        assert_eq!(delta.apply(&parent), next);
+
+        let mut unchanged = parent.clone();
+        unchanged.set_previous_root(cid(9));
+        assert!(unchanged.delta_from(&parent, 1).is_empty());
+    }
+
+    #[test]
+    fn diffs_keep_metadata_apart_from_content() {
+        let schema_name = "review".to_string();
+        let mut a = DorStore::default();
+        a.insert_object(PathBuf::from("retagged"), Object::new(cid(1)));
+        a.insert_object(PathBuf::from("edited"), Object::new(cid(2)));
+        a.insert_object(PathBuf::from("removed"), Object::new(cid(3)));
+        a.tag_object(
+            &PathBuf::from("retagged"),
+            &schema_name,
+            &Value::from("draft"),
+        );
+
+        let mut b = a.clone();
+        b.tag_object(
+            &PathBuf::from("retagged"),
+            &schema_name,
+            &Value::from("final"),
+        );
+        b.update_object(&PathBuf::from("edited"), cid(4));
+        b.remove_object(&PathBuf::from("removed"));
+        b.insert_object(PathBuf::from("added"), Object::new(cid(5)));
+
+        let diff = a.diff(&b);
+        assert_eq!(diff.added, vec![(PathBuf::from("added"), cid(5))]);
+        assert_eq!(diff.removed, vec![(PathBuf::from("removed"), cid(3))]);
+        assert_eq!(
+            diff.content,
+            vec![(PathBuf::from("edited"), cid(2), cid(4))]
+        );
+        assert_eq!(
+            diff.metadata,
+            vec![(
+                PathBuf::from("retagged"),
+                MetadataChange::Entry {
+                    schema_name,
+                    from: Some(Value::from("draft")),
+                    to: Some(Value::from("final")),
+                }
+            )]
+        );
+        assert!(a.diff(&a).is_empty());
+    }
+
+    #[test]
+    fn legacy_paths_load_normalized_or_not_at_all() {
+        let manifest = |paths: &[&str]| {
+            let mut manifest = serde_json::to_value(DorStore::default()).unwrap();
+            for path in paths {
+                manifest["object_set"][path] = serde_json::to_value(Object::new(cid(1))).unwrap();
+            }
+            serde_json::from_value::<DorStore>(manifest)
+        };
+
+        let dor_store = manifest(&["./a/b", "c\\d"]).unwrap();
+        assert!(dor_store.get_object(&PathBuf::from("a/b")).is_some());
+        assert!(dor_store.objects().contains_key(&PathBuf::from("c/d")));
+
+        assert!(manifest(&["a/../b"]).is_err());
+        assert!(manifest(&["a", "./a"]).is_err());
+    }
+}
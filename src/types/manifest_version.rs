@@ -0,0 +1,115 @@
+use serde_json::Value;
+
+/// Current version of the manifest schema, written into every manifest
+/// Bump this and add a migration below whenever the shape of a manifest changes, so
+/// older readers refuse manifests they'd otherwise mis-parse
+pub const MANIFEST_SCHEMA_VERSION: u64 = 1;
+
+pub(super) fn current_schema_version() -> u64 {
+    MANIFEST_SCHEMA_VERSION
+}
+
+/// Upgrade a raw manifest, whole or delta, to the current schema version
+/// Manifests written before versioning are treated as version 0
+/// # Returns the migrated manifest, and whether anything changed
+pub fn migrate_manifest(mut manifest: Value) -> Result<(Value, bool), ManifestVersionError> {
+    let version = match manifest.get("schema_version") {
+        None => 0,
+        Some(version) => version
+            .as_u64()
+            .ok_or(ManifestVersionError::Invalid(version.to_string()))?,
+    };
+    if version > MANIFEST_SCHEMA_VERSION {
+        return Err(ManifestVersionError::Unsupported(version));
+    }
+
+    for from in version..MANIFEST_SCHEMA_VERSION {
+        match from {
+            0 => v0_to_v1(&mut manifest)?,
+            _ => return Err(ManifestVersionError::MissingMigration(from)),
+        }
+    }
+    manifest["schema_version"] = Value::from(MANIFEST_SCHEMA_VERSION);
+
+    Ok((manifest, version != MANIFEST_SCHEMA_VERSION))
+}
+
+/// v1 only started recording the schema version -- the shape is otherwise the same
+fn v0_to_v1(manifest: &mut Value) -> Result<(), ManifestVersionError> {
+    if !manifest.is_object() {
+        return Err(ManifestVersionError::Invalid("not an object".to_string()));
+    }
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ManifestVersionError {
+    #[error("invalid manifest schema version: {0}")]
+    Invalid(String),
+    #[error(
+        "manifest uses schema version {0}, but we only read up to {} -- upgrade to read it",
+        MANIFEST_SCHEMA_VERSION
+    )]
+    Unsupported(u64),
+    #[error("no migration from manifest schema version {0} -- this is a bug")]
+    MissingMigration(u64),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use cid::Cid;
+
+    use super::super::{DorStore, Object};
+    use super::*;
+
+    fn dor_store() -> DorStore {
+        let mut dor_store = DorStore::default();
+        dor_store.insert_object(PathBuf::from("a"), Object::new(Cid::default()));
+        dor_store
+    }
+
+    fn v0_manifest() -> Value {
+        let mut manifest = serde_json::to_value(dor_store()).unwrap();
+        manifest.as_object_mut().unwrap().remove("schema_version");
+        manifest
+    }
+
+    #[test]
+    fn migrate_v0() {
+        let (manifest, migrated) = migrate_manifest(v0_manifest()).unwrap();
+        assert!(migrated);
+        assert_eq!(manifest["schema_version"], MANIFEST_SCHEMA_VERSION);
+        let dor_store: DorStore = serde_json::from_value(manifest).unwrap();
+        assert_eq!(dor_store.schema_version(), MANIFEST_SCHEMA_VERSION);
+        assert_eq!(dor_store.objects(), self::dor_store().objects());
+    }
+
+    #[test]
+    fn current_round_trips() {
+        let manifest = serde_json::to_value(dor_store()).unwrap();
+        assert_eq!(manifest["schema_version"], MANIFEST_SCHEMA_VERSION);
+        let (migrated_manifest, migrated) = migrate_manifest(manifest.clone()).unwrap();
+        assert!(!migrated);
+        assert_eq!(migrated_manifest, manifest);
+        let dor_store: DorStore = serde_json::from_value(migrated_manifest).unwrap();
+        assert_eq!(dor_store, self::dor_store());
+    }
+
+    #[test]
+    fn migrate_future_errors() {
+        let mut manifest = serde_json::to_value(dor_store()).unwrap();
+        manifest["schema_version"] = Value::from(MANIFEST_SCHEMA_VERSION + 1);
+        assert!(matches!(
+            migrate_manifest(manifest),
+            Err(ManifestVersionError::Unsupported(_))
+        ));
+        let mut manifest = serde_json::to_value(dor_store()).unwrap();
+        manifest["schema_version"] = Value::from("one");
+        assert!(matches!(
+            migrate_manifest(manifest),
+            Err(ManifestVersionError::Invalid(_))
+        ));
+    }
+}
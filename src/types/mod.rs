@@ -1,6 +1,24 @@
+mod cid_info;
+pub(crate) mod cid_serde;
 mod dor_store;
+mod manifest_version;
+mod normalize;
 mod object;
+mod patch;
+mod replication;
 mod schema;
+#[cfg(feature = "sqlite")]
+mod sqlite_manifest;
+mod tags;
 
-pub use dor_store::DorStore;
+pub use cid_info::{hash_code, hash_name, CidInfo};
+pub use dor_store::{DeltaManifest, DorStore, ManifestDiff, MetadataChange};
+pub use manifest_version::{migrate_manifest, ManifestVersionError, MANIFEST_SCHEMA_VERSION};
+pub use normalize::normalize_path;
 pub use object::Object;
+pub use patch::Patch;
+pub use replication::{Replica, Replication, ReplicationError};
+pub use schema::Schema;
+#[cfg(feature = "sqlite")]
+pub use sqlite_manifest::{SqliteManifest, SqliteManifestError};
+pub use tags::{is_tag_name, Tag, Tags};
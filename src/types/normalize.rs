@@ -0,0 +1,86 @@
+use std::path::{Path, PathBuf};
+
+/// Normalize a path into the canonical form we store in a DorStore:
+/// relative, forward-slash separated, without `.` or `..` components,
+/// and without empty or trailing separators. Backslashes are treated as
+/// separators so manifests built on Windows match ones built elsewhere
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut components: Vec<&str> = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            // Paths can't escape the root of the store
+            ".." => {
+                components.pop();
+            }
+            // Drop drive prefixes like `C:`
+            component if components.is_empty() && is_drive_prefix(component) => {}
+            component => components.push(component),
+        }
+    }
+    PathBuf::from(components.join("/"))
+}
+
+/// Whether a path, separated either way, steps up a directory anywhere
+pub fn has_parent_component(path: &Path) -> bool {
+    path.to_string_lossy()
+        .split(['/', '\\'])
+        .any(|component| component == "..")
+}
+
+fn is_drive_prefix(component: &str) -> bool {
+    let bytes = component.as_bytes();
+    bytes.len() == 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalize(path: &str) -> String {
+        normalize_path(Path::new(path))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    #[test]
+    fn normalize_clean_path_is_noop() {
+        assert_eq!(normalize("a/b/c.txt"), "a/b/c.txt");
+    }
+
+    #[test]
+    fn normalize_strips_leading_dot_and_slash() {
+        assert_eq!(normalize("./a/b"), "a/b");
+        assert_eq!(normalize("/a/b"), "a/b");
+        assert_eq!(normalize("a/b/"), "a/b");
+    }
+
+    #[test]
+    fn normalize_collapses_redundant_separators() {
+        assert_eq!(normalize("a//b///c"), "a/b/c");
+        assert_eq!(normalize("a/./b/./c"), "a/b/c");
+    }
+
+    #[test]
+    fn normalize_resolves_parent_components() {
+        assert_eq!(normalize("./a/../b"), "b");
+        assert_eq!(normalize("a/b/../../c"), "c");
+        assert_eq!(normalize("../a"), "a");
+    }
+
+    #[test]
+    fn normalize_windows_paths() {
+        assert_eq!(normalize("a\\b\\c.txt"), "a/b/c.txt");
+        assert_eq!(normalize(".\\a\\..\\b"), "b");
+        assert_eq!(normalize("C:\\data\\a.txt"), "data/a.txt");
+        assert_eq!(normalize("a\\\\b/c"), "a/b/c");
+    }
+
+    #[test]
+    fn parent_components_are_found_either_way() {
+        assert!(has_parent_component(Path::new("../a")));
+        assert!(has_parent_component(Path::new("a\\..\\b")));
+        assert!(!has_parent_component(Path::new("a/..b/c..")));
+    }
+}
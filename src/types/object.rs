@@ -5,13 +5,56 @@ use std::path::PathBuf;
 
 use chrono::{DateTime, Utc};
 use cid::Cid;
-use serde::{Deserialize, Serialize};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::Value;
 
-#[derive(Default, Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+use super::cid_info::CidInfo;
+use super::normalize::{has_parent_component, normalize_path};
+use super::replication::Replication;
+
+#[derive(Default, Debug, Serialize, Clone, PartialEq, Eq)]
 /// A set of Objects within DorFS
+/// Paths are normalized on load, so legacy manifests match ones written now
+/// Legacy paths with `..` in them, or that normalize onto another path, are refused
+/// rather than guessed at
 pub struct ObjectSet(BTreeMap<PathBuf, Object>);
 
+impl<'de> Deserialize<'de> for ObjectSet {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let objects = BTreeMap::<PathBuf, Object>::deserialize(deserializer)?;
+        let mut normalized = BTreeMap::new();
+        // Where each normalized path came from, to name both sides of a collision
+        let mut sources = BTreeMap::new();
+        for (path, object) in objects {
+            if has_parent_component(&path) {
+                return Err(D::Error::custom(format!(
+                    "object path {:?} has a `..` component",
+                    path
+                )));
+            }
+            let normalized_path = normalize_path(&path);
+            if let Some(other) = sources.insert(normalized_path.clone(), path.clone()) {
+                return Err(D::Error::custom(format!(
+                    "object paths {:?} and {:?} both normalize to {:?}",
+                    other, path, normalized_path
+                )));
+            }
+            normalized.insert(normalized_path, object);
+        }
+        Ok(Self(normalized))
+    }
+}
+
+impl From<BTreeMap<PathBuf, Object>> for ObjectSet {
+    fn from(objects: BTreeMap<PathBuf, Object>) -> Self {
+        Self(objects)
+    }
+}
+
 impl Deref for ObjectSet {
     type Target = BTreeMap<PathBuf, Object>;
 
@@ -32,12 +75,33 @@ impl DerefMut for ObjectSet {
 /// - updated_at: the time the file was last updated
 /// - cid: the cid of the file (this should be an IPLD link)
 /// - metadata: Map from a schema name to a JSON object
+/// - link: the target of the symlink this object was stored as, if it was one
+///   The cid is then that of the target path, not of anything it points at
+/// - mtime: the file's modification time when it was staged, if the device records
+///   them. Restored on pull and export. An mtime is filesystem state rather than
+///   content, so recording them makes root cids depend on when files were written,
+///   and keeps the same files staged on two machines from giving the same root --
+///   which is why it's off by default
+/// - external: whether the object was added by its cid alone, i.e. content already
+///   on ipfs we never had the bytes of. It's pinned by cid rather than uploaded, and
+///   isn't expected in the working dir
+/// - replication: which of the device's replicas pushes copy the object to, if not
+///   the device's default
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct Object {
     created_at: DateTime<Utc>,
     updated_at: DateTime<Utc>,
+    #[serde(with = "super::cid_serde")]
     cid: Cid,
     metadata: BTreeMap<String, Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    link: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mtime: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    external: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    replication: Option<Replication>,
 }
 
 impl Object {
@@ -47,14 +111,62 @@ impl Object {
             updated_at: Utc::now(),
             cid,
             metadata: BTreeMap::new(),
+            link: None,
+            mtime: None,
+            external: false,
+            replication: None,
         }
     }
 
+    /// An object for content already on ipfs, referenced by its cid alone
+    pub fn external(cid: Cid) -> Self {
+        Self {
+            external: true,
+            ..Self::new(cid)
+        }
+    }
+
+    /// Whether the object was added by cid, without a file of ours behind it
+    pub fn is_external(&self) -> bool {
+        self.external
+    }
+
     pub fn update(&mut self, cid: Cid) {
         self.cid = cid;
         self.updated_at = Utc::now();
     }
 
+    /// Set or clear the symlink target this object is stored as
+    pub fn set_link(&mut self, link: Option<PathBuf>) {
+        self.link = link;
+    }
+
+    /// The symlink target this object is stored as, if it's a link
+    pub fn link(&self) -> Option<&PathBuf> {
+        self.link.as_ref()
+    }
+
+    /// Set or clear the modification time the object's file is restored with
+    pub fn set_mtime(&mut self, mtime: Option<DateTime<Utc>>) {
+        self.mtime = mtime;
+    }
+
+    /// The modification time the object's file is restored with, if one was recorded
+    pub fn mtime(&self) -> Option<&DateTime<Utc>> {
+        self.mtime.as_ref()
+    }
+
+    /// Set or clear the replicas pushes copy the object to
+    pub fn set_replication(&mut self, replication: Option<Replication>) {
+        self.replication = replication;
+        self.updated_at = Utc::now();
+    }
+
+    /// The replicas pushes copy the object to, if it doesn't use the device's default
+    pub fn replication(&self) -> Option<&Replication> {
+        self.replication.as_ref()
+    }
+
     pub fn created_at(&self) -> &DateTime<Utc> {
         &self.created_at
     }
@@ -67,6 +179,15 @@ impl Object {
         &self.cid
     }
 
+    /// Decode the version, codec, and hash function of the object's cid
+    pub fn cid_info(&self) -> CidInfo {
+        CidInfo::of(&self.cid)
+    }
+
+    pub fn metadata(&self) -> &BTreeMap<String, Value> {
+        &self.metadata
+    }
+
     pub fn metadata_entry(&self, schema_name: &String) -> Option<&Value> {
         self.metadata.get(schema_name)
     }
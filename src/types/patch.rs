@@ -0,0 +1,210 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use cid::Cid;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use super::dor_store::DorStore;
+use super::normalize::normalize_path;
+use super::object::Object;
+use super::schema::Schema;
+
+/// A portable description of going from one root to another: every object added,
+/// changed, or removed, whole, so applying it to the first root's manifest
+/// reproduces the second's objects and schema
+/// Patches may come from anywhere, so paths that aren't already normalized, or
+/// that would reach outside the store, are refused on load rather than cleaned up
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Patch {
+    /// The root this applies on top of
+    #[serde(with = "super::cid_serde")]
+    from: Cid,
+    /// The root it was taken to
+    #[serde(with = "super::cid_serde")]
+    to: Cid,
+    /// Objects only in `to`
+    #[serde(deserialize_with = "normalized_objects")]
+    added: BTreeMap<PathBuf, Object>,
+    /// Objects whose content or metadata changed, as they are in `to`
+    #[serde(deserialize_with = "normalized_objects")]
+    modified: BTreeMap<PathBuf, Object>,
+    /// Objects only in `from`, as they were there
+    #[serde(deserialize_with = "normalized_objects")]
+    removed: BTreeMap<PathBuf, Object>,
+    /// The schema of `to`, carried whole
+    schema: Schema,
+}
+
+/// Read a map of objects, refusing any path normalizing would change -- an
+/// absolute path, or one with `..` in it, could land outside the working dir
+fn normalized_objects<'de, D>(deserializer: D) -> Result<BTreeMap<PathBuf, Object>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let objects = BTreeMap::<PathBuf, Object>::deserialize(deserializer)?;
+    for path in objects.keys() {
+        if path.as_os_str().is_empty() || normalize_path(path) != *path {
+            return Err(D::Error::custom(format!(
+                "{:?} isn't a normalized path within the store",
+                path
+            )));
+        }
+    }
+    Ok(objects)
+}
+
+impl Patch {
+    /// Describe the change from the manifest of one root to that of another
+    pub fn new(from: Cid, from_store: &DorStore, to: Cid, to_store: &DorStore) -> Self {
+        let mut added = BTreeMap::new();
+        let mut modified = BTreeMap::new();
+        for (path, object) in to_store.objects().iter() {
+            match from_store.objects().get(path) {
+                None => {
+                    added.insert(path.clone(), object.clone());
+                }
+                Some(previous)
+                    if previous.cid() != object.cid()
+                        || previous.metadata() != object.metadata()
+                        || previous.mtime() != object.mtime() =>
+                {
+                    modified.insert(path.clone(), object.clone());
+                }
+                Some(_) => {}
+            }
+        }
+        let removed = from_store
+            .objects()
+            .iter()
+            .filter(|(path, _)| !to_store.objects().contains_key(*path))
+            .map(|(path, object)| (path.clone(), object.clone()))
+            .collect();
+        Self {
+            from,
+            to,
+            added,
+            modified,
+            removed,
+            schema: to_store.schema().clone(),
+        }
+    }
+
+    pub fn from(&self) -> &Cid {
+        &self.from
+    }
+
+    pub fn to(&self) -> &Cid {
+        &self.to
+    }
+
+    pub fn added(&self) -> &BTreeMap<PathBuf, Object> {
+        &self.added
+    }
+
+    pub fn modified(&self) -> &BTreeMap<PathBuf, Object> {
+        &self.modified
+    }
+
+    pub fn removed(&self) -> &BTreeMap<PathBuf, Object> {
+        &self.removed
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.modified.is_empty() && self.removed.is_empty()
+    }
+
+    /// Apply the patch on top of a manifest holding the objects of `from`
+    /// # Returns the patched manifest, or the first path that doesn't match what the
+    ///   patch expects of `from`
+    pub fn apply(&self, base: &DorStore) -> Result<DorStore, PathBuf> {
+        for path in self.added.keys() {
+            if base.objects().contains_key(path) {
+                return Err(path.clone());
+            }
+        }
+        for path in self.modified.keys() {
+            if !base.objects().contains_key(path) {
+                return Err(path.clone());
+            }
+        }
+        for (path, object) in self.removed.iter() {
+            if base.objects().get(path).map(|o| o.cid()) != Some(object.cid()) {
+                return Err(path.clone());
+            }
+        }
+
+        let mut dor_store = base.clone();
+        for path in self.removed.keys() {
+            dor_store.remove_object(path);
+        }
+        for (path, object) in self.added.iter().chain(self.modified.iter()) {
+            dor_store.insert_object(path.clone(), object.clone());
+        }
+        dor_store.set_schema(self.schema.clone());
+        Ok(dor_store)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::test_utils::cid;
+
+    #[test]
+    fn patches_reproduce_the_transition() {
+        let mut a = DorStore::default();
+        a.insert_object(PathBuf::from("kept"), Object::new(cid(1)));
+        a.insert_object(PathBuf::from("edited"), Object::new(cid(2)));
+        a.insert_object(PathBuf::from("removed"), Object::new(cid(3)));
+
+        let mut b = a.clone();
+        b.update_object(&PathBuf::from("edited"), cid(4));
+        b.tag_object(
+            &PathBuf::from("kept"),
+            &"review".to_string(),
+            &Value::from("ok"),
+        );
+        b.remove_object(&PathBuf::from("removed"));
+        b.insert_object(PathBuf::from("added"), Object::new(cid(5)));
+        b.insert_schema_entry(&"review".to_string(), &vec!["status".to_string()]);
+
+        let patch = Patch::new(cid(10), &a, cid(11), &b);
+        assert_eq!(patch.added().len(), 1);
+        assert_eq!(patch.modified().len(), 2);
+        assert_eq!(patch.removed().len(), 1);
+
+        let json = serde_json::to_string(&patch).unwrap();
+        let patch: Patch = serde_json::from_str(&json).unwrap();
+        let patched = patch.apply(&a).unwrap();
+        assert_eq!(patched.objects(), b.objects());
+        assert_eq!(patched.schema(), b.schema());
+
+        // A base missing what's being removed is the wrong base
+        assert_eq!(patch.apply(&b), Err(PathBuf::from("added")));
+        assert!(Patch::new(cid(10), &a, cid(10), &a).is_empty());
+    }
+
+    #[test]
+    fn patches_with_escaping_paths_are_refused() {
+        let a = DorStore::default();
+        let mut b = DorStore::default();
+        b.insert_object(PathBuf::from("ok"), Object::new(cid(1)));
+        let patch = serde_json::to_value(Patch::new(cid(10), &a, cid(11), &b)).unwrap();
+        assert!(serde_json::from_value::<Patch>(patch.clone()).is_ok());
+
+        for path in ["../../x", "/etc/x", "a/../../x", "C:/x", ""] {
+            let mut patch = patch.clone();
+            let added = patch["added"].as_object_mut().unwrap();
+            let object = added.remove("ok").unwrap();
+            added.insert(path.to_string(), object);
+            assert!(
+                serde_json::from_value::<Patch>(patch).is_err(),
+                "{:?} was let in",
+                path
+            );
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// Somewhere a push keeps copies of an object's blocks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Replica {
+    /// The ipfs node we push to. It holds every object -- the other replicas copy
+    /// from it, and readers fall back to it
+    Node,
+    /// The node's remote pinning service
+    PinService,
+    /// The device's S3 mirror
+    S3,
+}
+
+impl Display for Replica {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Replica::Node => "node",
+            Replica::PinService => "pin_service",
+            Replica::S3 => "s3",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl FromStr for Replica {
+    type Err = ReplicationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "node" => Ok(Replica::Node),
+            "pin_service" | "pin-service" => Ok(Replica::PinService),
+            "s3" => Ok(Replica::S3),
+            _ => Err(ReplicationError::UnknownReplica(s.to_string())),
+        }
+    }
+}
+
+/// Which of a device's replicas a push copies an object to
+/// Roots always go to every replica, so whatever an object was kept on can be found
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Replication {
+    /// Every replica the device has
+    #[default]
+    All,
+    /// This many replicas, counting the node, taken in the order node, pinning
+    /// service, S3 mirror from those the device has
+    Count(usize),
+    /// The node, and these replicas
+    Replicas(BTreeSet<Replica>),
+}
+
+impl Replication {
+    /// Pick the replicas an object goes to
+    /// # Args
+    /// - available: the replicas the device has, the node first
+    /// # Returns the replicas to copy the object to, always including the node
+    pub fn resolve(&self, available: &[Replica]) -> Result<BTreeSet<Replica>, ReplicationError> {
+        let mut replicas = BTreeSet::from([Replica::Node]);
+        match self {
+            Replication::All => replicas.extend(available.iter().copied()),
+            Replication::Count(count) => {
+                if *count == 0 || *count > available.len() {
+                    return Err(ReplicationError::Count(*count, available.len()));
+                }
+                replicas.extend(available.iter().copied().take(*count));
+            }
+            Replication::Replicas(wanted) => {
+                if let Some(missing) = wanted.iter().find(|replica| !available.contains(replica)) {
+                    return Err(ReplicationError::Unavailable(*missing));
+                }
+                replicas.extend(wanted.iter().copied());
+            }
+        }
+        Ok(replicas)
+    }
+}
+
+impl Display for Replication {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Replication::All => write!(f, "all"),
+            Replication::Count(count) => write!(f, "{}", count),
+            Replication::Replicas(replicas) => {
+                let names: Vec<String> = replicas.iter().map(Replica::to_string).collect();
+                write!(f, "{}", names.join(","))
+            }
+        }
+    }
+}
+
+/// Parse `all`, a replica count, or a comma separated list of replicas
+impl FromStr for Replication {
+    type Err = ReplicationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "all" {
+            return Ok(Replication::All);
+        }
+        if let Ok(count) = s.parse::<usize>() {
+            if count == 0 {
+                return Err(ReplicationError::ZeroCount);
+            }
+            return Ok(Replication::Count(count));
+        }
+        let replicas = s
+            .split(',')
+            .map(|name| name.trim().parse())
+            .collect::<Result<BTreeSet<Replica>, _>>()?;
+        Ok(Replication::Replicas(replicas))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ReplicationError {
+    #[error("no replica named {0:?} -- use node, pin_service, or s3")]
+    UnknownReplica(String),
+    #[error("objects need at least one replica")]
+    ZeroCount,
+    #[error("wants {0} replicas, but the device has {1}")]
+    Count(usize, usize),
+    #[error("wants a replica on {0}, which the device doesn't have")]
+    Unavailable(Replica),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replicas(replicas: &[Replica]) -> BTreeSet<Replica> {
+        replicas.iter().copied().collect()
+    }
+
+    #[test]
+    fn policies_pick_from_what_the_device_has() {
+        let everything = [Replica::Node, Replica::PinService, Replica::S3];
+        let no_pin_service = [Replica::Node, Replica::S3];
+
+        assert_eq!(
+            Replication::All.resolve(&no_pin_service),
+            Ok(replicas(&no_pin_service))
+        );
+        assert_eq!(
+            Replication::Count(1).resolve(&everything),
+            Ok(replicas(&[Replica::Node]))
+        );
+        assert_eq!(
+            Replication::Count(2).resolve(&everything),
+            Ok(replicas(&[Replica::Node, Replica::PinService]))
+        );
+        assert_eq!(
+            Replication::Count(2).resolve(&no_pin_service),
+            Ok(replicas(&[Replica::Node, Replica::S3]))
+        );
+        assert_eq!(
+            Replication::Count(3).resolve(&no_pin_service),
+            Err(ReplicationError::Count(3, 2))
+        );
+
+        // The node is always kept
+        let s3_only = Replication::Replicas(replicas(&[Replica::S3]));
+        assert_eq!(
+            s3_only.resolve(&everything),
+            Ok(replicas(&[Replica::Node, Replica::S3]))
+        );
+        let pin_service_only = Replication::Replicas(replicas(&[Replica::PinService]));
+        assert_eq!(
+            pin_service_only.resolve(&no_pin_service),
+            Err(ReplicationError::Unavailable(Replica::PinService))
+        );
+    }
+
+    #[test]
+    fn policies_parse_and_print() {
+        for policy in ["all", "2", "node", "pin_service,s3"] {
+            assert_eq!(policy.parse::<Replication>().unwrap().to_string(), policy);
+        }
+        assert_eq!(
+            "s3, pin-service".parse(),
+            Ok(Replication::Replicas(replicas(&[
+                Replica::PinService,
+                Replica::S3
+            ])))
+        );
+        assert_eq!("0".parse::<Replication>(), Err(ReplicationError::ZeroCount));
+        assert!(matches!(
+            "s3,tape".parse::<Replication>(),
+            Err(ReplicationError::UnknownReplica(name)) if name == "tape"
+        ));
+
+        // As they're kept in manifests
+        let policy = Replication::Replicas(replicas(&[Replica::S3]));
+        let json = serde_json::to_value(&policy).unwrap();
+        assert_eq!(json, serde_json::json!({ "replicas": ["s3"] }));
+        assert_eq!(serde_json::from_value::<Replication>(json).unwrap(), policy);
+    }
+}
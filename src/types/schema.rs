@@ -21,4 +21,8 @@ impl Schema {
     pub fn get(&self, name: &str) -> Option<&Vec<String>> {
         self.0.get(name)
     }
+
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.0.keys()
+    }
 }
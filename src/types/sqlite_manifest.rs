@@ -0,0 +1,431 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use cid::Cid;
+use rusqlite::{params, Connection, OptionalExtension, Rows, Statement};
+use serde_json::Value;
+
+use super::dor_store::{DorStore, ManifestDiff};
+use super::normalize::normalize_path;
+use super::object::Object;
+
+/// Tables of a manifest database
+/// - objects: one row per object, by normalized path, with its cid, size and
+///   metadata pulled out as columns to query on. The whole object is kept as json,
+///   so nothing is lost on the way back
+/// - store: the rest of the manifest -- previous root, version, schema -- as json
+///   in a single row
+/// Objects don't record their sizes, so a size is only there when the writer knew
+/// it, and NULL otherwise
+const CREATE_TABLES: &str = "
+    CREATE TABLE IF NOT EXISTS objects (
+        path TEXT PRIMARY KEY NOT NULL,
+        cid TEXT NOT NULL,
+        size INTEGER,
+        metadata TEXT NOT NULL,
+        object TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS objects_cid ON objects (cid);
+    CREATE TABLE IF NOT EXISTS store (
+        id INTEGER PRIMARY KEY CHECK (id = 0),
+        header TEXT NOT NULL
+    );
+";
+/// Key the objects of a manifest are serialized under
+const OBJECT_SET_KEY: &str = "object_set";
+
+/// A DorStore kept in a SQLite database rather than a json file, so stores with
+/// hundreds of thousands of objects can be read, edited, and diffed a row at a
+/// time instead of loaded whole
+/// Converts to and from a DorStore losslessly -- it's the DorStore that gets
+/// hashed and pushed, so roots don't depend on which form a store was kept in
+/// Only copies of manifests are kept this way. The working dir's change log holds
+/// each staged version of its manifest whole, as json, so add, stage, status, and
+/// push all load them whole whether or not the feature is on -- keeping those in
+/// SQLite would mean a change log of databases, which this isn't. A database is a
+/// copy to query, edit, and diff outside of them, converted with `manifest-db`
+pub struct SqliteManifest {
+    conn: Connection,
+}
+
+impl SqliteManifest {
+    /// Open a manifest database, creating an empty one if there's none at the path
+    pub fn open(path: &Path) -> Result<Self, SqliteManifestError> {
+        Self::init(Connection::open(path)?)
+    }
+
+    /// Open a manifest database held only in memory
+    pub fn open_in_memory() -> Result<Self, SqliteManifestError> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self, SqliteManifestError> {
+        conn.execute_batch(CREATE_TABLES)?;
+        // Databases written before objects had a size column
+        let has_size = conn
+            .prepare("SELECT 1 FROM pragma_table_info('objects') WHERE name = 'size'")?
+            .exists([])?;
+        if !has_size {
+            conn.execute("ALTER TABLE objects ADD COLUMN size INTEGER", [])?;
+        }
+        Ok(Self { conn })
+    }
+
+    /// Replace everything in the database with a manifest, in one transaction
+    /// # Args
+    /// - sizes: sizes of the objects the writer knows them for, by path
+    pub fn write_dor_store(
+        &mut self,
+        dor_store: &DorStore,
+        sizes: &BTreeMap<PathBuf, u64>,
+    ) -> Result<(), SqliteManifestError> {
+        let mut header = serde_json::to_value(dor_store)?;
+        if let Value::Object(header) = &mut header {
+            header.remove(OBJECT_SET_KEY);
+        }
+
+        let tx = self.conn.transaction()?;
+        tx.execute("DELETE FROM objects", [])?;
+        tx.execute(
+            "INSERT OR REPLACE INTO store (id, header) VALUES (0, ?1)",
+            params![header.to_string()],
+        )?;
+        {
+            let mut insert = tx.prepare(INSERT_OBJECT)?;
+            for (path, object) in dor_store.objects().iter() {
+                insert_object(&mut insert, path, object, sizes.get(path).copied())?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Read the whole manifest back out, i.e. to hash and push it
+    pub fn read_dor_store(&self) -> Result<DorStore, SqliteManifestError> {
+        let mut dor_store = self.read_header()?;
+        let mut select = self.conn.prepare(SELECT_OBJECTS)?;
+        let mut rows = select.query([])?;
+        while let Some((path, _cid, object)) = next_row(&mut rows)? {
+            dor_store.insert_object(PathBuf::from(path), serde_json::from_str(&object)?);
+        }
+        Ok(dor_store)
+    }
+
+    /// Read the manifest without any of its objects
+    pub fn read_header(&self) -> Result<DorStore, SqliteManifestError> {
+        let header: Option<String> = self
+            .conn
+            .query_row("SELECT header FROM store WHERE id = 0", [], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        let mut header = match header {
+            Some(header) => serde_json::from_str(&header)?,
+            None => serde_json::to_value(DorStore::default())?,
+        };
+        if let Value::Object(header) = &mut header {
+            header.insert(
+                OBJECT_SET_KEY.to_string(),
+                Value::Object(Default::default()),
+            );
+        }
+        Ok(serde_json::from_value(header)?)
+    }
+
+    /// How many objects the manifest holds
+    pub fn len(&self) -> Result<usize, SqliteManifestError> {
+        let len: i64 = self
+            .conn
+            .query_row("SELECT COUNT(*) FROM objects", [], |row| row.get(0))?;
+        Ok(len as usize)
+    }
+
+    pub fn is_empty(&self) -> Result<bool, SqliteManifestError> {
+        Ok(self.len()? == 0)
+    }
+
+    pub fn get_object(&self, path: &Path) -> Result<Option<Object>, SqliteManifestError> {
+        let object: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT object FROM objects WHERE path = ?1",
+                params![path_key(path)],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(object
+            .map(|object| serde_json::from_str(&object))
+            .transpose()?)
+    }
+
+    /// Insert or replace the object at a path, with its size if it's known
+    pub fn insert_object(
+        &self,
+        path: &Path,
+        object: &Object,
+        size: Option<u64>,
+    ) -> Result<(), SqliteManifestError> {
+        let mut insert = self.conn.prepare_cached(INSERT_OBJECT)?;
+        insert_object(&mut insert, path, object, size)
+    }
+
+    /// The size recorded for the object at a path, if there's one there and its
+    /// size was known
+    pub fn size_of(&self, path: &Path) -> Result<Option<u64>, SqliteManifestError> {
+        let size: Option<Option<i64>> = self
+            .conn
+            .query_row(
+                "SELECT size FROM objects WHERE path = ?1",
+                params![path_key(path)],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(size.flatten().map(|size| size as u64))
+    }
+
+    /// Total size of the objects under a directory whose sizes are known, and how
+    /// many objects there have none recorded
+    pub fn size_under(&self, dir: &Path) -> Result<(u64, usize), SqliteManifestError> {
+        let (lower, upper) = dir_range(dir);
+        let (size, unknown): (i64, i64) = self.conn.query_row(
+            "SELECT COALESCE(SUM(size), 0), COUNT(*) - COUNT(size) FROM objects
+             WHERE path >= ?1 AND path < ?2",
+            params![lower, upper],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        Ok((size as u64, unknown as usize))
+    }
+
+    /// # Returns whether there was an object at the path
+    pub fn remove_object(&self, path: &Path) -> Result<bool, SqliteManifestError> {
+        let removed = self.conn.execute(
+            "DELETE FROM objects WHERE path = ?1",
+            params![path_key(path)],
+        )?;
+        Ok(removed > 0)
+    }
+
+    /// Paths of objects referencing a cid
+    pub fn paths_of(&self, cid: &Cid) -> Result<Vec<PathBuf>, SqliteManifestError> {
+        let mut select = self
+            .conn
+            .prepare("SELECT path FROM objects WHERE cid = ?1 ORDER BY path")?;
+        let paths = select
+            .query_map(params![cid.to_string()], |row| row.get::<_, String>(0))?
+            .map(|path| path.map(PathBuf::from))
+            .collect::<Result<_, _>>()?;
+        Ok(paths)
+    }
+
+    /// Objects below a directory, without reading any others
+    /// The empty path is the root of the store, so every object is below it
+    pub fn objects_under(&self, dir: &Path) -> Result<Vec<(PathBuf, Object)>, SqliteManifestError> {
+        let (lower, upper) = dir_range(dir);
+        let mut select = self.conn.prepare(
+            "SELECT path, cid, object FROM objects WHERE path >= ?1 AND path < ?2 ORDER BY path",
+        )?;
+        let mut rows = select.query(params![lower, upper])?;
+        let mut objects = Vec::new();
+        while let Some((path, _cid, object)) = next_row(&mut rows)? {
+            objects.push((PathBuf::from(path), serde_json::from_str(&object)?));
+        }
+        Ok(objects)
+    }
+
+    /// Compare the objects of this manifest to those of `next`, as DorStore::diff
+    /// would, walking both in path order so only a row of each is held at a time
+    /// Changes come out in the databases' path order, which can differ from a
+    /// DorStore's where one path is a prefix of another
+    pub fn diff(&self, next: &SqliteManifest) -> Result<ManifestDiff, SqliteManifestError> {
+        let mut diff = ManifestDiff::default();
+        let mut ours = self.conn.prepare(SELECT_OBJECTS)?;
+        let mut theirs = next.conn.prepare(SELECT_OBJECTS)?;
+        let mut ours = ours.query([])?;
+        let mut theirs = theirs.query([])?;
+
+        let mut previous = next_row(&mut ours)?;
+        let mut current = next_row(&mut theirs)?;
+        loop {
+            let ordering = match (&previous, &current) {
+                (None, None) => break,
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                (Some((a, _, _)), Some((b, _, _))) => a.as_bytes().cmp(b.as_bytes()),
+            };
+            match ordering {
+                std::cmp::Ordering::Less => {
+                    let (path, cid, _) = previous.take().unwrap();
+                    diff.removed
+                        .push((PathBuf::from(path), Cid::try_from(cid)?));
+                    previous = next_row(&mut ours)?;
+                }
+                std::cmp::Ordering::Greater => {
+                    let (path, cid, _) = current.take().unwrap();
+                    diff.added.push((PathBuf::from(path), Cid::try_from(cid)?));
+                    current = next_row(&mut theirs)?;
+                }
+                std::cmp::Ordering::Equal => {
+                    let (path, _, from) = previous.take().unwrap();
+                    let (_, _, to) = current.take().unwrap();
+                    // Rows only need parsing when they differ at all
+                    if from != to {
+                        let from: Object = serde_json::from_str(&from)?;
+                        let to: Object = serde_json::from_str(&to)?;
+                        diff.compare(&PathBuf::from(path), &from, &to);
+                    }
+                    previous = next_row(&mut ours)?;
+                    current = next_row(&mut theirs)?;
+                }
+            }
+        }
+        Ok(diff)
+    }
+}
+
+const INSERT_OBJECT: &str = "INSERT OR REPLACE INTO objects (path, cid, size, metadata, object)
+    VALUES (?1, ?2, ?3, ?4, ?5)";
+const SELECT_OBJECTS: &str = "SELECT path, cid, object FROM objects ORDER BY path";
+
+/// How a path is keyed in the database -- normalized, as in a DorStore
+fn path_key(path: &Path) -> String {
+    normalize_path(path).to_string_lossy().to_string()
+}
+
+/// Bounds of the keys below a directory, as `lower <= key < upper`
+fn dir_range(dir: &Path) -> (String, String) {
+    let dir = path_key(dir);
+    // Keys are never empty, and nothing sorts before the empty string
+    if dir.is_empty() {
+        return (String::new(), char::MAX.to_string());
+    }
+    // '0' sorts right after '/', so this is every path starting with `dir/`
+    (format!("{}/", dir), format!("{}0", dir))
+}
+
+fn insert_object(
+    insert: &mut Statement<'_>,
+    path: &Path,
+    object: &Object,
+    size: Option<u64>,
+) -> Result<(), SqliteManifestError> {
+    insert.execute(params![
+        path_key(path),
+        object.cid().to_string(),
+        size.map(|size| size as i64),
+        serde_json::to_string(object.metadata())?,
+        serde_json::to_string(object)?,
+    ])?;
+    Ok(())
+}
+
+/// The next (path, cid, object) row of a SELECT_OBJECTS query
+fn next_row(rows: &mut Rows<'_>) -> Result<Option<(String, String, String)>, SqliteManifestError> {
+    match rows.next()? {
+        Some(row) => Ok(Some((row.get(0)?, row.get(1)?, row.get(2)?))),
+        None => Ok(None),
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SqliteManifestError {
+    #[error("sqlite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("serde json error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("cid error: {0}")]
+    Cid(#[from] cid::Error),
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_utils::cid;
+
+    fn manifest() -> DorStore {
+        let mut dor_store = DorStore::default();
+        dor_store.set_previous_root(cid(9));
+        dor_store.insert_schema_entry(&"schema".to_string(), &vec!["field".to_string()]);
+        dor_store.insert_object(PathBuf::from("a"), Object::new(cid(1)));
+        dor_store.insert_object(PathBuf::from("dir/b"), Object::new(cid(2)));
+        dor_store.insert_object(PathBuf::from("dir/c"), Object::new(cid(2)));
+        dor_store.insert_object(PathBuf::from("e"), Object::new(cid(3)));
+        let value = serde_json::json!({"field": "value"});
+        dor_store.tag_object(&PathBuf::from("e"), &"schema".to_string(), &value);
+        dor_store
+    }
+
+    #[test]
+    fn manifests_round_trip() {
+        let dor_store = manifest();
+        let mut db = SqliteManifest::open_in_memory().unwrap();
+        assert_eq!(db.read_dor_store().unwrap(), DorStore::default());
+        let sizes = BTreeMap::from([(PathBuf::from("a"), 7)]);
+        db.write_dor_store(&dor_store, &sizes).unwrap();
+        assert_eq!(db.read_dor_store().unwrap(), dor_store);
+        assert_eq!(db.len().unwrap(), 4);
+        assert_eq!(db.size_of(Path::new("a")).unwrap(), Some(7));
+        assert_eq!(db.size_of(Path::new("e")).unwrap(), None);
+
+        // Writing again replaces what was there
+        let mut smaller = dor_store.clone();
+        smaller.remove_object(&PathBuf::from("a"));
+        db.write_dor_store(&smaller, &BTreeMap::new()).unwrap();
+        assert_eq!(db.read_dor_store().unwrap(), smaller);
+    }
+
+    #[test]
+    fn objects_are_read_and_edited_a_row_at_a_time() {
+        let dor_store = manifest();
+        let mut db = SqliteManifest::open_in_memory().unwrap();
+        db.write_dor_store(&dor_store, &BTreeMap::new()).unwrap();
+
+        assert_eq!(
+            db.get_object(Path::new("./dir/b")).unwrap().as_ref(),
+            dor_store.get_object(&PathBuf::from("dir/b"))
+        );
+        assert_eq!(
+            db.paths_of(&cid(2)).unwrap(),
+            vec![PathBuf::from("dir/b"), PathBuf::from("dir/c")]
+        );
+        let under = db.objects_under(Path::new("dir")).unwrap();
+        assert_eq!(under.len(), 2);
+        assert!(db.objects_under(Path::new("di")).unwrap().is_empty());
+        // The root of the store holds everything
+        assert_eq!(db.objects_under(Path::new("")).unwrap().len(), 4);
+        assert_eq!(db.objects_under(Path::new(".")).unwrap().len(), 4);
+
+        let added = Object::new(cid(4));
+        db.insert_object(Path::new("f"), &added, Some(10)).unwrap();
+        db.insert_object(Path::new("dir/g"), &added, Some(5))
+            .unwrap();
+        assert_eq!(db.size_under(Path::new("")).unwrap(), (15, 4));
+        assert_eq!(db.size_under(Path::new("dir")).unwrap(), (5, 2));
+        db.remove_object(Path::new("dir/g")).unwrap();
+        assert!(db.remove_object(Path::new("a")).unwrap());
+        assert!(!db.remove_object(Path::new("a")).unwrap());
+        let mut expected = dor_store.clone();
+        expected.insert_object(PathBuf::from("f"), added);
+        expected.remove_object(&PathBuf::from("a"));
+        assert_eq!(db.read_dor_store().unwrap(), expected);
+    }
+
+    #[test]
+    fn diffs_match_dor_store_diffs() {
+        let previous = manifest();
+        let mut next = previous.clone();
+        next.update_object(&PathBuf::from("a"), cid(5));
+        next.remove_object(&PathBuf::from("dir/c"));
+        next.insert_object(PathBuf::from("dir/d"), Object::new(cid(6)));
+        let value = serde_json::json!({"field": "other"});
+        next.tag_object(&PathBuf::from("e"), &"schema".to_string(), &value);
+
+        let mut a = SqliteManifest::open_in_memory().unwrap();
+        a.write_dor_store(&previous, &BTreeMap::new()).unwrap();
+        let mut b = SqliteManifest::open_in_memory().unwrap();
+        b.write_dor_store(&next, &BTreeMap::new()).unwrap();
+        let diff = a.diff(&b).unwrap();
+        assert_eq!(diff, previous.diff(&next));
+        assert!(a.diff(&a).unwrap().is_empty());
+    }
+}
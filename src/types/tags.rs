@@ -0,0 +1,68 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use cid::Cid;
+use serde::{Deserialize, Serialize};
+
+/// A human name for a root, like a git tag
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Tag {
+    #[serde(with = "super::cid_serde")]
+    root: Cid,
+    created_at: DateTime<Utc>,
+}
+
+impl Tag {
+    pub fn new(root: Cid) -> Self {
+        Self {
+            root,
+            created_at: Utc::now(),
+        }
+    }
+
+    pub fn root(&self) -> &Cid {
+        &self.root
+    }
+
+    pub fn created_at(&self) -> &DateTime<Utc> {
+        &self.created_at
+    }
+}
+
+/// Named roots, by tag name
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Tags(BTreeMap<String, Tag>);
+
+impl Tags {
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        self.0.get(name)
+    }
+
+    /// Tag a root, replacing whatever the name pointed at before
+    /// # Returns the tag it replaced, if any
+    pub fn insert(&mut self, name: String, tag: Tag) -> Option<Tag> {
+        self.0.insert(name, tag)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Tag> {
+        self.0.remove(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Tag)> {
+        self.0.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Whether a name can be used as a tag: letters, digits, `.`, `_`, and `-`, not
+/// leading with a `-` so it can't be mistaken for a flag
+pub fn is_tag_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('-')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+}
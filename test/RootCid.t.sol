@@ -27,6 +27,20 @@ contract CounterTest is Test {
         assertEq(cid[1], update[1]);
     }
 
+    function testPreviousCid() public {
+        bytes32[2] memory previous = root_cid.previousCid();
+        assertEq(previous[0], bytes32(0));
+        assertEq(previous[1], bytes32(0));
+        root_cid.update(init, update);
+        previous = root_cid.previousCid();
+        assertEq(previous[0], init[0]);
+        assertEq(previous[1], init[1]);
+        root_cid.update(update, init);
+        previous = root_cid.previousCid();
+        assertEq(previous[0], update[0]);
+        assertEq(previous[1], update[1]);
+    }
+
     function testGrantWriter() public {
         root_cid.grantWriter(address(this));
         root_cid.update(init, update);
@@ -43,4 +57,62 @@ contract CounterTest is Test {
         vm.prank(address(0));
         root_cid.update(init, update);
     }
+
+    function batch(RootCid mirror) internal view returns (
+        address[] memory roots,
+        bytes32[2][] memory previous_cids,
+        bytes32[2][] memory cids
+    ) {
+        roots = new address[](2);
+        roots[0] = address(root_cid);
+        roots[1] = address(mirror);
+        previous_cids = new bytes32[2][](2);
+        previous_cids[0] = init;
+        previous_cids[1] = init;
+        cids = new bytes32[2][](2);
+        cids[0] = update;
+        cids[1] = update;
+    }
+
+    function testUpdateMany() public {
+        RootCid mirror = new RootCid(init);
+        mirror.grantBatcher(address(root_cid));
+        (address[] memory roots, bytes32[2][] memory previous_cids, bytes32[2][] memory cids) =
+            batch(mirror);
+        root_cid.updateMany(roots, previous_cids, cids);
+        bytes32[2] memory cid = root_cid.read();
+        assertEq(cid[0], update[0]);
+        assertEq(cid[1], update[1]);
+        cid = mirror.read();
+        assertEq(cid[0], update[0]);
+        assertEq(cid[1], update[1]);
+    }
+
+    function testFail_UpdateManyWithoutBatcher() public {
+        RootCid mirror = new RootCid(init);
+        (address[] memory roots, bytes32[2][] memory previous_cids, bytes32[2][] memory cids) =
+            batch(mirror);
+        root_cid.updateMany(roots, previous_cids, cids);
+    }
+
+    function testFail_UpdateManyAsNonWriter() public {
+        RootCid mirror = new RootCid(init);
+        mirror.grantBatcher(address(root_cid));
+        (address[] memory roots, bytes32[2][] memory previous_cids, bytes32[2][] memory cids) =
+            batch(mirror);
+        vm.prank(address(0));
+        root_cid.updateMany(roots, previous_cids, cids);
+    }
+
+    function testUpdateManyRevertsWhole() public {
+        RootCid mirror = new RootCid(update);
+        mirror.grantBatcher(address(root_cid));
+        (address[] memory roots, bytes32[2][] memory previous_cids, bytes32[2][] memory cids) =
+            batch(mirror);
+        vm.expectRevert();
+        root_cid.updateMany(roots, previous_cids, cids);
+        bytes32[2] memory cid = root_cid.read();
+        assertEq(cid[0], init[0]);
+        assertEq(cid[1], init[1]);
+    }
 }